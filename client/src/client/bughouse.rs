@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+use chess_core::protocol::BughouseBoard;
+use chess_core::{BughouseId, ChessColor, ChessPiece, GameId, WoodPiece};
+
+/// Something changed on one of the two boards of our bughouse match: a move, a drop, a
+/// piece that went to a pocket, or the result.
+#[derive(Event)]
+pub struct BughouseUpdated;
+
+/// The bughouse match we play in. Our own board is the `ActiveGame`; here are both boards
+/// as the server sees them, with their pockets and clocks.
+#[derive(Resource)]
+pub struct BughouseState {
+    pub bid: BughouseId,
+    pub boards: Vec<BughouseBoard>,
+    pub result: Option<f32>,          // points of the first team
+    pub selected: Option<ChessPiece>, // the piece to drop on the next square clicked
+}
+
+impl BughouseState {
+    pub fn new(bid: BughouseId) -> Self {
+        BughouseState {
+            bid,
+            boards: vec![],
+            result: None,
+            selected: None,
+        }
+    }
+
+    pub fn board(&self, gid: GameId) -> Option<&BughouseBoard> {
+        self.boards.iter().find(|b| b.game == gid)
+    }
+
+    /// The board of our partner, next to ours.
+    pub fn partner_board(&self, gid: GameId) -> Option<&BughouseBoard> {
+        self.boards.iter().find(|b| b.game != gid)
+    }
+
+    /// The pieces in our pocket on a board, which only we can drop.
+    pub fn own_pocket(&self, gid: GameId, color: ChessColor) -> Vec<WoodPiece> {
+        self.board(gid)
+            .map(|b| {
+                b.pocket
+                    .iter()
+                    .filter(|p| p.color == color)
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -5,6 +5,8 @@ use std::fs::read_to_string;
 pub struct Config {
     pub server: String,
     pub name: String,
+    pub password: Option<String>, // log in to the account `name`; play as a guest without
+    pub new_account: bool,        // create the account first
 }
 
 impl Config {
@@ -32,6 +34,8 @@ impl Config {
                 .get("name")
                 .cloned()
                 .unwrap_or_else(|| "UnnamedPlayer".to_string()),
+            password: settings.get("password").cloned(),
+            new_account: settings.get("new_account").is_some_and(|v| v == "true"),
         }
     }
 }
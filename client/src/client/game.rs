@@ -1,7 +1,7 @@
 use bevy::prelude::{Event, Resource};
-use chess_core::protocol::UserRoleSelection;
+use chess_core::protocol::{MoveRecord, UserRoleSelection};
 use chess_core::states::GameOverReason;
-use chess_core::{ClientId, GameId};
+use chess_core::{ClientId, GameId, Rating, Score, Tile, Wdl, WoodPiece};
 use std::collections::HashMap;
 
 #[derive(Event)]
@@ -21,12 +21,20 @@ pub struct GameOverEvent {
 #[derive(Event)]
 pub struct DrawOfferedEvent;
 
-#[derive(Debug, Copy, Clone)]
+/// We stepped through the moves of the game: the board shows another past position, or the
+/// live one again.
+#[derive(Event)]
+pub struct ReplayUpdated;
+
+#[derive(Debug, Default, Copy, Clone)]
 pub struct GameDetails {
     pub white_player: Option<ClientId>,
     pub black_player: Option<ClientId>,
     pub _time: u32,
     pub _time_inc: u32,
+    pub rated: bool,
+    pub white_rating: Option<Rating>,
+    pub black_rating: Option<Rating>,
 }
 
 #[derive(Resource, Debug)]
@@ -37,9 +45,43 @@ pub struct ActiveGame {
     pub game_info: GameDetails,
 
     pub move_history: Vec<String>,
+    pub records: Vec<MoveRecord>, // the moves with their positions and times, as last sent in full
+    pub replay: Option<usize>,    // while stepping through the game: how many moves are shown
+    pub book_moves: Vec<(String, u16)>, // (SAN, weight) of the opening book for the current position
+    pub analysis: Option<(u8, Score, Vec<String>)>, // latest engine analysis: depth, score, PV
+    pub tablebase: Option<(Wdl, i32, String)>, // tablebase result of the current position: WDL, DTZ, best move
 }
 
 impl ActiveGame {
+    /// Play a move the server accepted: note it and update the squares it changed.
+    pub fn apply_move(&mut self, san: String, updates: Vec<(Tile, Option<WoodPiece>)>) {
+        self.move_history.push(san);
+        for (tile, piece) in updates {
+            if let Some(p) = piece {
+                self.internal_board.insert(tile.to_string(), p.as_byte());
+            } else {
+                self.internal_board.remove(&tile.to_string());
+            }
+        }
+    }
+
+    /// Take one step back or forth in the replay. Stepping back from the live board starts
+    /// it, stepping forward to the last move ends it. The records have no position before
+    /// the first move, so the replay stops there. Returns false if there was no step to take.
+    pub fn step_replay(&mut self, forward: bool) -> bool {
+        let moves = self.move_history.len();
+        let shown = self.replay.unwrap_or(moves);
+        let next = match forward {
+            true => shown + 1,
+            false => shown.saturating_sub(1),
+        };
+        if next == 0 || next > moves || next == shown {
+            return false;
+        }
+        self.replay = Some(next).filter(|&n| n < moves);
+        true
+    }
+
     pub fn update_internal_board_from_fen(&mut self, fen: &str) {
         self.internal_board.clear();
         let fen_parts: Vec<&str> = fen.split(' ').collect();
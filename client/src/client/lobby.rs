@@ -1,13 +1,20 @@
 use crate::client::game::GameDetails;
 use bevy::prelude::Resource;
-use chess_core::{ClientId, GameId};
+use chess_core::protocol::{ChallengeParams, ChatChannel, ThemeStats};
+use chess_core::{ChallengeId, ClientId, GameId, Rating, RatingRecord, RoomId, UserId};
 use std::collections::HashMap;
 
 #[derive(Resource, Default)]
 pub struct LobbyState {
     games: HashMap<GameId, GameDetails>,
     clients: HashMap<ClientId, String>,
+    rating_history: HashMap<UserId, Vec<RatingRecord>>,
+    challenges: HashMap<ChallengeId, (ClientId, ClientId, ChallengeParams)>, // from, to, game
+    chat: HashMap<ChatChannel, Vec<(String, String)>>,                       // (name, text)
+    rooms: HashMap<RoomId, Vec<ClientId>>, // analysis rooms and who is in them
+    puzzle_stats: HashMap<UserId, (Option<Rating>, Vec<ThemeStats>)>, // puzzle rating, per theme
     pub pending_join_game: Option<GameId>,
+    pub last_finished_game: Option<GameId>, // to analyse
 }
 
 impl LobbyState {
@@ -38,4 +45,70 @@ impl LobbyState {
     pub fn has_client_info(&self, cid: ClientId) -> bool {
         self.clients.contains_key(&cid)
     }
+
+    pub fn get_rating_history(&self, uid: UserId) -> Option<&Vec<RatingRecord>> {
+        self.rating_history.get(&uid)
+    }
+
+    /// The open challenges from and to us.
+    pub fn get_challenges(&self) -> &HashMap<ChallengeId, (ClientId, ClientId, ChallengeParams)> {
+        &self.challenges
+    }
+
+    pub fn add_challenge(
+        &mut self,
+        id: ChallengeId,
+        from: ClientId,
+        to: ClientId,
+        params: ChallengeParams,
+    ) {
+        self.challenges.insert(id, (from, to, params));
+    }
+
+    pub fn remove_challenge(&mut self, id: ChallengeId) {
+        self.challenges.remove(&id);
+    }
+
+    pub fn get_chat(&self, channel: ChatChannel) -> &[(String, String)] {
+        self.chat
+            .get(&channel)
+            .map_or(&[], |lines| lines.as_slice())
+    }
+
+    pub fn add_chat_line(&mut self, channel: ChatChannel, name: String, text: String) {
+        self.chat.entry(channel).or_default().push((name, text));
+    }
+
+    /// The open analysis rooms and their members, the first one opened first.
+    pub fn get_rooms(&self) -> Vec<(RoomId, &Vec<ClientId>)> {
+        let mut rooms: Vec<_> = self.rooms.iter().map(|(rid, m)| (*rid, m)).collect();
+        rooms.sort_by_key(|(rid, _)| *rid);
+        rooms
+    }
+
+    /// A room without members has closed.
+    pub fn update_room(&mut self, rid: RoomId, members: Vec<ClientId>) {
+        if members.is_empty() {
+            self.rooms.remove(&rid);
+        } else {
+            self.rooms.insert(rid, members);
+        }
+    }
+
+    pub fn update_rating_history(&mut self, uid: UserId, history: Vec<RatingRecord>) {
+        self.rating_history.insert(uid, history);
+    }
+
+    pub fn get_puzzle_stats(&self, uid: UserId) -> Option<&(Option<Rating>, Vec<ThemeStats>)> {
+        self.puzzle_stats.get(&uid)
+    }
+
+    pub fn update_puzzle_stats(
+        &mut self,
+        uid: UserId,
+        rating: Option<Rating>,
+        themes: Vec<ThemeStats>,
+    ) {
+        self.puzzle_stats.insert(uid, (rating, themes));
+    }
 }
@@ -2,13 +2,18 @@ use super::client::network::*;
 use crate::client::config::*;
 use bevy::prelude::*;
 use lobby::LobbyState;
-use session::ClientSession;
+use session::{ClientSession, TOKEN_FILE};
+use simul::switch_board;
 
+pub mod bughouse;
 pub mod config;
 pub mod game;
 pub mod lobby;
 pub mod network;
+pub mod puzzle;
+pub mod room;
 pub mod session;
+pub mod simul;
 
 pub struct ClientPlugin;
 
@@ -20,10 +25,17 @@ impl Plugin for ClientPlugin {
         app.insert_resource(ClientSession {
             name: config.name,
             id: None,
+            password: config.password,
+            new_account: config.new_account,
+            user: None,
+            token: std::fs::read_to_string(TOKEN_FILE)
+                .ok()
+                .map(|t| t.trim().to_string()),
         });
         app.init_resource::<LobbyState>();
         app.add_systems(FixedUpdate, poll_network);
         app.insert_resource(Time::<Fixed>::from_hz(30.0)); // FixedUpdate tick-rate
         app.add_observer(send_message);
+        app.add_observer(switch_board);
     }
 }
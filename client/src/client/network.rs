@@ -1,8 +1,15 @@
 use crate::client::session::*;
 use crate::ui::gamelist_menu::UpdateGamesList;
 
-use crate::client::game::{ActiveGame, BoardUpdate, GameDetails, GameJoinedEvent, GameOverEvent};
+use crate::client::bughouse::{BughouseState, BughouseUpdated};
+use crate::client::game::{
+    ActiveGame, BoardUpdate, GameDetails, GameJoinedEvent, GameOverEvent, ReplayUpdated,
+};
 use crate::client::lobby::LobbyState;
+use crate::client::puzzle::{PUZZLE_BOARD, PuzzleSession, PuzzleUpdated, UpdatePuzzleStats};
+use crate::client::room::{AnalysisRoom, UpdateRoomsList};
+use crate::client::simul::{SimulBoardsUpdated, SimulState, SwitchBoard};
+use crate::ui::views::gameview::chessboard::board::RotateBoardEvent;
 use crate::ui::views::gameview::game_screen::DrawOffered;
 use crate::ui::views::gameview::historypanel::movehistory::{
     MoveHistoryFullRefresh, MoveHistoryUpdated,
@@ -11,6 +18,8 @@ use bevy::prelude::*;
 use chess_core::net::connection::Connection;
 use chess_core::protocol::messages::{ClientMessage, ServerMessage};
 use chess_core::protocol::parser::NetMessage;
+use chess_core::protocol::Credentials;
+use chess_core::protocol::UserRoleSelection;
 use chess_core::{GameId, NetResult};
 use smol::channel::{Receiver, Sender};
 use smol::net::TcpStream;
@@ -105,6 +114,10 @@ pub fn poll_network(
     network: Res<NetTransport>,
     mut lobby: ResMut<LobbyState>,
     active_game: Option<ResMut<ActiveGame>>,
+    mut simul: Option<ResMut<SimulState>>,
+    mut bughouse: Option<ResMut<BughouseState>>,
+    mut room: Option<ResMut<AnalysisRoom>>,
+    mut puzzle: Option<ResMut<PuzzleSession>>,
     mut session: ResMut<ClientSession>,
 ) {
     let mut active_game = active_game;
@@ -122,12 +135,24 @@ pub fn poll_network(
             }
 
             /* We received the lobby details of a specific game. */
-            ServerMessage::GameDetails(gid, white_id, black_id, time, inc) => {
+            ServerMessage::GameDetails(
+                gid,
+                white_id,
+                black_id,
+                time,
+                inc,
+                rated,
+                white_rating,
+                black_rating,
+            ) => {
                 let game_details = GameDetails {
                     white_player: white_id,
                     black_player: black_id,
                     _time: time,
                     _time_inc: inc,
+                    rated,
+                    white_rating,
+                    black_rating,
                 };
                 lobby.update_game_info(gid, game_details);
 
@@ -154,6 +179,7 @@ pub fn poll_network(
             ServerMessage::ClientDetails(cid, name) => {
                 lobby.update_client_info(cid, name);
                 commands.trigger(UpdateGamesList);
+                commands.trigger(UpdateRoomsList);
             }
 
             /* A new game has been created, we query for a new games list. */
@@ -169,7 +195,8 @@ pub fn poll_network(
 
                 // it is us, so set the active game
                 if session.id == Some(cid) {
-                    let game_info = lobby.get_game_info(gid).copied().unwrap();
+                    // a game we come back to may not be in the lobby yet, the details query fills it in
+                    let game_info = lobby.get_game_info(gid).copied().unwrap_or_default();
                     let game = ActiveGame {
                         gid,
                         side,
@@ -177,7 +204,22 @@ pub fn poll_network(
                         game_info,
 
                         move_history: Vec::new(),
+                        records: Vec::new(),
+                        replay: None,
+                        book_moves: Vec::new(),
+                        analysis: None,
+                        tablebase: None,
                     };
+                    // hosting a simul, the boards after the first one wait in the background
+                    if let Some(simul) = simul.as_mut() {
+                        if simul.on_screen.is_some() {
+                            simul.park(game);
+                            commands.trigger(NetworkSend(ClientMessage::QueryBoard(gid)));
+                            commands.trigger(NetworkSend(ClientMessage::QueryMoveHistory(gid)));
+                            continue;
+                        }
+                        simul.on_screen = Some(gid);
+                    }
                     commands.insert_resource(game);
                     // send event to the UI to trigger the switch to the game screen, query game info
                     commands.trigger(GameJoinedEvent { gid, side });
@@ -186,40 +228,372 @@ pub fn poll_network(
                 }
             }
 
-            /* A piece in the current game has been moved. */
-            ServerMessage::MoveAccepted(_, san, updates) => {
-                if let Some(game) = active_game.as_mut() {
-                    game.move_history.push(san.clone());
-
-                    for (tile, piece) in updates {
-                        if let Some(p) = piece {
-                            game.internal_board.insert(tile.to_string(), p.as_byte());
-                        } else {
-                            game.internal_board.remove(&tile.to_string());
-                        }
+            /* A piece has been moved, in the current game or on a simul board in the background. */
+            ServerMessage::MoveAccepted(gid, _, san, updates) => {
+                if let Some(game) = active_game.as_mut().filter(|g| g.gid == gid) {
+                    game.apply_move(san, updates);
+                    // a move ends the replay, the board goes live again
+                    if game.replay.take().is_some() {
+                        commands.trigger(ReplayUpdated);
                     }
                     commands.trigger(BoardUpdate);
                     commands.trigger(MoveHistoryUpdated);
                     commands.trigger(DrawOffered(false)); // Reset any draw offer
+                } else if let Some(game) = simul.as_mut().and_then(|s| s.parked_mut(gid)) {
+                    game.apply_move(san, updates);
                 }
             }
 
             /* Our last move was illegal. */
             ServerMessage::IllegalMove(_) => {}
 
-            /* We received a game over message. */
-            ServerMessage::GameOver(_gid, reason) => {
-                commands.trigger(GameOverEvent { reason });
+            /* We received a game over message. In a simul, we go on with the next board. */
+            ServerMessage::GameOver(gid, reason) => {
+                lobby.last_finished_game = Some(gid);
+                match simul.as_ref() {
+                    Some(simul) if simul.has_board(gid) => {
+                        log::info!("Simul board {}: {}", gid, reason);
+                        match simul.next_board(gid) {
+                            Some(next) if simul.on_screen == Some(gid) => {
+                                commands.trigger(SwitchBoard(next))
+                            }
+                            Some(_) => {}
+                            None => commands.trigger(GameOverEvent { reason }),
+                        }
+                    }
+                    _ => commands.trigger(GameOverEvent { reason }),
+                }
             }
 
-            /* Our Login has been accepted. Send the server our nickname. */
+            /* Our Login has been accepted. Log in to our account, or send the server our nickname. */
             ServerMessage::LoginAccepted(cid) => {
                 session.id = Some(cid);
                 let name = session.name.clone();
 
                 log::info!("Assigned session id: {}", cid);
 
-                commands.trigger(NetworkSend(ClientMessage::SetNickname(name)));
+                let name_only = session.password.is_none();
+                let msg = match session.password.clone() {
+                    Some(password) => {
+                        let credentials = Credentials { name, password };
+                        if session.new_account {
+                            ClientMessage::CreateAccount(credentials)
+                        } else {
+                            ClientMessage::Login(credentials)
+                        }
+                    }
+                    None => ClientMessage::SetNickname(name),
+                };
+                commands.trigger(NetworkSend(msg));
+                // a guest gets back the seats kept since the last session
+                if let Some(token) = session.token.clone().filter(|_| name_only) {
+                    commands.trigger(NetworkSend(ClientMessage::Reconnect(token)));
+                }
+            }
+
+            /* The token to get our seats back with after a restart; kept on disk. */
+            ServerMessage::ReconnectToken(token) => {
+                if let Err(e) = std::fs::write(TOKEN_FILE, &token) {
+                    log::warn!("Failed to save the reconnect token: {}", e);
+                }
+                session.token = Some(token);
+            }
+
+            /* We are logged in to our account. */
+            ServerMessage::LoggedIn(uid, name) => {
+                log::info!("Logged in as {} (#{})", name, uid);
+                session.user = Some(uid);
+                session.name = name;
+            }
+
+            /* The login failed. Maybe the account is already there, else we play as a guest. */
+            ServerMessage::RatingHistory(uid, history) => {
+                lobby.update_rating_history(uid, history);
+            }
+
+            /* Our seek waits for an opponent; a match arrives as `GameJoined`. */
+            ServerMessage::SeekPending => {
+                log::info!("Seek posted, waiting for an opponent");
+            }
+
+            ServerMessage::SeekCancelled(reason) => {
+                log::info!("Seek cancelled: {}", reason);
+            }
+
+            /* A challenge from or to us; the server sends them wherever we are. */
+            ServerMessage::ChallengeIssued(id, from, to, params) => {
+                log::info!("Challenge {} from {} to {}: {:?}", id, from, to, params);
+                for cid in [from, to] {
+                    if !lobby.has_client_info(cid) {
+                        commands.trigger(NetworkSend(ClientMessage::QueryClientDetails(cid)));
+                    }
+                }
+                lobby.add_challenge(id, from, to, params);
+            }
+
+            ServerMessage::ChallengeClosed(id, outcome) => {
+                log::info!("Challenge {}: {}", id, outcome);
+                lobby.remove_challenge(id);
+            }
+
+            ServerMessage::Chat(channel, _, name, text) => {
+                log::info!("[{}] {}: {}", channel, name, text);
+                lobby.add_chat_line(channel, name, text);
+            }
+
+            ServerMessage::ChatRejected(reason) => {
+                log::warn!("Chat message rejected: {}", reason);
+            }
+
+            /* The teams of a consultation game, and what our team proposes to play. */
+            ServerMessage::Teams(gid, mode, white, black) => {
+                log::info!("Game {} ({}): {:?} vs {:?}", gid, mode, white, black);
+            }
+
+            ServerMessage::Proposals(gid, proposals) => {
+                log::info!("Proposals in game {}: {:?}", gid, proposals);
+            }
+
+            /* An analysis room has been opened. If we opened it, we are in it. */
+            ServerMessage::RoomCreated(rid, creator) => {
+                if !lobby.has_client_info(creator) {
+                    commands.trigger(NetworkSend(ClientMessage::QueryClientDetails(creator)));
+                }
+                lobby.update_room(rid, vec![creator]);
+                if session.id == Some(creator) {
+                    commands.insert_resource(AnalysisRoom::new(rid));
+                }
+                commands.trigger(UpdateRoomsList);
+            }
+
+            ServerMessage::RoomMembers(rid, members) => {
+                for &cid in &members {
+                    if !lobby.has_client_info(cid) {
+                        commands.trigger(NetworkSend(ClientMessage::QueryClientDetails(cid)));
+                    }
+                }
+                lobby.update_room(rid, members);
+                commands.trigger(UpdateRoomsList);
+            }
+
+            /* The board of our room changed. The first time, it goes on screen. */
+            ServerMessage::RoomState(rid, tree) => {
+                let Some(room) = room.as_mut().filter(|r| r.rid == rid) else {
+                    continue;
+                };
+                room.tree = Some(tree);
+                let Some(game) = room.to_game() else {
+                    continue;
+                };
+                let on_screen = active_game.as_ref().is_some_and(|g| g.gid == rid);
+                commands.insert_resource(game);
+                if !on_screen {
+                    let side = UserRoleSelection::Both;
+                    commands.trigger(GameJoinedEvent { gid: rid, side });
+                }
+                commands.trigger(BoardUpdate);
+                commands.trigger(MoveHistoryFullRefresh);
+            }
+
+            /* The engine of our room, about a position the room may have left already. */
+            ServerMessage::RoomAnalysis(rid, node, depth, score, pv) => {
+                let current = room
+                    .as_ref()
+                    .filter(|r| r.rid == rid)
+                    .and_then(|r| r.tree.as_ref())
+                    .map(|tree| tree.current);
+                if let Some(game) = active_game.as_mut().filter(|g| g.gid == rid) {
+                    if current == Some(node) {
+                        game.analysis = Some((depth, score, pv));
+                    }
+                }
+            }
+
+            /* A puzzle we asked for, or how it goes on after our move. */
+            ServerMessage::PuzzleState(state) => {
+                let Some(puzzle) = puzzle.as_mut() else {
+                    continue;
+                };
+                puzzle.state = Some(state);
+                let Some(game) = puzzle.to_game() else {
+                    continue;
+                };
+                let shown = active_game.as_ref().filter(|g| g.gid == PUZZLE_BOARD);
+                match shown.map(|g| g.side) {
+                    None => {
+                        commands.trigger(GameJoinedEvent {
+                            gid: PUZZLE_BOARD,
+                            side: game.side,
+                        });
+                    }
+                    // the next puzzle may be for the other side
+                    Some(side) if side != game.side => {
+                        commands.trigger(RotateBoardEvent);
+                    }
+                    Some(_) => {}
+                }
+                commands.insert_resource(game);
+                commands.trigger(BoardUpdate);
+                commands.trigger(MoveHistoryFullRefresh);
+                commands.trigger(PuzzleUpdated);
+            }
+
+            ServerMessage::NoPuzzle(reason) => {
+                log::info!("No puzzle: {}", reason);
+                // nothing to show if there was no puzzle before
+                if puzzle.as_ref().is_some_and(|p| p.state.is_none()) {
+                    commands.remove_resource::<PuzzleSession>();
+                }
+            }
+
+            /* How some user does on puzzles; after a puzzle of ours, our new rating. */
+            ServerMessage::PuzzleStats(uid, rating, themes) => {
+                if session.user == Some(uid) {
+                    if let Some(puzzle) = puzzle.as_mut() {
+                        puzzle.rating = rating;
+                        commands.trigger(PuzzleUpdated);
+                    }
+                }
+                lobby.update_puzzle_stats(uid, rating, themes);
+                commands.trigger(UpdatePuzzleStats);
+            }
+
+            ServerMessage::TakebackOffered(gid, plies) => {
+                log::info!("Takeback of {} plies offered in game {}", plies, gid);
+            }
+
+            ServerMessage::TakebackDeclined(gid) => {
+                log::info!("Takeback declined in game {}", gid);
+            }
+
+            ServerMessage::GameAborted(gid) => {
+                log::info!("Game {} aborted", gid);
+            }
+
+            ServerMessage::RematchOffered(gid, cid) => {
+                log::info!("Client {} wants a rematch of game {}", cid, gid);
+            }
+
+            ServerMessage::TournamentCreated(tid, organizer, params) => {
+                log::info!(
+                    "Client {} opened tournament {}: {}",
+                    organizer,
+                    tid,
+                    params.name
+                );
+            }
+
+            ServerMessage::TournamentPlayers(tid, players) => {
+                log::info!("Tournament {} players: {:?}", tid, players);
+            }
+
+            ServerMessage::TournamentRound(tid, round, bye) => {
+                log::info!("Tournament {} round {} (bye: {:?})", tid, round, bye);
+            }
+
+            ServerMessage::Standings(tid, rounds, finished, standings) => {
+                log::info!(
+                    "Tournament {} after {} rounds (finished: {})",
+                    tid,
+                    rounds,
+                    finished
+                );
+                for (rank, s) in standings.iter().enumerate() {
+                    log::info!("{:>3}. {} {}", rank + 1, s.name, s.score);
+                }
+            }
+
+            ServerMessage::TournamentExport(tid, text) => {
+                log::info!("Tournament {}:\n{}", tid, text);
+            }
+
+            ServerMessage::ArenaCreated(tid, organizer, params) => {
+                log::info!("Client {} opened arena {}: {}", organizer, tid, params.name);
+            }
+
+            ServerMessage::Leaderboard(tid, seconds_left, standings) => {
+                log::info!("Arena {}, {} seconds left", tid, seconds_left);
+                for (rank, s) in standings.iter().enumerate() {
+                    log::info!(
+                        "{:>3}. {} {} ({} games)",
+                        rank + 1,
+                        s.name,
+                        s.score,
+                        s.games
+                    );
+                }
+            }
+
+            /* A simul has been opened. If we host it, we keep track of its boards. */
+            ServerMessage::SimulCreated(sid, host, params) => {
+                log::info!("Client {} hosts simul {}: {}", host, sid, params.name);
+                if session.id == Some(host) {
+                    commands.insert_resource(SimulState::new(sid));
+                }
+            }
+
+            ServerMessage::SimulPlayers(sid, players) => {
+                log::info!("Simul {} opponents: {:?}", sid, players);
+            }
+
+            ServerMessage::SimulBoards(sid, boards) => match simul.as_mut() {
+                Some(simul) if simul.sid == sid => {
+                    simul.boards = boards;
+                    commands.trigger(SimulBoardsUpdated);
+                    if simul.is_finished() {
+                        let (host, opponents) = simul.score();
+                        log::info!("Simul {} is over, {} to {}", sid, host, opponents);
+                        commands.remove_resource::<SimulState>();
+                    }
+                }
+                _ => log::info!("Simul {}: {:?}", sid, boards),
+            },
+
+            /* A bughouse match has been opened, or someone took or left a seat. */
+            ServerMessage::BughouseCreated(bid, creator, params) => {
+                let clock = format!("{}+{}", params.time, params.time_inc);
+                log::info!("Client {} opened bughouse {} ({})", creator, bid, clock);
+            }
+
+            ServerMessage::BughouseSeats(bid, seats) => {
+                log::info!("Bughouse {} seats: {:?}", bid, seats);
+            }
+
+            /* Both boards of our bughouse match. It's over as soon as there is a result. */
+            ServerMessage::BughouseState(bid, result, boards) => {
+                match bughouse.as_mut() {
+                    Some(state) if state.bid == bid => {
+                        state.boards = boards.to_vec();
+                        state.result = result;
+                    }
+                    _ => {
+                        let mut state = BughouseState::new(bid);
+                        state.boards = boards.to_vec();
+                        state.result = result;
+                        commands.insert_resource(state);
+                    }
+                }
+                commands.trigger(BughouseUpdated);
+                if let Some(points) = result {
+                    log::info!("Bughouse {} is over, {} for the first team", bid, points);
+                    commands.remove_resource::<BughouseState>();
+                }
+            }
+
+            ServerMessage::LoginFailed(reason) => {
+                let name = session.name.clone();
+                match session.password.clone() {
+                    Some(password) if session.new_account => {
+                        log::info!("Creating the account failed: {}, trying to log in", reason);
+                        session.new_account = false;
+                        let credentials = Credentials { name, password };
+                        commands.trigger(NetworkSend(ClientMessage::Login(credentials)));
+                    }
+                    _ => {
+                        log::warn!("Login failed: {}, playing as a guest", reason);
+                        commands.trigger(NetworkSend(ClientMessage::SetNickname(name)));
+                    }
+                }
             }
 
             /* Someone (or we) left the game. */
@@ -233,21 +607,31 @@ pub fn poll_network(
 
             /* We received the board state (FEN). */
             ServerMessage::BoardState(gid, fen) => {
-                if let Some(game) = active_game.as_mut() {
-                    if game.gid == gid {
-                        game.update_internal_board_from_fen(&fen);
-                        commands.trigger(BoardUpdate);
-                    }
+                if let Some(game) = active_game.as_mut().filter(|g| g.gid == gid) {
+                    game.update_internal_board_from_fen(&fen);
+                    commands.trigger(BoardUpdate);
+                } else if let Some(game) = simul.as_mut().and_then(|s| s.parked_mut(gid)) {
+                    game.update_internal_board_from_fen(&fen);
                 }
             }
 
             /* We received the move history of a game */
             ServerMessage::MoveHistory(gid, history) => {
-                if let Some(game) = active_game.as_mut() {
-                    if game.gid == gid {
-                        game.move_history = history;
-                        commands.trigger(MoveHistoryFullRefresh);
+                let sans: Vec<String> = history.iter().map(|r| r.san.clone()).collect();
+                if let Some(game) = active_game.as_mut().filter(|g| g.gid == gid) {
+                    game.move_history = sans;
+                    game.records = history;
+                    // after a takeback the replay may be past the end
+                    if game.replay.is_some_and(|n| n >= game.move_history.len()) {
+                        game.replay = None;
+                    }
+                    if game.replay.is_some() {
+                        commands.trigger(ReplayUpdated);
                     }
+                    commands.trigger(MoveHistoryFullRefresh);
+                } else if let Some(game) = simul.as_mut().and_then(|s| s.parked_mut(gid)) {
+                    game.move_history = sans;
+                    game.records = history;
                 }
             }
 
@@ -259,6 +643,33 @@ pub fn poll_network(
                     }
                 }
             }
+
+            /* We received the opening book moves for the current position */
+            ServerMessage::BookMoves(gid, moves) => {
+                if let Some(game) = active_game.as_mut() {
+                    if game.gid == gid {
+                        game.book_moves = moves;
+                    }
+                }
+            }
+
+            /* We received an engine analysis of the current position */
+            ServerMessage::Analysis(gid, depth, score, pv) => {
+                if let Some(game) = active_game.as_mut() {
+                    if game.gid == gid {
+                        game.analysis = Some((depth, score, pv));
+                    }
+                }
+            }
+
+            /* We received the tablebase result of the current position */
+            ServerMessage::TablebaseResult(gid, result) => {
+                if let Some(game) = active_game.as_mut() {
+                    if game.gid == gid {
+                        game.tablebase = result;
+                    }
+                }
+            }
         }
     }
 }
@@ -0,0 +1,88 @@
+use crate::client::game::ActiveGame;
+use bevy::prelude::*;
+use chess_core::protocol::{PuzzleParams, PuzzleState, PuzzleStatus, UserRoleSelection};
+use chess_core::{ChessColor, GameId, Rating};
+use std::collections::HashMap;
+
+/// Puzzles aren't games of the server; their board goes on screen under this ID.
+pub const PUZZLE_BOARD: GameId = 0;
+
+/// The puzzle changed: it came in, the opponent replied, or it is over.
+#[derive(Event)]
+pub struct PuzzleUpdated;
+
+/// Our puzzle rating or how we do on the themes changed.
+#[derive(Event)]
+pub struct UpdatePuzzleStats;
+
+/// The puzzles we solve. The board of the current one is on screen as the `ActiveGame`;
+/// moves on it go to the puzzle instead of a game. The next puzzle is of the same kind.
+#[derive(Resource)]
+pub struct PuzzleSession {
+    pub params: PuzzleParams,
+    pub state: Option<PuzzleState>, // until the server sends a puzzle
+    pub rating: Option<Rating>,     // ours, after the last puzzle; `None` for guests
+}
+
+impl PuzzleSession {
+    pub fn new(params: PuzzleParams) -> Self {
+        PuzzleSession {
+            params,
+            state: None,
+            rating: None,
+        }
+    }
+
+    /// The board of the puzzle, from the solver's side, with the moves played so far.
+    pub fn to_game(&self) -> Option<ActiveGame> {
+        let state = self.state.as_ref()?;
+        let mut game = ActiveGame {
+            gid: PUZZLE_BOARD,
+            side: match state.color {
+                ChessColor::White => UserRoleSelection::White,
+                ChessColor::Black => UserRoleSelection::Black,
+            },
+            internal_board: HashMap::new(),
+            game_info: Default::default(),
+
+            move_history: state.played.clone(),
+            records: Vec::new(),
+            replay: None,
+            book_moves: Vec::new(),
+            analysis: None,
+            tablebase: None,
+        };
+        game.update_internal_board_from_fen(&state.fen);
+        Some(game)
+    }
+
+    /// E.g. "Puzzle 00sHx (1760, fork middlegame): Failed, the solution was Rb7 Kf8 Ra8#;
+    /// your rating 1488".
+    pub fn status_text(&self) -> String {
+        let Some(state) = &self.state else {
+            return "Waiting for a puzzle...".to_string();
+        };
+        let mut text = format!(
+            "Puzzle {} ({}, {}): {}",
+            state.id,
+            state.rating,
+            state.themes.join(" "),
+            state.status
+        );
+        match state.status {
+            PuzzleStatus::Running => {}
+            PuzzleStatus::Solved => text.push_str(", N for the next one"),
+            PuzzleStatus::Failed => text.push_str(&format!(
+                ", the solution was {}; N for the next one",
+                state.solution.join(" ")
+            )),
+        }
+        if let Some(rating) = self
+            .rating
+            .filter(|_| state.status != PuzzleStatus::Running)
+        {
+            text.push_str(&format!("; your rating {}", rating));
+        }
+        text
+    }
+}
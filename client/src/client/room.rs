@@ -0,0 +1,59 @@
+use crate::client::game::ActiveGame;
+use bevy::prelude::*;
+use chess_core::RoomId;
+use chess_core::protocol::{RoomTree, UserRoleSelection};
+use std::collections::HashMap;
+
+/// The analysis rooms of the lobby changed: one opened or closed, or someone came or went.
+#[derive(Event)]
+pub struct UpdateRoomsList;
+
+/// The analysis room we are in. Its board is on screen as the `ActiveGame`, under the ID
+/// of the room; moves on it go to the room instead of a game.
+#[derive(Resource)]
+pub struct AnalysisRoom {
+    pub rid: RoomId,
+    pub tree: Option<RoomTree>, // until the server sends it
+}
+
+impl AnalysisRoom {
+    pub fn new(rid: RoomId) -> Self {
+        AnalysisRoom { rid, tree: None }
+    }
+
+    /// The node before the current one, to go back.
+    pub fn previous(&self) -> Option<u32> {
+        let tree = self.tree.as_ref()?;
+        tree.nodes
+            .get(tree.current as usize)
+            .filter(|_| tree.current != 0)
+            .map(|(parent, _)| *parent)
+    }
+
+    /// The next node on the main line, to go forward.
+    pub fn next(&self) -> Option<u32> {
+        let tree = self.tree.as_ref()?;
+        tree.children(tree.current).first().copied()
+    }
+
+    /// The board of the room, to put on screen: the current position, with the moves
+    /// that lead to it.
+    pub fn to_game(&self) -> Option<ActiveGame> {
+        let tree = self.tree.as_ref()?;
+        let mut game = ActiveGame {
+            gid: self.rid,
+            side: UserRoleSelection::Both,
+            internal_board: HashMap::new(),
+            game_info: Default::default(),
+
+            move_history: tree.line(),
+            records: Vec::new(),
+            replay: None,
+            book_moves: Vec::new(),
+            analysis: None,
+            tablebase: None,
+        };
+        game.update_internal_board_from_fen(&tree.fen);
+        Some(game)
+    }
+}
@@ -1,8 +1,15 @@
 use bevy::prelude::Resource;
-use chess_core::ClientId;
+use chess_core::{ClientId, UserId};
+
+/// Where a guest keeps the reconnect token, to get their seats back after a restart.
+pub const TOKEN_FILE: &str = "reconnect.token";
 
 #[derive(Resource)]
 pub struct ClientSession {
     pub name: String,
     pub id: Option<ClientId>,
+    pub password: Option<String>,
+    pub new_account: bool,
+    pub user: Option<UserId>,  // `None` while we play as a guest
+    pub token: Option<String>, // the last reconnect token the server gave us
 }
@@ -0,0 +1,101 @@
+use crate::client::game::{ActiveGame, BoardUpdate};
+use crate::ui::views::gameview::game_screen::DrawOffered;
+use crate::ui::views::gameview::historypanel::movehistory::MoveHistoryFullRefresh;
+use bevy::prelude::*;
+use chess_core::protocol::SimulBoard;
+use chess_core::{GameId, SimulId};
+use std::collections::HashMap;
+
+/// The boards of the simul changed: a move, a result, or another board on screen.
+#[derive(Event)]
+pub struct SimulBoardsUpdated;
+
+/// Put another board of the simul on screen.
+#[derive(Event)]
+pub struct SwitchBoard(pub GameId);
+
+/// A simul we host. One board is the `ActiveGame` on screen, the others are kept here
+/// and follow their moves in the background.
+#[derive(Resource)]
+pub struct SimulState {
+    pub sid: SimulId,
+    pub boards: Vec<SimulBoard>,
+    pub on_screen: Option<GameId>,
+    parked: HashMap<GameId, ActiveGame>,
+}
+
+impl SimulState {
+    pub fn new(sid: SimulId) -> Self {
+        SimulState {
+            sid,
+            boards: vec![],
+            on_screen: None,
+            parked: HashMap::new(),
+        }
+    }
+
+    pub fn has_board(&self, gid: GameId) -> bool {
+        self.on_screen == Some(gid) || self.parked.contains_key(&gid)
+    }
+
+    /// Keep a board in the background.
+    pub fn park(&mut self, game: ActiveGame) {
+        self.parked.insert(game.gid, game);
+    }
+
+    /// A board in the background, to follow its moves.
+    pub fn parked_mut(&mut self, gid: GameId) -> Option<&mut ActiveGame> {
+        self.parked.get_mut(&gid)
+    }
+
+    /// The board to look at next, other than `gid`: one waiting for our move if there is
+    /// any, else one that is still running.
+    pub fn next_board(&self, gid: GameId) -> Option<GameId> {
+        let running = || {
+            self.boards
+                .iter()
+                .filter(move |b| b.game != gid && b.result.is_none())
+        };
+        running()
+            .find(|b| b.host_to_move)
+            .or_else(|| running().next())
+            .map(|b| b.game)
+    }
+
+    /// Our points and those of all opponents together.
+    pub fn score(&self) -> (f32, f32) {
+        self.boards
+            .iter()
+            .filter_map(|b| b.result)
+            .fold((0.0, 0.0), |(host, opponents), points| {
+                (host + points, opponents + 1.0 - points)
+            })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.boards.is_empty() && self.boards.iter().all(|b| b.result.is_some())
+    }
+}
+
+/// Swap the board on screen with one from the background.
+pub fn switch_board(
+    ev: On<SwitchBoard>,
+    mut commands: Commands,
+    active_game: Option<ResMut<ActiveGame>>,
+    simul: Option<ResMut<SimulState>>,
+) {
+    let (Some(mut active_game), Some(mut simul)) = (active_game, simul) else {
+        return;
+    };
+    let Some(mut next) = simul.parked.remove(&ev.0) else {
+        return;
+    };
+    std::mem::swap(&mut *active_game, &mut next);
+    simul.on_screen = Some(active_game.gid);
+    simul.park(next);
+
+    commands.trigger(BoardUpdate);
+    commands.trigger(MoveHistoryFullRefresh);
+    commands.trigger(DrawOffered(false));
+    commands.trigger(SimulBoardsUpdated);
+}
@@ -0,0 +1,159 @@
+use crate::client::bughouse::{BughouseState, BughouseUpdated};
+use crate::client::game::ActiveGame;
+use crate::ui::views::gameview::GameScreenComponent;
+use bevy::prelude::*;
+use bevy_flair::prelude::*;
+use chess_core::protocol::BughouseBoard;
+use chess_core::protocol::UserRoleSelection;
+use chess_core::{ChessColor, ChessPiece, WoodPiece};
+
+/// The board of our partner and both pockets, next to the board on screen.
+#[derive(Component)]
+pub struct BughousePanel;
+
+/// Picks a piece of our pocket to drop when pressed.
+#[derive(Component)]
+pub struct DropPieceButton(pub ChessPiece);
+
+/// "4:05" for milliseconds left on a clock.
+fn format_clock(millis: u32) -> String {
+    let seconds = millis / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// The ranks of a board as rows of glyphs, the eighth rank first.
+fn board_rows(fen: &str) -> Vec<String> {
+    let placement = fen.split(' ').next().unwrap_or_default();
+    placement
+        .split('/')
+        .map(|rank| {
+            rank.chars()
+                .map(|c| match c.to_digit(10) {
+                    Some(n) => "·".repeat(n as usize),
+                    None => WoodPiece::from_char(c).map_or(String::new(), |p| p.to_string()),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn pocket_text(pocket: &[WoodPiece]) -> String {
+    match pocket.is_empty() {
+        true => "-".to_string(),
+        false => pocket.iter().map(|p| p.to_string()).collect(),
+    }
+}
+
+fn clocks_text(board: &BughouseBoard) -> String {
+    let [white, black] = board.clocks;
+    format!("{} - {}", format_clock(white), format_clock(black))
+}
+
+/// Rebuild the panel: the board of our partner with its clocks and pocket, our own clocks,
+/// and a button per piece in our pocket.
+pub fn refresh_bughouse_panel(
+    _ev: On<BughouseUpdated>,
+    bughouse: Option<Res<BughouseState>>,
+    active_game: Option<Res<ActiveGame>>,
+    query: Query<Entity, With<BughousePanel>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    let (Some(bughouse), Some(game)) = (bughouse, active_game) else {
+        return;
+    };
+    let (Some(own), Some(partner)) = (bughouse.board(game.gid), bughouse.partner_board(game.gid))
+    else {
+        return;
+    };
+    let color = match game.side {
+        UserRoleSelection::White => ChessColor::White,
+        UserRoleSelection::Black => ChessColor::Black,
+        _ => return,
+    };
+    let result = match bughouse.result {
+        Some(points) => format!("Result: {} - {}", points, 1.0 - points),
+        None => "Running".to_string(),
+    };
+
+    commands
+        .spawn((
+            GameScreenComponent,
+            BughousePanel,
+            NodeStyleSheet::new(asset_server.load("style.css")),
+            ClassList::new("bughouse-panel"),
+            Node {
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                top: Val::Px(0.0),
+                right: Val::Px(0.0),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new(result), ClassList::new("label-small")));
+            parent.spawn((
+                Text::new(format!("Partner: {}", clocks_text(partner))),
+                ClassList::new("label-small"),
+            ));
+            for row in board_rows(&partner.fen) {
+                parent.spawn((Text::new(row), ClassList::new("bughouse-rank")));
+            }
+            parent.spawn((
+                Text::new(format!("Pocket: {}", pocket_text(&partner.pocket))),
+                ClassList::new("label-small"),
+            ));
+            parent.spawn((
+                Text::new(format!("Our board: {}", clocks_text(own))),
+                ClassList::new("label-small"),
+            ));
+            parent.spawn((
+                Text::new(format!("Pocket: {}", pocket_text(&own.pocket))),
+                ClassList::new("label-small"),
+            ));
+            parent
+                .spawn((Node::default(), ClassList::new("bughouse-drops")))
+                .with_children(|drops| {
+                    for piece in bughouse.own_pocket(game.gid, color) {
+                        let mut classes = ClassList::new("bughouse-piece");
+                        if bughouse.selected == Some(piece.typ) {
+                            classes.add("bughouse-piece-selected");
+                        }
+                        drops.spawn((
+                            Button,
+                            Interaction::default(),
+                            DropPieceButton(piece.typ),
+                            classes,
+                            children![Text::new(piece.to_string())],
+                        ));
+                    }
+                });
+        });
+}
+
+/// Pressing a piece of our pocket selects it for the next square clicked; pressing it
+/// again takes it back.
+pub fn drop_piece_button_system(
+    interaction_query: Query<
+        (&Interaction, &DropPieceButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    bughouse: Option<ResMut<BughouseState>>,
+    mut commands: Commands,
+) {
+    let Some(mut bughouse) = bughouse else {
+        return;
+    };
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction == Interaction::Pressed {
+            bughouse.selected = match bughouse.selected {
+                Some(piece) if piece == button.0 => None,
+                _ => Some(button.0),
+            };
+            commands.trigger(BughouseUpdated);
+        }
+    }
+}
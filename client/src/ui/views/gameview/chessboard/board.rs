@@ -3,8 +3,11 @@ use super::assets::ChessAssets;
 use super::piece::ChessPiece;
 use super::square::ChessSquare;
 use super::*;
+use crate::client::bughouse::{BughouseState, BughouseUpdated};
 use crate::client::game::{ActiveGame, BoardUpdate};
 use crate::client::network::NetworkSend;
+use crate::client::puzzle::PuzzleSession;
+use crate::client::room::AnalysisRoom;
 use crate::ui::views::gameview::game_screen::{
     DESTINATION_COLOR, GameScreenInitialized, SOURCE_COLOR,
 };
@@ -13,7 +16,7 @@ use bevy::color::Color;
 use bevy::math::{Vec2, Vec3};
 use chess_core::protocol::UserRoleSelection;
 use chess_core::protocol::messages::ClientMessage;
-use chess_core::{ChessMove, Tile};
+use chess_core::{ChessDrop, ChessMove, Tile};
 
 #[derive(Event)]
 pub struct MoveSquaresSelected;
@@ -144,10 +147,27 @@ pub fn select_square(
     mut commands: Commands,
     mut query_board: Query<&mut ChessBoard>,
     mut query: Query<&mut Sprite, With<ChessSquare>>,
+    squares: Query<&ChessSquare>,
+    bughouse: Option<ResMut<BughouseState>>,
+    active_game: Option<Res<ActiveGame>>,
 ) {
     let clicked: Entity = ev.event_target();
     let mut board = query_board.single_mut().unwrap();
 
+    // in bughouse, a piece picked from the pocket goes to the square clicked
+    if let (Some(mut bughouse), Some(game)) = (bughouse, active_game) {
+        if let (Some(typ), Ok(square)) = (bughouse.selected, squares.get(clicked)) {
+            bughouse.selected = None;
+            let drop = ChessDrop {
+                typ,
+                dst: Tile::from(square.name.as_str()),
+            };
+            commands.trigger(NetworkSend(ClientMessage::Drop(game.gid, drop)));
+            commands.trigger(BughouseUpdated);
+            return;
+        }
+    }
+
     if board.selected_src == None {
         if let Ok(mut sprite) = query.get_mut(clicked) {
             board.selected_src = Some(clicked);
@@ -209,6 +229,12 @@ pub fn handle_move(
             let (is_pawn, is_last_rank) = match side {
                 UserRoleSelection::White => (piece.id == 'P', dst_sq.name.ends_with('8')),
                 UserRoleSelection::Black => (piece.id == 'p', dst_sq.name.ends_with('1')),
+                // both sides, as on the board of an analysis room: the piece tells
+                UserRoleSelection::Both => match piece.id {
+                    'P' => (true, dst_sq.name.ends_with('8')),
+                    'p' => (true, dst_sq.name.ends_with('1')),
+                    _ => (false, false),
+                },
                 _ => return Err("Invalid side".into()),
             };
 
@@ -261,19 +287,33 @@ pub fn reset_selections(
     board.selected_dst = None;
 }
 
-pub fn on_move_request(ev: On<RequestMove>, mut commands: Commands, active_game: Res<ActiveGame>) {
+pub fn on_move_request(
+    ev: On<RequestMove>,
+    mut commands: Commands,
+    active_game: Res<ActiveGame>,
+    room: Option<Res<AnalysisRoom>>,
+    puzzle: Option<Res<PuzzleSession>>,
+) {
+    // the board shows a past position
+    if active_game.replay.is_some() {
+        commands.trigger(ResetSelection);
+        return;
+    }
     let src = Tile::from(ev.source.as_str());
     let dst = Tile::from(ev.destination.as_str());
-    let promotion = ev.promotion;
-    let game_id = active_game.gid;
+    let mov = ChessMove {
+        src,
+        dst,
+        special: ev.promotion,
+    };
 
-    commands.trigger(NetworkSend(ClientMessage::Move(
-        game_id,
-        ChessMove {
-            src,
-            dst,
-            special: promotion,
-        },
-    )));
+    // the board of an analysis room is free, everybody in the room moves on it
+    // and the moves of a puzzle go to the puzzle, which the server checks
+    let msg = match (room, puzzle) {
+        (Some(room), _) => ClientMessage::RoomMove(room.rid, mov),
+        (None, Some(_)) => ClientMessage::PuzzleMove(mov),
+        (None, None) => ClientMessage::Move(active_game.gid, mov),
+    };
+    commands.trigger(NetworkSend(msg));
     commands.trigger(ResetSelection);
 }
@@ -1,7 +1,9 @@
-use crate::client::game::GameOverEvent;
+use crate::client::game::{ActiveGame, GameOverEvent};
+use crate::client::network::NetworkSend;
 use crate::ui::{Overlay, Screen};
 use bevy::prelude::*;
 use bevy_flair::prelude::*;
+use chess_core::protocol::messages::ClientMessage;
 
 #[derive(Component)]
 pub struct GameOverDialogComponent;
@@ -34,6 +36,12 @@ pub fn on_game_over(
             children![
                 (Text::new("Game Over"), ClassList::new("label-large")),
                 (Text::new(dialog_text), ClassList::new("label-small")),
+                (
+                    Button,
+                    Interaction::default(),
+                    GameOverAction::Rematch,
+                    children![Text::new("Rematch")],
+                ),
                 (
                     Button,
                     Interaction::default(),
@@ -47,6 +55,7 @@ pub fn on_game_over(
 
 #[derive(Component)]
 pub enum GameOverAction {
+    Rematch,
     Ok,
 }
 
@@ -66,10 +75,18 @@ pub fn game_over_dialog_action_system(
     >,
     mut next_screen: ResMut<NextState<Screen>>,
     mut next_overlay: ResMut<NextState<Overlay>>,
+    game: Option<Res<ActiveGame>>,
+    mut commands: Commands,
 ) {
     for (interaction, action) in interaction_query.iter_mut() {
         if *interaction == Interaction::Pressed {
             match action {
+                // if the opponent agrees, the new game takes over the screen
+                GameOverAction::Rematch => {
+                    if let Some(game) = &game {
+                        commands.trigger(NetworkSend(ClientMessage::Rematch(game.gid)));
+                    }
+                }
                 GameOverAction::Ok => {
                     next_screen.set(Screen::Menu);
                     next_overlay.set(Overlay::None);
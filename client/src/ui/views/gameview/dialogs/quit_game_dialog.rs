@@ -1,5 +1,7 @@
 use crate::client::game::ActiveGame;
 use crate::client::network::NetworkSend;
+use crate::client::puzzle::PuzzleSession;
+use crate::client::room::AnalysisRoom;
 use crate::ui::{Overlay, Screen};
 use bevy::prelude::*;
 use bevy_flair::prelude::*;
@@ -71,13 +73,32 @@ pub fn quit_game_dialog_action_system(
     mut next_overlay: ResMut<NextState<Overlay>>,
     mut next_screen: ResMut<NextState<Screen>>,
     game: Res<ActiveGame>,
+    room: Option<Res<AnalysisRoom>>,
+    puzzle: Option<Res<PuzzleSession>>,
     mut commands: Commands,
 ) {
     for (interaction, action) in interaction_query.iter_mut() {
         if *interaction == Interaction::Pressed {
             match action {
                 QuitGameAction::Confirm => {
-                    commands.trigger(NetworkSend(ClientMessage::LeaveGame(game.gid)));
+                    // a puzzle is no game, the server just forgets it with the next one
+                    if puzzle.is_some() {
+                        commands.remove_resource::<PuzzleSession>();
+                        commands.remove_resource::<ActiveGame>();
+                        next_screen.set(Screen::Menu);
+                        next_overlay.set(Overlay::None);
+                        continue;
+                    }
+                    let msg = match &room {
+                        Some(room) => ClientMessage::LeaveRoom(room.rid),
+                        None => ClientMessage::LeaveGame(game.gid),
+                    };
+                    commands.trigger(NetworkSend(msg));
+                    // nothing comes back for a room, we are out right away
+                    if room.is_some() {
+                        commands.remove_resource::<AnalysisRoom>();
+                        commands.remove_resource::<ActiveGame>();
+                    }
                     next_screen.set(Screen::Menu);
                     next_overlay.set(Overlay::None);
                 }
@@ -1,14 +1,24 @@
 use super::GameScreenComponent;
-use crate::client::game::{ActiveGame, GameJoinedEvent};
+use crate::client::game::{ActiveGame, BoardUpdate, GameJoinedEvent, ReplayUpdated};
 use crate::client::lobby::LobbyState;
 use crate::client::network::NetworkSend;
+use crate::client::puzzle::{PuzzleSession, PuzzleUpdated};
+use crate::client::room::AnalysisRoom;
+use crate::ui::views::gameview::bughousepanel::partner::{
+    drop_piece_button_system, refresh_bughouse_panel,
+};
 use crate::ui::views::gameview::chessboard::board::{ChessBoard, RotateBoardEvent};
 use crate::ui::views::gameview::historypanel::movehistory::{
     MoveHistory, on_scroll_handler, refresh_move_history, send_scroll_events, update_move_history,
 };
+use crate::ui::views::gameview::simulpanel::dashboard::{
+    refresh_simul_dashboard, simul_dashboard_button_system,
+};
 use crate::ui::{Overlay, Screen};
 use bevy::prelude::*;
 use bevy_flair::prelude::{ClassList, NodeStyleSheet};
+use chess_core::ChessColor;
+use chess_core::protocol::MoveRecord;
 use chess_core::protocol::messages::ClientMessage;
 use std::f32::consts::PI;
 
@@ -43,9 +53,18 @@ impl Plugin for GameScreenPlugin {
             .add_observer(refresh_move_history)
             .add_observer(on_scroll_handler)
             .add_observer(on_draw_offered)
+            .add_observer(on_replay_updated)
+            .add_observer(on_puzzle_updated)
+            .add_observer(refresh_simul_dashboard)
+            .add_observer(refresh_bughouse_panel)
             .add_systems(
                 Update,
-                gamescreen_button_system.run_if(in_state(Screen::Game)),
+                (
+                    gamescreen_button_system,
+                    simul_dashboard_button_system,
+                    drop_piece_button_system,
+                )
+                    .run_if(in_state(Screen::Game)),
             )
             .add_systems(Update, on_resize.run_if(in_state(Screen::Game)));
     }
@@ -65,6 +84,10 @@ pub struct WhitePlayerLabel;
 #[derive(Component)]
 pub struct BlackPlayerLabel;
 #[derive(Component)]
+pub struct ReplayLabel;
+#[derive(Component)]
+pub struct PuzzleLabel;
+#[derive(Component)]
 pub struct ResignButton;
 #[derive(Component)]
 pub struct DrawButton;
@@ -80,6 +103,7 @@ fn setup_gamescreen(
     mut commands: Commands,
     win_query: Query<(Entity, &Window)>,
     asset_server: Res<AssetServer>,
+    puzzle: Option<Res<PuzzleSession>>,
 ) {
     log::info!("Setting up gamescreen");
 
@@ -111,6 +135,35 @@ fn setup_gamescreen(
         children![Text::new("Waiting for Black...")],
     ));
 
+    commands.spawn((
+        GameScreenComponent,
+        ReplayLabel,
+        NodeStyleSheet::new(asset_server.load("style.css")),
+        ClassList::new("label-small"),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(0.0),
+            ..default()
+        },
+        Text::new(""),
+    ));
+
+    commands.spawn((
+        GameScreenComponent,
+        PuzzleLabel,
+        NodeStyleSheet::new(asset_server.load("style.css")),
+        ClassList::new("label-small"),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(0.0),
+            ..default()
+        },
+        // the puzzle came in before the screen was there
+        Text::new(puzzle.map_or(String::new(), |p| p.status_text())),
+    ));
+
     commands.spawn((
         GameScreenComponent,
         NodeStyleSheet::new(asset_server.load("style.css")),
@@ -175,14 +228,120 @@ fn cleanup_gamescreen(
     ui_scale.0 = 1.0;
 }
 
+/// Escape quits. The arrow keys step back and forth through the moves of a game. In an
+/// analysis room, they go back and forth on the moves for everybody in the room, and E turns
+/// the engine on or off. In a puzzle, N asks for the next one.
 fn listen_keyboard_input(
     keys: Res<ButtonInput<KeyCode>>,
     mut next_overlay: ResMut<NextState<Overlay>>,
     overlay: Res<State<Overlay>>,
+    room: Option<Res<AnalysisRoom>>,
+    puzzle: Option<Res<PuzzleSession>>,
+    active_game: Option<ResMut<ActiveGame>>,
+    mut commands: Commands,
 ) {
     if keys.just_pressed(KeyCode::Escape) && *overlay.get() == Overlay::None {
         next_overlay.set(Overlay::QuitGameDialog);
     }
+    if let Some(puzzle) = puzzle {
+        if keys.just_pressed(KeyCode::KeyN) {
+            let msg = ClientMessage::RequestPuzzle(puzzle.params.clone());
+            commands.trigger(NetworkSend(msg));
+        }
+        return;
+    }
+    let Some(room) = room else {
+        let Some(mut game) = active_game else {
+            return;
+        };
+        let step = if keys.just_pressed(KeyCode::ArrowLeft) {
+            game.step_replay(false)
+        } else if keys.just_pressed(KeyCode::ArrowRight) {
+            game.step_replay(true)
+        } else {
+            false
+        };
+        if step {
+            commands.trigger(ReplayUpdated);
+        }
+        return;
+    };
+    let node = if keys.just_pressed(KeyCode::ArrowLeft) {
+        room.previous()
+    } else if keys.just_pressed(KeyCode::ArrowRight) {
+        room.next()
+    } else {
+        None
+    };
+    if let Some(node) = node {
+        commands.trigger(NetworkSend(ClientMessage::RoomGoTo(room.rid, node)));
+    }
+    if keys.just_pressed(KeyCode::KeyE) {
+        let engine = room.tree.as_ref().is_some_and(|tree| tree.engine);
+        commands.trigger(NetworkSend(ClientMessage::RoomEngine(room.rid, !engine)));
+    }
+}
+
+/// Put the position of the replay on the board, with who made the move, how long it took and
+/// what was left on the clock; or the live board again at the end of the replay.
+fn on_replay_updated(
+    _ev: On<ReplayUpdated>,
+    game: Option<ResMut<ActiveGame>>,
+    mut label: Single<&mut Text, With<ReplayLabel>>,
+    mut commands: Commands,
+) {
+    let Some(mut game) = game else {
+        return;
+    };
+    label.0.clear();
+    let Some(shown) = game.replay else {
+        commands.trigger(NetworkSend(ClientMessage::QueryBoard(game.gid)));
+        return;
+    };
+    // moves made since the history came in have no record yet
+    let Some(record) = game.records.get(shown - 1).cloned() else {
+        commands.trigger(NetworkSend(ClientMessage::QueryMoveHistory(game.gid)));
+        return;
+    };
+    game.update_internal_board_from_fen(&record.fen);
+    commands.trigger(BoardUpdate);
+    label.0 = replay_text(&record);
+}
+
+/// What the puzzle on the board is about, and how we are doing.
+fn on_puzzle_updated(
+    _ev: On<PuzzleUpdated>,
+    puzzle: Option<Res<PuzzleSession>>,
+    mut label: Single<&mut Text, With<PuzzleLabel>>,
+) {
+    label.0 = puzzle.map_or(String::new(), |p| p.status_text());
+}
+
+/// E.g. "Replay: 12... Nf6 by Black, 3.4s, 4:51 left".
+fn replay_text(record: &MoveRecord) -> String {
+    // the FEN has the number of the move to come, which is the next one after black moved
+    let fullmove: usize = record
+        .fen
+        .split(' ')
+        .nth(5)
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(1);
+    let number = match record.color() {
+        ChessColor::White => format!("{}.", fullmove),
+        ChessColor::Black => format!("{}...", fullmove.saturating_sub(1)),
+    };
+    let mut text = format!(
+        "Replay: {} {} by {}, {:.1}s",
+        number,
+        record.san,
+        record.color(),
+        record.think_time as f32 / 1000.0
+    );
+    if let Some(millis) = record.clock {
+        let seconds = millis / 1000;
+        text.push_str(&format!(", {}:{:02} left", seconds / 60, seconds % 60));
+    }
+    text
 }
 
 fn update_player_names(
@@ -333,7 +492,13 @@ pub fn gamescreen_button_system(
     mut interaction_query: Query<(&Interaction, &GameAction), (Changed<Interaction>, With<Button>)>,
     mut commands: Commands,
     game: ResMut<ActiveGame>,
+    room: Option<Res<AnalysisRoom>>,
+    puzzle: Option<Res<PuzzleSession>>,
 ) {
+    // there is nobody to resign to in an analysis room or a puzzle
+    if room.is_some() || puzzle.is_some() {
+        return;
+    }
     for (interaction, action) in interaction_query.iter_mut() {
         if *interaction == Interaction::Pressed {
             match action {
@@ -1,9 +1,11 @@
 use bevy::prelude::Component;
 
+pub mod bughousepanel;
 pub mod chessboard;
 pub mod dialogs;
 pub mod game_screen;
 pub mod historypanel;
+pub mod simulpanel;
 
 /// Marker component for everything that is on the in-game screen.
 /// (The board, player names, move list, etc. ...)
@@ -0,0 +1,101 @@
+use crate::client::lobby::LobbyState;
+use crate::client::simul::{SimulBoardsUpdated, SimulState, SwitchBoard};
+use crate::ui::views::gameview::GameScreenComponent;
+use bevy::prelude::*;
+use bevy_flair::prelude::*;
+use chess_core::GameId;
+
+/// The boards of the simul we host, next to the board on screen.
+#[derive(Component)]
+pub struct SimulDashboard;
+
+/// Puts its board on screen when pressed.
+#[derive(Component)]
+pub struct SimulBoardButton(pub GameId);
+
+/// Rebuild the dashboard: the score, and a button per board that shows whether it waits
+/// for our move or how it ended.
+pub fn refresh_simul_dashboard(
+    _ev: On<SimulBoardsUpdated>,
+    simul: Option<Res<SimulState>>,
+    lobby: Res<LobbyState>,
+    query: Query<Entity, With<SimulDashboard>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    let Some(simul) = simul else {
+        return;
+    };
+
+    let waiting = simul
+        .boards
+        .iter()
+        .filter(|b| b.host_to_move && b.result.is_none())
+        .count();
+    let (host, opponents) = simul.score();
+    let summary = format!("{} - {}, {} waiting", host, opponents, waiting);
+
+    commands
+        .spawn((
+            GameScreenComponent,
+            SimulDashboard,
+            NodeStyleSheet::new(asset_server.load("style.css")),
+            ClassList::new("simul-dashboard"),
+            Node {
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                top: Val::Px(0.0),
+                left: Val::Px(100.0),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new(summary), ClassList::new("label-small")));
+            for (i, board) in simul.boards.iter().enumerate() {
+                let name = lobby
+                    .get_client_info(board.opponent)
+                    .cloned()
+                    .unwrap_or_else(|| format!("Player {}", board.opponent));
+                let status = match board.result {
+                    Some(1.0) => "won",
+                    Some(0.5) => "draw",
+                    Some(_) => "lost",
+                    None if board.host_to_move => "your move",
+                    None => "",
+                };
+                let mut classes = ClassList::new("simul-board");
+                if board.host_to_move && board.result.is_none() {
+                    classes.add("simul-board-waiting");
+                }
+                if simul.on_screen == Some(board.game) {
+                    classes.add("simul-board-current");
+                }
+                parent.spawn((
+                    Button,
+                    Interaction::default(),
+                    SimulBoardButton(board.game),
+                    classes,
+                    children![Text::new(format!("{}. {} {}", i + 1, name, status))],
+                ));
+            }
+        });
+}
+
+pub fn simul_dashboard_button_system(
+    interaction_query: Query<
+        (&Interaction, &SimulBoardButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    simul: Option<Res<SimulState>>,
+    mut commands: Commands,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        let on_screen = simul.as_ref().and_then(|s| s.on_screen);
+        if *interaction == Interaction::Pressed && on_screen != Some(button.0) {
+            commands.trigger(SwitchBoard(button.0));
+        }
+    }
+}
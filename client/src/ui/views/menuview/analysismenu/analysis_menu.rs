@@ -1,16 +1,27 @@
-use crate::ui::views::menuview::menuroot::MenuTabContainer;
+use crate::client::lobby::LobbyState;
+use crate::client::network::NetworkSend;
+use crate::client::room::{AnalysisRoom, UpdateRoomsList};
 use crate::ui::views::menuview::MenuTabComponent;
+use crate::ui::views::menuview::menuroot::MenuTabContainer;
 use bevy::prelude::*;
 use bevy_flair::prelude::*;
+use chess_core::RoomId;
+use chess_core::protocol::RoomSource;
+use chess_core::protocol::messages::ClientMessage;
 
 #[derive(Component)]
 pub struct AnalysisMenuComponent;
 
 #[derive(Component)]
 pub enum AnalysisAction {
-    Todo,
+    NewRoom,
+    AnalyseLastGame,
+    JoinRoom(RoomId),
 }
 
+#[derive(Component)]
+pub struct RoomsListContainer;
+
 pub fn setup_analysis_menu(
     mut commands: Commands,
     container_query: Query<Entity, With<MenuTabContainer>>,
@@ -28,8 +39,38 @@ pub fn setup_analysis_menu(
             children![
                 (Text::new("Analysis Menu"), ClassList::new("label-large")),
                 (
-                    Text::new("Under construction..."),
-                    ClassList::new("label-small")
+                    Node {
+                        column_gap: Val::Px(50.0),
+                        ..default()
+                    },
+                    children![
+                        (
+                            Button,
+                            Interaction::default(),
+                            ClassList::new(""),
+                            AnalysisAction::NewRoom,
+                            children![Text::new("New Analysis")],
+                        ),
+                        (
+                            Button,
+                            Interaction::default(),
+                            ClassList::new(""),
+                            AnalysisAction::AnalyseLastGame,
+                            children![Text::new("Analyse Last Game")],
+                        )
+                    ],
+                ),
+                (
+                    Node {
+                        width: Val::Percent(100.0),
+                        display: Display::Flex,
+                        flex_direction: FlexDirection::Column,
+                        margin: UiRect::top(Val::Px(20.0)),
+                        ..default()
+                    },
+                    ClassList::new("game-list-container"),
+                    RoomsListContainer,
+                    children![],
                 )
             ],
         ))
@@ -38,6 +79,7 @@ pub fn setup_analysis_menu(
     if let Ok(container) = container {
         commands.entity(container).add_child(menu_node);
     }
+    commands.trigger(UpdateRoomsList);
 }
 
 pub fn cleanup_analysis_menu(
@@ -48,3 +90,85 @@ pub fn cleanup_analysis_menu(
         commands.entity(entity).despawn();
     }
 }
+
+/// Open a room, or join one. The board comes up once the server sends the room.
+pub fn analysis_menu_action_system(
+    interaction_query: Query<(&Interaction, &AnalysisAction), (Changed<Interaction>, With<Button>)>,
+    lobby: Res<LobbyState>,
+    mut commands: Commands,
+) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction == Interaction::Pressed {
+            match action {
+                AnalysisAction::NewRoom => {
+                    let msg = ClientMessage::CreateRoom(RoomSource::Empty);
+                    commands.trigger(NetworkSend(msg));
+                }
+                AnalysisAction::AnalyseLastGame => {
+                    let Some(gid) = lobby.last_finished_game else {
+                        log::info!("No finished game to analyse");
+                        continue;
+                    };
+                    let msg = ClientMessage::CreateRoom(RoomSource::Game(gid));
+                    commands.trigger(NetworkSend(msg));
+                }
+                AnalysisAction::JoinRoom(rid) => {
+                    commands.insert_resource(AnalysisRoom::new(*rid));
+                    commands.trigger(NetworkSend(ClientMessage::JoinRoom(*rid)));
+                }
+            }
+        }
+    }
+}
+
+/// Render the open rooms with who is in them.
+pub fn update_rooms_list(
+    _ev: On<UpdateRoomsList>,
+    lobby: Res<LobbyState>,
+    mut commands: Commands,
+    container_query: Query<Entity, With<RoomsListContainer>>,
+    children_query: Query<&Children, With<RoomsListContainer>>,
+) {
+    let Ok(container) = container_query.single() else {
+        return;
+    };
+
+    if let Ok(children) = children_query.get(container) {
+        for child in children {
+            commands.entity(*child).despawn();
+        }
+    }
+
+    commands.entity(container).with_children(|parent| {
+        for (rid, members) in lobby.get_rooms() {
+            let names: Vec<String> = members
+                .iter()
+                .map(|&cid| {
+                    lobby
+                        .get_client_info(cid)
+                        .cloned()
+                        .unwrap_or_else(|| cid.to_string())
+                })
+                .collect();
+
+            parent.spawn((
+                Node::default(),
+                ClassList::new("game-item"),
+                children![
+                    (
+                        Text::new(format!("Room #{}", rid)),
+                        ClassList::new("label-small"),
+                    ),
+                    (Text::new(names.join(", ")), ClassList::new("label-small")),
+                    (
+                        Button,
+                        Interaction::default(),
+                        ClassList::new("join-button"),
+                        AnalysisAction::JoinRoom(rid),
+                        children![Text::new("Join")],
+                    )
+                ],
+            ));
+        }
+    });
+}
@@ -2,8 +2,8 @@ use crate::client::network::NetworkSend;
 use crate::ui::Overlay;
 use bevy::prelude::*;
 use bevy_flair::prelude::*;
-use chess_core::protocol::NewGameParams;
 use chess_core::protocol::messages::ClientMessage;
+use chess_core::protocol::NewGameParams;
 
 #[derive(Component)]
 pub struct CreateDialogComponent;
@@ -67,6 +67,7 @@ pub fn create_dialog_action_system(
                 CreateAction::Confirm => {
                     commands.trigger(NetworkSend(ClientMessage::NewGame(NewGameParams {
                         mode: 0,
+                        rated: false,
                         time: 600,
                         time_inc: 10,
                     })));
@@ -1,6 +1,6 @@
 use crate::client::network::NetworkSend;
 use crate::ui::views::menuview::analysismenu::analysis_menu::{
-    cleanup_analysis_menu, setup_analysis_menu,
+    analysis_menu_action_system, cleanup_analysis_menu, setup_analysis_menu, update_rooms_list,
 };
 use crate::ui::views::menuview::gamemenu::dialogs::create_game_dialog::{
     cleanup_create_dialog, create_dialog_action_system, setup_create_dialog,
@@ -11,7 +11,9 @@ use crate::ui::views::menuview::gamemenu::dialogs::join_game_dialog::{
 use crate::ui::views::menuview::gamemenu::gamelist_menu::{
     cleanup_gamelist_menu, gamelist_menu_action_system, setup_gamelist_menu, update_games_list,
 };
-use crate::ui::views::menuview::puzzlemenu::puzzle_menu::{cleanup_puzzle_menu, setup_puzzle_menu};
+use crate::ui::views::menuview::puzzlemenu::puzzle_menu::{
+    cleanup_puzzle_menu, puzzle_menu_action_system, setup_puzzle_menu, update_puzzle_stats,
+};
 
 use crate::ui::{MenuTab, Overlay, Screen};
 use bevy::prelude::*;
@@ -49,6 +51,20 @@ impl Plugin for MenuRootPlugin {
                     .run_if(in_state(MenuTab::Games)),),
             )
             .add_observer(update_games_list)
+            .add_systems(
+                Update,
+                analysis_menu_action_system
+                    .run_if(in_state(Screen::Menu))
+                    .run_if(in_state(MenuTab::Analysis)),
+            )
+            .add_observer(update_rooms_list)
+            .add_systems(
+                Update,
+                puzzle_menu_action_system
+                    .run_if(in_state(Screen::Menu))
+                    .run_if(in_state(MenuTab::Puzzle)),
+            )
+            .add_observer(update_puzzle_stats)
             // Create Game Dialog
             .add_systems(OnEnter(Overlay::CreateDialog), setup_create_dialog)
             .add_systems(OnExit(Overlay::CreateDialog), cleanup_create_dialog)
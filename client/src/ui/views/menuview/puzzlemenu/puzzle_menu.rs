@@ -1,20 +1,40 @@
-use crate::ui::views::menuview::menuroot::MenuTabContainer;
+use crate::client::lobby::LobbyState;
+use crate::client::network::NetworkSend;
+use crate::client::puzzle::{PuzzleSession, UpdatePuzzleStats};
+use crate::client::session::ClientSession;
 use crate::ui::views::menuview::MenuTabComponent;
+use crate::ui::views::menuview::menuroot::MenuTabContainer;
 use bevy::prelude::*;
 use bevy_flair::prelude::*;
+use chess_core::protocol::PuzzleParams;
+use chess_core::protocol::messages::ClientMessage;
+
+/// The themes to pick from, as the Lichess database names them; the empty one is any.
+const THEMES: [(&str, &str); 5] = [
+    ("Any", ""),
+    ("Mate in 1", "mateIn1"),
+    ("Mate in 2", "mateIn2"),
+    ("Fork", "fork"),
+    ("Pin", "pin"),
+];
 
 #[derive(Component)]
 pub struct PuzzleMenuComponent;
 
+/// Our puzzle rating and how we do per theme, best first.
+#[derive(Component)]
+pub struct PuzzleStatsContainer;
+
 #[derive(Component)]
 pub enum PuzzleAction {
-    Todo,
+    Solve(&'static str), // theme
 }
 
 pub fn setup_puzzle_menu(
     mut commands: Commands,
     container_query: Query<Entity, With<MenuTabContainer>>,
     asset_server: Res<AssetServer>,
+    session: Res<ClientSession>,
 ) {
     let container = container_query.single();
 
@@ -25,19 +45,48 @@ pub fn setup_puzzle_menu(
             PuzzleMenuComponent,
             MenuTabComponent,
             ClassList::new("menu"),
-            children![
-                (Text::new("Puzzle Menu"), ClassList::new("label-large")),
-                (
-                    Text::new("Under construction..."),
-                    ClassList::new("label-small")
-                )
-            ],
+            children![(Text::new("Puzzle Menu"), ClassList::new("label-large"))],
         ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    column_gap: Val::Px(50.0),
+                    ..default()
+                })
+                .with_children(|row| {
+                    for (label, theme) in THEMES {
+                        row.spawn((
+                            Button,
+                            Interaction::default(),
+                            ClassList::new(""),
+                            PuzzleAction::Solve(theme),
+                            children![Text::new(label)],
+                        ));
+                    }
+                });
+            parent.spawn((
+                Node {
+                    width: Val::Percent(100.0),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    margin: UiRect::top(Val::Px(20.0)),
+                    ..default()
+                },
+                ClassList::new("game-list-container"),
+                PuzzleStatsContainer,
+                children![],
+            ));
+        })
         .id();
 
     if let Ok(container) = container {
         commands.entity(container).add_child(menu_node);
     }
+
+    // Only accounts have puzzle ratings; the stats show up once they are in.
+    if let Some(uid) = session.user {
+        commands.trigger(NetworkSend(ClientMessage::QueryPuzzleStats(uid)));
+    }
 }
 
 pub fn cleanup_puzzle_menu(
@@ -48,3 +97,68 @@ pub fn cleanup_puzzle_menu(
         commands.entity(entity).despawn();
     }
 }
+
+/// Ask for a puzzle of a theme. The board comes up once the server sends it.
+pub fn puzzle_menu_action_system(
+    interaction_query: Query<(&Interaction, &PuzzleAction), (Changed<Interaction>, With<Button>)>,
+    mut commands: Commands,
+) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction == Interaction::Pressed {
+            match action {
+                PuzzleAction::Solve(theme) => {
+                    let params = PuzzleParams {
+                        theme: theme.to_string(),
+                        ..Default::default()
+                    };
+                    commands.insert_resource(PuzzleSession::new(params.clone()));
+                    commands.trigger(NetworkSend(ClientMessage::RequestPuzzle(params)));
+                }
+            }
+        }
+    }
+}
+
+/// Show our puzzle rating and the themes, from the strongest to the weakest.
+pub fn update_puzzle_stats(
+    _ev: On<UpdatePuzzleStats>,
+    lobby: Res<LobbyState>,
+    session: Res<ClientSession>,
+    mut commands: Commands,
+    container_query: Query<Entity, With<PuzzleStatsContainer>>,
+    children_query: Query<&Children, With<PuzzleStatsContainer>>,
+) {
+    let Ok(container) = container_query.single() else {
+        return;
+    };
+    let Some((rating, themes)) = session.user.and_then(|uid| lobby.get_puzzle_stats(uid)) else {
+        return;
+    };
+
+    if let Ok(children) = children_query.get(container) {
+        for child in children {
+            commands.entity(*child).despawn();
+        }
+    }
+
+    commands.entity(container).with_children(|parent| {
+        let rating = match rating {
+            Some(rating) => format!("Puzzle rating: {}", rating),
+            None => "Puzzle rating: none yet".to_string(),
+        };
+        parent.spawn((Text::new(rating), ClassList::new("label-small")));
+        for stats in themes {
+            parent.spawn((
+                Node::default(),
+                ClassList::new("game-item"),
+                children![(
+                    Text::new(format!(
+                        "{}: {}/{} solved, performance {}",
+                        stats.theme, stats.solved, stats.attempts, stats.performance
+                    )),
+                    ClassList::new("label-small"),
+                )],
+            ));
+        }
+    });
+}
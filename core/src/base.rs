@@ -1,6 +1,12 @@
 #![allow(non_upper_case_globals)]
 pub type GameId = u32;
 pub type ClientId = usize;
+pub type UserId = u32;
+pub type ChallengeId = u32;
+pub type TournamentId = u32;
+pub type SimulId = u32;
+pub type BughouseId = u32;
+pub type RoomId = u32;
 
 pub const style_bold: &str = "\x1B[1m";
 pub const style_underline: &str = "\x1B[4m";
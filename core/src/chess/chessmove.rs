@@ -70,6 +70,43 @@ impl fmt::Display for ChessMove {
     }
 }
 
+/// A piece put onto the board from the pocket (bughouse), written like "N@f3".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChessDrop {
+    pub typ: ChessPiece,
+    pub dst: Tile,
+}
+
+impl FromStr for ChessDrop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let (Some(piece), Some('@'), Some(file), Some(rank), None) = (
+            chars.next(),
+            chars.next(),
+            chars.next(),
+            chars.next(),
+            chars.next(),
+        ) else {
+            return Err(format!("Not a drop: {}", s));
+        };
+        let typ = WoodPiece::from_char(piece)
+            .filter(|p| p.color == ChessColor::White)
+            .ok_or_else(|| format!("Not a piece: {}", piece))?
+            .typ;
+        let dst = Tile::new(file, rank).ok_or_else(|| format!("Not a tile: {}", s))?;
+        Ok(ChessDrop { typ, dst })
+    }
+}
+
+impl fmt::Display for ChessDrop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let piece = WoodPiece::new(self.typ, ChessColor::White).as_byte();
+        write!(f, "{}@{}", piece, self.dst)
+    }
+}
+
 pub trait ToChessMove {
     fn parse(&self) -> Option<ChessMove>;
 }
@@ -1,6 +1,6 @@
 use core::fmt;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum ChessColor {
     Black = 0,
     White = 1,
@@ -0,0 +1,431 @@
+use crate::ChessMove;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// The position games start from, unless a PGN says otherwise with its `FEN` tag.
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+pub type NodeId = usize;
+
+/// A position of a game tree: the start position, or the position after a move.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GameNode {
+    pub parent: Option<NodeId>, // `None` for the start position
+    pub children: Vec<NodeId>,  // the main continuation first, then the variations
+    pub mov: Option<ChessMove>, // `None` for the start position, and for moves read from PGN
+    pub san: String,
+    pub fen: String, // of the position after the move; empty until known
+    pub hash: u64,   // Zobrist hash of that position; 0 until known
+    pub comment: String,
+    pub nags: Vec<u8>,
+    pub clock: Option<u32>, // milliseconds left after the move
+}
+
+/// A game with its variations: the mainline, and at every move any number of other moves
+/// that could have been played instead, each with its own continuation.
+/// Nodes keep their id when others are added or deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameTree {
+    pub tags: Vec<(String, String)>,
+    pub result: String,
+    nodes: HashMap<NodeId, GameNode>,
+    next_id: NodeId,
+}
+
+impl GameTree {
+    /// The start position.
+    pub const ROOT: NodeId = 0;
+
+    pub fn new(start_fen: &str) -> Self {
+        let root = GameNode {
+            fen: start_fen.to_string(),
+            ..GameNode::default()
+        };
+        GameTree {
+            tags: vec![],
+            result: "*".to_string(),
+            nodes: HashMap::from([(Self::ROOT, root)]),
+            next_id: Self::ROOT + 1,
+        }
+    }
+
+    pub fn start_fen(&self) -> &str {
+        &self.nodes[&Self::ROOT].fen
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&GameNode> {
+        self.nodes.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut GameNode> {
+        self.nodes.get_mut(&id)
+    }
+
+    /// Get the value of a tag pair, e.g. `tag("White")`.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Add a move after `parent`. The first move there continues the line, any other starts
+    /// a variation. A move that is already there (same SAN) is not added again.
+    /// Returns the node of the move, or `None` if there is no `parent`.
+    pub fn add_move(&mut self, parent: NodeId, node: GameNode) -> Option<NodeId> {
+        let known = self
+            .nodes
+            .get(&parent)?
+            .children
+            .iter()
+            .copied()
+            .find(|c| self.nodes[c].san == node.san);
+        if let Some(id) = known {
+            return Some(id);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.nodes.insert(
+            id,
+            GameNode {
+                parent: Some(parent),
+                children: vec![],
+                ..node
+            },
+        );
+        self.nodes.get_mut(&parent)?.children.push(id);
+        Some(id)
+    }
+
+    /// Move a variation one place up among the moves played instead of it. A first variation
+    /// becomes the main continuation. Returns false if there was nothing to promote.
+    pub fn promote(&mut self, id: NodeId) -> bool {
+        let Some(parent) = self.nodes.get(&id).and_then(|n| n.parent) else {
+            return false;
+        };
+        let children = &mut self.nodes.get_mut(&parent).unwrap().children;
+        match children.iter().position(|&c| c == id) {
+            Some(i) if i > 0 => {
+                children.swap(i - 1, i);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Promote a node and all moves leading to it to the mainline.
+    pub fn make_mainline(&mut self, id: NodeId) {
+        let mut node = id;
+        while let Some(parent) = self.nodes.get(&node).and_then(|n| n.parent) {
+            let children = &mut self.nodes.get_mut(&parent).unwrap().children;
+            children.retain(|&c| c != node);
+            children.insert(0, node);
+            node = parent;
+        }
+    }
+
+    /// Delete a move with everything that follows it. The start position can't be deleted.
+    pub fn delete(&mut self, id: NodeId) -> bool {
+        let Some(parent) = self.nodes.get(&id).and_then(|n| n.parent) else {
+            return false;
+        };
+        self.nodes
+            .get_mut(&parent)
+            .unwrap()
+            .children
+            .retain(|&c| c != id);
+        let mut doomed = vec![id];
+        while let Some(node) = doomed.pop() {
+            if let Some(node) = self.nodes.remove(&node) {
+                doomed.extend(node.children);
+            }
+        }
+        true
+    }
+
+    /// The moves of the mainline, in order.
+    pub fn mainline(&self) -> Vec<NodeId> {
+        let mut line = vec![];
+        let mut node = Self::ROOT;
+        while let Some(&next) = self.nodes[&node].children.first() {
+            line.push(next);
+            node = next;
+        }
+        line
+    }
+
+    /// The moves from the start position to a node, in order; empty for the start position.
+    pub fn line_to(&self, id: NodeId) -> Vec<NodeId> {
+        let mut line = vec![];
+        let mut node = id;
+        while let Some(parent) = self.nodes.get(&node).and_then(|n| n.parent) {
+            line.push(node);
+            node = parent;
+        }
+        line.reverse();
+        line
+    }
+
+    /// Half moves played before the start position, from its fullmove number and side to move.
+    fn start_ply(&self) -> usize {
+        let mut fields = self.start_fen().split_whitespace().skip(1);
+        let black = fields.next() == Some("b");
+        let fullmove = fields.nth(3).and_then(|n| n.parse().ok()).unwrap_or(1usize);
+        2 * fullmove.saturating_sub(1) + black as usize
+    }
+
+    /// The whole tree as PGN: the tags, then the movetext with variations in parentheses,
+    /// comments with the clock as `[%clk 0:04:59]`, and NAGs as `$1`. Games that don't start
+    /// from the usual position get a `FEN` tag.
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (tag, value) in &self.tags {
+            let _ = writeln!(pgn, "[{} \"{}\"]", tag, value);
+        }
+        if self.start_fen() != START_FEN && self.tag("FEN").is_none() {
+            let _ = writeln!(pgn, "[SetUp \"1\"]");
+            let _ = writeln!(pgn, "[FEN \"{}\"]", self.start_fen());
+        }
+        if !self.tags.is_empty() || self.start_fen() != START_FEN {
+            pgn.push('\n');
+        }
+
+        let mut tokens = vec![];
+        let root = &self.nodes[&Self::ROOT];
+        if !root.comment.is_empty() {
+            tokens.push(format!("{{{}}}", root.comment));
+        }
+        self.write_line(Self::ROOT, self.start_ply(), &mut tokens, true);
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                let _ = writeln!(pgn, "{}", line);
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        let _ = writeln!(pgn, "{}", line);
+        pgn
+    }
+
+    /// The moves after `parent`, which is `ply` half moves into the game. Every move gets its
+    /// variations right after it, in parentheses.
+    fn write_line(&self, parent: NodeId, ply: usize, tokens: &mut Vec<String>, numbered: bool) {
+        let (mut parent, mut ply, mut numbered) = (parent, ply, numbered);
+        while let Some((&main, variations)) = self.nodes[&parent].children.split_first() {
+            numbered = self.write_move(main, ply, tokens, numbered);
+            for &variation in variations {
+                let mut line = vec![];
+                let numbered = self.write_move(variation, ply, &mut line, true);
+                self.write_line(variation, ply + 1, &mut line, numbered);
+                line[0].insert(0, '(');
+                line.last_mut().unwrap().push(')');
+                tokens.extend(line);
+            }
+            numbered |= !variations.is_empty();
+            parent = main;
+            ply += 1;
+        }
+    }
+
+    /// A move with its number, NAGs and comment. The number of a black move is only written
+    /// when `numbered`, i.e. when something came between it and the white move. Returns
+    /// whether the next move needs its number.
+    fn write_move(&self, id: NodeId, ply: usize, tokens: &mut Vec<String>, numbered: bool) -> bool {
+        let node = &self.nodes[&id];
+        if ply.is_multiple_of(2) {
+            tokens.push(format!("{}.", ply / 2 + 1));
+        } else if numbered {
+            tokens.push(format!("{}...", ply / 2 + 1));
+        }
+        tokens.push(node.san.clone());
+        tokens.extend(node.nags.iter().map(|nag| format!("${}", nag)));
+
+        let mut comment = node.clock.map(format_clock).unwrap_or_default();
+        if !node.comment.is_empty() {
+            if !comment.is_empty() {
+                comment.push(' ');
+            }
+            comment.push_str(&node.comment);
+        }
+        if comment.is_empty() {
+            return false;
+        }
+        tokens.push(format!("{{{}}}", comment));
+        true
+    }
+
+    /// Read the first game of a PGN text, with its variations, comments, NAGs and clock
+    /// times. The moves are only read as SAN: `mov`, `fen` and `hash` stay empty, as they
+    /// need the rules to be worked out. Fails on parentheses that don't match.
+    pub fn from_pgn(text: &str) -> Result<GameTree, String> {
+        let mut tags = vec![];
+        let mut tree = GameTree::new(START_FEN);
+        let mut current = Self::ROOT; // the last move read
+        let mut fresh = true; // no move read yet in the current line
+        let mut pending = String::new(); // a comment before the first move of a variation
+        let mut variations = vec![]; // where the enclosing lines were when a variation started
+        let mut in_movetext = false;
+
+        let mut chars = text.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            match c {
+                // tag pair, e.g. [White "Carlsen"]; a tag after the movetext starts the next game
+                '[' if in_movetext => break,
+                '[' => {
+                    chars.next();
+                    let tag: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                    if let Some((key, value)) = tag.split_once(' ') {
+                        let value = value.trim().trim_matches('"').to_string();
+                        tags.push((key.trim().to_string(), value));
+                    }
+                }
+                '{' | ';' => {
+                    chars.next();
+                    let end = if c == '{' { '}' } else { '\n' };
+                    let text: String = chars.by_ref().take_while(|c| *c != end).collect();
+                    let (clock, text) = parse_comment(&text);
+                    if fresh && !variations.is_empty() {
+                        pending = join_comments(&pending, &text);
+                        continue;
+                    }
+                    let node = tree.nodes.get_mut(&current).unwrap();
+                    node.comment = join_comments(&node.comment, &text);
+                    node.clock = clock.or(node.clock);
+                }
+                '(' => {
+                    chars.next();
+                    in_movetext = true;
+                    let parent = tree.nodes[&current]
+                        .parent
+                        .ok_or("Variation before a move")?;
+                    variations.push(current);
+                    current = parent;
+                    fresh = true;
+                }
+                ')' => {
+                    chars.next();
+                    current = variations.pop().ok_or("Unexpected ')'")?;
+                    fresh = false;
+                }
+                _ if c.is_whitespace() => {
+                    chars.next();
+                }
+                _ => {
+                    let mut token = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || "[{;()".contains(c) {
+                            break;
+                        }
+                        token.push(c);
+                        chars.next();
+                    }
+                    in_movetext = true;
+
+                    match token.as_str() {
+                        "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                            tree.result = token;
+                            break;
+                        }
+                        // numeric annotation glyph, e.g. $1
+                        _ if token.starts_with('$') => {
+                            if let Ok(nag) = token[1..].parse() {
+                                tree.nodes.get_mut(&current).unwrap().nags.push(nag);
+                            }
+                        }
+                        _ => {
+                            // strip move numbers ("12." or "12..."), annotations ("!?") are NAGs
+                            let san =
+                                token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                            let suffix = san.trim_start_matches(|c| c != '!' && c != '?');
+                            let san = &san[..san.len() - suffix.len()];
+                            if san.is_empty() {
+                                continue;
+                            }
+                            let node = GameNode {
+                                san: san.to_string(),
+                                nags: suffix_nag(suffix).into_iter().collect(),
+                                comment: std::mem::take(&mut pending),
+                                ..GameNode::default()
+                            };
+                            current = tree.add_move(current, node).unwrap();
+                            fresh = false;
+                        }
+                    }
+                }
+            }
+        }
+        if !variations.is_empty() {
+            return Err("Missing ')'".to_string());
+        }
+
+        if let Some((_, fen)) = tags.iter().find(|(key, _)| key == "FEN") {
+            tree.get_mut(Self::ROOT).unwrap().fen = fen.clone();
+        }
+        tree.tags = tags;
+        Ok(tree)
+    }
+}
+
+/// The NAG of a move suffix, e.g. 1 for "!".
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+fn join_comments(a: &str, b: &str) -> String {
+    match (a.is_empty(), b.is_empty()) {
+        (true, _) => b.to_string(),
+        (_, true) => a.to_string(),
+        _ => format!("{} {}", a, b),
+    }
+}
+
+/// "[%clk 1:05:09]" for milliseconds on the clock.
+fn format_clock(millis: u32) -> String {
+    let seconds = millis / 1000;
+    format!(
+        "[%clk {}:{:02}:{:02}]",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Take the clock command out of a comment: the clock in milliseconds, and the rest.
+fn parse_comment(text: &str) -> (Option<u32>, String) {
+    let Some(start) = text.find("[%clk ") else {
+        return (None, text.trim().to_string());
+    };
+    let Some(len) = text[start..].find(']') else {
+        return (None, text.trim().to_string());
+    };
+    let clock = text[start + 6..start + len]
+        .trim()
+        .split(':')
+        .try_fold(0.0, |acc, part| {
+            part.parse::<f64>().ok().map(|n| acc * 60.0 + n)
+        });
+    let rest = format!(
+        "{} {}",
+        text[..start].trim(),
+        text[start + len + 1..].trim()
+    );
+    (
+        clock.map(|seconds| (seconds * 1000.0).round() as u32),
+        rest.trim().to_string(),
+    )
+}
@@ -1,10 +1,18 @@
 pub mod chessmove;
 pub mod color;
+pub mod gametree;
 pub mod piece;
+pub mod rating;
+pub mod score;
 pub mod states;
 pub mod tile;
+pub mod wdl;
 
-pub use chessmove::{ChessMove, Promotion};
+pub use chessmove::{ChessDrop, ChessMove, Promotion};
 pub use color::ChessColor;
+pub use gametree::{GameNode, GameTree, NodeId, START_FEN};
 pub use piece::{ChessPiece, WoodPiece};
+pub use rating::{Rating, RatingRecord, TimeCategory, Variant};
+pub use score::Score;
 pub use tile::Tile;
+pub use wdl::Wdl;
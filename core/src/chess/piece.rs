@@ -92,58 +92,19 @@ pub trait Id {
     fn id(&self) -> u32;
 }
 
-/// used for Zobrist Hashing
+/// used for Zobrist Hashing.
+/// The order follows the piece kinds of the Polyglot book format:
+/// black pawn, white pawn, black knight, white knight, ..., black king, white king.
 impl Id for WoodPiece {
     fn id(&self) -> u32 {
-        match self {
-            WoodPiece {
-                typ: ChessPiece::King,
-                color: ChessColor::White,
-            } => 0,
-            WoodPiece {
-                typ: ChessPiece::Queen,
-                color: ChessColor::White,
-            } => 1,
-            WoodPiece {
-                typ: ChessPiece::Rook,
-                color: ChessColor::White,
-            } => 2,
-            WoodPiece {
-                typ: ChessPiece::Bishop,
-                color: ChessColor::White,
-            } => 3,
-            WoodPiece {
-                typ: ChessPiece::Knight,
-                color: ChessColor::White,
-            } => 4,
-            WoodPiece {
-                typ: ChessPiece::Pawn,
-                color: ChessColor::White,
-            } => 5,
-            WoodPiece {
-                typ: ChessPiece::King,
-                color: ChessColor::Black,
-            } => 6,
-            WoodPiece {
-                typ: ChessPiece::Queen,
-                color: ChessColor::Black,
-            } => 7,
-            WoodPiece {
-                typ: ChessPiece::Rook,
-                color: ChessColor::Black,
-            } => 8,
-            WoodPiece {
-                typ: ChessPiece::Bishop,
-                color: ChessColor::Black,
-            } => 9,
-            WoodPiece {
-                typ: ChessPiece::Knight,
-                color: ChessColor::Black,
-            } => 10,
-            WoodPiece {
-                typ: ChessPiece::Pawn,
-                color: ChessColor::Black,
-            } => 11,
-        }
+        let kind = match self.typ {
+            ChessPiece::Pawn => 0,
+            ChessPiece::Knight => 1,
+            ChessPiece::Bishop => 2,
+            ChessPiece::Rook => 3,
+            ChessPiece::Queen => 4,
+            ChessPiece::King => 5,
+        };
+        2 * kind + self.color as u32
     }
 }
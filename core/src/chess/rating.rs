@@ -0,0 +1,117 @@
+use std::fmt;
+
+/// The rules a game is played by. Ratings are kept apart per variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Variant {
+    #[default]
+    Standard = 0,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 1] = [Variant::Standard];
+
+    pub fn from_u8(value: u8) -> Option<Variant> {
+        Self::ALL.into_iter().find(|v| *v as u8 == value)
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Variant::Standard => "Standard",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How fast a game is, by its time control. Ratings are kept apart per category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeCategory {
+    Bullet = 0,
+    Blitz = 1,
+    Rapid = 2,
+    Classical = 3,
+    Correspondence = 4,
+}
+
+impl TimeCategory {
+    pub const ALL: [TimeCategory; 5] = [
+        TimeCategory::Bullet,
+        TimeCategory::Blitz,
+        TimeCategory::Rapid,
+        TimeCategory::Classical,
+        TimeCategory::Correspondence,
+    ];
+
+    /// The category of a time control (in seconds), by the expected duration of a game of
+    /// 40 moves: under 3 minutes is bullet, under 8 blitz, under 25 rapid.
+    /// Games without a clock are correspondence games.
+    pub fn from_clock(time: u32, time_inc: u32) -> TimeCategory {
+        if time == 0 && time_inc == 0 {
+            return TimeCategory::Correspondence;
+        }
+        match time + 40 * time_inc {
+            0..180 => TimeCategory::Bullet,
+            180..480 => TimeCategory::Blitz,
+            480..1500 => TimeCategory::Rapid,
+            _ => TimeCategory::Classical,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<TimeCategory> {
+        Self::ALL.into_iter().find(|c| *c as u8 == value)
+    }
+}
+
+impl fmt::Display for TimeCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TimeCategory::Bullet => "Bullet",
+            TimeCategory::Blitz => "Blitz",
+            TimeCategory::Rapid => "Rapid",
+            TimeCategory::Classical => "Classical",
+            TimeCategory::Correspondence => "Correspondence",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A rating as clients see it: the Glicko-2 rating and its deviation, rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rating {
+    pub rating: u16,
+    pub deviation: u16,
+}
+
+impl Rating {
+    /// With a high deviation, we don't know the player well yet.
+    pub fn is_provisional(&self) -> bool {
+        self.deviation > 110
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.rating.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.deviation.to_le_bytes());
+        bytes
+    }
+}
+
+/// `1500?` for a provisional rating.
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.rating)?;
+        if self.is_provisional() {
+            write!(f, "?")?;
+        }
+        Ok(())
+    }
+}
+
+/// A rating of a player after a rated game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RatingRecord {
+    pub variant: Variant,
+    pub category: TimeCategory,
+    pub time: u32, // seconds since the Unix epoch
+    pub rating: Rating,
+}
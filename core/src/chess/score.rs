@@ -0,0 +1,18 @@
+use std::fmt;
+
+/// An engine evaluation of a position, from the view of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Cp(i32),   // centipawns
+    Mate(i32), // moves until mate; positive if the side to move mates, negative if it gets mated
+}
+
+/// Same notation as UCI uses, e.g. `cp 35` or `mate -2`.
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Score::Cp(cp) => write!(f, "cp {}", cp),
+            Score::Mate(n) => write!(f, "mate {}", n),
+        }
+    }
+}
@@ -11,6 +11,8 @@ pub enum GameOverReason {
     InsufficientMaterial,
     FiftyMovesRule,
     DrawAgreement,
+    Adjudication(Option<ChessColor>), // by the server, e.g. from a tablebase; `None` is a draw
+    Abandoned(ChessColor),            // the winner; the loser stayed away on their move
 }
 
 impl GameOverReason {
@@ -24,6 +26,8 @@ impl GameOverReason {
             GameOverReason::InsufficientMaterial => 6,
             GameOverReason::FiftyMovesRule => 7,
             GameOverReason::DrawAgreement => 8,
+            GameOverReason::Adjudication(_) => 9,
+            GameOverReason::Abandoned(_) => 10,
         }
     }
 
@@ -31,7 +35,9 @@ impl GameOverReason {
         match self {
             GameOverReason::Checkmate(c)
             | GameOverReason::Resignation(c)
-            | GameOverReason::TimeOut(c) => Some(*c),
+            | GameOverReason::TimeOut(c)
+            | GameOverReason::Abandoned(c) => Some(*c),
+            GameOverReason::Adjudication(c) => *c,
             _ => None,
         }
     }
@@ -48,6 +54,8 @@ impl Display for GameOverReason {
             GameOverReason::InsufficientMaterial => "Insufficient Material",
             GameOverReason::FiftyMovesRule => "50-Moves-Rule",
             GameOverReason::DrawAgreement => "Agreement",
+            GameOverReason::Adjudication(_) => "Adjudication",
+            GameOverReason::Abandoned(_) => "Abandoned",
         };
         write!(f, "{}", text)
     }
@@ -0,0 +1,39 @@
+use std::fmt;
+
+/// Win/draw/loss of a tablebase position, from the view of the side to move.
+/// Cursed wins and blessed losses are wins and losses that the 50-moves rule turns into draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wdl {
+    Loss = 0,
+    BlessedLoss = 1,
+    Draw = 2,
+    CursedWin = 3,
+    Win = 4,
+}
+
+impl Wdl {
+    pub fn from_u8(value: u8) -> Option<Wdl> {
+        match value {
+            0 => Some(Wdl::Loss),
+            1 => Some(Wdl::BlessedLoss),
+            2 => Some(Wdl::Draw),
+            3 => Some(Wdl::CursedWin),
+            4 => Some(Wdl::Win),
+            _ => None,
+        }
+    }
+}
+
+/// Same names as Syzygy tools use, e.g. `CursedWin`.
+impl fmt::Display for Wdl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Wdl::Loss => "Loss",
+            Wdl::BlessedLoss => "BlessedLoss",
+            Wdl::Draw => "Draw",
+            Wdl::CursedWin => "CursedWin",
+            Wdl::Win => "Win",
+        };
+        write!(f, "{}", s)
+    }
+}
@@ -1,4 +1,4 @@
-use crate::chess::ChessMove;
+use crate::chess::{ChessDrop, ChessMove};
 use std::fmt;
 use std::io;
 
@@ -40,6 +40,7 @@ pub type NetResult<T> = std::result::Result<T, NetError>;
 pub enum ChessError {
     IllegalMove(ChessMove),
     NotYourTurn,
+    IllegalDrop(ChessDrop),
 }
 
 impl fmt::Display for ChessError {
@@ -59,6 +60,7 @@ impl fmt::Display for ChessError {
                 }
             ),
             ChessError::NotYourTurn => write!(f, "It's not your turn"),
+            ChessError::IllegalDrop(d) => write!(f, "Illegal drop: {}", d),
         }
     }
 }
@@ -77,6 +79,11 @@ impl ChessError {
             ChessError::NotYourTurn => {
                 vec![1u8] // discriminant for NotYourTurn
             }
+            ChessError::IllegalDrop(drop) => {
+                let mut bytes = vec![2u8]; // discriminant for IllegalDrop
+                bytes.extend_from_slice(drop.to_string().as_bytes());
+                bytes
+            }
         }
     }
 
@@ -97,6 +104,13 @@ impl ChessError {
                 Ok(ChessError::IllegalMove(mov))
             }
             1 => Ok(ChessError::NotYourTurn),
+            2 => {
+                // IllegalDrop
+                let drop_str = String::from_utf8(bytes[1..].to_vec())
+                    .map_err(|_| "Failed to parse drop string".to_string())?;
+                let drop = drop_str.parse()?;
+                Ok(ChessError::IllegalDrop(drop))
+            }
             _ => Err(format!("Unknown ChessError discriminant: {}", bytes[0])),
         }
     }
@@ -1,4 +1,9 @@
-use crate::protocol::{JoinGameParams, NewGameParams, UserRoleSelection};
+use crate::protocol::{
+    ArenaParams, ArenaStanding, BughouseBoard, BughouseParams, ChallengeOutcome, ChallengeParams,
+    ChatChannel, ConsultationParams, Credentials, DeclineReason, ExportFormat, JoinGameParams,
+    MoveRecord, NewGameParams, PuzzleParams, PuzzleState, RoomSource, RoomTree, SeekParams,
+    SimulBoard, SimulParams, Standing, ThemeStats, TournamentParams, UserRoleSelection, VotingMode,
+};
 use crate::states::GameOverReason;
 use crate::*;
 use smol::channel::Sender;
@@ -23,6 +28,55 @@ pub enum ClientMessage {
     LeaveGame(GameId),
     Resign(GameId),
     OfferDraw(GameId),
+    QueryBookMoves(GameId),
+    AddComputer(GameId, UserRoleSelection, u8), // side, level
+    Analyse(GameId),
+    QueryTablebase(GameId),
+    CreateAccount(Credentials), // also logs in
+    Login(Credentials),
+    QueryRatingHistory(UserId),
+    Seek(SeekParams), // replaces an earlier seek of the client
+    CancelSeek,
+    Challenge(ClientId, ChallengeParams), // the challenged client
+    AcceptChallenge(ChallengeId),
+    DeclineChallenge(ChallengeId, DeclineReason),
+    CancelChallenge(ChallengeId),
+    Chat(ChatChannel, String),
+    RequestTakeback(GameId, u8), // plies: 1 for the last half-move, 2 for a full move
+    AnswerTakeback(GameId, bool), // accept?
+    Abort(GameId),               // only before both sides have moved
+    Rematch(GameId),             // of a game that just ended
+    CreateTournament(TournamentParams),
+    JoinTournament(TournamentId),
+    WithdrawTournament(TournamentId), // before the start, or to stop playing
+    StartTournament(TournamentId),    // only the organizer
+    QueryStandings(TournamentId),
+    ExportTournament(TournamentId, ExportFormat),
+    CreateArena(ArenaParams),
+    JoinArena(TournamentId),     // also to come back after a pause
+    PauseArena(TournamentId),    // no new games until joining again
+    WithdrawArena(TournamentId), // leave; the points stay
+    CreateSimul(SimulParams),
+    JoinSimul(SimulId),
+    LeaveSimul(SimulId), // before the start
+    StartSimul(SimulId), // only the host
+    CreateBughouse(BughouseParams),
+    JoinBughouse(BughouseId, u8), // seat: 0, 1 white, black on the first board; 2, 3 on the second
+    LeaveBughouse(BughouseId),    // before the start
+    Drop(GameId, ChessDrop),      // a piece from the pocket
+    CreateConsultation(ConsultationParams),
+    JoinTeam(GameId, ChessColor), // the first to join a side is its captain
+    CreateRoom(RoomSource),       // the creator joins right away
+    JoinRoom(RoomId),
+    LeaveRoom(RoomId),
+    RoomMove(RoomId, ChessMove), // in the current node; a move played before is followed
+    RoomSetup(RoomId, String),   // FEN; starts the tree over
+    RoomGoTo(RoomId, u32),       // node
+    RoomEngine(RoomId, bool),    // on?
+    RequestPuzzle(PuzzleParams), // gives up the puzzle being solved
+    PuzzleMove(ChessMove),
+    QueryPuzzleStats(UserId),
+    Reconnect(String), // the token of an earlier connection, to get its seats back
 }
 
 impl ClientMessage {
@@ -38,6 +92,55 @@ impl ClientMessage {
     pub const QUERY_MOVE_HISTORY: u8 = 0x13;
     pub const RESIGN: u8 = 0x14;
     pub const OFFER_DRAW: u8 = 0x15;
+    pub const QUERY_BOOK_MOVES: u8 = 0x16;
+    pub const ADD_COMPUTER: u8 = 0x17;
+    pub const ANALYSE: u8 = 0x18;
+    pub const QUERY_TABLEBASE: u8 = 0x19;
+    pub const CREATE_ACCOUNT: u8 = 0x1A;
+    pub const LOGIN: u8 = 0x1B;
+    pub const QUERY_RATING_HISTORY: u8 = 0x1C;
+    pub const SEEK: u8 = 0x1D;
+    pub const CANCEL_SEEK: u8 = 0x1E;
+    pub const CHALLENGE: u8 = 0x1F;
+    pub const ACCEPT_CHALLENGE: u8 = 0x20;
+    pub const DECLINE_CHALLENGE: u8 = 0x21;
+    pub const CANCEL_CHALLENGE: u8 = 0x22;
+    pub const CHAT: u8 = 0x23;
+    pub const REQUEST_TAKEBACK: u8 = 0x24;
+    pub const ANSWER_TAKEBACK: u8 = 0x25;
+    pub const ABORT: u8 = 0x26;
+    pub const REMATCH: u8 = 0x27;
+    pub const CREATE_TOURNAMENT: u8 = 0x28;
+    pub const JOIN_TOURNAMENT: u8 = 0x29;
+    pub const WITHDRAW_TOURNAMENT: u8 = 0x2A;
+    pub const START_TOURNAMENT: u8 = 0x2B;
+    pub const QUERY_STANDINGS: u8 = 0x2C;
+    pub const EXPORT_TOURNAMENT: u8 = 0x2D;
+    pub const CREATE_ARENA: u8 = 0x2E;
+    pub const JOIN_ARENA: u8 = 0x2F;
+    pub const PAUSE_ARENA: u8 = 0x30;
+    pub const WITHDRAW_ARENA: u8 = 0x31;
+    pub const CREATE_SIMUL: u8 = 0x32;
+    pub const JOIN_SIMUL: u8 = 0x33;
+    pub const LEAVE_SIMUL: u8 = 0x34;
+    pub const START_SIMUL: u8 = 0x35;
+    pub const CREATE_BUGHOUSE: u8 = 0x36;
+    pub const JOIN_BUGHOUSE: u8 = 0x37;
+    pub const LEAVE_BUGHOUSE: u8 = 0x38;
+    pub const DROP: u8 = 0x39;
+    pub const CREATE_CONSULTATION: u8 = 0x3A;
+    pub const JOIN_TEAM: u8 = 0x3B;
+    pub const CREATE_ROOM: u8 = 0x3C;
+    pub const JOIN_ROOM: u8 = 0x3D;
+    pub const LEAVE_ROOM: u8 = 0x3E;
+    pub const ROOM_MOVE: u8 = 0x3F;
+    pub const ROOM_SETUP: u8 = 0x40;
+    pub const ROOM_GO_TO: u8 = 0x41;
+    pub const ROOM_ENGINE: u8 = 0x42;
+    pub const REQUEST_PUZZLE: u8 = 0x43;
+    pub const PUZZLE_MOVE: u8 = 0x44;
+    pub const QUERY_PUZZLE_STATS: u8 = 0x45;
+    pub const RECONNECT: u8 = 0x46;
 }
 
 impl fmt::Display for ClientMessage {
@@ -56,6 +159,55 @@ impl fmt::Display for ClientMessage {
             ClientMessage::QueryMoveHistory(_) => "Query Move History",
             ClientMessage::Resign(_) => "Resign",
             ClientMessage::OfferDraw(_) => "Offer Draw",
+            ClientMessage::QueryBookMoves(_) => "Query Book Moves",
+            ClientMessage::AddComputer(_, _, _) => "Add Computer",
+            ClientMessage::Analyse(_) => "Analyse",
+            ClientMessage::QueryTablebase(_) => "Query Tablebase",
+            ClientMessage::CreateAccount(_) => "Create Account",
+            ClientMessage::Login(_) => "Login",
+            ClientMessage::QueryRatingHistory(_) => "Query Rating History",
+            ClientMessage::Seek(_) => "Seek",
+            ClientMessage::CancelSeek => "Cancel Seek",
+            ClientMessage::Challenge(_, _) => "Challenge",
+            ClientMessage::AcceptChallenge(_) => "Accept Challenge",
+            ClientMessage::DeclineChallenge(_, _) => "Decline Challenge",
+            ClientMessage::CancelChallenge(_) => "Cancel Challenge",
+            ClientMessage::Chat(_, _) => "Chat",
+            ClientMessage::RequestTakeback(_, _) => "Request Takeback",
+            ClientMessage::AnswerTakeback(_, _) => "Answer Takeback",
+            ClientMessage::Abort(_) => "Abort",
+            ClientMessage::Rematch(_) => "Rematch",
+            ClientMessage::CreateTournament(_) => "Create Tournament",
+            ClientMessage::JoinTournament(_) => "Join Tournament",
+            ClientMessage::WithdrawTournament(_) => "Withdraw from Tournament",
+            ClientMessage::StartTournament(_) => "Start Tournament",
+            ClientMessage::QueryStandings(_) => "Query Standings",
+            ClientMessage::ExportTournament(_, _) => "Export Tournament",
+            ClientMessage::CreateArena(_) => "Create Arena",
+            ClientMessage::JoinArena(_) => "Join Arena",
+            ClientMessage::PauseArena(_) => "Pause Arena",
+            ClientMessage::WithdrawArena(_) => "Withdraw from Arena",
+            ClientMessage::CreateSimul(_) => "Create Simul",
+            ClientMessage::JoinSimul(_) => "Join Simul",
+            ClientMessage::LeaveSimul(_) => "Leave Simul",
+            ClientMessage::StartSimul(_) => "Start Simul",
+            ClientMessage::CreateBughouse(_) => "Create Bughouse",
+            ClientMessage::JoinBughouse(_, _) => "Join Bughouse",
+            ClientMessage::LeaveBughouse(_) => "Leave Bughouse",
+            ClientMessage::Drop(_, _) => "Drop Piece",
+            ClientMessage::CreateConsultation(_) => "Create Consultation",
+            ClientMessage::JoinTeam(_, _) => "Join Team",
+            ClientMessage::CreateRoom(_) => "Create Analysis Room",
+            ClientMessage::JoinRoom(_) => "Join Analysis Room",
+            ClientMessage::LeaveRoom(_) => "Leave Analysis Room",
+            ClientMessage::RoomMove(_, _) => "Move in Analysis Room",
+            ClientMessage::RoomSetup(_, _) => "Set up Analysis Room",
+            ClientMessage::RoomGoTo(_, _) => "Go to Node",
+            ClientMessage::RoomEngine(_, _) => "Toggle Room Engine",
+            ClientMessage::RequestPuzzle(_) => "Request Puzzle",
+            ClientMessage::PuzzleMove(_) => "Puzzle Move",
+            ClientMessage::QueryPuzzleStats(_) => "Query Puzzle Stats",
+            ClientMessage::Reconnect(_) => "Reconnect",
         };
         write!(f, "{}", s)
     }
@@ -67,19 +219,67 @@ impl fmt::Display for ClientMessage {
 
 #[derive(Debug, Clone)]
 pub enum ServerMessage {
-    MoveAccepted(u8, String, Vec<(Tile, Option<WoodPiece>)>), // len(SAN), SAN, [updates tiles]
+    MoveAccepted(GameId, u8, String, Vec<(Tile, Option<WoodPiece>)>), // game, len(SAN), SAN, [updated tiles]
     GameCreated(GameId, ClientId),
     GameJoined(GameId, ClientId, UserRoleSelection),
     GameLeft(GameId, ClientId),
     IllegalMove(ChessError),
     GamesList(Vec<GameId>),
-    GameDetails(GameId, Option<ClientId>, Option<ClientId>, u32, u32),
+    GameDetails(
+        GameId,
+        Option<ClientId>, // white
+        Option<ClientId>, // black
+        u32,              // time
+        u32,              // increment
+        bool,             // rated
+        Option<Rating>,   // white, in the category of the game; `None` for guests
+        Option<Rating>,   // black
+    ),
     ClientDetails(ClientId, String),
     GameOver(GameId, GameOverReason),
-    LoginAccepted(ClientId),
+    LoginAccepted(ClientId), // the connection is set up; the ID of this session
     BoardState(GameId, String),
-    MoveHistory(GameId, Vec<String>),
+    MoveHistory(GameId, Vec<MoveRecord>),
     DrawOffered(GameId),
+    BookMoves(GameId, Vec<(String, u16)>),    // [(SAN, weight)]
+    Analysis(GameId, u8, Score, Vec<String>), // depth, score (side to move), principal variation in SAN
+    TablebaseResult(GameId, Option<(Wdl, i32, String)>), // WDL, DTZ, best move in SAN; `None` if not in the tablebase
+    LoggedIn(UserId, String), // logged in to an account: user ID, account name
+    LoginFailed(String),      // reason
+    RatingHistory(UserId, Vec<RatingRecord>), // oldest first
+    SeekPending,              // no match yet, the seek waits in the pool
+    SeekCancelled(String),    // reason
+    ChallengeIssued(ChallengeId, ClientId, ClientId, ChallengeParams), // to both: challenger, challenged
+    ChallengeClosed(ChallengeId, ChallengeOutcome),                    // to both
+    Chat(ChatChannel, ClientId, String, String), // sender, name of the sender, text
+    ChatRejected(String),                        // reason
+    TakebackOffered(GameId, u8), // plies; if accepted, `BoardState` and `MoveHistory` follow
+    TakebackDeclined(GameId),
+    GameAborted(GameId),              // ended without a result
+    RematchOffered(GameId, ClientId), // to both players of the ended game: who asked
+    TournamentCreated(TournamentId, ClientId, TournamentParams), // to all: organizer, settings
+    TournamentPlayers(TournamentId, Vec<ClientId>), // to all: who takes part
+    TournamentRound(TournamentId, u8, Option<ClientId>), // to the players: round, who has the bye
+    Standings(TournamentId, u8, bool, Vec<Standing>), // rounds played, finished?, best first
+    TournamentExport(TournamentId, String),
+    ArenaCreated(TournamentId, ClientId, ArenaParams), // to all: organizer, settings
+    Leaderboard(TournamentId, u32, Vec<ArenaStanding>), // to the players: seconds left, best first
+    SimulCreated(SimulId, ClientId, SimulParams),      // to all: host, settings
+    SimulPlayers(SimulId, Vec<ClientId>),              // to all: the opponents so far
+    SimulBoards(SimulId, Vec<SimulBoard>),             // to the host and the opponents
+    BughouseCreated(BughouseId, ClientId, BughouseParams), // to all: who opened it, clock
+    BughouseSeats(BughouseId, [Option<ClientId>; 4]),  // to all: in the order of the seats
+    BughouseState(BughouseId, Option<f32>, [BughouseBoard; 2]), // to the match: result of the first team
+    Teams(GameId, VotingMode, Vec<ClientId>, Vec<ClientId>), // to all: white, black, captains first
+    Proposals(GameId, Vec<(ClientId, String)>), // to the team to move: who proposes what, in SAN
+    RoomCreated(RoomId, ClientId),              // to all: who opened it
+    RoomMembers(RoomId, Vec<ClientId>),         // to all; nobody left closes the room
+    RoomState(RoomId, RoomTree),                // to the members, after every change
+    RoomAnalysis(RoomId, u32, u8, Score, Vec<String>), // to the members: node, depth, score, PV
+    PuzzleState(PuzzleState), // after the puzzle is set up and after every move of the solver
+    NoPuzzle(String),         // reason
+    PuzzleStats(UserId, Option<Rating>, Vec<ThemeStats>), // puzzle rating, best themes first
+    ReconnectToken(String),   // secret; a guest gets their seats back with it after a drop
 }
 
 impl ServerMessage {
@@ -95,23 +295,101 @@ impl ServerMessage {
     pub const BOARD_STATE: u8 = 0x8F;
     pub const MOVE_HISTORY: u8 = 0x90;
     pub const DRAW_OFFERED: u8 = 0x91;
+    pub const BOOK_MOVES: u8 = 0x92;
+    pub const ANALYSIS: u8 = 0x93;
+    pub const TABLEBASE_RESULT: u8 = 0x94;
+    pub const LOGGED_IN: u8 = 0x95;
+    pub const LOGIN_FAILED: u8 = 0x96;
+    pub const RATING_HISTORY: u8 = 0x97;
+    pub const SEEK_PENDING: u8 = 0x98;
+    pub const SEEK_CANCELLED: u8 = 0x99;
+    pub const CHALLENGE_ISSUED: u8 = 0x9A;
+    pub const CHALLENGE_CLOSED: u8 = 0x9B;
+    pub const CHAT: u8 = 0x9C;
+    pub const CHAT_REJECTED: u8 = 0x9D;
+    pub const TAKEBACK_OFFERED: u8 = 0x9E;
+    pub const TAKEBACK_DECLINED: u8 = 0x9F;
+    pub const GAME_ABORTED: u8 = 0xA0;
+    pub const REMATCH_OFFERED: u8 = 0xA1;
+    pub const TOURNAMENT_CREATED: u8 = 0xA2;
+    pub const TOURNAMENT_PLAYERS: u8 = 0xA3;
+    pub const TOURNAMENT_ROUND: u8 = 0xA4;
+    pub const STANDINGS: u8 = 0xA5;
+    pub const TOURNAMENT_EXPORT: u8 = 0xA6;
+    pub const ARENA_CREATED: u8 = 0xA7;
+    pub const LEADERBOARD: u8 = 0xA8;
+    pub const SIMUL_CREATED: u8 = 0xA9;
+    pub const SIMUL_PLAYERS: u8 = 0xAA;
+    pub const SIMUL_BOARDS: u8 = 0xAB;
+    pub const BUGHOUSE_CREATED: u8 = 0xAC;
+    pub const BUGHOUSE_SEATS: u8 = 0xAD;
+    pub const BUGHOUSE_STATE: u8 = 0xAE;
+    pub const TEAMS: u8 = 0xAF;
+    pub const PROPOSALS: u8 = 0xB0;
+    pub const ROOM_CREATED: u8 = 0xB1;
+    pub const ROOM_MEMBERS: u8 = 0xB2;
+    pub const ROOM_STATE: u8 = 0xB3;
+    pub const ROOM_ANALYSIS: u8 = 0xB4;
+    pub const PUZZLE_STATE: u8 = 0xB5;
+    pub const NO_PUZZLE: u8 = 0xB6;
+    pub const PUZZLE_STATS: u8 = 0xB7;
+    pub const RECONNECT_TOKEN: u8 = 0xB8;
     pub const LOGIN_ACCEPTED: u8 = 0xF0;
 
     pub fn opcode(&self) -> u8 {
         match self {
             ServerMessage::GameCreated(_, _) => Self::GAME_CREATED,
             ServerMessage::GameJoined(_, _, _) => Self::GAME_JOINED,
-            ServerMessage::MoveAccepted(_, _, _) => Self::MOVE_ACCEPTED,
+            ServerMessage::MoveAccepted(..) => Self::MOVE_ACCEPTED,
             ServerMessage::IllegalMove(_) => Self::ILLEGAL_MOVE,
             ServerMessage::GamesList(_) => Self::GAMES_LIST,
             ServerMessage::GameOver(_, _) => Self::GAME_OVER,
-            ServerMessage::GameDetails(_, _, _, _, _) => Self::GAME_DETAILS,
+            ServerMessage::GameDetails(..) => Self::GAME_DETAILS,
             ServerMessage::ClientDetails(_, _) => Self::CLIENT_DETAILS,
             ServerMessage::LoginAccepted(_) => Self::LOGIN_ACCEPTED,
             ServerMessage::GameLeft(_, _) => Self::GAME_LEFT,
             ServerMessage::BoardState(_, _) => Self::BOARD_STATE,
             ServerMessage::MoveHistory(_, _) => Self::MOVE_HISTORY,
             ServerMessage::DrawOffered(_) => Self::DRAW_OFFERED,
+            ServerMessage::BookMoves(_, _) => Self::BOOK_MOVES,
+            ServerMessage::Analysis(_, _, _, _) => Self::ANALYSIS,
+            ServerMessage::TablebaseResult(_, _) => Self::TABLEBASE_RESULT,
+            ServerMessage::LoggedIn(_, _) => Self::LOGGED_IN,
+            ServerMessage::LoginFailed(_) => Self::LOGIN_FAILED,
+            ServerMessage::RatingHistory(_, _) => Self::RATING_HISTORY,
+            ServerMessage::SeekPending => Self::SEEK_PENDING,
+            ServerMessage::SeekCancelled(_) => Self::SEEK_CANCELLED,
+            ServerMessage::ChallengeIssued(..) => Self::CHALLENGE_ISSUED,
+            ServerMessage::ChallengeClosed(_, _) => Self::CHALLENGE_CLOSED,
+            ServerMessage::Chat(..) => Self::CHAT,
+            ServerMessage::ChatRejected(_) => Self::CHAT_REJECTED,
+            ServerMessage::TakebackOffered(_, _) => Self::TAKEBACK_OFFERED,
+            ServerMessage::TakebackDeclined(_) => Self::TAKEBACK_DECLINED,
+            ServerMessage::GameAborted(_) => Self::GAME_ABORTED,
+            ServerMessage::RematchOffered(_, _) => Self::REMATCH_OFFERED,
+            ServerMessage::TournamentCreated(..) => Self::TOURNAMENT_CREATED,
+            ServerMessage::TournamentPlayers(_, _) => Self::TOURNAMENT_PLAYERS,
+            ServerMessage::TournamentRound(..) => Self::TOURNAMENT_ROUND,
+            ServerMessage::Standings(..) => Self::STANDINGS,
+            ServerMessage::TournamentExport(_, _) => Self::TOURNAMENT_EXPORT,
+            ServerMessage::ArenaCreated(..) => Self::ARENA_CREATED,
+            ServerMessage::Leaderboard(..) => Self::LEADERBOARD,
+            ServerMessage::SimulCreated(..) => Self::SIMUL_CREATED,
+            ServerMessage::SimulPlayers(_, _) => Self::SIMUL_PLAYERS,
+            ServerMessage::SimulBoards(_, _) => Self::SIMUL_BOARDS,
+            ServerMessage::BughouseCreated(..) => Self::BUGHOUSE_CREATED,
+            ServerMessage::BughouseSeats(_, _) => Self::BUGHOUSE_SEATS,
+            ServerMessage::BughouseState(..) => Self::BUGHOUSE_STATE,
+            ServerMessage::Teams(..) => Self::TEAMS,
+            ServerMessage::Proposals(_, _) => Self::PROPOSALS,
+            ServerMessage::RoomCreated(_, _) => Self::ROOM_CREATED,
+            ServerMessage::RoomMembers(_, _) => Self::ROOM_MEMBERS,
+            ServerMessage::RoomState(_, _) => Self::ROOM_STATE,
+            ServerMessage::RoomAnalysis(..) => Self::ROOM_ANALYSIS,
+            ServerMessage::PuzzleState(_) => Self::PUZZLE_STATE,
+            ServerMessage::NoPuzzle(_) => Self::NO_PUZZLE,
+            ServerMessage::PuzzleStats(..) => Self::PUZZLE_STATS,
+            ServerMessage::ReconnectToken(_) => Self::RECONNECT_TOKEN,
         }
     }
 }
@@ -1,4 +1,4 @@
-use crate::{NetError, NetResult};
+use crate::{ChessColor, ChessMove, ClientId, GameId, NetError, NetResult, Variant, WoodPiece};
 use std::fmt::Display;
 
 pub mod messages;
@@ -7,13 +7,14 @@ pub mod parser;
 #[derive(Debug, Clone)]
 pub struct NewGameParams {
     pub mode: u8,
+    pub rated: bool, // casual games don't change ratings
     pub time: u32,
     pub time_inc: u32,
 }
 
 impl NewGameParams {
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = vec![self.mode, 0];
+        let mut bytes = vec![self.mode, self.rated as u8];
         bytes.extend_from_slice(&self.time.to_le_bytes());
         bytes.extend_from_slice(&self.time_inc.to_le_bytes());
         bytes
@@ -34,6 +35,1039 @@ impl JoinGameParams {
         bytes
     }
 }
+
+/// A seek: the kind of game a player wants, without a particular opponent.
+/// Seeks wait in the pool of the server until a compatible seek comes in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeekParams {
+    pub time: u32,
+    pub time_inc: u32,
+    pub variant: Variant,
+    pub rated: bool,
+    pub rating_min: u16, // of the opponent; 0 to u16::MAX takes anyone, guests as well
+    pub rating_max: u16,
+    pub color: UserRoleSelection, // White, Black or Random
+}
+
+impl SeekParams {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+        bytes.extend_from_slice(&self.time_inc.to_le_bytes());
+        bytes.push(self.variant as u8);
+        bytes.push(self.rated as u8);
+        bytes.extend_from_slice(&self.rating_min.to_le_bytes());
+        bytes.extend_from_slice(&self.rating_max.to_le_bytes());
+        bytes.push(self.color as u8);
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let time = reader.read_u32_le()?;
+        let time_inc = reader.read_u32_le()?;
+        let variant = Variant::from_u8(reader.read_u8()?)
+            .ok_or_else(|| NetError::Protocol("Invalid variant".to_string()))?;
+        let rated = reader.read_u8()? != 0;
+        let rating_min = reader.read_u16_le()?;
+        let rating_max = reader.read_u16_le()?;
+        let color = UserRoleSelection::from_u8(reader.read_u8()?);
+        Ok(SeekParams {
+            time,
+            time_inc,
+            variant,
+            rated,
+            rating_min,
+            rating_max,
+            color,
+        })
+    }
+
+    /// Without a rating range, anyone can take the seek.
+    pub fn is_open(&self) -> bool {
+        self.rating_min == 0 && self.rating_max == u16::MAX
+    }
+}
+
+/// The game a player proposes to a particular opponent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChallengeParams {
+    pub time: u32,
+    pub time_inc: u32,
+    pub variant: Variant,
+    pub rated: bool,
+    pub color: UserRoleSelection, // of the challenger: White, Black or Random
+}
+
+impl ChallengeParams {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+        bytes.extend_from_slice(&self.time_inc.to_le_bytes());
+        bytes.push(self.variant as u8);
+        bytes.push(self.rated as u8);
+        bytes.push(self.color as u8);
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let time = reader.read_u32_le()?;
+        let time_inc = reader.read_u32_le()?;
+        let variant = Variant::from_u8(reader.read_u8()?)
+            .ok_or_else(|| NetError::Protocol("Invalid variant".to_string()))?;
+        let rated = reader.read_u8()? != 0;
+        let color = UserRoleSelection::from_u8(reader.read_u8()?);
+        Ok(ChallengeParams {
+            time,
+            time_inc,
+            variant,
+            rated,
+            color,
+        })
+    }
+}
+
+/// Why a player turned a challenge down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclineReason {
+    Generic = 0,
+    Later = 1,
+    TimeControl = 2,
+    Rated = 3,  // would rather play casual
+    Casual = 4, // would rather play rated
+    Variant = 5,
+}
+
+impl DeclineReason {
+    pub fn from_u8(v: u8) -> Self {
+        match v {
+            1 => DeclineReason::Later,
+            2 => DeclineReason::TimeControl,
+            3 => DeclineReason::Rated,
+            4 => DeclineReason::Casual,
+            5 => DeclineReason::Variant,
+            _ => DeclineReason::Generic,
+        }
+    }
+}
+
+impl Display for DeclineReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            DeclineReason::Generic => "Not accepting challenges right now",
+            DeclineReason::Later => "Not now, maybe later",
+            DeclineReason::TimeControl => "Please use another time control",
+            DeclineReason::Rated => "Please send a casual challenge",
+            DeclineReason::Casual => "Please send a rated challenge",
+            DeclineReason::Variant => "Please use another variant",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How a challenge ended. An accepted challenge is followed by the new game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeOutcome {
+    Accepted,
+    Declined(DeclineReason),
+    Cancelled, // by the challenger, or one of the players left
+    Expired,
+}
+
+impl ChallengeOutcome {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            ChallengeOutcome::Accepted => vec![0],
+            ChallengeOutcome::Declined(reason) => vec![1, *reason as u8],
+            ChallengeOutcome::Cancelled => vec![2],
+            ChallengeOutcome::Expired => vec![3],
+        }
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        match reader.read_u8()? {
+            0 => Ok(ChallengeOutcome::Accepted),
+            1 => Ok(ChallengeOutcome::Declined(DeclineReason::from_u8(
+                reader.read_u8()?,
+            ))),
+            2 => Ok(ChallengeOutcome::Cancelled),
+            3 => Ok(ChallengeOutcome::Expired),
+            v => Err(NetError::Protocol(format!(
+                "Invalid challenge outcome {}",
+                v
+            ))),
+        }
+    }
+}
+
+impl Display for ChallengeOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChallengeOutcome::Accepted => write!(f, "Accepted"),
+            ChallengeOutcome::Declined(reason) => write!(f, "Declined: {}", reason),
+            ChallengeOutcome::Cancelled => write!(f, "Cancelled"),
+            ChallengeOutcome::Expired => write!(f, "Expired"),
+        }
+    }
+}
+
+/// Where a chat message goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChatChannel {
+    Lobby,                    // everyone on the server
+    Game(GameId),             // the two players of a game
+    Kibitz(GameId),           // the spectators of a game, hidden from the players
+    Team(GameId, ChessColor), // a team of a consultation game, hidden from everyone else
+}
+
+impl ChatChannel {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (tag, gid) = match self {
+            ChatChannel::Lobby => (0u8, 0),
+            ChatChannel::Game(gid) => (1, *gid),
+            ChatChannel::Kibitz(gid) => (2, *gid),
+            ChatChannel::Team(gid, _) => (3, *gid),
+        };
+        let mut bytes = vec![tag];
+        bytes.extend_from_slice(&gid.to_le_bytes());
+        if let ChatChannel::Team(_, color) = self {
+            bytes.push(*color as u8);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let tag = reader.read_u8()?;
+        let gid = reader.read_u32_le()?;
+        match tag {
+            0 => Ok(ChatChannel::Lobby),
+            1 => Ok(ChatChannel::Game(gid)),
+            2 => Ok(ChatChannel::Kibitz(gid)),
+            3 => match reader.read_u8()? {
+                0 => Ok(ChatChannel::Team(gid, ChessColor::Black)),
+                _ => Ok(ChatChannel::Team(gid, ChessColor::White)),
+            },
+            _ => Err(NetError::Protocol(format!("Invalid chat channel {}", tag))),
+        }
+    }
+}
+
+impl Display for ChatChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatChannel::Lobby => write!(f, "lobby"),
+            ChatChannel::Game(gid) => write!(f, "game {}", gid),
+            ChatChannel::Kibitz(gid) => write!(f, "kibitz {}", gid),
+            ChatChannel::Team(gid, color) => write!(f, "{} team {}", color, gid),
+        }
+    }
+}
+
+/// How the games of a tournament are paired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TournamentSystem {
+    RoundRobin = 0, // everyone plays everyone
+    Swiss = 1,      // Dutch system: players with the same score meet
+}
+
+impl TournamentSystem {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(TournamentSystem::RoundRobin),
+            1 => Some(TournamentSystem::Swiss),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TournamentSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TournamentSystem::RoundRobin => write!(f, "Round robin"),
+            TournamentSystem::Swiss => write!(f, "Swiss"),
+        }
+    }
+}
+
+/// The settings of a tournament. All its games use the same time control and variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TournamentParams {
+    pub name: String,
+    pub system: TournamentSystem,
+    pub rounds: u8, // Swiss only; a round robin takes as many rounds as it needs
+    pub time: u32,
+    pub time_inc: u32,
+    pub variant: Variant,
+    pub rated: bool,
+}
+
+impl TournamentParams {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.system as u8, self.rounds];
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+        bytes.extend_from_slice(&self.time_inc.to_le_bytes());
+        bytes.push(self.variant as u8);
+        bytes.push(self.rated as u8);
+        bytes.push(self.name.len() as u8);
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let system = TournamentSystem::from_u8(reader.read_u8()?)
+            .ok_or_else(|| NetError::Protocol("Invalid tournament system".to_string()))?;
+        let rounds = reader.read_u8()?;
+        let time = reader.read_u32_le()?;
+        let time_inc = reader.read_u32_le()?;
+        let variant = Variant::from_u8(reader.read_u8()?)
+            .ok_or_else(|| NetError::Protocol("Invalid variant".to_string()))?;
+        let rated = reader.read_u8()? != 0;
+        let name_len = reader.read_u8()?;
+        let name = reader.read_str(name_len as usize)?.to_string();
+        Ok(TournamentParams {
+            name,
+            system,
+            rounds,
+            time,
+            time_inc,
+            variant,
+            rated,
+        })
+    }
+}
+
+/// A line of the standings of a tournament, best first.
+/// Scores are in points: 1 for a win or a bye, ½ for a draw.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub player: ClientId,
+    pub name: String,
+    pub score: f32,
+    pub buchholz: f32,         // the scores of the opponents
+    pub sonneborn_berger: f32, // the scores of the beaten opponents, and half of the drawn ones
+}
+
+impl Standing {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&(self.player as u32).to_le_bytes());
+        for value in [self.score, self.buchholz, self.sonneborn_berger] {
+            bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+        }
+        bytes.push(self.name.len() as u8);
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let player = reader.read_u32_le()? as ClientId;
+        let score = f32::from_bits(reader.read_u32_le()?);
+        let buchholz = f32::from_bits(reader.read_u32_le()?);
+        let sonneborn_berger = f32::from_bits(reader.read_u32_le()?);
+        let name_len = reader.read_u8()?;
+        let name = reader.read_str(name_len as usize)?.to_string();
+        Ok(Standing {
+            player,
+            name,
+            score,
+            buchholz,
+            sonneborn_berger,
+        })
+    }
+}
+
+/// The settings of an arena: players keep getting new opponents until the time is up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArenaParams {
+    pub name: String,
+    pub minutes: u16, // how long the arena runs
+    pub time: u32,
+    pub time_inc: u32,
+    pub variant: Variant,
+    pub rated: bool,
+}
+
+impl ArenaParams {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.minutes.to_le_bytes());
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+        bytes.extend_from_slice(&self.time_inc.to_le_bytes());
+        bytes.push(self.variant as u8);
+        bytes.push(self.rated as u8);
+        bytes.push(self.name.len() as u8);
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let minutes = reader.read_u16_le()?;
+        let time = reader.read_u32_le()?;
+        let time_inc = reader.read_u32_le()?;
+        let variant = Variant::from_u8(reader.read_u8()?)
+            .ok_or_else(|| NetError::Protocol("Invalid variant".to_string()))?;
+        let rated = reader.read_u8()? != 0;
+        let name_len = reader.read_u8()?;
+        let name = reader.read_str(name_len as usize)?.to_string();
+        Ok(ArenaParams {
+            name,
+            minutes,
+            time,
+            time_inc,
+            variant,
+            rated,
+        })
+    }
+}
+
+/// A line of the leaderboard of an arena, best first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArenaStanding {
+    pub player: ClientId,
+    pub name: String,
+    pub score: u16,
+    pub games: u16,
+    pub streak: u8,    // wins in a row; from two on, points count double
+    pub playing: bool, // false while paused or withdrawn
+}
+
+impl ArenaStanding {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&(self.player as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.score.to_le_bytes());
+        bytes.extend_from_slice(&self.games.to_le_bytes());
+        bytes.push(self.streak);
+        bytes.push(self.playing as u8);
+        bytes.push(self.name.len() as u8);
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let player = reader.read_u32_le()? as ClientId;
+        let score = reader.read_u16_le()?;
+        let games = reader.read_u16_le()?;
+        let streak = reader.read_u8()?;
+        let playing = reader.read_u8()? != 0;
+        let name_len = reader.read_u8()?;
+        let name = reader.read_str(name_len as usize)?.to_string();
+        Ok(ArenaStanding {
+            player,
+            name,
+            score,
+            games,
+            streak,
+            playing,
+        })
+    }
+}
+
+/// The settings of a simultaneous exhibition: the host plays every opponent at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulParams {
+    pub name: String,
+    pub host_white: bool, // the colour of the host on all boards
+    pub boards: u8,       // at most that many opponents; 0 for no limit
+    pub time: u32,
+    pub time_inc: u32,
+    pub variant: Variant,
+}
+
+impl SimulParams {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.host_white as u8, self.boards];
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+        bytes.extend_from_slice(&self.time_inc.to_le_bytes());
+        bytes.push(self.variant as u8);
+        bytes.push(self.name.len() as u8);
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let host_white = reader.read_u8()? != 0;
+        let boards = reader.read_u8()?;
+        let time = reader.read_u32_le()?;
+        let time_inc = reader.read_u32_le()?;
+        let variant = Variant::from_u8(reader.read_u8()?)
+            .ok_or_else(|| NetError::Protocol("Invalid variant".to_string()))?;
+        let name_len = reader.read_u8()?;
+        let name = reader.read_str(name_len as usize)?.to_string();
+        Ok(SimulParams {
+            name,
+            host_white,
+            boards,
+            time,
+            time_inc,
+            variant,
+        })
+    }
+}
+
+/// A board of a simul, as the host sees it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulBoard {
+    pub game: GameId,
+    pub opponent: ClientId,
+    pub host_to_move: bool,
+    pub result: Option<f32>, // points of the host, once the game is over
+}
+
+impl SimulBoard {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.game.to_le_bytes());
+        bytes.extend_from_slice(&(self.opponent as u32).to_le_bytes());
+        bytes.push(self.host_to_move as u8);
+        // 0 while running, else 1 + the host's half points
+        bytes.push(self.result.map_or(0, |points| 1 + (points * 2.0) as u8));
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let game = reader.read_u32_le()?;
+        let opponent = reader.read_u32_le()? as ClientId;
+        let host_to_move = reader.read_u8()? != 0;
+        let result = match reader.read_u8()? {
+            0 => None,
+            half_points => Some((half_points - 1) as f32 / 2.0),
+        };
+        Ok(SimulBoard {
+            game,
+            opponent,
+            host_to_move,
+            result,
+        })
+    }
+}
+
+/// The clock of a bughouse match; both boards start with it at the same time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BughouseParams {
+    pub time: u32,
+    pub time_inc: u32,
+}
+
+impl BughouseParams {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.time.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.time_inc.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let time = reader.read_u32_le()?;
+        let time_inc = reader.read_u32_le()?;
+        Ok(BughouseParams { time, time_inc })
+    }
+}
+
+/// A board of a bughouse match, as everybody in the match sees it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BughouseBoard {
+    pub game: GameId,
+    pub white: ClientId,
+    pub black: ClientId,
+    pub fen: String,
+    pub pocket: Vec<WoodPiece>, // pieces to drop, of both sides
+    pub clocks: [u32; 2],       // milliseconds left for white and black
+}
+
+impl BughouseBoard {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.game.to_le_bytes());
+        bytes.extend_from_slice(&(self.white as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.black as u32).to_le_bytes());
+        for millis in self.clocks {
+            bytes.extend_from_slice(&millis.to_le_bytes());
+        }
+        bytes.push(self.pocket.len() as u8);
+        bytes.extend(self.pocket.iter().map(|p| p.as_byte() as u8));
+        bytes.push(self.fen.len() as u8);
+        bytes.extend_from_slice(self.fen.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let game = reader.read_u32_le()?;
+        let white = reader.read_u32_le()? as ClientId;
+        let black = reader.read_u32_le()? as ClientId;
+        let clocks = [reader.read_u32_le()?, reader.read_u32_le()?];
+        let pocket_len = reader.read_u8()?;
+        let pocket = reader
+            .read_str(pocket_len as usize)?
+            .chars()
+            .map(|c| {
+                WoodPiece::from_char(c)
+                    .ok_or_else(|| NetError::Protocol(format!("Invalid piece: {}", c)))
+            })
+            .collect::<NetResult<Vec<_>>>()?;
+        let fen_len = reader.read_u8()?;
+        let fen = reader.read_str(fen_len as usize)?.to_string();
+        Ok(BughouseBoard {
+            game,
+            white,
+            black,
+            fen,
+            pocket,
+            clocks,
+        })
+    }
+}
+
+/// A move of a game as it was played: what was moved, the position it led to, and when.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MoveRecord {
+    pub mov: Option<ChessMove>, // `None` for a drop (bughouse), which only has its SAN
+    pub san: String,
+    pub fen: String,        // the position after the move
+    pub timestamp: u64,     // milliseconds since the Unix epoch
+    pub clock: Option<u32>, // milliseconds left for the side that moved; `None` without a clock
+    pub think_time: u32,    // milliseconds the side took for the move
+}
+
+impl MoveRecord {
+    /// The side that made the move: in the position after it, the other side is to move.
+    pub fn color(&self) -> ChessColor {
+        match self.fen.split(' ').nth(1) {
+            Some("b") => ChessColor::White,
+            _ => ChessColor::Black,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mov = self.mov.map(|m| m.to_string()).unwrap_or_default();
+        for s in [&mov, &self.san, &self.fen] {
+            bytes.push(s.len() as u8);
+            bytes.extend_from_slice(s.as_bytes());
+        }
+        bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+        bytes.push(self.clock.is_some() as u8);
+        bytes.extend_from_slice(&self.clock.unwrap_or(0).to_le_bytes());
+        bytes.extend_from_slice(&self.think_time.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let mut strings = vec![];
+        for _ in 0..3 {
+            let len = reader.read_u8()?;
+            strings.push(reader.read_str(len as usize)?.to_string());
+        }
+        let fen = strings.pop().unwrap();
+        let san = strings.pop().unwrap();
+        let mov = match strings.pop().unwrap() {
+            m if m.is_empty() => None,
+            m => Some(m.parse().map_err(NetError::Protocol)?),
+        };
+        let timestamp = reader.read_u64_le()?;
+        let timed = reader.read_u8()? != 0;
+        let clock = Some(reader.read_u32_le()?).filter(|_| timed);
+        let think_time = reader.read_u32_le()?;
+        Ok(MoveRecord {
+            mov,
+            san,
+            fen,
+            timestamp,
+            clock,
+            think_time,
+        })
+    }
+}
+
+/// How the team of a consultation game agrees on a move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VotingMode {
+    FirstProposal = 0, // the first move proposed is played
+    Majority = 1,      // a move proposed by more than half of the team is played
+    Captain = 2,       // the captain decides, the others advise
+}
+
+impl VotingMode {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(VotingMode::FirstProposal),
+            1 => Some(VotingMode::Majority),
+            2 => Some(VotingMode::Captain),
+            _ => None,
+        }
+    }
+}
+
+impl Display for VotingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VotingMode::FirstProposal => write!(f, "First proposal"),
+            VotingMode::Majority => write!(f, "Majority"),
+            VotingMode::Captain => write!(f, "Captain"),
+        }
+    }
+}
+
+/// The settings of a consultation game, where a team of clients plays each side.
+/// A team that can't agree in `vote_time` seconds plays its most proposed move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsultationParams {
+    pub time: u32,
+    pub time_inc: u32,
+    pub mode: VotingMode,
+    pub vote_time: u32, // 0 to wait for the team as long as it takes
+}
+
+impl ConsultationParams {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.time.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.time_inc.to_le_bytes());
+        bytes.push(self.mode as u8);
+        bytes.extend_from_slice(&self.vote_time.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let time = reader.read_u32_le()?;
+        let time_inc = reader.read_u32_le()?;
+        let mode = VotingMode::from_u8(reader.read_u8()?)
+            .ok_or_else(|| NetError::Protocol("Invalid voting mode".to_string()))?;
+        let vote_time = reader.read_u32_le()?;
+        Ok(ConsultationParams {
+            time,
+            time_inc,
+            mode,
+            vote_time,
+        })
+    }
+}
+
+/// What a new analysis room starts from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomSource {
+    Empty,        // the initial position
+    Fen(String),  // any position, also one that can't come up in a game
+    Game(GameId), // the moves of a game that ended
+}
+
+impl RoomSource {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RoomSource::Empty => vec![0],
+            RoomSource::Fen(fen) => {
+                let mut bytes = vec![1];
+                bytes.extend_from_slice(fen.as_bytes());
+                bytes
+            }
+            RoomSource::Game(gid) => {
+                let mut bytes = vec![2];
+                bytes.extend_from_slice(&gid.to_le_bytes());
+                bytes
+            }
+        }
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        match reader.read_u8()? {
+            0 => Ok(RoomSource::Empty),
+            1 => {
+                let fen = String::from_utf8(reader.remaining().to_vec())
+                    .map_err(|_| NetError::Protocol("Failed to parse FEN".to_string()))?;
+                Ok(RoomSource::Fen(fen))
+            }
+            2 => Ok(RoomSource::Game(reader.read_u32_le()?)),
+            _ => Err(NetError::Protocol("Invalid room source".to_string())),
+        }
+    }
+}
+
+/// The shared board of an analysis room: a tree of variations below a start position, and
+/// the node everybody looks at. Node 0 is the start position; every other node is a move,
+/// played in the node before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomTree {
+    pub start_fen: String,
+    pub nodes: Vec<(u32, String)>, // the node before, SAN; `(0, "")` for the start position
+    pub current: u32,
+    pub fen: String,  // of the current node
+    pub engine: bool, // whether the engine analyses every position looked at
+}
+
+impl RoomTree {
+    /// The moves from the start position to the current node, in SAN.
+    pub fn line(&self) -> Vec<String> {
+        let mut line = vec![];
+        let mut node = self.current;
+        while node != 0 {
+            let Some((parent, san)) = self.nodes.get(node as usize) else {
+                break;
+            };
+            line.push(san.clone());
+            node = *parent;
+        }
+        line.reverse();
+        line
+    }
+
+    /// The moves played in a node, the main line first.
+    pub fn children(&self, node: u32) -> Vec<u32> {
+        (1..self.nodes.len() as u32)
+            .filter(|&n| self.nodes[n as usize].0 == node)
+            .collect()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for fen in [&self.start_fen, &self.fen] {
+            bytes.push(fen.len() as u8);
+            bytes.extend_from_slice(fen.as_bytes());
+        }
+        bytes.extend_from_slice(&self.current.to_le_bytes());
+        bytes.push(self.engine as u8);
+        // the start position goes without saying
+        for (parent, san) in self.nodes.iter().skip(1) {
+            bytes.extend_from_slice(&parent.to_le_bytes());
+            bytes.push(san.len() as u8);
+            bytes.extend_from_slice(san.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let start_len = reader.read_u8()?;
+        let start_fen = reader.read_str(start_len as usize)?.to_string();
+        let fen_len = reader.read_u8()?;
+        let fen = reader.read_str(fen_len as usize)?.to_string();
+        let current = reader.read_u32_le()?;
+        let engine = reader.read_u8()? != 0;
+        let mut nodes = vec![(0, String::new())];
+        while !reader.remaining().is_empty() {
+            let parent = reader.read_u32_le()?;
+            let san_len = reader.read_u8()?;
+            nodes.push((parent, reader.read_str(san_len as usize)?.to_string()));
+        }
+        Ok(RoomTree {
+            start_fen,
+            nodes,
+            current,
+            fen,
+            engine,
+        })
+    }
+}
+
+/// What kind of puzzle a client asks for. A rating bound of 0 and an empty theme
+/// leave that out of the choice.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PuzzleParams {
+    pub min_rating: u16,
+    pub max_rating: u16,
+    pub theme: String, // as in the Lichess database: "fork", "mateIn2", ...
+}
+
+impl PuzzleParams {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.min_rating.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.max_rating.to_le_bytes());
+        bytes.extend_from_slice(self.theme.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let min_rating = reader.read_u16_le()?;
+        let max_rating = reader.read_u16_le()?;
+        let theme = String::from_utf8(reader.remaining().to_vec())
+            .map_err(|_| NetError::Protocol("Failed to parse theme".to_string()))?;
+        Ok(PuzzleParams {
+            min_rating,
+            max_rating,
+            theme,
+        })
+    }
+}
+
+/// Where an attempt at a puzzle stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PuzzleStatus {
+    Running = 0, // the solver is to move
+    Solved = 1,
+    Failed = 2, // a move off the solution; the solution is revealed
+}
+
+impl PuzzleStatus {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(PuzzleStatus::Running),
+            1 => Some(PuzzleStatus::Solved),
+            2 => Some(PuzzleStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+impl Display for PuzzleStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PuzzleStatus::Running => write!(f, "Your move"),
+            PuzzleStatus::Solved => write!(f, "Solved"),
+            PuzzleStatus::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+/// A puzzle as its solver sees it: the position after the last move, the moves played
+/// since the puzzle was set up, the opponent's first one included, and once it is over,
+/// the moves the solution went on with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PuzzleState {
+    pub id: String,
+    pub rating: u16,
+    pub themes: Vec<String>,
+    pub color: ChessColor, // of the solver
+    pub fen: String,
+    pub played: Vec<String>, // SAN
+    pub status: PuzzleStatus,
+    pub solution: Vec<String>, // SAN; empty while running
+}
+
+impl PuzzleState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for s in [&self.id, &self.fen] {
+            bytes.push(s.len() as u8);
+            bytes.extend_from_slice(s.as_bytes());
+        }
+        bytes.extend_from_slice(&self.rating.to_le_bytes());
+        bytes.push(match self.color {
+            ChessColor::Black => 0,
+            ChessColor::White => 1,
+        });
+        bytes.push(self.status as u8);
+        for list in [&self.themes, &self.played, &self.solution] {
+            bytes.push(list.len() as u8);
+            for s in list {
+                bytes.push(s.len() as u8);
+                bytes.extend_from_slice(s.as_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let id_len = reader.read_u8()?;
+        let id = reader.read_str(id_len as usize)?.to_string();
+        let fen_len = reader.read_u8()?;
+        let fen = reader.read_str(fen_len as usize)?.to_string();
+        let rating = reader.read_u16_le()?;
+        let color = match reader.read_u8()? {
+            0 => ChessColor::Black,
+            _ => ChessColor::White,
+        };
+        let status = PuzzleStatus::from_u8(reader.read_u8()?)
+            .ok_or_else(|| NetError::Protocol("Invalid puzzle status".to_string()))?;
+        let mut lists = [vec![], vec![], vec![]];
+        for list in lists.iter_mut() {
+            let count = reader.read_u8()?;
+            for _ in 0..count {
+                let len = reader.read_u8()?;
+                list.push(reader.read_str(len as usize)?.to_string());
+            }
+        }
+        let [themes, played, solution] = lists;
+        Ok(PuzzleState {
+            id,
+            rating,
+            themes,
+            color,
+            fen,
+            played,
+            status,
+            solution,
+        })
+    }
+}
+
+/// How a solver does on the puzzles of a theme. The performance is the rating the results
+/// are worth: the average rating of the puzzles, 400 up for every one solved more than
+/// failed, divided by the attempts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThemeStats {
+    pub theme: String,
+    pub attempts: u32,
+    pub solved: u32,
+    pub performance: u16,
+}
+
+impl ThemeStats {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.theme.len() as u8];
+        bytes.extend_from_slice(self.theme.as_bytes());
+        bytes.extend_from_slice(&self.attempts.to_le_bytes());
+        bytes.extend_from_slice(&self.solved.to_le_bytes());
+        bytes.extend_from_slice(&self.performance.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let theme_len = reader.read_u8()?;
+        let theme = reader.read_str(theme_len as usize)?.to_string();
+        Ok(ThemeStats {
+            theme,
+            attempts: reader.read_u32_le()?,
+            solved: reader.read_u32_le()?,
+            performance: reader.read_u16_le()?,
+        })
+    }
+}
+
+/// How a tournament is exported: a crosstable with the standings, or its games.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Text = 0,
+    Pgn = 1,
+}
+
+impl ExportFormat {
+    pub fn from_u8(v: u8) -> Self {
+        match v {
+            1 => ExportFormat::Pgn,
+            _ => ExportFormat::Text,
+        }
+    }
+}
+
+/// Name and password for creating an account or logging in.
+#[derive(Clone)]
+pub struct Credentials {
+    pub name: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.name.len() as u8];
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes.extend_from_slice(self.password.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> NetResult<Self> {
+        let name_len = reader.read_u8()?;
+        let name = reader.read_str(name_len as usize)?.to_string();
+        let password = String::from_utf8(reader.remaining().to_vec())
+            .map_err(|_| NetError::Protocol("Failed to parse password".to_string()))?;
+        Ok(Credentials { name, password })
+    }
+}
+
+// messages are logged, passwords must not be
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("name", &self.name)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UserRoleSelection {
     Black = 0,
@@ -101,6 +1135,28 @@ impl<'a> Reader<'a> {
         }
     }
 
+    pub fn read_u16_le(&mut self) -> NetResult<u16> {
+        if self.offset + 2 <= self.bytes.len() {
+            let val =
+                u16::from_le_bytes(self.bytes[self.offset..self.offset + 2].try_into().unwrap());
+            self.offset += 2;
+            Ok(val)
+        } else {
+            Err(NetError::Protocol("Unexpected end of data".to_string()))
+        }
+    }
+
+    pub fn read_u64_le(&mut self) -> NetResult<u64> {
+        if self.offset + 8 <= self.bytes.len() {
+            let val =
+                u64::from_le_bytes(self.bytes[self.offset..self.offset + 8].try_into().unwrap());
+            self.offset += 8;
+            Ok(val)
+        } else {
+            Err(NetError::Protocol("Unexpected end of data".to_string()))
+        }
+    }
+
     pub fn read_str(&mut self, len: usize) -> NetResult<&'a str> {
         if self.offset + len <= self.bytes.len() {
             let s = std::str::from_utf8(&self.bytes[self.offset..self.offset + len])
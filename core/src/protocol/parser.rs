@@ -1,9 +1,15 @@
 use crate::chess::ChessColor;
 use crate::protocol::messages::{ClientMessage, ServerMessage};
-use crate::protocol::{JoinGameParams, NewGameParams, Reader, UserRoleSelection};
+use crate::protocol::{
+    ArenaParams, ArenaStanding, BughouseBoard, BughouseParams, ChallengeOutcome, ChallengeParams,
+    ChatChannel, ConsultationParams, Credentials, DeclineReason, ExportFormat, JoinGameParams,
+    MoveRecord, NewGameParams, PuzzleParams, PuzzleState, Reader, RoomSource, RoomTree, SeekParams,
+    SimulBoard, SimulParams, Standing, ThemeStats, TournamentParams, UserRoleSelection, VotingMode,
+};
 use crate::states::GameOverReason;
+use crate::WoodPiece as Piece;
+use crate::{ChessDrop, ChessMove, Rating, RatingRecord, Score, Tile, TimeCategory, Variant, Wdl};
 use crate::{ChessError, NetError, NetResult};
-use crate::{ChessMove, Tile, WoodPiece as Piece};
 
 pub trait NetMessage: Sized {
     fn from_bytes(bytes: &[u8]) -> NetResult<Self>;
@@ -22,11 +28,13 @@ impl NetMessage for ClientMessage {
         match opcode {
             Self::NEW_GAME => {
                 let mode = reader.read_u8()?;
+                let rated = reader.read_u8()? != 0;
                 let time = reader.read_u32_le()?;
                 let time_inc = reader.read_u32_le()?;
 
                 let game_params = NewGameParams {
                     mode,
+                    rated,
                     time,
                     time_inc,
                 };
@@ -85,6 +93,185 @@ impl NetMessage for ClientMessage {
                 let gid = reader.read_u32_le()?;
                 Ok(ClientMessage::OfferDraw(gid))
             }
+            Self::QUERY_BOOK_MOVES => {
+                let gid = reader.read_u32_le()?;
+                Ok(ClientMessage::QueryBookMoves(gid))
+            }
+            Self::ADD_COMPUTER => {
+                let gid = reader.read_u32_le()?;
+                let side = UserRoleSelection::from_u8(reader.read_u8()?);
+                let level = reader.read_u8()?;
+                Ok(ClientMessage::AddComputer(gid, side, level))
+            }
+            Self::ANALYSE => {
+                let gid = reader.read_u32_le()?;
+                Ok(ClientMessage::Analyse(gid))
+            }
+            Self::QUERY_TABLEBASE => {
+                let gid = reader.read_u32_le()?;
+                Ok(ClientMessage::QueryTablebase(gid))
+            }
+            Self::CREATE_ACCOUNT => {
+                let credentials = Credentials::from_bytes(&mut reader)?;
+                Ok(ClientMessage::CreateAccount(credentials))
+            }
+            Self::LOGIN => {
+                let credentials = Credentials::from_bytes(&mut reader)?;
+                Ok(ClientMessage::Login(credentials))
+            }
+            Self::QUERY_RATING_HISTORY => {
+                let uid = reader.read_u32_le()?;
+                Ok(ClientMessage::QueryRatingHistory(uid))
+            }
+            Self::SEEK => {
+                let params = SeekParams::from_bytes(&mut reader)?;
+                Ok(ClientMessage::Seek(params))
+            }
+            Self::CANCEL_SEEK => Ok(ClientMessage::CancelSeek),
+            Self::CHALLENGE => {
+                let cid = reader.read_u32_le()? as usize;
+                let params = ChallengeParams::from_bytes(&mut reader)?;
+                Ok(ClientMessage::Challenge(cid, params))
+            }
+            Self::ACCEPT_CHALLENGE => {
+                let id = reader.read_u32_le()?;
+                Ok(ClientMessage::AcceptChallenge(id))
+            }
+            Self::DECLINE_CHALLENGE => {
+                let id = reader.read_u32_le()?;
+                let reason = DeclineReason::from_u8(reader.read_u8()?);
+                Ok(ClientMessage::DeclineChallenge(id, reason))
+            }
+            Self::CANCEL_CHALLENGE => {
+                let id = reader.read_u32_le()?;
+                Ok(ClientMessage::CancelChallenge(id))
+            }
+            Self::CHAT => {
+                let channel = ChatChannel::from_bytes(&mut reader)?;
+                let text = String::from_utf8(reader.remaining().to_vec())
+                    .map_err(|_| NetError::Protocol("Failed to parse chat text".to_string()))?;
+                Ok(ClientMessage::Chat(channel, text))
+            }
+            Self::REQUEST_TAKEBACK => {
+                let gid = reader.read_u32_le()?;
+                let plies = reader.read_u8()?;
+                Ok(ClientMessage::RequestTakeback(gid, plies))
+            }
+            Self::ANSWER_TAKEBACK => {
+                let gid = reader.read_u32_le()?;
+                let accept = reader.read_u8()? != 0;
+                Ok(ClientMessage::AnswerTakeback(gid, accept))
+            }
+            Self::ABORT => {
+                let gid = reader.read_u32_le()?;
+                Ok(ClientMessage::Abort(gid))
+            }
+            Self::REMATCH => {
+                let gid = reader.read_u32_le()?;
+                Ok(ClientMessage::Rematch(gid))
+            }
+            Self::CREATE_TOURNAMENT => {
+                let params = TournamentParams::from_bytes(&mut reader)?;
+                Ok(ClientMessage::CreateTournament(params))
+            }
+            Self::JOIN_TOURNAMENT => Ok(ClientMessage::JoinTournament(reader.read_u32_le()?)),
+            Self::WITHDRAW_TOURNAMENT => {
+                Ok(ClientMessage::WithdrawTournament(reader.read_u32_le()?))
+            }
+            Self::START_TOURNAMENT => Ok(ClientMessage::StartTournament(reader.read_u32_le()?)),
+            Self::QUERY_STANDINGS => Ok(ClientMessage::QueryStandings(reader.read_u32_le()?)),
+            Self::EXPORT_TOURNAMENT => {
+                let tid = reader.read_u32_le()?;
+                let format = ExportFormat::from_u8(reader.read_u8()?);
+                Ok(ClientMessage::ExportTournament(tid, format))
+            }
+            Self::CREATE_ARENA => {
+                let params = ArenaParams::from_bytes(&mut reader)?;
+                Ok(ClientMessage::CreateArena(params))
+            }
+            Self::JOIN_ARENA => Ok(ClientMessage::JoinArena(reader.read_u32_le()?)),
+            Self::PAUSE_ARENA => Ok(ClientMessage::PauseArena(reader.read_u32_le()?)),
+            Self::WITHDRAW_ARENA => Ok(ClientMessage::WithdrawArena(reader.read_u32_le()?)),
+            Self::CREATE_SIMUL => {
+                let params = SimulParams::from_bytes(&mut reader)?;
+                Ok(ClientMessage::CreateSimul(params))
+            }
+            Self::JOIN_SIMUL => Ok(ClientMessage::JoinSimul(reader.read_u32_le()?)),
+            Self::LEAVE_SIMUL => Ok(ClientMessage::LeaveSimul(reader.read_u32_le()?)),
+            Self::START_SIMUL => Ok(ClientMessage::StartSimul(reader.read_u32_le()?)),
+            Self::CREATE_BUGHOUSE => {
+                let params = BughouseParams::from_bytes(&mut reader)?;
+                Ok(ClientMessage::CreateBughouse(params))
+            }
+            Self::JOIN_BUGHOUSE => {
+                let bid = reader.read_u32_le()?;
+                let seat = reader.read_u8()?;
+                Ok(ClientMessage::JoinBughouse(bid, seat))
+            }
+            Self::LEAVE_BUGHOUSE => Ok(ClientMessage::LeaveBughouse(reader.read_u32_le()?)),
+            Self::DROP => {
+                let gid = reader.read_u32_le()?;
+                let drop_str = String::from_utf8(reader.remaining().to_vec())
+                    .map_err(|_| NetError::Protocol("Failed to parse drop string".to_string()))?;
+                let drop: ChessDrop = drop_str.parse().map_err(NetError::Protocol)?;
+                Ok(ClientMessage::Drop(gid, drop))
+            }
+            Self::CREATE_CONSULTATION => {
+                let params = ConsultationParams::from_bytes(&mut reader)?;
+                Ok(ClientMessage::CreateConsultation(params))
+            }
+            Self::JOIN_TEAM => {
+                let gid = reader.read_u32_le()?;
+                let color = match reader.read_u8()? {
+                    0 => ChessColor::Black,
+                    _ => ChessColor::White,
+                };
+                Ok(ClientMessage::JoinTeam(gid, color))
+            }
+            Self::CREATE_ROOM => {
+                let source = RoomSource::from_bytes(&mut reader)?;
+                Ok(ClientMessage::CreateRoom(source))
+            }
+            Self::JOIN_ROOM => Ok(ClientMessage::JoinRoom(reader.read_u32_le()?)),
+            Self::LEAVE_ROOM => Ok(ClientMessage::LeaveRoom(reader.read_u32_le()?)),
+            Self::ROOM_MOVE => {
+                let rid = reader.read_u32_le()?;
+                let move_str = String::from_utf8(reader.remaining().to_vec())
+                    .map_err(|_| NetError::Protocol("Failed to parse move string".to_string()))?;
+                let mov: ChessMove = move_str.parse().map_err(NetError::Protocol)?;
+                Ok(ClientMessage::RoomMove(rid, mov))
+            }
+            Self::ROOM_SETUP => {
+                let rid = reader.read_u32_le()?;
+                let fen = String::from_utf8(reader.remaining().to_vec())
+                    .map_err(|_| NetError::Protocol("Failed to parse FEN".to_string()))?;
+                Ok(ClientMessage::RoomSetup(rid, fen))
+            }
+            Self::ROOM_GO_TO => {
+                let rid = reader.read_u32_le()?;
+                let node = reader.read_u32_le()?;
+                Ok(ClientMessage::RoomGoTo(rid, node))
+            }
+            Self::ROOM_ENGINE => {
+                let rid = reader.read_u32_le()?;
+                let on = reader.read_u8()? != 0;
+                Ok(ClientMessage::RoomEngine(rid, on))
+            }
+            Self::REQUEST_PUZZLE => Ok(ClientMessage::RequestPuzzle(PuzzleParams::from_bytes(
+                &mut reader,
+            )?)),
+            Self::PUZZLE_MOVE => {
+                let move_str = String::from_utf8(reader.remaining().to_vec())
+                    .map_err(|_| NetError::Protocol("Failed to parse move string".to_string()))?;
+                let mov: ChessMove = move_str.parse().map_err(NetError::Protocol)?;
+                Ok(ClientMessage::PuzzleMove(mov))
+            }
+            Self::QUERY_PUZZLE_STATS => Ok(ClientMessage::QueryPuzzleStats(reader.read_u32_le()?)),
+            Self::RECONNECT => {
+                let token = String::from_utf8(reader.remaining().to_vec())
+                    .map_err(|_| NetError::Protocol("Failed to parse token".to_string()))?;
+                Ok(ClientMessage::Reconnect(token))
+            }
             _ => Err(NetError::Protocol(format!(
                 "parse: invalid command 0x{:02X}",
                 opcode
@@ -154,6 +341,262 @@ impl NetMessage for ClientMessage {
                 data.extend_from_slice(&gid.to_le_bytes());
                 data
             }
+            ClientMessage::QueryBookMoves(gid) => {
+                let mut data = vec![Self::QUERY_BOOK_MOVES];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data
+            }
+            ClientMessage::AddComputer(gid, side, level) => {
+                let mut data = vec![Self::ADD_COMPUTER];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data.push(*side as u8);
+                data.push(*level);
+                data
+            }
+            ClientMessage::Analyse(gid) => {
+                let mut data = vec![Self::ANALYSE];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data
+            }
+            ClientMessage::QueryTablebase(gid) => {
+                let mut data = vec![Self::QUERY_TABLEBASE];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data
+            }
+            ClientMessage::CreateAccount(credentials) => {
+                let mut data = vec![Self::CREATE_ACCOUNT];
+                data.extend_from_slice(&credentials.to_bytes());
+                data
+            }
+            ClientMessage::Login(credentials) => {
+                let mut data = vec![Self::LOGIN];
+                data.extend_from_slice(&credentials.to_bytes());
+                data
+            }
+            ClientMessage::QueryRatingHistory(uid) => {
+                let mut data = vec![Self::QUERY_RATING_HISTORY];
+                data.extend_from_slice(&uid.to_le_bytes());
+                data
+            }
+            ClientMessage::Seek(params) => {
+                let mut data = vec![Self::SEEK];
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ClientMessage::CancelSeek => vec![Self::CANCEL_SEEK],
+            ClientMessage::Challenge(cid, params) => {
+                let mut data = vec![Self::CHALLENGE];
+                data.extend_from_slice(&(*cid as u32).to_le_bytes());
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ClientMessage::AcceptChallenge(id) => {
+                let mut data = vec![Self::ACCEPT_CHALLENGE];
+                data.extend_from_slice(&id.to_le_bytes());
+                data
+            }
+            ClientMessage::DeclineChallenge(id, reason) => {
+                let mut data = vec![Self::DECLINE_CHALLENGE];
+                data.extend_from_slice(&id.to_le_bytes());
+                data.push(*reason as u8);
+                data
+            }
+            ClientMessage::CancelChallenge(id) => {
+                let mut data = vec![Self::CANCEL_CHALLENGE];
+                data.extend_from_slice(&id.to_le_bytes());
+                data
+            }
+            ClientMessage::Chat(channel, text) => {
+                let mut data = vec![Self::CHAT];
+                data.extend_from_slice(&channel.to_bytes());
+                data.extend_from_slice(text.as_bytes());
+                data
+            }
+            ClientMessage::RequestTakeback(gid, plies) => {
+                let mut data = vec![Self::REQUEST_TAKEBACK];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data.push(*plies);
+                data
+            }
+            ClientMessage::AnswerTakeback(gid, accept) => {
+                let mut data = vec![Self::ANSWER_TAKEBACK];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data.push(*accept as u8);
+                data
+            }
+            ClientMessage::Abort(gid) => {
+                let mut data = vec![Self::ABORT];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data
+            }
+            ClientMessage::Rematch(gid) => {
+                let mut data = vec![Self::REMATCH];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data
+            }
+            ClientMessage::CreateTournament(params) => {
+                let mut data = vec![Self::CREATE_TOURNAMENT];
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ClientMessage::JoinTournament(tid) => {
+                let mut data = vec![Self::JOIN_TOURNAMENT];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data
+            }
+            ClientMessage::WithdrawTournament(tid) => {
+                let mut data = vec![Self::WITHDRAW_TOURNAMENT];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data
+            }
+            ClientMessage::StartTournament(tid) => {
+                let mut data = vec![Self::START_TOURNAMENT];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data
+            }
+            ClientMessage::QueryStandings(tid) => {
+                let mut data = vec![Self::QUERY_STANDINGS];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data
+            }
+            ClientMessage::ExportTournament(tid, format) => {
+                let mut data = vec![Self::EXPORT_TOURNAMENT];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data.push(*format as u8);
+                data
+            }
+            ClientMessage::CreateArena(params) => {
+                let mut data = vec![Self::CREATE_ARENA];
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ClientMessage::JoinArena(tid) => {
+                let mut data = vec![Self::JOIN_ARENA];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data
+            }
+            ClientMessage::PauseArena(tid) => {
+                let mut data = vec![Self::PAUSE_ARENA];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data
+            }
+            ClientMessage::WithdrawArena(tid) => {
+                let mut data = vec![Self::WITHDRAW_ARENA];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data
+            }
+            ClientMessage::CreateSimul(params) => {
+                let mut data = vec![Self::CREATE_SIMUL];
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ClientMessage::JoinSimul(sid) => {
+                let mut data = vec![Self::JOIN_SIMUL];
+                data.extend_from_slice(&sid.to_le_bytes());
+                data
+            }
+            ClientMessage::LeaveSimul(sid) => {
+                let mut data = vec![Self::LEAVE_SIMUL];
+                data.extend_from_slice(&sid.to_le_bytes());
+                data
+            }
+            ClientMessage::StartSimul(sid) => {
+                let mut data = vec![Self::START_SIMUL];
+                data.extend_from_slice(&sid.to_le_bytes());
+                data
+            }
+            ClientMessage::CreateBughouse(params) => {
+                let mut data = vec![Self::CREATE_BUGHOUSE];
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ClientMessage::JoinBughouse(bid, seat) => {
+                let mut data = vec![Self::JOIN_BUGHOUSE];
+                data.extend_from_slice(&bid.to_le_bytes());
+                data.push(*seat);
+                data
+            }
+            ClientMessage::LeaveBughouse(bid) => {
+                let mut data = vec![Self::LEAVE_BUGHOUSE];
+                data.extend_from_slice(&bid.to_le_bytes());
+                data
+            }
+            ClientMessage::Drop(gid, drop) => {
+                let mut data = vec![Self::DROP];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data.extend_from_slice(drop.to_string().as_bytes());
+                data
+            }
+            ClientMessage::CreateConsultation(params) => {
+                let mut data = vec![Self::CREATE_CONSULTATION];
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ClientMessage::JoinTeam(gid, color) => {
+                let mut data = vec![Self::JOIN_TEAM];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data.push(*color as u8);
+                data
+            }
+            ClientMessage::CreateRoom(source) => {
+                let mut data = vec![Self::CREATE_ROOM];
+                data.extend_from_slice(&source.to_bytes());
+                data
+            }
+            ClientMessage::JoinRoom(rid) => {
+                let mut data = vec![Self::JOIN_ROOM];
+                data.extend_from_slice(&rid.to_le_bytes());
+                data
+            }
+            ClientMessage::LeaveRoom(rid) => {
+                let mut data = vec![Self::LEAVE_ROOM];
+                data.extend_from_slice(&rid.to_le_bytes());
+                data
+            }
+            ClientMessage::RoomMove(rid, mov) => {
+                let mut data = vec![Self::ROOM_MOVE];
+                data.extend_from_slice(&rid.to_le_bytes());
+                data.extend_from_slice(mov.to_string().as_bytes());
+                data
+            }
+            ClientMessage::RoomSetup(rid, fen) => {
+                let mut data = vec![Self::ROOM_SETUP];
+                data.extend_from_slice(&rid.to_le_bytes());
+                data.extend_from_slice(fen.as_bytes());
+                data
+            }
+            ClientMessage::RoomGoTo(rid, node) => {
+                let mut data = vec![Self::ROOM_GO_TO];
+                data.extend_from_slice(&rid.to_le_bytes());
+                data.extend_from_slice(&node.to_le_bytes());
+                data
+            }
+            ClientMessage::RoomEngine(rid, on) => {
+                let mut data = vec![Self::ROOM_ENGINE];
+                data.extend_from_slice(&rid.to_le_bytes());
+                data.push(*on as u8);
+                data
+            }
+            ClientMessage::RequestPuzzle(params) => {
+                let mut data = vec![Self::REQUEST_PUZZLE];
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ClientMessage::PuzzleMove(mov) => {
+                let mut data = vec![Self::PUZZLE_MOVE];
+                data.extend_from_slice(mov.to_string().as_bytes());
+                data
+            }
+            ClientMessage::QueryPuzzleStats(uid) => {
+                let mut data = vec![Self::QUERY_PUZZLE_STATS];
+                data.extend_from_slice(&uid.to_le_bytes());
+                data
+            }
+            ClientMessage::Reconnect(token) => {
+                let mut data = vec![Self::RECONNECT];
+                data.extend_from_slice(token.as_bytes());
+                data
+            }
         }
     }
 }
@@ -169,6 +612,7 @@ impl NetMessage for ServerMessage {
 
         match opcode_byte {
             Self::MOVE_ACCEPTED => {
+                let gid = reader.read_u32_le()?;
                 let san_len = reader.read_u8()?;
                 let san = reader.read_str(san_len as usize)?.to_string();
                 let mut updates = Vec::new();
@@ -179,7 +623,7 @@ impl NetMessage for ServerMessage {
                     let piece = Piece::from_char(piece_char);
                     updates.push((tile, piece));
                 }
-                Ok(ServerMessage::MoveAccepted(san_len, san, updates))
+                Ok(ServerMessage::MoveAccepted(gid, san_len, san, updates))
             }
             Self::GAME_CREATED => {
                 let gid = reader.read_u32_le()?;
@@ -225,6 +669,11 @@ impl NetMessage for ServerMessage {
                     6 => GameOverReason::InsufficientMaterial,
                     7 => GameOverReason::FiftyMovesRule,
                     8 => GameOverReason::DrawAgreement,
+                    9 => GameOverReason::Adjudication(match winner_byte {
+                        0 | 1 => Some(winner),
+                        _ => None,
+                    }),
+                    10 => GameOverReason::Abandoned(winner),
                     _ => panic!("Invalid game over reason"),
                 };
                 Ok(ServerMessage::GameOver(gid, reason))
@@ -245,6 +694,9 @@ impl NetMessage for ServerMessage {
                 };
                 let time = reader.read_u32_le()?;
                 let inc = reader.read_u32_le()?;
+                let rated = reader.read_u8()? != 0;
+                let white_rating = read_rating(&mut reader)?;
+                let black_rating = read_rating(&mut reader)?;
 
                 Ok(ServerMessage::GameDetails(
                     gid,
@@ -252,6 +704,9 @@ impl NetMessage for ServerMessage {
                     black_id_opt,
                     time,
                     inc,
+                    rated,
+                    white_rating,
+                    black_rating,
                 ))
             }
             Self::CLIENT_DETAILS => {
@@ -272,14 +727,10 @@ impl NetMessage for ServerMessage {
             }
             Self::MOVE_HISTORY => {
                 let gid = reader.read_u32_le()?;
-                let mut history = Vec::new();
-                let history_str = String::from_utf8(reader.remaining().to_vec())
-                    .map_err(|_| NetError::Protocol("Failed to parse move history".to_string()))?;
-                if !history_str.is_empty() {
-                    history_str.split(' ').for_each(|mov| {
-                        history.push(mov.to_string());
-                    });
-                }
+                let count = reader.read_u16_le()?;
+                let history = (0..count)
+                    .map(|_| MoveRecord::from_bytes(&mut reader))
+                    .collect::<NetResult<Vec<_>>>()?;
                 Ok(ServerMessage::MoveHistory(gid, history))
             }
             Self::LOGIN_ACCEPTED => {
@@ -290,6 +741,312 @@ impl NetMessage for ServerMessage {
                 let gid = reader.read_u32_le()?;
                 Ok(ServerMessage::DrawOffered(gid))
             }
+            Self::BOOK_MOVES => {
+                let gid = reader.read_u32_le()?;
+                let mut moves = Vec::new();
+                while !reader.remaining().is_empty() {
+                    let weight = reader.read_u16_le()?;
+                    let san_len = reader.read_u8()?;
+                    let san = reader.read_str(san_len as usize)?.to_string();
+                    moves.push((san, weight));
+                }
+                Ok(ServerMessage::BookMoves(gid, moves))
+            }
+            Self::ANALYSIS => {
+                let gid = reader.read_u32_le()?;
+                let depth = reader.read_u8()?;
+                let kind = reader.read_u8()?;
+                let value = reader.read_u32_le()? as i32;
+                let score = match kind {
+                    0 => Score::Cp(value),
+                    1 => Score::Mate(value),
+                    _ => return Err(NetError::Protocol("Invalid score kind".to_string())),
+                };
+                let mut pv = Vec::new();
+                while !reader.remaining().is_empty() {
+                    let san_len = reader.read_u8()?;
+                    pv.push(reader.read_str(san_len as usize)?.to_string());
+                }
+                Ok(ServerMessage::Analysis(gid, depth, score, pv))
+            }
+            Self::TABLEBASE_RESULT => {
+                let gid = reader.read_u32_le()?;
+                if reader.remaining().is_empty() {
+                    return Ok(ServerMessage::TablebaseResult(gid, None));
+                }
+                let wdl = Wdl::from_u8(reader.read_u8()?)
+                    .ok_or_else(|| NetError::Protocol("Invalid WDL".to_string()))?;
+                let dtz = reader.read_u32_le()? as i32;
+                let san_len = reader.read_u8()?;
+                let san = reader.read_str(san_len as usize)?.to_string();
+                Ok(ServerMessage::TablebaseResult(gid, Some((wdl, dtz, san))))
+            }
+            Self::LOGGED_IN => {
+                let uid = reader.read_u32_le()?;
+                let name = String::from_utf8(reader.remaining().to_vec())
+                    .map_err(|_| NetError::Protocol("Failed to parse account name".to_string()))?;
+                Ok(ServerMessage::LoggedIn(uid, name))
+            }
+            Self::LOGIN_FAILED => {
+                let reason = String::from_utf8_lossy(reader.remaining()).to_string();
+                Ok(ServerMessage::LoginFailed(reason))
+            }
+            Self::RATING_HISTORY => {
+                let uid = reader.read_u32_le()?;
+                let mut records = Vec::new();
+                while !reader.remaining().is_empty() {
+                    let variant = Variant::from_u8(reader.read_u8()?)
+                        .ok_or_else(|| NetError::Protocol("Invalid variant".to_string()))?;
+                    let category = TimeCategory::from_u8(reader.read_u8()?)
+                        .ok_or_else(|| NetError::Protocol("Invalid time category".to_string()))?;
+                    let time = reader.read_u32_le()?;
+                    let rating = Rating {
+                        rating: reader.read_u16_le()?,
+                        deviation: reader.read_u16_le()?,
+                    };
+                    records.push(RatingRecord {
+                        variant,
+                        category,
+                        time,
+                        rating,
+                    });
+                }
+                Ok(ServerMessage::RatingHistory(uid, records))
+            }
+            Self::SEEK_PENDING => Ok(ServerMessage::SeekPending),
+            Self::SEEK_CANCELLED => {
+                let reason = String::from_utf8_lossy(reader.remaining()).to_string();
+                Ok(ServerMessage::SeekCancelled(reason))
+            }
+            Self::CHALLENGE_ISSUED => {
+                let id = reader.read_u32_le()?;
+                let challenger = reader.read_u32_le()? as usize;
+                let challenged = reader.read_u32_le()? as usize;
+                let params = ChallengeParams::from_bytes(&mut reader)?;
+                Ok(ServerMessage::ChallengeIssued(
+                    id, challenger, challenged, params,
+                ))
+            }
+            Self::CHALLENGE_CLOSED => {
+                let id = reader.read_u32_le()?;
+                let outcome = ChallengeOutcome::from_bytes(&mut reader)?;
+                Ok(ServerMessage::ChallengeClosed(id, outcome))
+            }
+            Self::CHAT => {
+                let channel = ChatChannel::from_bytes(&mut reader)?;
+                let cid = reader.read_u32_le()? as usize;
+                let text_len = reader.read_u16_le()?;
+                let text = reader.read_str(text_len as usize)?.to_string();
+                let name = String::from_utf8_lossy(reader.remaining()).to_string();
+                Ok(ServerMessage::Chat(channel, cid, name, text))
+            }
+            Self::CHAT_REJECTED => {
+                let reason = String::from_utf8_lossy(reader.remaining()).to_string();
+                Ok(ServerMessage::ChatRejected(reason))
+            }
+            Self::TAKEBACK_OFFERED => {
+                let gid = reader.read_u32_le()?;
+                let plies = reader.read_u8()?;
+                Ok(ServerMessage::TakebackOffered(gid, plies))
+            }
+            Self::TAKEBACK_DECLINED => {
+                let gid = reader.read_u32_le()?;
+                Ok(ServerMessage::TakebackDeclined(gid))
+            }
+            Self::GAME_ABORTED => {
+                let gid = reader.read_u32_le()?;
+                Ok(ServerMessage::GameAborted(gid))
+            }
+            Self::REMATCH_OFFERED => {
+                let gid = reader.read_u32_le()?;
+                let cid = reader.read_u32_le()? as usize;
+                Ok(ServerMessage::RematchOffered(gid, cid))
+            }
+            Self::TOURNAMENT_CREATED => {
+                let tid = reader.read_u32_le()?;
+                let organizer = reader.read_u32_le()? as usize;
+                let params = TournamentParams::from_bytes(&mut reader)?;
+                Ok(ServerMessage::TournamentCreated(tid, organizer, params))
+            }
+            Self::TOURNAMENT_PLAYERS => {
+                let tid = reader.read_u32_le()?;
+                let mut players = Vec::new();
+                while reader.remaining().len() >= 4 {
+                    players.push(reader.read_u32_le()? as usize);
+                }
+                Ok(ServerMessage::TournamentPlayers(tid, players))
+            }
+            Self::TOURNAMENT_ROUND => {
+                let tid = reader.read_u32_le()?;
+                let round = reader.read_u8()?;
+                let bye = match reader.read_u32_le()? {
+                    0 => None,
+                    cid => Some(cid as usize),
+                };
+                Ok(ServerMessage::TournamentRound(tid, round, bye))
+            }
+            Self::STANDINGS => {
+                let tid = reader.read_u32_le()?;
+                let rounds = reader.read_u8()?;
+                let finished = reader.read_u8()? != 0;
+                let mut standings = Vec::new();
+                while !reader.remaining().is_empty() {
+                    standings.push(Standing::from_bytes(&mut reader)?);
+                }
+                Ok(ServerMessage::Standings(tid, rounds, finished, standings))
+            }
+            Self::TOURNAMENT_EXPORT => {
+                let tid = reader.read_u32_le()?;
+                let text = String::from_utf8_lossy(reader.remaining()).to_string();
+                Ok(ServerMessage::TournamentExport(tid, text))
+            }
+            Self::ARENA_CREATED => {
+                let tid = reader.read_u32_le()?;
+                let organizer = reader.read_u32_le()? as usize;
+                let params = ArenaParams::from_bytes(&mut reader)?;
+                Ok(ServerMessage::ArenaCreated(tid, organizer, params))
+            }
+            Self::LEADERBOARD => {
+                let tid = reader.read_u32_le()?;
+                let seconds_left = reader.read_u32_le()?;
+                let mut standings = Vec::new();
+                while !reader.remaining().is_empty() {
+                    standings.push(ArenaStanding::from_bytes(&mut reader)?);
+                }
+                Ok(ServerMessage::Leaderboard(tid, seconds_left, standings))
+            }
+            Self::SIMUL_CREATED => {
+                let sid = reader.read_u32_le()?;
+                let host = reader.read_u32_le()? as usize;
+                let params = SimulParams::from_bytes(&mut reader)?;
+                Ok(ServerMessage::SimulCreated(sid, host, params))
+            }
+            Self::SIMUL_PLAYERS => {
+                let sid = reader.read_u32_le()?;
+                let mut players = Vec::new();
+                while reader.remaining().len() >= 4 {
+                    players.push(reader.read_u32_le()? as usize);
+                }
+                Ok(ServerMessage::SimulPlayers(sid, players))
+            }
+            Self::SIMUL_BOARDS => {
+                let sid = reader.read_u32_le()?;
+                let mut boards = Vec::new();
+                while !reader.remaining().is_empty() {
+                    boards.push(SimulBoard::from_bytes(&mut reader)?);
+                }
+                Ok(ServerMessage::SimulBoards(sid, boards))
+            }
+            Self::BUGHOUSE_CREATED => {
+                let bid = reader.read_u32_le()?;
+                let creator = reader.read_u32_le()? as usize;
+                let params = BughouseParams::from_bytes(&mut reader)?;
+                Ok(ServerMessage::BughouseCreated(bid, creator, params))
+            }
+            Self::BUGHOUSE_SEATS => {
+                let bid = reader.read_u32_le()?;
+                let mut seats = [None; 4];
+                for seat in &mut seats {
+                    *seat = match reader.read_u32_le()? {
+                        0 => None,
+                        cid => Some(cid as usize),
+                    };
+                }
+                Ok(ServerMessage::BughouseSeats(bid, seats))
+            }
+            Self::BUGHOUSE_STATE => {
+                let bid = reader.read_u32_le()?;
+                let result = match reader.read_u8()? {
+                    0 => None,
+                    half_points => Some((half_points - 1) as f32 / 2.0),
+                };
+                let boards = [
+                    BughouseBoard::from_bytes(&mut reader)?,
+                    BughouseBoard::from_bytes(&mut reader)?,
+                ];
+                Ok(ServerMessage::BughouseState(bid, result, boards))
+            }
+            Self::TEAMS => {
+                let gid = reader.read_u32_le()?;
+                let mode = VotingMode::from_u8(reader.read_u8()?)
+                    .ok_or_else(|| NetError::Protocol("Invalid voting mode".to_string()))?;
+                let mut teams = [vec![], vec![]];
+                for team in &mut teams {
+                    let len = reader.read_u8()?;
+                    for _ in 0..len {
+                        team.push(reader.read_u32_le()? as usize);
+                    }
+                }
+                let [white, black] = teams;
+                Ok(ServerMessage::Teams(gid, mode, white, black))
+            }
+            Self::PROPOSALS => {
+                let gid = reader.read_u32_le()?;
+                let mut proposals = Vec::new();
+                while !reader.remaining().is_empty() {
+                    let cid = reader.read_u32_le()? as usize;
+                    let san_len = reader.read_u8()?;
+                    let san = reader.read_str(san_len as usize)?.to_string();
+                    proposals.push((cid, san));
+                }
+                Ok(ServerMessage::Proposals(gid, proposals))
+            }
+            Self::ROOM_CREATED => {
+                let rid = reader.read_u32_le()?;
+                let cid = reader.read_u32_le()? as usize;
+                Ok(ServerMessage::RoomCreated(rid, cid))
+            }
+            Self::ROOM_MEMBERS => {
+                let rid = reader.read_u32_le()?;
+                let mut members = Vec::new();
+                while !reader.remaining().is_empty() {
+                    members.push(reader.read_u32_le()? as usize);
+                }
+                Ok(ServerMessage::RoomMembers(rid, members))
+            }
+            Self::ROOM_STATE => {
+                let rid = reader.read_u32_le()?;
+                let tree = RoomTree::from_bytes(&mut reader)?;
+                Ok(ServerMessage::RoomState(rid, tree))
+            }
+            Self::ROOM_ANALYSIS => {
+                let rid = reader.read_u32_le()?;
+                let node = reader.read_u32_le()?;
+                let depth = reader.read_u8()?;
+                let kind = reader.read_u8()?;
+                let value = reader.read_u32_le()? as i32;
+                let score = match kind {
+                    0 => Score::Cp(value),
+                    1 => Score::Mate(value),
+                    _ => return Err(NetError::Protocol("Invalid score kind".to_string())),
+                };
+                let mut pv = Vec::new();
+                while !reader.remaining().is_empty() {
+                    let san_len = reader.read_u8()?;
+                    pv.push(reader.read_str(san_len as usize)?.to_string());
+                }
+                Ok(ServerMessage::RoomAnalysis(rid, node, depth, score, pv))
+            }
+            Self::PUZZLE_STATE => Ok(ServerMessage::PuzzleState(PuzzleState::from_bytes(
+                &mut reader,
+            )?)),
+            Self::NO_PUZZLE => {
+                let reason = String::from_utf8_lossy(reader.remaining()).to_string();
+                Ok(ServerMessage::NoPuzzle(reason))
+            }
+            Self::PUZZLE_STATS => {
+                let uid = reader.read_u32_le()?;
+                let rating = read_rating(&mut reader)?;
+                let mut themes = Vec::new();
+                while !reader.remaining().is_empty() {
+                    themes.push(ThemeStats::from_bytes(&mut reader)?);
+                }
+                Ok(ServerMessage::PuzzleStats(uid, rating, themes))
+            }
+            Self::RECONNECT_TOKEN => {
+                let token = String::from_utf8_lossy(reader.remaining()).to_string();
+                Ok(ServerMessage::ReconnectToken(token))
+            }
             _ => Err(NetError::Protocol(format!(
                 "Unknown opcode: {}",
                 opcode_byte
@@ -299,8 +1056,9 @@ impl NetMessage for ServerMessage {
 
     fn to_bytes(&self) -> Vec<u8> {
         match self {
-            ServerMessage::MoveAccepted(san_len, san, tiles) => {
+            ServerMessage::MoveAccepted(gid, san_len, san, tiles) => {
                 let mut msg = vec![Self::MOVE_ACCEPTED];
+                msg.extend_from_slice(&gid.to_le_bytes());
                 msg.push(*san_len);
                 msg.extend_from_slice(san.as_bytes());
                 for u in tiles {
@@ -356,7 +1114,7 @@ impl NetMessage for ServerMessage {
                 data.extend_from_slice(&(*cid as u32).to_le_bytes());
                 data
             }
-            ServerMessage::GameDetails(gid, white_id, black_id, time, inc) => {
+            ServerMessage::GameDetails(gid, white_id, black_id, time, inc, rated, wr, br) => {
                 let mut data = vec![Self::GAME_DETAILS];
                 data.extend_from_slice(&gid.to_le_bytes());
                 let white_id = white_id.map(|id| id as u32).unwrap_or(0);
@@ -365,6 +1123,16 @@ impl NetMessage for ServerMessage {
                 data.extend_from_slice(&black_id.to_le_bytes());
                 data.extend_from_slice(&time.to_le_bytes());
                 data.extend_from_slice(&inc.to_le_bytes());
+                data.push(*rated as u8);
+                for rating in [wr, br] {
+                    match rating {
+                        Some(rating) => {
+                            data.push(1);
+                            data.extend_from_slice(&rating.to_bytes());
+                        }
+                        None => data.push(0),
+                    }
+                }
                 data
             }
             ServerMessage::ClientDetails(cid, name) => {
@@ -388,12 +1156,9 @@ impl NetMessage for ServerMessage {
             ServerMessage::MoveHistory(gid, history) => {
                 let mut data = vec![Self::MOVE_HISTORY];
                 data.extend_from_slice(&gid.to_le_bytes());
-                for mov in history {
-                    data.extend_from_slice(mov.to_string().as_bytes());
-                    data.push(' ' as u8);
-                }
-                if history.len() > 0 {
-                    data.pop(); // remove last ' ' if we have a history
+                data.extend_from_slice(&(history.len() as u16).to_le_bytes());
+                for record in history {
+                    data.extend_from_slice(&record.to_bytes());
                 }
                 data
             }
@@ -402,6 +1167,324 @@ impl NetMessage for ServerMessage {
                 data.extend_from_slice(&gid.to_le_bytes());
                 data
             }
+            ServerMessage::BookMoves(gid, moves) => {
+                let mut data = vec![Self::BOOK_MOVES];
+                data.extend_from_slice(&gid.to_le_bytes());
+                for (san, weight) in moves {
+                    data.extend_from_slice(&weight.to_le_bytes());
+                    data.push(san.len() as u8);
+                    data.extend_from_slice(san.as_bytes());
+                }
+                data
+            }
+            ServerMessage::Analysis(gid, depth, score, pv) => {
+                let mut data = vec![Self::ANALYSIS];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data.push(*depth);
+                let (kind, value) = match score {
+                    Score::Cp(cp) => (0, cp),
+                    Score::Mate(n) => (1, n),
+                };
+                data.push(kind);
+                data.extend_from_slice(&value.to_le_bytes());
+                for san in pv {
+                    data.push(san.len() as u8);
+                    data.extend_from_slice(san.as_bytes());
+                }
+                data
+            }
+            ServerMessage::TablebaseResult(gid, result) => {
+                let mut data = vec![Self::TABLEBASE_RESULT];
+                data.extend_from_slice(&gid.to_le_bytes());
+                if let Some((wdl, dtz, san)) = result {
+                    data.push(*wdl as u8);
+                    data.extend_from_slice(&dtz.to_le_bytes());
+                    data.push(san.len() as u8);
+                    data.extend_from_slice(san.as_bytes());
+                }
+                data
+            }
+            ServerMessage::LoggedIn(uid, name) => {
+                let mut data = vec![Self::LOGGED_IN];
+                data.extend_from_slice(&uid.to_le_bytes());
+                data.extend_from_slice(name.as_bytes());
+                data
+            }
+            ServerMessage::LoginFailed(reason) => {
+                let mut data = vec![Self::LOGIN_FAILED];
+                data.extend_from_slice(reason.as_bytes());
+                data
+            }
+            ServerMessage::RatingHistory(uid, records) => {
+                let mut data = vec![Self::RATING_HISTORY];
+                data.extend_from_slice(&uid.to_le_bytes());
+                for record in records {
+                    data.push(record.variant as u8);
+                    data.push(record.category as u8);
+                    data.extend_from_slice(&record.time.to_le_bytes());
+                    data.extend_from_slice(&record.rating.to_bytes());
+                }
+                data
+            }
+            ServerMessage::SeekPending => vec![Self::SEEK_PENDING],
+            ServerMessage::SeekCancelled(reason) => {
+                let mut data = vec![Self::SEEK_CANCELLED];
+                data.extend_from_slice(reason.as_bytes());
+                data
+            }
+            ServerMessage::ChallengeIssued(id, challenger, challenged, params) => {
+                let mut data = vec![Self::CHALLENGE_ISSUED];
+                data.extend_from_slice(&id.to_le_bytes());
+                data.extend_from_slice(&(*challenger as u32).to_le_bytes());
+                data.extend_from_slice(&(*challenged as u32).to_le_bytes());
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ServerMessage::ChallengeClosed(id, outcome) => {
+                let mut data = vec![Self::CHALLENGE_CLOSED];
+                data.extend_from_slice(&id.to_le_bytes());
+                data.extend_from_slice(&outcome.to_bytes());
+                data
+            }
+            ServerMessage::Chat(channel, cid, name, text) => {
+                let mut data = vec![Self::CHAT];
+                data.extend_from_slice(&channel.to_bytes());
+                data.extend_from_slice(&(*cid as u32).to_le_bytes());
+                // chat lines are short, nicknames may not be
+                data.extend_from_slice(&(text.len() as u16).to_le_bytes());
+                data.extend_from_slice(text.as_bytes());
+                data.extend_from_slice(name.as_bytes());
+                data
+            }
+            ServerMessage::ChatRejected(reason) => {
+                let mut data = vec![Self::CHAT_REJECTED];
+                data.extend_from_slice(reason.as_bytes());
+                data
+            }
+            ServerMessage::TakebackOffered(gid, plies) => {
+                let mut data = vec![Self::TAKEBACK_OFFERED];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data.push(*plies);
+                data
+            }
+            ServerMessage::TakebackDeclined(gid) => {
+                let mut data = vec![Self::TAKEBACK_DECLINED];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data
+            }
+            ServerMessage::GameAborted(gid) => {
+                let mut data = vec![Self::GAME_ABORTED];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data
+            }
+            ServerMessage::RematchOffered(gid, cid) => {
+                let mut data = vec![Self::REMATCH_OFFERED];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data.extend_from_slice(&(*cid as u32).to_le_bytes());
+                data
+            }
+            ServerMessage::TournamentCreated(tid, organizer, params) => {
+                let mut data = vec![Self::TOURNAMENT_CREATED];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data.extend_from_slice(&(*organizer as u32).to_le_bytes());
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ServerMessage::TournamentPlayers(tid, players) => {
+                let mut data = vec![Self::TOURNAMENT_PLAYERS];
+                data.extend_from_slice(&tid.to_le_bytes());
+                for cid in players {
+                    data.extend_from_slice(&(*cid as u32).to_le_bytes());
+                }
+                data
+            }
+            ServerMessage::TournamentRound(tid, round, bye) => {
+                let mut data = vec![Self::TOURNAMENT_ROUND];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data.push(*round);
+                data.extend_from_slice(&(bye.unwrap_or(0) as u32).to_le_bytes());
+                data
+            }
+            ServerMessage::Standings(tid, rounds, finished, standings) => {
+                let mut data = vec![Self::STANDINGS];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data.push(*rounds);
+                data.push(*finished as u8);
+                for standing in standings {
+                    data.extend_from_slice(&standing.to_bytes());
+                }
+                data
+            }
+            ServerMessage::TournamentExport(tid, text) => {
+                let mut data = vec![Self::TOURNAMENT_EXPORT];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data.extend_from_slice(text.as_bytes());
+                data
+            }
+            ServerMessage::ArenaCreated(tid, organizer, params) => {
+                let mut data = vec![Self::ARENA_CREATED];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data.extend_from_slice(&(*organizer as u32).to_le_bytes());
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ServerMessage::Leaderboard(tid, seconds_left, standings) => {
+                let mut data = vec![Self::LEADERBOARD];
+                data.extend_from_slice(&tid.to_le_bytes());
+                data.extend_from_slice(&seconds_left.to_le_bytes());
+                for standing in standings {
+                    data.extend_from_slice(&standing.to_bytes());
+                }
+                data
+            }
+            ServerMessage::SimulCreated(sid, host, params) => {
+                let mut data = vec![Self::SIMUL_CREATED];
+                data.extend_from_slice(&sid.to_le_bytes());
+                data.extend_from_slice(&(*host as u32).to_le_bytes());
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ServerMessage::SimulPlayers(sid, players) => {
+                let mut data = vec![Self::SIMUL_PLAYERS];
+                data.extend_from_slice(&sid.to_le_bytes());
+                for cid in players {
+                    data.extend_from_slice(&(*cid as u32).to_le_bytes());
+                }
+                data
+            }
+            ServerMessage::SimulBoards(sid, boards) => {
+                let mut data = vec![Self::SIMUL_BOARDS];
+                data.extend_from_slice(&sid.to_le_bytes());
+                for board in boards {
+                    data.extend_from_slice(&board.to_bytes());
+                }
+                data
+            }
+            ServerMessage::BughouseCreated(bid, creator, params) => {
+                let mut data = vec![Self::BUGHOUSE_CREATED];
+                data.extend_from_slice(&bid.to_le_bytes());
+                data.extend_from_slice(&(*creator as u32).to_le_bytes());
+                data.extend_from_slice(&params.to_bytes());
+                data
+            }
+            ServerMessage::BughouseSeats(bid, seats) => {
+                let mut data = vec![Self::BUGHOUSE_SEATS];
+                data.extend_from_slice(&bid.to_le_bytes());
+                for seat in seats {
+                    data.extend_from_slice(&(seat.unwrap_or(0) as u32).to_le_bytes());
+                }
+                data
+            }
+            ServerMessage::BughouseState(bid, result, boards) => {
+                let mut data = vec![Self::BUGHOUSE_STATE];
+                data.extend_from_slice(&bid.to_le_bytes());
+                // 0 while running, else 1 + the half points of the first team
+                data.push(result.map_or(0, |points| 1 + (points * 2.0) as u8));
+                for board in boards {
+                    data.extend_from_slice(&board.to_bytes());
+                }
+                data
+            }
+            ServerMessage::Teams(gid, mode, white, black) => {
+                let mut data = vec![Self::TEAMS];
+                data.extend_from_slice(&gid.to_le_bytes());
+                data.push(*mode as u8);
+                for team in [white, black] {
+                    data.push(team.len() as u8);
+                    for cid in team {
+                        data.extend_from_slice(&(*cid as u32).to_le_bytes());
+                    }
+                }
+                data
+            }
+            ServerMessage::Proposals(gid, proposals) => {
+                let mut data = vec![Self::PROPOSALS];
+                data.extend_from_slice(&gid.to_le_bytes());
+                for (cid, san) in proposals {
+                    data.extend_from_slice(&(*cid as u32).to_le_bytes());
+                    data.push(san.len() as u8);
+                    data.extend_from_slice(san.as_bytes());
+                }
+                data
+            }
+            ServerMessage::RoomCreated(rid, cid) => {
+                let mut data = vec![Self::ROOM_CREATED];
+                data.extend_from_slice(&rid.to_le_bytes());
+                data.extend_from_slice(&(*cid as u32).to_le_bytes());
+                data
+            }
+            ServerMessage::RoomMembers(rid, members) => {
+                let mut data = vec![Self::ROOM_MEMBERS];
+                data.extend_from_slice(&rid.to_le_bytes());
+                for cid in members {
+                    data.extend_from_slice(&(*cid as u32).to_le_bytes());
+                }
+                data
+            }
+            ServerMessage::RoomState(rid, tree) => {
+                let mut data = vec![Self::ROOM_STATE];
+                data.extend_from_slice(&rid.to_le_bytes());
+                data.extend_from_slice(&tree.to_bytes());
+                data
+            }
+            ServerMessage::RoomAnalysis(rid, node, depth, score, pv) => {
+                let mut data = vec![Self::ROOM_ANALYSIS];
+                data.extend_from_slice(&rid.to_le_bytes());
+                data.extend_from_slice(&node.to_le_bytes());
+                data.push(*depth);
+                let (kind, value) = match score {
+                    Score::Cp(cp) => (0, cp),
+                    Score::Mate(n) => (1, n),
+                };
+                data.push(kind);
+                data.extend_from_slice(&value.to_le_bytes());
+                for san in pv {
+                    data.push(san.len() as u8);
+                    data.extend_from_slice(san.as_bytes());
+                }
+                data
+            }
+            ServerMessage::PuzzleState(state) => {
+                let mut data = vec![Self::PUZZLE_STATE];
+                data.extend_from_slice(&state.to_bytes());
+                data
+            }
+            ServerMessage::NoPuzzle(reason) => {
+                let mut data = vec![Self::NO_PUZZLE];
+                data.extend_from_slice(reason.as_bytes());
+                data
+            }
+            ServerMessage::PuzzleStats(uid, rating, themes) => {
+                let mut data = vec![Self::PUZZLE_STATS];
+                data.extend_from_slice(&uid.to_le_bytes());
+                match rating {
+                    Some(rating) => {
+                        data.push(1);
+                        data.extend_from_slice(&rating.to_bytes());
+                    }
+                    None => data.push(0),
+                }
+                for stats in themes {
+                    data.extend_from_slice(&stats.to_bytes());
+                }
+                data
+            }
+            ServerMessage::ReconnectToken(token) => {
+                let mut data = vec![Self::RECONNECT_TOKEN];
+                data.extend_from_slice(token.as_bytes());
+                data
+            }
         }
     }
 }
+
+/// A rating behind a presence byte.
+fn read_rating(reader: &mut Reader) -> NetResult<Option<Rating>> {
+    if reader.read_u8()? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(Rating {
+        rating: reader.read_u16_le()?,
+        deviation: reader.read_u16_le()?,
+    }))
+}
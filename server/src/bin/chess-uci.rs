@@ -0,0 +1,18 @@
+use chess_server::engine::uci_frontend::UciFrontend;
+use std::io::{self, BufRead};
+
+/// Our engine as a UCI engine, for chess GUIs and engine tournaments.
+/// Speaks UCI on stdin/stdout; log output goes to stderr (`RUST_LOG`).
+fn main() -> io::Result<()> {
+    env_logger::init();
+
+    let mut uci = UciFrontend::new(io::stdout());
+    for line in io::stdin().lock().lines() {
+        if !uci.handle(&line?) {
+            return Ok(());
+        }
+    }
+    // the GUI went away without `quit`
+    uci.handle("quit");
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use chess_server::engine::xboard::XBoardFrontend;
+use std::io::{self, BufRead};
+
+/// Our engine as a CECP engine, for XBoard/WinBoard and other GUIs that don't speak UCI.
+/// Speaks CECP on stdin/stdout; log output goes to stderr (`RUST_LOG`).
+fn main() -> io::Result<()> {
+    env_logger::init();
+
+    let mut xboard = XBoardFrontend::new(io::stdout());
+    for line in io::stdin().lock().lines() {
+        if !xboard.handle(&line?) {
+            return Ok(());
+        }
+    }
+    // the GUI went away without `quit`
+    xboard.handle("quit");
+    Ok(())
+}
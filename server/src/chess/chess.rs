@@ -56,6 +56,12 @@ impl<'a> IntoIterator for &'a Chess {
     }
 }
 
+impl Default for Chess {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Chess {
     /// create a new chess board with default starting position.
     pub fn new() -> Chess {
@@ -199,12 +205,35 @@ impl Chess {
             &chess.tiles,
             chess.active_player,
             &chess.castle_rights,
-            chess.en_passant,
+            chess.en_passant_capturable(),
         );
 
         chess
     }
 
+    /// The en passant tile, but only if a pawn of the active player stands next to the pawn that
+    /// just made the double step, i.e., if it could actually capture en passant.
+    /// Only then the en passant tile is part of the position hash (same rule as Polyglot books and
+    /// FIDE repetition rules: a position only differs if the capture is possible).
+    pub fn en_passant_capturable(&self) -> Option<Tile> {
+        let ep = self.en_passant?;
+        let capture_from = if self.active_player == ChessColor::White {
+            [Tile::DOWNLEFT, Tile::DOWNRIGHT]
+        } else {
+            [Tile::UPLEFT, Tile::UPRIGHT]
+        };
+        for dir in capture_from {
+            if let Some(t) = ep + dir {
+                if let Some(p) = self[t] {
+                    if p.typ == ChessPiece::Pawn && p.color == self.active_player {
+                        return Some(ep);
+                    }
+                }
+            }
+        }
+        None
+    }
+
     /// Get the FEN of the current position.
     pub fn get_fen(&self) -> String {
         let mut fen = String::with_capacity(90);
@@ -513,54 +542,183 @@ impl Chess {
         true
     }
 
-    /// Execute a move on the board.
-    /// Returns a vector of tiles that have been changed. This approach is helpful for
-    /// en passant and castling, where more tiles  than the src and dest tiles are affected.
-    ///
-    ///
-    pub fn make_move(&mut self, mov: ChessMove) -> ChessResult<Vec<(Tile, Option<Piece>)>> {
+    /// Test if a move is legal in the current position, i.e., pseudo-legal and it doesn't leave
+    /// (or castle through) check.
+    pub fn is_legal_move(&self, mov: &ChessMove) -> bool {
         // First we check with the piece movement rules the pseudo-legal move, i.e., if the piece
         // can move by its own rules to the destination square. We only test for validity and
         // no test for _check_ is performed yet.
-        if !self.is_pseudo_legal_move(&mov) {
-            return Err(ChessError::IllegalMove(mov));
+        if !self.is_pseudo_legal_move(mov) {
+            return false;
         }
         // After the pseudo-legal test, we now clone the board to create a simulation.
         // We execute the pseudo-legal move on the simulation board. The pieces are now at their
         // new positions on the simulation.
         let mut simulation = self.clone();
-        simulation.make_move_unchecked(mov)?;
+        if simulation.make_move_unchecked(*mov).is_err() {
+            return false;
+        }
 
         // Now we test for checks on the simulation. If a player moved a piece but now his king
         // is in check, the move is illegal and will not be executed on the real board.
-        if simulation.is_in_check(simulation.active_player) {
-            return Err(ChessError::IllegalMove(mov));
+        if simulation.is_in_check(self.active_player) {
+            return false;
         }
 
-        let src = mov.src;
-        let dst = mov.dst;
-        let p = self[src].unwrap(); // already covered by is_pseudo_legal_move()
+        let p = self[mov.src].unwrap(); // already covered by is_pseudo_legal_move()
 
         // Special check for castling through check;
         // A bit awkward to make the test here, but it's yet again such a special chess rule;
         // castling is the only move we can't do to leave a check + we can't castle through check.
         if p.typ == ChessPiece::King && (mov.dst.file as i8 - mov.src.file as i8).abs() == 2 {
             if self.is_in_check(self.active_player) {
-                return Err(ChessError::IllegalMove(mov));
+                return false;
             }
             let direction = if mov.dst.file == 'g' { 1 } else { -1 };
             let through_file = (mov.src.file as i8 + direction) as u8 as char;
             let through_tile = Tile::new(through_file, mov.src.rank).unwrap();
             if self.is_attacked(through_tile, !self.active_player) {
-                return Err(ChessError::IllegalMove(mov));
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Get all moves of the active player by the movement rules of the pieces, without testing
+    /// for check. Pawn moves to the last rank are expanded into the four promotions.
+    pub fn pseudo_legal_moves(&self) -> Vec<ChessMove> {
+        let mut moves = vec![];
+        for src in Tile::all() {
+            let Some(p) = self[src] else {
+                continue;
+            };
+            if p.color != self.active_player {
+                continue;
+            }
+            for dst in self.get_moves(src) {
+                if p.typ == ChessPiece::Pawn && (dst.rank == '8' || dst.rank == '1') {
+                    for promotion in [
+                        Promotion::Queen,
+                        Promotion::Knight,
+                        Promotion::Rook,
+                        Promotion::Bishop,
+                    ] {
+                        moves.push(ChessMove {
+                            src,
+                            dst,
+                            special: Some(promotion),
+                        });
+                    }
+                } else {
+                    moves.push(ChessMove {
+                        src,
+                        dst,
+                        special: None,
+                    });
+                }
             }
         }
+        moves
+    }
+
+    /// Get all legal moves of the active player.
+    pub fn legal_moves(&self) -> Vec<ChessMove> {
+        self.pseudo_legal_moves()
+            .into_iter()
+            .filter(|mov| self.is_legal_move(mov))
+            .collect()
+    }
+
+    /// Half-moves since the last capture or pawn move.
+    pub fn half_moves(&self) -> usize {
+        self.half_moves
+    }
+
+    /// The piece a move takes off the board, if any; en passant takes the pawn next to `dst`.
+    pub fn captured_piece(&self, mov: &ChessMove) -> Option<WoodPiece> {
+        if let Some(p) = self[mov.dst] {
+            return Some(p.piece);
+        }
+        let pawn = self[mov.src].is_some_and(|p| p.typ == ChessPiece::Pawn);
+        if pawn && self.en_passant == Some(mov.dst) {
+            return Some(WoodPiece::new(ChessPiece::Pawn, !self.active_player));
+        }
+        None
+    }
+
+    /// Test if the active player may drop a piece onto a tile (bughouse): the tile is empty,
+    /// pawns don't go to the first or last rank, and the own king isn't left in check.
+    /// Whether the piece is in hand is up to the caller.
+    pub fn is_legal_drop(&self, drop: &ChessDrop) -> bool {
+        let (typ, dst) = (drop.typ, drop.dst);
+        if self[dst].is_some() || typ == ChessPiece::King {
+            return false;
+        }
+        if typ == ChessPiece::Pawn && (dst.rank == '8' || dst.rank == '1') {
+            return false;
+        }
+        let mut simulation = self.clone();
+        simulation[dst] = piece!(WoodPiece::new(typ, self.active_player).as_byte());
+        !simulation.is_in_check(self.active_player)
+    }
+
+    /// Drop a piece of the active player onto the board. Returns the changed tile, like
+    /// `make_move()` does.
+    pub fn drop_piece(&mut self, drop: ChessDrop) -> ChessResult<Vec<(Tile, Option<Piece>)>> {
+        if !self.is_legal_drop(&drop) {
+            return Err(ChessError::IllegalDrop(drop));
+        }
+        let dst = drop.dst;
+        let piece = piece!(WoodPiece::new(drop.typ, self.active_player).as_byte());
+        let prev_en_passant = self.en_passant_capturable();
+
+        self[dst] = piece;
+        self.en_passant = None;
+        if self.active_player == ChessColor::White {
+            self.full_moves += 1;
+        }
+        self.half_moves += 1;
+        self.active_player = !self.active_player;
+
+        self.hash.update_hash(
+            vec![(dst, None, piece)],
+            self.castle_rights,
+            self.castle_rights,
+            prev_en_passant,
+            None,
+            true,
+        );
+        Ok(vec![(dst, piece)])
+    }
+
+    /// Execute a move on the board.
+    /// Returns a vector of tiles that have been changed. This approach is helpful for
+    /// en passant and castling, where more tiles  than the src and dest tiles are affected.
+    ///
+    ///
+    pub fn make_move(&mut self, mov: ChessMove) -> ChessResult<Vec<(Tile, Option<Piece>)>> {
+        if !self.is_legal_move(&mov) {
+            return Err(ChessError::IllegalMove(mov));
+        }
+        self.apply_move(mov)
+    }
+
+    /// Execute a move that is already known to be legal, e.g. one from `legal_moves()`.
+    /// Same as `make_move()`, but skips the validation. Used by the engine, which generates
+    /// legal moves anyway and doesn't want to pay for the check test twice.
+    pub fn apply_move(&mut self, mov: ChessMove) -> ChessResult<Vec<(Tile, Option<Piece>)>> {
+        let src = mov.src;
+        let dst = mov.dst;
+        let Some(p) = self[src] else {
+            return Err(ChessError::IllegalMove(mov));
+        };
 
         let is_capture = self[dst].is_some();
-        let is_pawn_move = self[src].unwrap().typ == ChessPiece::Pawn;
+        let is_pawn_move = p.typ == ChessPiece::Pawn;
 
         let prev_castle_rights = self.castle_rights.clone();
-        let prev_en_passant = self.en_passant.clone();
+        let prev_en_passant = self.en_passant_capturable();
 
         let diff_tiles = self.make_move_unchecked(mov)?;
         let updated_tiles: Vec<(Tile, Option<Piece>)> = diff_tiles
@@ -581,12 +739,13 @@ impl Chess {
         // it's the opponents' turn now
         self.active_player = !self.active_player;
 
+        let en_passant = self.en_passant_capturable();
         self.hash.update_hash(
             diff_tiles,
             prev_castle_rights,
             self.castle_rights,
             prev_en_passant,
-            self.en_passant,
+            en_passant,
             true,
         );
 
@@ -833,4 +992,29 @@ mod tests {
         game.make_move("b1c3".parse().unwrap()).unwrap();
         assert!(game.is_repetition());
     }
+
+    #[test]
+    fn test_drops() {
+        // Black checks with the queen along the diagonal; white may block by dropping
+        let mut game = Chess::load_fen("4k3/8/8/q7/8/8/8/4K3 w - - 0 1");
+        let drop = |s: &str| s.parse::<ChessDrop>().unwrap();
+        assert!(!game.is_legal_drop(&drop("N@h3")));
+        assert!(!game.is_legal_drop(&drop("N@a5")));
+        assert!(game.is_legal_drop(&drop("N@d2")) && game.is_legal_drop(&drop("N@c3")));
+        // pawns never on the first or last rank
+        assert!(!game.is_legal_drop(&drop("P@d1")));
+        assert!(game.is_legal_drop(&drop("P@c3")));
+
+        let changes = game.drop_piece(drop("B@b4")).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(game.active_player, ChessColor::Black);
+        assert_eq!(game.get_fen(), "4k3/8/8/q7/1B6/8/8/4K3 b - - 1 2");
+
+        // the queen takes what got dropped
+        let mov: ChessMove = "a5b4".parse().unwrap();
+        assert_eq!(
+            game.captured_piece(&mov).map(|p| p.typ),
+            Some(ChessPiece::Bishop)
+        );
+    }
 }
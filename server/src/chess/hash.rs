@@ -1,62 +1,47 @@
-use rand::Rng;
-
 use chess_core::piece::Id;
 use chess_core::{ChessColor, Tile};
 
 use crate::chess::pieces::Piece;
+use crate::chess::random64::RANDOM64;
+
+const CASTLE_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
 
 #[derive(Clone)]
 pub struct ZobristHash {
-    is_black: u64,
-    table: [[u64; 12]; 64],
-    castle_rights: [u64; 4],
-    en_passant_file: [u64; 8],
-
     pub hash_list: Vec<u64>,
 }
 
 /// Initialize the Zobrist Hash.
-/// Zobrist Hash generates a set of random numbers to uniquely identify the state of a chess board.
+/// Zobrist Hash uses a set of random numbers to uniquely identify the state of a chess board.
 /// Basically, we need a random number for every "state atom". That is, for the active player,
 /// for the 4 castling rights, for 8 en-passant files, and for each piece type and color combination.
 ///
-/// That is, we generate 1 + 4 + 8 + 12 * 64 = 781 random numbers.
+/// That is, we need 1 + 4 + 8 + 12 * 64 = 781 random numbers.
 /// We XOR those numbers where the corresponding fields are set, that is
-/// - Black is active
+/// - White is active
 /// - Castling bits are set
-/// - En passant is on that file
+/// - En passant is on that file (only if a pawn can actually capture en passant)
 /// - A piece of type 1..12 is on square 1..64
+///
+/// The random numbers are the fixed ones from the Polyglot book format (see `RANDOM64`),
+/// so our hashes are the standard Polyglot keys and can be used to look up opening books.
 impl ZobristHash {
     pub fn new() -> Self {
-        let mut rng = rand::rng();
-
-        let is_black = rng.next_u64();
-
-        let mut table = [[0; 12]; 64];
-        for i in 0..64 {
-            for j in 0..12 {
-                table[i][j] = rng.next_u64();
-            }
-        }
-
-        let mut castle_rights = [0; 4];
-        for i in 0..4 {
-            castle_rights[i] = rng.next_u64();
-        }
-
-        let mut en_passant_file = [0; 8];
-        for i in 0..8 {
-            en_passant_file[i] = rng.next_u64();
-        }
+        Self { hash_list: vec![] }
+    }
 
-        Self {
-            is_black,
-            table,
-            castle_rights,
-            en_passant_file,
+    /// random number of a piece on a tile.
+    /// Polyglot counts squares from a1, while our tile index starts at a8.
+    fn piece_key(tile_idx: usize, piece: &Piece) -> u64 {
+        let square = tile_idx ^ 56;
+        RANDOM64[64 * piece.piece.id() as usize + square]
+    }
 
-            hash_list: vec![],
-        }
+    fn en_passant_key(tile: Tile) -> u64 {
+        let f = tile.file as u8 - 97;
+        RANDOM64[EN_PASSANT_OFFSET + f as usize]
     }
 
     pub fn compute_full_hash(
@@ -68,26 +53,30 @@ impl ZobristHash {
     ) {
         let mut hash = 0;
 
-        if active_player == ChessColor::Black {
-            hash = hash ^ self.is_black;
+        if active_player == ChessColor::White {
+            hash ^= RANDOM64[TURN_OFFSET];
         }
         for (i, &rights) in castle_rights.iter().enumerate() {
-            hash = hash ^ (self.castle_rights[i] * if rights { 1 } else { 0 });
+            if rights {
+                hash ^= RANDOM64[CASTLE_OFFSET + i];
+            }
         }
         if let Some(t) = en_passant {
-            let f = t.file as u8 - 97;
-            hash = hash ^ self.en_passant_file[f as usize];
+            hash ^= Self::en_passant_key(t);
         };
 
         for (i, t) in tiles.iter().enumerate() {
             if let Some(p) = t {
-                hash = hash ^ self.table[i][p.piece.id() as usize];
+                hash ^= Self::piece_key(i, p);
             }
         }
 
         self.hash_list.push(hash);
     }
 
+    /// Update the hash incrementally after a move.
+    /// The en passant tiles must only be given if they are relevant for hashing,
+    /// see `Chess::en_passant_capturable()`.
     pub fn update_hash(
         &mut self,
         tiles: Vec<(Tile, Option<Piece>, Option<Piece>)>, // (Tile, old_piece, new_piece)
@@ -105,32 +94,30 @@ impl ZobristHash {
         };
 
         if turn_changed {
-            hash ^= self.is_black;
+            hash ^= RANDOM64[TURN_OFFSET];
         }
 
         for i in 0..4 {
             if old_castle_rights[i] != new_castle_rights[i] {
-                hash ^= self.castle_rights[i];
+                hash ^= RANDOM64[CASTLE_OFFSET + i];
             }
         }
 
         if let Some(t) = old_en_passant {
-            let f = t.file as u8 - 97;
-            hash ^= self.en_passant_file[f as usize];
+            hash ^= Self::en_passant_key(t);
         }
 
         if let Some(t) = new_en_passant {
-            let f = t.file as u8 - 97;
-            hash ^= self.en_passant_file[f as usize];
+            hash ^= Self::en_passant_key(t);
         }
 
         for (tile, old_piece, new_piece) in tiles {
-            let idx = tile.to_index();
+            let idx = tile.to_index() as usize;
             if let Some(p) = old_piece {
-                hash ^= self.table[idx as usize][p.piece.id() as usize];
+                hash ^= Self::piece_key(idx, &p);
             }
             if let Some(p) = new_piece {
-                hash ^= self.table[idx as usize][p.piece.id() as usize];
+                hash ^= Self::piece_key(idx, &p);
             }
         }
         self.hash_list.push(hash);
@@ -172,3 +159,41 @@ fn test_zobrist_hash() {
         "Hash should be the same after Nc3Nc6Nb1Nb8"
     );
 }
+
+#[test]
+fn test_zobrist_hash_polyglot_keys() {
+    use crate::chess::chess::Chess;
+
+    // reference keys from the Polyglot book format specification
+    let mut chess = Chess::new();
+    assert_eq!(chess.hash.get_current_hash(), 0x463b96181691fc9c);
+
+    let moves = [
+        ("e2e4", 0x823c9b50fd114196),
+        ("d7d5", 0x0756b94461c50fb0),
+        ("e4e5", 0x662fafb965db29d4),
+        ("f7f5", 0x22a48b5a8e47ff78),
+        ("e1e2", 0x652a607ca3f242c1),
+        ("e8f7", 0x00fdd303c946bdd9),
+    ];
+    for (mov, key) in moves {
+        chess.make_move(mov.parse().unwrap()).unwrap();
+        assert_eq!(chess.hash.get_current_hash(), key, "after {}", mov);
+    }
+
+    let mut chess = Chess::new();
+    for mov in ["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"] {
+        chess.make_move(mov.parse().unwrap()).unwrap();
+    }
+    assert_eq!(chess.hash.get_current_hash(), 0x3c8123ea7b067637);
+    chess.make_move("b4c3".parse().unwrap()).unwrap();
+    chess.make_move("a1a3".parse().unwrap()).unwrap();
+    assert_eq!(chess.hash.get_current_hash(), 0x5c3f9b829b279560);
+
+    // the full hash of a loaded position must match the incrementally updated one
+    let loaded = Chess::load_fen(&chess.get_fen());
+    assert_eq!(
+        loaded.hash.get_current_hash(),
+        chess.hash.get_current_hash()
+    );
+}
@@ -1,4 +1,7 @@
 pub mod chess;
 mod hash;
+pub mod pgn;
 pub mod pieces;
+pub mod polyglot;
+mod random64;
 pub mod san;
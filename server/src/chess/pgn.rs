@@ -0,0 +1,267 @@
+use crate::chess::chess::Chess;
+use crate::chess::san::San;
+use chess_core::{ChessMove, GameTree};
+
+/// A single game read from a PGN file.
+/// Only the tag pairs and the mainline are kept. Comments, variations and NAGs are skipped;
+/// `GameTree::from_pgn` keeps them.
+#[derive(Debug, Clone, Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>, // SAN of the mainline
+    pub result: String,
+}
+
+impl PgnGame {
+    /// Get the value of a tag pair, e.g. `tag("White")`.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position the game starts from.
+    /// Games can start from a custom position given by the `FEN` tag.
+    pub fn start_position(&self) -> Chess {
+        match self.tag("FEN") {
+            Some(fen) => Chess::load_fen(fen),
+            None => Chess::new(),
+        }
+    }
+
+    /// Replays the mainline and converts it into `ChessMove`s.
+    /// Fails with the offending SAN if a move can't be resolved or is illegal.
+    pub fn replay(&self) -> Result<Vec<ChessMove>, String> {
+        let mut chess = self.start_position();
+        let mut moves = Vec::with_capacity(self.moves.len());
+        for san in &self.moves {
+            let mov = ChessMove::from_san(&chess, san).ok_or_else(|| san.clone())?;
+            chess.make_move(mov).map_err(|_| san.clone())?;
+            moves.push(mov);
+        }
+        Ok(moves)
+    }
+}
+
+/// Parses all games of a PGN text.
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = vec![];
+    let mut game = PgnGame::default();
+    let mut in_movetext = false;
+
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            // tag pair, e.g. [White "Carlsen"]
+            '[' => {
+                // a tag after the movetext starts a new game (if the previous one had no result)
+                if in_movetext {
+                    games.push(std::mem::take(&mut game));
+                    in_movetext = false;
+                }
+                chars.next();
+                let tag: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                if let Some((key, value)) = tag.split_once(' ') {
+                    let value = value.trim().trim_matches('"').to_string();
+                    game.tags.push((key.trim().to_string(), value));
+                }
+            }
+            // comment until closing brace
+            '{' => {
+                chars.by_ref().find(|c| *c == '}');
+            }
+            // comment until end of line
+            ';' => {
+                chars.by_ref().find(|c| *c == '\n');
+            }
+            // variation, may be nested
+            '(' => {
+                let mut depth = 0;
+                for c in chars.by_ref() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[{;(".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                in_movetext = true;
+
+                match token.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                        game.result = token;
+                        games.push(std::mem::take(&mut game));
+                        in_movetext = false;
+                    }
+                    // numeric annotation glyph, e.g. $1
+                    _ if token.starts_with('$') => {}
+                    _ => {
+                        // strip move numbers ("12." or "12...") and annotations ("!?")
+                        let san =
+                            token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                        let san = san.trim_end_matches(['!', '?']);
+                        if !san.is_empty() {
+                            game.moves.push(san.to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if in_movetext || !game.tags.is_empty() {
+        games.push(game);
+    }
+
+    games
+}
+
+/// Work out the moves of a tree read from PGN, with the positions they lead to.
+/// Fails with the offending SAN if a move can't be resolved or is illegal, in any variation.
+pub fn resolve_tree(tree: &mut GameTree) -> Result<(), String> {
+    let start = Chess::load_fen(tree.start_fen());
+    tree.get_mut(GameTree::ROOT).unwrap().hash = start.hash.get_current_hash();
+    let mut todo = vec![(GameTree::ROOT, start)];
+    while let Some((id, chess)) = todo.pop() {
+        let children = tree.get(id).map(|n| n.children.clone()).unwrap_or_default();
+        for child in children {
+            let node = tree.get_mut(child).unwrap();
+            let mut next = chess.clone();
+            let mov = ChessMove::from_san(&next, &node.san).ok_or_else(|| node.san.clone())?;
+            next.make_move(mov).map_err(|_| node.san.clone())?;
+            node.mov = Some(mov);
+            node.fen = next.get_fen();
+            node.hash = next.hash.get_current_hash();
+            todo.push((child, next));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pgn() {
+        let text = r#"
+[Event "Test"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {the knight} Nc6 (2... d6 3. d4) 3. Bb5 $1 a6?! 1-0
+
+[Event "Second"]
+[Result "*"]
+
+1.d4 d5 *
+"#;
+        let games = parse_pgn(text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("White"), Some("A"));
+        assert_eq!(games[0].moves, vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].moves, vec!["d4", "d5"]);
+
+        let moves = games[0].replay().unwrap();
+        assert_eq!(moves.len(), 6);
+        assert_eq!(moves[4].to_string(), "f1b5");
+    }
+
+    #[test]
+    fn test_game_tree_pgn() {
+        let text = r#"[Event "Test"]
+[Result "1-0"]
+
+{A short one} 1. e4 {[%clk 0:05:00]} e5 2. Nf3 $1 Nc6 (2... d6 {Philidor} 3. d4 (3. Bc4)) 3. Bb5!? a6?! 1-0
+"#;
+        let mut tree = GameTree::from_pgn(text).unwrap();
+        assert_eq!(tree.tag("Event"), Some("Test"));
+        assert_eq!(tree.result, "1-0");
+        let mainline = tree.mainline();
+        let san = |tree: &GameTree, line: &[usize]| -> Vec<String> {
+            line.iter()
+                .map(|&n| tree.get(n).unwrap().san.clone())
+                .collect()
+        };
+        assert_eq!(
+            san(&tree, &mainline),
+            vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]
+        );
+        let e4 = tree.get(mainline[0]).unwrap();
+        assert_eq!(e4.clock, Some(300_000));
+        assert_eq!(tree.get(GameTree::ROOT).unwrap().comment, "A short one");
+        assert_eq!(tree.get(mainline[2]).unwrap().nags, vec![1]);
+        assert_eq!(tree.get(mainline[4]).unwrap().nags, vec![5]);
+        assert_eq!(tree.get(mainline[5]).unwrap().nags, vec![6]);
+
+        // the variation after 2. Nf3, with one of its own
+        let d6 = tree.get(mainline[2]).unwrap().children[1];
+        assert_eq!(tree.get(d6).unwrap().comment, "Philidor");
+        let d4 = tree.get(d6).unwrap().children[0];
+        let bc4 = tree.get(d6).unwrap().children[1];
+        assert_eq!(
+            san(&tree, &tree.line_to(bc4)),
+            vec!["e4", "e5", "Nf3", "d6", "Bc4"]
+        );
+
+        resolve_tree(&mut tree).unwrap();
+        let mut chess = Chess::new();
+        for mov in ["e2e4", "e7e5", "g1f3", "d7d6", "f1c4"] {
+            chess.make_move(mov.parse().unwrap()).unwrap();
+        }
+        let node = tree.get(bc4).unwrap();
+        assert_eq!(node.mov, Some("f1c4".parse().unwrap()));
+        assert_eq!(node.fen, chess.get_fen());
+        assert_eq!(node.hash, chess.hash.get_current_hash());
+
+        // written out and read again, it is the same tree
+        let pgn = tree.to_pgn();
+        assert!(
+            pgn.contains("3. d4 (3. Bc4)) 3. Bb5 $5 a6 $6 1-0"),
+            "{}",
+            pgn
+        );
+        let mut again = GameTree::from_pgn(&pgn).unwrap();
+        resolve_tree(&mut again).unwrap();
+        assert_eq!(again, tree);
+
+        // promote the variation to the mainline, then delete it
+        tree.make_mainline(bc4);
+        assert_eq!(
+            san(&tree, &tree.mainline()),
+            vec!["e4", "e5", "Nf3", "d6", "Bc4"]
+        );
+        assert!(tree.promote(d4));
+        assert_eq!(tree.get(d6).unwrap().children, vec![d4, bc4]);
+        assert!(!tree.promote(d4));
+        assert!(tree.delete(d6));
+        assert!(tree.get(bc4).is_none());
+        assert_eq!(
+            san(&tree, &tree.mainline()),
+            vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]
+        );
+        assert!(!tree.delete(GameTree::ROOT));
+
+        // a move that isn't legal in its variation
+        let mut bad = GameTree::from_pgn("1. e4 e5 (1... Nf3) *").unwrap();
+        assert_eq!(resolve_tree(&mut bad), Err("Nf3".to_string()));
+        assert!(GameTree::from_pgn("1. e4 (e5 *").is_err());
+    }
+}
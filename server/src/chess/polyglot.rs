@@ -0,0 +1,327 @@
+use crate::chess::chess::Chess;
+use crate::chess::pgn::PgnGame;
+use chess_core::{ChessMove, ChessPiece, Promotion, Tile};
+use rand::RngExt;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// One entry of a Polyglot opening book.
+/// A book is a sequence of these 16 byte entries, sorted by key. All numbers are big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,    // Polyglot hash of the position, see `ZobristHash`
+    pub mov: u16,    // encoded move, see `encode_move()`
+    pub weight: u16, // how good the move is; relative to the other moves of the position
+    pub learn: u32,  // unused by us
+}
+
+impl BookEntry {
+    pub const SIZE: usize = 16;
+
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        BookEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mov: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mov.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+/// Polyglot counts squares from a1 (0) to h8 (63).
+fn square_of(tile: Tile) -> u16 {
+    (tile.rank as u16 - '1' as u16) * 8 + (tile.file as u16 - 'a' as u16)
+}
+
+fn tile_of(square: u16) -> Tile {
+    let file = (b'a' + (square % 8) as u8) as char;
+    let rank = (b'1' + (square / 8) as u8) as char;
+    Tile::new(file, rank).unwrap()
+}
+
+fn is_castling(chess: &Chess, mov: &ChessMove) -> bool {
+    matches!(chess.peek(mov.src), Some(p) if p.typ == ChessPiece::King)
+        && mov.src.file == 'e'
+        && (mov.dst.file == 'g' || mov.dst.file == 'c')
+        && mov.src.rank == mov.dst.rank
+}
+
+/// Encodes a move in the Polyglot format:
+/// bits 0-5 destination square, bits 6-11 source square, bits 12-14 promotion piece.
+/// Castling is encoded as "king captures own rook", e.g. e1h1 instead of e1g1.
+pub fn encode_move(chess: &Chess, mov: ChessMove) -> u16 {
+    let mut dst = mov.dst;
+    if is_castling(chess, &mov) {
+        let rook_file = if mov.dst.file == 'g' { 'h' } else { 'a' };
+        dst = Tile::new(rook_file, mov.dst.rank).unwrap();
+    }
+    let promotion = match mov.special {
+        None => 0,
+        Some(Promotion::Knight) => 1,
+        Some(Promotion::Bishop) => 2,
+        Some(Promotion::Rook) => 3,
+        Some(Promotion::Queen) => 4,
+    };
+    square_of(dst) | (square_of(mov.src) << 6) | (promotion << 12)
+}
+
+/// Decodes a Polyglot move for the given position.
+/// Returns `None` for invalid encodings.
+pub fn decode_move(chess: &Chess, raw: u16) -> Option<ChessMove> {
+    let src = tile_of((raw >> 6) & 0x3F);
+    let mut dst = tile_of(raw & 0x3F);
+    let special = match (raw >> 12) & 0x7 {
+        0 => None,
+        1 => Some(Promotion::Knight),
+        2 => Some(Promotion::Bishop),
+        3 => Some(Promotion::Rook),
+        4 => Some(Promotion::Queen),
+        _ => return None,
+    };
+
+    // king captures own rook: this is castling
+    if let (Some(king), Some(rook)) = (chess.peek(src), chess.peek(dst)) {
+        if king.typ == ChessPiece::King
+            && rook.typ == ChessPiece::Rook
+            && king.color == rook.color
+            && src.file == 'e'
+        {
+            let file = if dst.file == 'h' { 'g' } else { 'c' };
+            dst = Tile::new(file, dst.rank)?;
+        }
+    }
+
+    Some(ChessMove { src, dst, special })
+}
+
+/// A Polyglot opening book (`.bin`).
+/// The book is read into memory completely; even big books are only a few MB.
+pub struct OpeningBook {
+    entries: Vec<BookEntry>,
+}
+
+impl OpeningBook {
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    /// Reads a book from its raw bytes. A trailing incomplete entry is ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(BookEntry::SIZE)
+            .map(|chunk| BookEntry::from_bytes(chunk.try_into().unwrap()))
+            .collect();
+        // books are sorted by definition, but we rely on it for the binary search
+        entries.sort_by_key(|e| e.key);
+        OpeningBook { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All entries of a position, found by binary search on the key.
+    fn entries_for(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|e| e.key < key);
+        let end = self.entries.partition_point(|e| e.key <= key);
+        &self.entries[start..end]
+    }
+
+    /// Looks up the book moves for the current position together with their weights.
+    /// Moves are sorted by weight (best first). Illegal moves of broken books are skipped.
+    pub fn lookup(&self, chess: &Chess) -> Vec<(ChessMove, u16)> {
+        let key = chess.hash.get_current_hash();
+        let mut moves: Vec<(ChessMove, u16)> = self
+            .entries_for(key)
+            .iter()
+            .filter_map(|e| decode_move(chess, e.mov).map(|m| (m, e.weight)))
+            .filter(|(m, _)| chess.clone().make_move(*m).is_ok())
+            .collect();
+        moves.sort_by_key(|(_, weight)| std::cmp::Reverse(*weight));
+        moves
+    }
+
+    /// True if the move is a book move in the current position.
+    pub fn contains(&self, chess: &Chess, mov: ChessMove) -> bool {
+        let key = chess.hash.get_current_hash();
+        let raw = encode_move(chess, mov);
+        self.entries_for(key).iter().any(|e| e.mov == raw)
+    }
+
+    /// Picks a book move at random, weighted by the move weights.
+    /// Returns `None` if the position is not in the book.
+    pub fn pick_move(&self, chess: &Chess) -> Option<ChessMove> {
+        let moves = self.lookup(chess);
+        if moves.is_empty() {
+            return None;
+        }
+        let mut rng = rand::rng();
+        let total: u32 = moves.iter().map(|(_, w)| *w as u32).sum();
+        if total == 0 {
+            return Some(moves[rng.random_range(0..moves.len())].0);
+        }
+        let mut pick = rng.random_range(0..total);
+        for (mov, weight) in &moves {
+            if pick < *weight as u32 {
+                return Some(*mov);
+            }
+            pick -= *weight as u32;
+        }
+        None
+    }
+}
+
+/// Builds a Polyglot book from PGN games.
+/// Every position of the first `max_ply` plies gets an entry for the move played. The weight
+/// of a move is the score of the side that played it over all games, 2 for a win and 1 for a draw.
+pub struct BookWriter {
+    max_ply: usize,
+    scores: HashMap<(u64, u16), u32>,
+}
+
+impl BookWriter {
+    pub fn new(max_ply: usize) -> Self {
+        BookWriter {
+            max_ply,
+            scores: HashMap::new(),
+        }
+    }
+
+    /// Adds the mainline of a game to the book.
+    /// Fails with the offending SAN if the game contains an illegal move.
+    pub fn add_game(&mut self, game: &PgnGame) -> Result<(), String> {
+        let moves = game.replay()?;
+        // score for (white, black)
+        let (white, black) = match game.result.as_str() {
+            "1-0" => (2, 0),
+            "0-1" => (0, 2),
+            _ => (1, 1),
+        };
+
+        let mut chess = game.start_position();
+        for mov in moves.into_iter().take(self.max_ply) {
+            let key = chess.hash.get_current_hash();
+            let score = match chess.active_player {
+                chess_core::ChessColor::White => white,
+                chess_core::ChessColor::Black => black,
+            };
+            *self
+                .scores
+                .entry((key, encode_move(&chess, mov)))
+                .or_insert(0) += score;
+            chess.make_move(mov).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Serializes the book. Weights are scaled down if they don't fit into 16 bits.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let max = self.scores.values().copied().max().unwrap_or(0);
+        let scale = |w: u32| {
+            if max > u16::MAX as u32 {
+                (w as u64 * u16::MAX as u64 / max as u64) as u16
+            } else {
+                w as u16
+            }
+        };
+
+        let mut entries: Vec<BookEntry> = self
+            .scores
+            .iter()
+            .map(|(&(key, mov), &weight)| BookEntry {
+                key,
+                mov,
+                weight: scale(weight),
+                learn: 0,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+
+        entries.iter().flat_map(|e| e.to_bytes()).collect()
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::pgn::parse_pgn;
+
+    #[test]
+    fn test_move_encoding() {
+        let chess = Chess::new();
+        let mov: ChessMove = "e2e4".parse().unwrap();
+        let raw = encode_move(&chess, mov);
+        assert_eq!(raw, 28 | (12 << 6));
+        assert_eq!(decode_move(&chess, raw), Some(mov));
+
+        // castling is encoded as king takes rook
+        let chess = Chess::load_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let castle: ChessMove = "e1g1".parse().unwrap();
+        let raw = encode_move(&chess, castle);
+        assert_eq!(raw, 7 | (4 << 6));
+        assert_eq!(decode_move(&chess, raw), Some(castle));
+        let castle: ChessMove = "e1c1".parse().unwrap();
+        assert_eq!(
+            decode_move(&chess, encode_move(&chess, castle)),
+            Some(castle)
+        );
+
+        let chess = Chess::load_fen("8/4P3/8/8/8/8/8/k6K w - - 0 1");
+        let promotion: ChessMove = "e7e8N".parse().unwrap();
+        let raw = encode_move(&chess, promotion);
+        assert_eq!(decode_move(&chess, raw), Some(promotion));
+    }
+
+    #[test]
+    fn test_write_and_read_book() {
+        let games = parse_pgn(
+            "1. e4 e5 2. Nf3 1-0\n\n1. e4 c5 0-1\n\n1. d4 d5 1/2-1/2\n\n1. e4 e5 2. Nf3 1-0",
+        );
+        let mut writer = BookWriter::new(10);
+        for game in &games {
+            writer.add_game(game).unwrap();
+        }
+        let bytes = writer.to_bytes();
+        assert_eq!(bytes.len() % BookEntry::SIZE, 0);
+
+        let book = OpeningBook::from_bytes(&bytes);
+        // start: e4 (2 + 0 + 2), d4 (1); after e4: e5 (0 + 0), c5 (2)
+        assert_eq!(book.len(), 6);
+
+        let chess = Chess::new();
+        let moves = book.lookup(&chess);
+        let moves: Vec<(String, u16)> = moves.iter().map(|(m, w)| (m.to_string(), *w)).collect();
+        assert_eq!(
+            moves,
+            vec![("e2e4".to_string(), 4), ("d2d4".to_string(), 1)]
+        );
+        assert!(book.contains(&chess, "e2e4".parse().unwrap()));
+        assert!(!book.contains(&chess, "c2c4".parse().unwrap()));
+
+        let picked = book.pick_move(&chess).unwrap().to_string();
+        assert!(picked == "e2e4" || picked == "d2d4");
+
+        let mut chess = Chess::new();
+        chess.make_move("c2c4".parse().unwrap()).unwrap();
+        assert!(book.lookup(&chess).is_empty());
+        assert!(book.pick_move(&chess).is_none());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::chess::chess::Chess;
-use chess_core::{ChessColor, ChessMove, ChessPiece, Promotion, Tile};
+use chess_core::{ChessColor, ChessDrop, ChessMove, ChessPiece, Promotion, Tile};
 
 pub trait San {
     fn to_san(&self, board: &Chess) -> String;
@@ -262,6 +262,19 @@ impl San for ChessMove {
     }
 }
 
+/// The SAN of a drop (bughouse): "N@f3", with a "+" if it gives check. Whether a check
+/// is mate depends on the pockets, so there is no "#".
+pub fn drop_to_san(drop: &ChessDrop, board: &Chess) -> String {
+    let mut next_board = board.clone();
+    if next_board.drop_piece(*drop).is_err() {
+        return String::new();
+    }
+    match next_board.is_in_check(next_board.active_player) {
+        true => format!("{}+", drop),
+        false => drop.to_string(),
+    }
+}
+
 fn resolve_castle(board: &Chess, kingside: bool) -> Option<ChessMove> {
     let color = board.active_player;
     let rank = if color == ChessColor::White { '1' } else { '8' };
@@ -0,0 +1,176 @@
+use crate::chess::chess::Chess;
+use chess_core::{ChessColor, ChessPiece};
+
+/// Material values in centipawns.
+pub fn piece_value(typ: ChessPiece) -> i32 {
+    match typ {
+        ChessPiece::Pawn => 100,
+        ChessPiece::Knight => 320,
+        ChessPiece::Bishop => 330,
+        ChessPiece::Rook => 500,
+        ChessPiece::Queen => 900,
+        ChessPiece::King => 0,
+    }
+}
+
+/// Piece-square tables, seen from White, indexed like `Chess::tiles` (index 0 = a8).
+/// Values for Black are looked up on the mirrored tile.
+/// These are the well-known tables of Tomasz Michniewski's "Simplified Evaluation Function".
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_MIDDLEGAME_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+/// Game phase weight of the pieces. The sum over the starting position is `MAX_PHASE`,
+/// a bare board is 0. Used to blend between middlegame and endgame king tables.
+fn phase_weight(typ: ChessPiece) -> i32 {
+    match typ {
+        ChessPiece::Knight | ChessPiece::Bishop => 1,
+        ChessPiece::Rook => 2,
+        ChessPiece::Queen => 4,
+        ChessPiece::Pawn | ChessPiece::King => 0,
+    }
+}
+
+const MAX_PHASE: i32 = 24;
+
+/// Static evaluation of a position: material plus piece-square tables.
+/// The score is in centipawns from the view of the active player, i.e., positive is good for
+/// the side to move (as needed by negamax).
+pub fn evaluate(chess: &Chess) -> i32 {
+    let mut score = 0; // from White's view
+    let mut king_middlegame = 0;
+    let mut king_endgame = 0;
+    let mut phase = 0;
+
+    for (idx, tile) in chess.tiles.iter().enumerate() {
+        let Some(p) = tile else {
+            continue;
+        };
+        let (sign, sq) = match p.color {
+            ChessColor::White => (1, idx),
+            ChessColor::Black => (-1, idx ^ 56),
+        };
+
+        phase += phase_weight(p.typ);
+        let positional = match p.typ {
+            ChessPiece::Pawn => PAWN_TABLE[sq],
+            ChessPiece::Knight => KNIGHT_TABLE[sq],
+            ChessPiece::Bishop => BISHOP_TABLE[sq],
+            ChessPiece::Rook => ROOK_TABLE[sq],
+            ChessPiece::Queen => QUEEN_TABLE[sq],
+            ChessPiece::King => {
+                king_middlegame += sign * KING_MIDDLEGAME_TABLE[sq];
+                king_endgame += sign * KING_ENDGAME_TABLE[sq];
+                0
+            }
+        };
+        score += sign * (piece_value(p.typ) + positional);
+    }
+
+    // tapered king safety: tucked away while there is material on the board,
+    // centralized once the board empties.
+    let phase = phase.min(MAX_PHASE);
+    score += (king_middlegame * phase + king_endgame * (MAX_PHASE - phase)) / MAX_PHASE;
+
+    match chess.active_player {
+        ChessColor::White => score,
+        ChessColor::Black => -score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        // symmetric start position
+        assert_eq!(evaluate(&Chess::new()), 0);
+
+        // White is a queen up; good for White, bad for Black when it is Black's turn
+        let chess = Chess::load_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert!(evaluate(&chess) > 800);
+        let chess = Chess::load_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1");
+        assert!(evaluate(&chess) < -800);
+    }
+}
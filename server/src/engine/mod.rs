@@ -0,0 +1,16 @@
+pub mod eval;
+pub mod search;
+pub mod tablebase;
+mod tt;
+pub mod uci;
+pub mod uci_frontend;
+pub mod xboard;
+
+pub use search::{perft, Engine, SearchLimits};
+
+/// An external engine binary and the protocol it speaks.
+#[derive(Clone, Debug)]
+pub enum ExternalEngine {
+    Uci(String),
+    XBoard(String),
+}
@@ -0,0 +1,581 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::chess::chess::Chess;
+use crate::engine::eval::{evaluate, piece_value};
+use crate::engine::tt::{Bound, TranspositionTable, TtEntry};
+use chess_core::{ChessMove, ChessPiece, Promotion, Score};
+
+/// Score of being checkmated right now. Mate in N plies is scored `MATE - N`,
+/// so the engine prefers the fastest mate and the slowest defeat.
+const MATE: i32 = 30_000;
+const INFINITY: i32 = 32_000;
+
+/// Hard limit for the search depth in plies, including extensions and quiescence.
+const MAX_PLY: usize = 64;
+/// Iterative deepening stops at this depth if no other limit is given.
+const MAX_DEPTH: u8 = 32;
+
+/// Highest level of the computer players.
+pub const MAX_LEVEL: u8 = 8;
+
+/// Under a clock without a known number of moves, we assume the game lasts this many more moves.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+/// Time we keep in reserve for the transport of our move.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+/// Limits of a single search. The search ends as soon as the first limit is hit;
+/// without any limit it runs until `MAX_DEPTH`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+}
+
+impl SearchLimits {
+    /// Limits for a computer player of the given level (1..=`MAX_LEVEL`).
+    /// The level is the search depth; higher levels also get more time per move.
+    pub fn level(level: u8) -> Self {
+        let level = level.clamp(1, MAX_LEVEL);
+        SearchLimits {
+            depth: Some(level),
+            nodes: None,
+            movetime: Some(Duration::from_millis(500 * level as u64)),
+        }
+    }
+
+    /// Limits for a move under a clock: `time` is left for this and the next `moves_to_go`
+    /// moves (or the rest of the game, if unknown), plus `inc` for each move.
+    pub fn for_clock(time: Duration, inc: Duration, moves_to_go: Option<u32>) -> Self {
+        let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let budget = time / moves_to_go + inc / 2;
+        let left = time.saturating_sub(MOVE_OVERHEAD);
+        SearchLimits {
+            depth: None,
+            nodes: None,
+            movetime: Some(budget.min(left).max(Duration::from_millis(1))),
+        }
+    }
+}
+
+/// Result of one finished iteration of iterative deepening.
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: u8,
+    pub score: Score,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<ChessMove>, // principal variation
+}
+
+impl fmt::Display for SearchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "depth {} score {} nodes {} time {}ms pv",
+            self.depth,
+            self.score,
+            self.nodes,
+            self.time.as_millis()
+        )?;
+        for mov in &self.pv {
+            write!(f, " {}", mov)?;
+        }
+        Ok(())
+    }
+}
+
+/// The built-in chess engine.
+/// Iterative-deepening alpha-beta (negamax) with quiescence search, a transposition table
+/// and move ordering (TT move, MVV-LVA captures, killer moves, history heuristic).
+/// The engine keeps its transposition table between searches, so one instance should be used
+/// for one game.
+pub struct Engine {
+    tt: TranspositionTable,
+    limits: SearchLimits,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+    stop: Arc<AtomicBool>, // set from outside to stop the search
+    killers: [[Option<ChessMove>; 2]; MAX_PLY],
+    history: [[i32; 64]; 64], // [src][dst] of quiet moves that caused a cutoff
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            tt: TranspositionTable::new(16),
+            limits: SearchLimits::default(),
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+            stop: Arc::new(AtomicBool::new(false)),
+            killers: [[None; 2]; MAX_PLY],
+            history: [[0; 64]; 64],
+        }
+    }
+
+    /// A flag to stop a running search from another thread.
+    /// The search returns its best move so far. The flag is not reset by the engine.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Resize the transposition table. This clears it.
+    pub fn set_hash_size(&mut self, mb: usize) {
+        self.tt = TranspositionTable::with_size_mb(mb);
+    }
+
+    /// Forget everything about previous searches, e.g. when a new game starts.
+    pub fn clear(&mut self) {
+        self.tt.clear();
+    }
+
+    /// Search the best move for the active player.
+    /// `report` is called after each finished iteration.
+    /// Returns `None` if there is no legal move (checkmate or stalemate).
+    pub fn search(
+        &mut self,
+        chess: &Chess,
+        limits: SearchLimits,
+        mut report: impl FnMut(&SearchInfo),
+    ) -> Option<ChessMove> {
+        self.limits = limits;
+        self.start = Instant::now();
+        self.nodes = 0;
+        self.stopped = false;
+        self.killers = [[None; 2]; MAX_PLY];
+        self.history = [[0; 64]; 64];
+
+        let root_moves = chess.legal_moves();
+        // fallback, in case not even the first iteration finishes
+        let mut best = *root_moves.first()?;
+
+        let max_depth = limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+        for depth in 1..=max_depth {
+            let mut pv = vec![];
+            let score = self.negamax(chess, depth as i32, 0, -INFINITY, INFINITY, &mut pv);
+            // an interrupted iteration didn't look at all moves, don't trust it
+            if self.stopped {
+                break;
+            }
+            if let Some(&mov) = pv.first() {
+                best = mov;
+            }
+
+            let info = SearchInfo {
+                depth,
+                score: to_score(score),
+                nodes: self.nodes,
+                time: self.start.elapsed(),
+                pv,
+            };
+            report(&info);
+
+            // a forced mate won't get any better by searching deeper
+            if matches!(info.score, Score::Mate(_)) {
+                break;
+            }
+            // don't start another iteration that we most likely can't finish in time
+            if let Some(movetime) = limits.movetime {
+                if self.start.elapsed() * 2 > movetime {
+                    break;
+                }
+            }
+        }
+
+        Some(best)
+    }
+
+    fn negamax(
+        &mut self,
+        chess: &Chess,
+        depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        pv: &mut Vec<ChessMove>,
+    ) -> i32 {
+        pv.clear();
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+        self.nodes += 1;
+
+        if ply > 0 && is_draw(chess) {
+            return 0;
+        }
+
+        let in_check = chess.is_in_check(chess.active_player);
+        // check extension: don't let a check push a threat over the horizon
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth <= 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(chess, ply, alpha, beta);
+        }
+
+        let key = chess.hash.get_current_hash();
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(key) {
+            tt_move = entry.best;
+            // at the root, we always search to get a move
+            if ply > 0 && entry.depth as i32 >= depth {
+                let score = score_from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let mut moves = chess.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        self.order_moves(chess, &mut moves, tt_move, ply);
+
+        let alpha_orig = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut child_pv = vec![];
+        for mov in moves {
+            let mut child = chess.clone();
+            if child.apply_move(mov).is_err() {
+                continue;
+            }
+            let score = -self.negamax(&child, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mov);
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(mov);
+                    pv.extend_from_slice(&child_pv);
+                }
+            }
+            if alpha >= beta {
+                if !is_capture(chess, &mov) {
+                    self.store_killer(mov, ply);
+                    self.history[mov.src.to_index() as usize][mov.dst.to_index() as usize] +=
+                        depth * depth;
+                }
+                break;
+            }
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > alpha_orig {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(TtEntry {
+            key,
+            depth: depth as u8,
+            score: score_to_tt(best_score, ply),
+            bound,
+            best: best_move,
+        });
+
+        best_score
+    }
+
+    /// Search only captures (and queen promotions) until the position is quiet,
+    /// so the static evaluation isn't taken in the middle of an exchange.
+    /// When in check, all evasions are searched, since standing pat isn't an option then.
+    fn quiescence(&mut self, chess: &Chess, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+        self.nodes += 1;
+
+        let in_check = chess.is_in_check(chess.active_player);
+        let mut best_score;
+        let mut moves = if in_check {
+            let moves = chess.legal_moves();
+            if moves.is_empty() {
+                return -MATE + ply as i32;
+            }
+            best_score = -INFINITY;
+            moves
+        } else {
+            let stand_pat = evaluate(chess);
+            if stand_pat >= beta || ply >= MAX_PLY - 1 {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            best_score = stand_pat;
+            chess
+                .pseudo_legal_moves()
+                .into_iter()
+                .filter(|mov| is_capture(chess, mov) || mov.special == Some(Promotion::Queen))
+                .filter(|mov| chess.is_legal_move(mov))
+                .collect()
+        };
+        if ply >= MAX_PLY - 1 {
+            return evaluate(chess);
+        }
+        self.order_moves(chess, &mut moves, None, ply);
+
+        for mov in moves {
+            let mut child = chess.clone();
+            if child.apply_move(mov).is_err() {
+                continue;
+            }
+            let score = -self.quiescence(&child, ply + 1, -beta, -alpha);
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                alpha = alpha.max(score);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        best_score
+    }
+
+    /// Sort moves so the most promising ones are searched first, which makes alpha-beta
+    /// cut off much earlier.
+    fn order_moves(
+        &self,
+        chess: &Chess,
+        moves: &mut [ChessMove],
+        tt_move: Option<ChessMove>,
+        ply: usize,
+    ) {
+        moves.sort_by_cached_key(|mov| {
+            let score = if Some(*mov) == tt_move {
+                1_000_000
+            } else if is_capture(chess, mov) {
+                // MVV-LVA: most valuable victim first, least valuable attacker breaks ties
+                let victim = chess[mov.dst].map_or(ChessPiece::Pawn, |p| p.typ); // en passant
+                let attacker = chess[mov.src].map_or(ChessPiece::Pawn, |p| p.typ);
+                100_000 + 10 * piece_value(victim) - piece_value(attacker)
+            } else if mov.special.is_some() {
+                90_000
+            } else if self.killers[ply][0] == Some(*mov) {
+                80_000
+            } else if self.killers[ply][1] == Some(*mov) {
+                79_000
+            } else {
+                self.history[mov.src.to_index() as usize][mov.dst.to_index() as usize]
+            };
+            Reverse(score)
+        });
+    }
+
+    fn store_killer(&mut self, mov: ChessMove, ply: usize) {
+        if self.killers[ply][0] != Some(mov) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(mov);
+        }
+    }
+
+    /// Set the `stopped` flag when a limit of the search is reached.
+    fn check_limits(&mut self) {
+        if self.stop.load(Ordering::Relaxed) {
+            self.stopped = true;
+        }
+        if let Some(nodes) = self.limits.nodes {
+            if self.nodes >= nodes {
+                self.stopped = true;
+            }
+        }
+        // looking at the clock is not free, so only do it every now and then
+        if self.nodes.is_multiple_of(1024) {
+            if let Some(movetime) = self.limits.movetime {
+                if self.start.elapsed() >= movetime {
+                    self.stopped = true;
+                }
+            }
+        }
+    }
+}
+
+fn is_capture(chess: &Chess, mov: &ChessMove) -> bool {
+    if chess[mov.dst].is_some() {
+        return true;
+    }
+    chess.en_passant == Some(mov.dst) && chess[mov.src].is_some_and(|p| p.typ == ChessPiece::Pawn)
+}
+
+/// Draw by the fifty-move rule or by repetition.
+/// Inside the search, a single repetition already counts as a draw: if repeating is good for
+/// one side, it can just repeat again.
+fn is_draw(chess: &Chess) -> bool {
+    if chess.half_moves() >= 100 {
+        return true;
+    }
+    let list = &chess.hash.hash_list;
+    let Some(&current) = list.last() else {
+        return false;
+    };
+    // only positions with the same side to move and since the last irreversible move
+    let start = list.len().saturating_sub(chess.half_moves() + 1);
+    list[start..list.len() - 1]
+        .iter()
+        .rev()
+        .skip(1)
+        .step_by(2)
+        .any(|&h| h == current)
+}
+
+/// Count the leaf nodes of the legal move tree up to the given depth.
+/// Used to check the move generator against known numbers.
+pub fn perft(chess: &Chess, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = chess.legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves
+        .into_iter()
+        .map(|mov| {
+            let mut next = chess.clone();
+            let _ = next.apply_move(mov);
+            perft(&next, depth - 1)
+        })
+        .sum()
+}
+
+/// Convert an internal score to centipawns or moves until mate.
+fn to_score(score: i32) -> Score {
+    if score >= MATE - MAX_PLY as i32 {
+        Score::Mate((MATE - score + 1) / 2)
+    } else if score <= -MATE + MAX_PLY as i32 {
+        Score::Mate(-(MATE + score) / 2)
+    } else {
+        Score::Cp(score)
+    }
+}
+
+/// Mate scores are stored relative to the node instead of the root,
+/// so they stay correct when the position is found again at another ply.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score <= -MATE + MAX_PLY as i32 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score <= -MATE + MAX_PLY as i32 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best_move(fen: &str, depth: u8) -> (ChessMove, SearchInfo) {
+        let chess = Chess::load_fen(fen);
+        let mut engine = Engine::new();
+        let mut last = None;
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+        let mov = engine
+            .search(&chess, limits, |info| last = Some(info.clone()))
+            .unwrap();
+        (mov, last.unwrap())
+    }
+
+    #[test]
+    fn test_mate_in_one() {
+        // back rank mate
+        let (mov, info) = best_move("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", 2);
+        assert_eq!(mov.to_string(), "a1a8");
+        assert_eq!(info.score, Score::Mate(1));
+    }
+
+    #[test]
+    fn test_wins_hanging_queen() {
+        let (mov, info) = best_move("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 2);
+        assert_eq!(mov.to_string(), "d2d5");
+        assert!(matches!(info.score, Score::Cp(cp) if cp > 300));
+    }
+
+    #[test]
+    fn test_quiescence_sees_recapture() {
+        // the queen takes a pawn defended by a pawn: a depth-1 search must not fall for it
+        let (mov, _) = best_move("4k3/2p5/3p4/8/8/8/3Q4/4K3 w - - 0 1", 1);
+        assert_ne!(mov.to_string(), "d2d6");
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let chess = Chess::new();
+        let mut engine = Engine::new();
+        let limits = SearchLimits {
+            nodes: Some(50),
+            ..Default::default()
+        };
+        let mov = engine.search(&chess, limits, |_| {});
+        assert!(mov.is_some_and(|m| chess.is_legal_move(&m)));
+        assert!(engine.nodes <= 50);
+    }
+
+    #[test]
+    fn test_no_moves() {
+        // black is checkmated
+        let chess = Chess::load_fen("R5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1");
+        let mut engine = Engine::new();
+        assert!(engine
+            .search(&chess, SearchLimits::level(1), |_| {})
+            .is_none());
+    }
+
+    #[test]
+    fn test_perft() {
+        assert_eq!(perft(&Chess::new(), 3), 8902);
+        // "Kiwipete": castling, en passant and promotions
+        let chess =
+            Chess::load_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(perft(&chess, 1), 48);
+        assert_eq!(perft(&chess, 2), 2039);
+    }
+
+    #[test]
+    fn test_stop_flag() {
+        let mut engine = Engine::new();
+        engine.stop_flag().store(true, Ordering::Relaxed);
+        // a stopped search still has to come up with a legal move
+        let chess = Chess::new();
+        let mov = engine.search(&chess, SearchLimits::default(), |_| {});
+        assert!(mov.is_some_and(|m| chess.is_legal_move(&m)));
+    }
+}
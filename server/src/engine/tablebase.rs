@@ -0,0 +1,300 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+
+use crate::chess::chess::Chess;
+use crate::chess::san::San;
+use crate::engine::uci::with_timeout;
+use chess_core::{ChessColor, ChessMove, ChessPiece, Wdl};
+use smol::process::Command;
+
+/// What the tablebase knows about a position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TablebaseEntry {
+    pub wdl: Wdl,
+    pub dtz: i32, // plies to the next capture or pawn move with best play; negative when losing
+    pub best: Option<ChessMove>, // `None` if the game is over
+}
+
+impl TablebaseEntry {
+    /// The winner with perfect play, `None` for a draw.
+    /// Cursed wins and blessed losses are draws, the 50-moves rule saves the losing side.
+    pub fn winner(&self, side_to_move: ChessColor) -> Option<ChessColor> {
+        wdl_winner(self.wdl, side_to_move)
+    }
+}
+
+pub fn wdl_winner(wdl: Wdl, side_to_move: ChessColor) -> Option<ChessColor> {
+    match wdl {
+        Wdl::Win => Some(side_to_move),
+        Wdl::Loss => Some(!side_to_move),
+        Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => None,
+    }
+}
+
+/// Syzygy endgame tablebases in a local directory.
+/// The tables are read by an external prober, Fathom (`fathom --path=<dir> <fen>`), which answers
+/// with the result, the DTZ and the best moves of the position as PGN tags.
+/// We only call it for positions it can answer: few enough pieces, a table for the material
+/// in the directory, and no castling rights.
+pub struct Tablebase {
+    dir: PathBuf,
+    prober: String,
+    tables: HashSet<String>, // material of the WDL tables in `dir`, e.g. `KQvK`
+    max_pieces: usize,
+}
+
+impl Tablebase {
+    /// Look for WDL tables (`*.rtbw`) in a directory. Fails if there are none.
+    pub fn open(dir: &str, prober: &str) -> io::Result<Tablebase> {
+        let mut tables = HashSet::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "rtbw") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    tables.insert(stem.to_string());
+                }
+            }
+        }
+        let Some(max_pieces) = tables.iter().map(|t| piece_count(t)).max() else {
+            let msg = "no Syzygy tables (*.rtbw)";
+            return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+        };
+
+        Ok(Tablebase {
+            dir: PathBuf::from(dir),
+            prober: prober.to_string(),
+            tables,
+            max_pieces,
+        })
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Is there a table for this position?
+    pub fn can_probe(&self, chess: &Chess) -> bool {
+        // tablebases don't know castling
+        if chess.castle_rights.iter().any(|&c| c) {
+            return false;
+        }
+        let white = material(chess, ChessColor::White);
+        let black = material(chess, ChessColor::Black);
+        if white.len() + black.len() > self.max_pieces {
+            return false;
+        }
+        // the table is named with the stronger side first, we don't need to know which one it is
+        self.tables.contains(&format!("{}v{}", white, black))
+            || self.tables.contains(&format!("{}v{}", black, white))
+    }
+
+    /// Look up a position. Returns `None` if there is no table for it.
+    pub async fn probe(&self, chess: &Chess) -> io::Result<Option<TablebaseEntry>> {
+        if !self.can_probe(chess) {
+            return Ok(None);
+        }
+        let output = Command::new(&self.prober)
+            .arg(format!("--path={}", self.dir.display()))
+            .arg(chess.get_fen())
+            .kill_on_drop(true)
+            .output();
+        let output = with_timeout(output).await?;
+        if !output.status.success() {
+            let err = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::other(format!("prober failed: {}", err.trim())));
+        }
+        Ok(parse_probe(chess, &String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+/// The pieces of one side in Syzygy order, e.g. `KRP`.
+fn material(chess: &Chess, color: ChessColor) -> String {
+    let mut pieces: Vec<ChessPiece> = chess
+        .into_iter()
+        .flatten()
+        .filter(|p| p.piece.color == color)
+        .map(|p| p.piece.typ)
+        .collect();
+    let order = |p: &ChessPiece| match p {
+        ChessPiece::King => 0,
+        ChessPiece::Queen => 1,
+        ChessPiece::Rook => 2,
+        ChessPiece::Bishop => 3,
+        ChessPiece::Knight => 4,
+        ChessPiece::Pawn => 5,
+    };
+    pieces.sort_by_key(order);
+    pieces
+        .iter()
+        .map(|p| match p {
+            ChessPiece::King => 'K',
+            ChessPiece::Queen => 'Q',
+            ChessPiece::Rook => 'R',
+            ChessPiece::Bishop => 'B',
+            ChessPiece::Knight => 'N',
+            ChessPiece::Pawn => 'P',
+        })
+        .collect()
+}
+
+/// `KRPvKR` has 5 pieces.
+fn piece_count(table: &str) -> usize {
+    table.chars().filter(|&c| c != 'v').count()
+}
+
+/// Read the answer of the prober. It looks like a PGN game:
+/// ```text
+/// [WDL "Win"]
+/// [DTZ "13"]
+/// [WinningMoves "Kb6, Kc6"]
+/// [DrawingMoves ""]
+/// [LosingMoves "Rb7"]
+///
+/// 1. Kb6 Kb8 2. Rh8# 1-0
+/// ```
+/// The game is the best play from the position; we only need its first move.
+fn parse_probe(chess: &Chess, text: &str) -> Option<TablebaseEntry> {
+    let mut wdl = None;
+    let mut dtz = 0;
+    let mut moves_by_result = vec![];
+    let mut line_moves = vec![];
+
+    for line in text.lines().map(str::trim) {
+        if let Some(tag) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let Some((name, value)) = tag.split_once(' ') else {
+                continue;
+            };
+            let value = value.trim_matches('"');
+            match name {
+                "WDL" => wdl = parse_wdl(value),
+                "DTZ" => dtz = value.parse::<i32>().unwrap_or(0).abs(),
+                "WinningMoves" | "DrawingMoves" | "LosingMoves" => {
+                    moves_by_result.extend(value.split(',').map(|m| m.trim().to_string()))
+                }
+                _ => {}
+            }
+        } else {
+            line_moves.extend(line.split_whitespace().map(String::from));
+        }
+    }
+
+    let wdl = wdl?;
+    let dtz = match wdl {
+        Wdl::Win | Wdl::CursedWin => dtz,
+        Wdl::Draw => 0,
+        Wdl::BlessedLoss | Wdl::Loss => -dtz,
+    };
+    // the first move of the game, or else the first of the listed moves, best results first
+    let best = line_moves
+        .iter()
+        .map(|m| m.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.'))
+        .find(|m| !m.is_empty())
+        .and_then(|san| ChessMove::from_san(chess, san))
+        .or_else(|| {
+            let san = moves_by_result.iter().find(|m| !m.is_empty())?;
+            ChessMove::from_san(chess, san)
+        })
+        .filter(|mov| chess.is_legal_move(mov));
+
+    Some(TablebaseEntry { wdl, dtz, best })
+}
+
+fn parse_wdl(name: &str) -> Option<Wdl> {
+    match name {
+        "Loss" => Some(Wdl::Loss),
+        "BlessedLoss" => Some(Wdl::BlessedLoss),
+        "Draw" => Some(Wdl::Draw),
+        "CursedWin" => Some(Wdl::CursedWin),
+        "Win" => Some(Wdl::Win),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// KRvK, white mates with Rh8.
+    const FEN: &str = "1k6/8/1K6/8/8/8/8/7R w - - 0 1";
+
+    const ANSWER: &str = r#"[Event ""]
+[Site ""]
+[White "Syzygy"]
+[Black "Syzygy"]
+[Result "1-0"]
+[FEN "1k6/8/1K6/8/8/8/8/7R w - - 0 1"]
+[Annotator "Fathom"]
+[WDL "Win"]
+[DTZ "1"]
+[WinningMoves "Rh8"]
+[DrawingMoves "Kc5, Ka5"]
+[LosingMoves ""]
+
+1. Rh8# 1-0
+"#;
+
+    #[test]
+    fn test_parse_probe() {
+        let chess = Chess::load_fen(FEN);
+        let entry = parse_probe(&chess, ANSWER).unwrap();
+        assert_eq!(entry.wdl, Wdl::Win);
+        assert_eq!(entry.dtz, 1);
+        assert_eq!(entry.best, Some("h1h8".parse().unwrap()));
+        assert_eq!(entry.winner(ChessColor::White), Some(ChessColor::White));
+
+        // a drawn position, without the game
+        let answer = "[WDL \"Draw\"]\n[DTZ \"0\"]\n[DrawingMoves \"Kc5\"]\n";
+        let entry = parse_probe(&chess, answer).unwrap();
+        assert_eq!(entry.wdl, Wdl::Draw);
+        assert_eq!(entry.best, Some("b6c5".parse().unwrap()));
+        assert_eq!(entry.winner(ChessColor::White), None);
+
+        assert!(parse_probe(&chess, "unknown position").is_none());
+    }
+
+    #[test]
+    fn test_material() {
+        let chess = Chess::load_fen("8/8/8/3k4/8/8/1P6/R3K3 w - - 0 1");
+        assert_eq!(material(&chess, ChessColor::White), "KRP");
+        assert_eq!(material(&chess, ChessColor::Black), "K");
+        assert_eq!(piece_count("KRPvK"), 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_probe() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // a directory with (empty) tables and a fake prober that always gives the same answer
+        let dir = std::env::temp_dir().join(format!("syzygy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for table in ["KRvK.rtbw", "KRvK.rtbz", "KQvK.rtbw"] {
+            std::fs::write(dir.join(table), b"").unwrap();
+        }
+        let prober = dir.join("fathom.sh");
+        std::fs::write(&prober, format!("#!/bin/sh\ncat <<'EOF'\n{}EOF\n", ANSWER)).unwrap();
+        std::fs::set_permissions(&prober, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let tb = Tablebase::open(dir.to_str().unwrap(), prober.to_str().unwrap()).unwrap();
+        assert_eq!(tb.table_count(), 2);
+        assert_eq!(tb.max_pieces(), 3);
+
+        let chess = Chess::load_fen(FEN);
+        assert!(tb.can_probe(&chess));
+        // the table is named for the stronger side first
+        assert!(tb.can_probe(&Chess::load_fen("1K6/8/1k6/8/8/8/8/7r b - - 0 1")));
+        assert!(!tb.can_probe(&Chess::load_fen("1k6/8/1K6/8/8/8/8/7B w - - 0 1")));
+        assert!(!tb.can_probe(&Chess::new()));
+
+        let entry = smol::block_on(tb.probe(&chess)).unwrap().unwrap();
+        assert_eq!(entry.best, Some("h1h8".parse().unwrap()));
+        assert!(smol::block_on(tb.probe(&Chess::new())).unwrap().is_none());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
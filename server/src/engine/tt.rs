@@ -0,0 +1,62 @@
+use chess_core::ChessMove;
+
+/// What the stored score tells about the real score of a position.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Bound {
+    Exact, // the score is exact (a PV node)
+    Lower, // the score is at least this (a beta cutoff)
+    Upper, // the score is at most this (no move raised alpha)
+}
+
+#[derive(Clone, Copy)]
+pub struct TtEntry {
+    pub key: u64,
+    pub depth: u8,
+    pub score: i32,
+    pub bound: Bound,
+    pub best: Option<ChessMove>,
+}
+
+/// Transposition table, keyed by the Zobrist hash of a position.
+/// A fixed number of slots (power of two), each position maps to one slot by its key.
+/// On collisions, deeper searches win over shallower ones.
+pub struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    /// Create a table with `2^bits` slots.
+    pub fn new(bits: u32) -> Self {
+        let size = 1 << bits;
+        TranspositionTable {
+            entries: vec![None; size],
+            mask: size - 1,
+        }
+    }
+
+    /// Create the biggest table that fits into the given number of megabytes.
+    pub fn with_size_mb(mb: usize) -> Self {
+        let slots = (mb.max(1) << 20) / std::mem::size_of::<Option<TtEntry>>();
+        Self::new(slots.ilog2())
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|e| *e = None);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<&TtEntry> {
+        self.entries[key as usize & self.mask]
+            .as_ref()
+            .filter(|e| e.key == key)
+    }
+
+    pub fn store(&mut self, entry: TtEntry) {
+        let slot = &mut self.entries[entry.key as usize & self.mask];
+        match slot {
+            // keep the deeper result of another position
+            Some(old) if old.key != entry.key && old.depth > entry.depth => {}
+            _ => *slot = Some(entry),
+        }
+    }
+}
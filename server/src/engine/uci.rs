@@ -0,0 +1,343 @@
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+use crate::engine::SearchLimits;
+use chess_core::{ChessMove, Score};
+use smol::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use smol::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use smol::stream::StreamExt;
+
+pub(crate) const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// How long we wait for an engine to answer `uci` or `isready`.
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One `info` line of a UCI engine. Only the fields we care about are kept.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UciInfo {
+    pub depth: Option<u8>,
+    pub score: Option<Score>,
+    pub nodes: Option<u64>,
+    pub pv: Vec<ChessMove>,
+}
+
+impl UciInfo {
+    /// Parse an `info` line, e.g. `info depth 12 score cp 35 nodes 81532 pv e2e4 e7e5`.
+    /// Unknown fields are skipped. Returns `None` if the line isn't an `info` line.
+    pub fn parse(line: &str) -> Option<UciInfo> {
+        let mut tokens = line.split_whitespace().peekable();
+        if tokens.next() != Some("info") {
+            return None;
+        }
+
+        let mut info = UciInfo::default();
+        while let Some(token) = tokens.next() {
+            match token {
+                "depth" => info.depth = tokens.next().and_then(|t| t.parse().ok()),
+                "nodes" => info.nodes = tokens.next().and_then(|t| t.parse().ok()),
+                "score" => {
+                    let kind = tokens.next();
+                    let value = tokens.next().and_then(|t| t.parse().ok());
+                    info.score = match (kind, value) {
+                        (Some("cp"), Some(v)) => Some(Score::Cp(v)),
+                        (Some("mate"), Some(v)) => Some(Score::Mate(v)),
+                        _ => None,
+                    };
+                }
+                "pv" => {
+                    while let Some(mov) = tokens.peek().and_then(|t| parse_move(t)) {
+                        info.pv.push(mov);
+                        tokens.next();
+                    }
+                }
+                // a free text until the end of the line
+                "string" => break,
+                _ => {}
+            }
+        }
+        Some(info)
+    }
+}
+
+/// Parse a move in UCI notation (`e2e4`, `e7e8q`).
+/// Unlike `ChessMove::from_str`, this checks that the string is actually a move.
+pub fn parse_move(s: &str) -> Option<ChessMove> {
+    let b = s.as_bytes();
+    let is_tile = |f: u8, r: u8| (b'a'..=b'h').contains(&f) && (b'1'..=b'8').contains(&r);
+    let valid = match b.len() {
+        4 => is_tile(b[0], b[1]) && is_tile(b[2], b[3]),
+        5 => is_tile(b[0], b[1]) && is_tile(b[2], b[3]) && b"qrbn".contains(&b[4]),
+        _ => false,
+    };
+    if !valid {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Format a move in UCI notation. UCI wants the promotion piece in lowercase.
+pub fn format_move(mov: &ChessMove) -> String {
+    mov.to_string().to_lowercase()
+}
+
+/// The `position` command for a start position and the moves played since.
+pub fn position_command(fen: &str, moves: &[ChessMove]) -> String {
+    let mut cmd = if fen == STARTPOS {
+        String::from("position startpos")
+    } else {
+        format!("position fen {}", fen)
+    };
+    if !moves.is_empty() {
+        cmd.push_str(" moves");
+        for mov in moves {
+            cmd.push(' ');
+            cmd.push_str(&format_move(mov));
+        }
+    }
+    cmd
+}
+
+/// The `go` command for the given limits.
+/// Without any limit, this is `go infinite`.
+pub fn go_command(limits: &SearchLimits) -> String {
+    let mut cmd = String::from("go");
+    if let Some(depth) = limits.depth {
+        cmd.push_str(&format!(" depth {}", depth));
+    }
+    if let Some(nodes) = limits.nodes {
+        cmd.push_str(&format!(" nodes {}", nodes));
+    }
+    if let Some(movetime) = limits.movetime {
+        cmd.push_str(&format!(" movetime {}", movetime.as_millis()));
+    }
+    if cmd == "go" {
+        cmd.push_str(" infinite");
+    }
+    cmd
+}
+
+/// An external chess engine speaking UCI, running as a child process.
+/// The engine is killed when this is dropped.
+pub struct UciEngine {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl UciEngine {
+    /// Start the engine binary and do the `uci`/`isready` handshake.
+    pub async fn start(path: &str) -> io::Result<UciEngine> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap(); // piped above
+        let stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+
+        let mut engine = UciEngine {
+            name: path.to_string(),
+            child,
+            stdin,
+            stdout,
+        };
+
+        engine.send("uci").await?;
+        let handshake = async {
+            loop {
+                let line = engine.read_line().await?;
+                if let Some(name) = line.strip_prefix("id name ") {
+                    engine.name = name.trim().to_string();
+                }
+                if line.trim() == "uciok" {
+                    return Ok(());
+                }
+            }
+        };
+        with_timeout(handshake).await?;
+        engine.is_ready().await?;
+
+        log::info!("started UCI engine {}", engine.name);
+        Ok(engine)
+    }
+
+    async fn send(&mut self, cmd: &str) -> io::Result<()> {
+        log::debug!("uci > {}", cmd);
+        self.stdin.write_all(cmd.as_bytes()).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await
+    }
+
+    async fn read_line(&mut self) -> io::Result<String> {
+        match self.stdout.next().await {
+            Some(line) => {
+                let line = line?;
+                log::debug!("uci < {}", line);
+                Ok(line)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "engine closed its output",
+            )),
+        }
+    }
+
+    /// Send `isready` and wait for `readyok`.
+    pub async fn is_ready(&mut self) -> io::Result<()> {
+        self.send("isready").await?;
+        with_timeout(async {
+            while self.read_line().await?.trim() != "readyok" {}
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send(&format!("setoption name {} value {}", name, value))
+            .await?;
+        self.is_ready().await
+    }
+
+    /// Search a position, given as start position and the moves played since.
+    /// `on_info` is called for every `info` line the engine sends while searching.
+    /// Returns the engine's `bestmove`, or `None` if the engine has no move (mate, stalemate).
+    /// The limits shouldn't be empty, or the engine searches forever.
+    pub async fn go(
+        &mut self,
+        fen: &str,
+        moves: &[ChessMove],
+        limits: &SearchLimits,
+        mut on_info: impl FnMut(&UciInfo),
+    ) -> io::Result<Option<ChessMove>> {
+        self.send(&position_command(fen, moves)).await?;
+        self.send(&go_command(limits)).await?;
+
+        loop {
+            let line = self.read_line().await?;
+            if let Some(info) = UciInfo::parse(&line) {
+                on_info(&info);
+            } else if let Some(rest) = line.strip_prefix("bestmove") {
+                let best = rest.split_whitespace().next().unwrap_or("(none)");
+                return Ok(parse_move(best));
+            }
+        }
+    }
+
+    /// Tell the engine to quit and wait for it to exit.
+    pub async fn quit(mut self) {
+        if self.send("quit").await.is_ok() {
+            let _ = with_timeout(self.child.status()).await;
+        }
+    }
+}
+
+/// An engine that hangs in the handshake shouldn't hang us as well.
+pub(crate) async fn with_timeout<T>(f: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    smol::future::or(f, async {
+        smol::Timer::after(HANDSHAKE_TIMEOUT).await;
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "engine did not answer",
+        ))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info() {
+        let info = UciInfo::parse("info depth 12 seldepth 18 multipv 1 score cp -35 nodes 81532 nps 900000 pv e2e4 e7e5 g1f3").unwrap();
+        assert_eq!(info.depth, Some(12));
+        assert_eq!(info.score, Some(Score::Cp(-35)));
+        assert_eq!(info.nodes, Some(81532));
+        assert_eq!(
+            info.pv,
+            vec![
+                "e2e4".parse().unwrap(),
+                "e7e5".parse().unwrap(),
+                "g1f3".parse().unwrap()
+            ]
+        );
+
+        let info = UciInfo::parse("info depth 5 score mate -2 upperbound pv a7a8q").unwrap();
+        assert_eq!(info.score, Some(Score::Mate(-2)));
+        assert_eq!(info.pv, vec!["a7a8q".parse().unwrap()]);
+
+        let info = UciInfo::parse("info string NNUE evaluation enabled").unwrap();
+        assert_eq!(info, UciInfo::default());
+
+        assert!(UciInfo::parse("bestmove e2e4").is_none());
+    }
+
+    #[test]
+    fn test_commands() {
+        let moves: Vec<ChessMove> = vec!["e2e4".parse().unwrap(), "e7e8Q".parse().unwrap()];
+        assert_eq!(
+            position_command(STARTPOS, &moves),
+            "position startpos moves e2e4 e7e8q"
+        );
+        assert_eq!(
+            position_command("8/8/8/8/8/8/8/K6k w - - 0 1", &[]),
+            "position fen 8/8/8/8/8/8/8/K6k w - - 0 1"
+        );
+
+        let limits = SearchLimits {
+            depth: Some(8),
+            nodes: None,
+            movetime: Some(Duration::from_millis(1500)),
+        };
+        assert_eq!(go_command(&limits), "go depth 8 movetime 1500");
+        assert_eq!(go_command(&SearchLimits::default()), "go infinite");
+    }
+
+    /// A fake engine that knows just enough UCI to be talked to.
+    #[cfg(unix)]
+    const FAKE_ENGINE: &str = r#"#!/bin/sh
+while read -r line; do
+    case "$line" in
+        uci) echo "id name Fake Engine 1.0"; echo "option name Hash type spin default 16 min 1 max 64"; echo "uciok";;
+        isready) echo "readyok";;
+        go*) echo "info depth 1 score cp 20 nodes 20 pv e2e4"; echo "info depth 2 score mate 3 nodes 400 pv d2d4 d7d5"; echo "bestmove d2d4 ponder d7d5";;
+        quit) exit 0;;
+    esac
+done
+"#;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fake_engine() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("fake-uci-{}.sh", std::process::id()));
+        std::fs::write(&path, FAKE_ENGINE).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        smol::block_on(async {
+            let mut engine = UciEngine::start(path.to_str().unwrap()).await.unwrap();
+            assert_eq!(engine.name, "Fake Engine 1.0");
+            engine.set_option("Hash", "32").await.unwrap();
+
+            let mut infos = vec![];
+            let best = engine
+                .go(STARTPOS, &[], &SearchLimits::level(1), |info| {
+                    infos.push(info.clone())
+                })
+                .await
+                .unwrap();
+            assert_eq!(best, Some("d2d4".parse().unwrap()));
+            assert_eq!(infos.len(), 2);
+            assert_eq!(infos[1].score, Some(Score::Mate(3)));
+            assert_eq!(infos[1].depth, Some(2));
+
+            engine.quit().await;
+        });
+
+        let _ = std::fs::remove_file(path);
+    }
+}
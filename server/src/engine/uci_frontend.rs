@@ -0,0 +1,376 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::chess::chess::Chess;
+use crate::chess::polyglot::OpeningBook;
+use crate::engine::search::SearchInfo;
+use crate::engine::uci::{format_move, parse_move};
+use crate::engine::{perft, Engine, SearchLimits};
+use chess_core::ChessColor;
+
+const NAME: &str = concat!("chess-server ", env!("CARGO_PKG_VERSION"));
+const AUTHOR: &str = "the chess-server authors";
+
+/// Size of the transposition table in MB, if the GUI doesn't set `Hash`.
+const DEFAULT_HASH: usize = 16;
+const MAX_HASH: usize = 1024;
+
+/// The other side of `UciEngine`: our own engine, speaking UCI to a GUI.
+/// Commands are passed in one line at a time through `handle()`; replies are written to `out`.
+/// A search runs in its own thread, so `stop` and `isready` are answered while it thinks.
+pub struct UciFrontend<W: Write + Send + 'static> {
+    out: Arc<Mutex<W>>,
+    engine: Arc<Mutex<Engine>>,
+    stop: Arc<AtomicBool>, // the engine's stop flag
+    chess: Chess,
+    book: Option<Arc<OpeningBook>>,
+    search: Option<JoinHandle<()>>,
+}
+
+impl<W: Write + Send + 'static> UciFrontend<W> {
+    pub fn new(out: W) -> Self {
+        let engine = Engine::new();
+        let stop = engine.stop_flag();
+        UciFrontend {
+            out: Arc::new(Mutex::new(out)),
+            engine: Arc::new(Mutex::new(engine)),
+            stop,
+            chess: Chess::new(),
+            book: None,
+            search: None,
+        }
+    }
+
+    /// Handle one command line. Returns `false` after `quit`.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                self.send(&format!("id name {}", NAME));
+                self.send(&format!("id author {}", AUTHOR));
+                self.send(&format!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_HASH, MAX_HASH
+                ));
+                self.send("option name BookFile type string default <empty>");
+                self.send("uciok");
+            }
+            Some("isready") => self.send("readyok"),
+            Some("ucinewgame") => {
+                self.wait_for_search();
+                self.engine.lock().unwrap().clear();
+                self.chess = Chess::new();
+            }
+            Some("setoption") => {
+                self.wait_for_search();
+                let rest: Vec<&str> = tokens.collect();
+                self.set_option(&rest);
+            }
+            Some("position") => {
+                let rest: Vec<&str> = tokens.collect();
+                match parse_position(&rest) {
+                    Some(chess) => self.chess = chess,
+                    None => log::warn!("invalid position: {}", line),
+                }
+            }
+            Some("go") => {
+                let rest: Vec<&str> = tokens.collect();
+                self.go(&rest);
+            }
+            Some("stop") => self.wait_for_search(),
+            Some("quit") => {
+                self.wait_for_search();
+                return false;
+            }
+            // `debug`, `ponderhit`, `register` and anything unknown are ignored, as UCI demands
+            _ => log::debug!("ignoring command: {}", line),
+        }
+        true
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.out, line);
+    }
+
+    /// Stop the running search, if any, and wait until its `bestmove` is out.
+    fn wait_for_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            let _ = search.join();
+        }
+    }
+
+    /// `setoption name <name> [value <value>]`. Names and values may contain spaces.
+    fn set_option(&mut self, tokens: &[&str]) {
+        let Some(pos) = tokens.iter().position(|&t| t == "name") else {
+            return;
+        };
+        let value_pos = tokens.iter().position(|&t| t == "value");
+        let name = tokens[pos + 1..value_pos.unwrap_or(tokens.len())].join(" ");
+        let value = value_pos
+            .map(|p| tokens[p + 1..].join(" "))
+            .unwrap_or_default();
+
+        match name.to_lowercase().as_str() {
+            "hash" => match value.parse::<usize>() {
+                Ok(mb) => {
+                    let mb = mb.clamp(1, MAX_HASH);
+                    self.engine.lock().unwrap().set_hash_size(mb);
+                }
+                Err(_) => log::warn!("invalid Hash value: {}", value),
+            },
+            "bookfile" => {
+                if value.is_empty() || value == "<empty>" {
+                    self.book = None;
+                    return;
+                }
+                match OpeningBook::open(&value) {
+                    Ok(book) => self.book = Some(Arc::new(book)),
+                    Err(e) => self.send(&format!("info string can't open book {}: {}", value, e)),
+                }
+            }
+            _ => log::warn!("unknown option: {}", name),
+        }
+    }
+
+    fn go(&mut self, tokens: &[&str]) {
+        self.wait_for_search();
+
+        let mut limits = SearchLimits::default();
+        let mut infinite = false;
+        let (mut time, mut inc, mut moves_to_go) = (None, Duration::ZERO, None);
+        let white = self.chess.active_player == ChessColor::White;
+
+        let mut tokens = tokens.iter();
+        while let Some(&token) = tokens.next() {
+            let mut number = || tokens.next().and_then(|t| t.parse::<u64>().ok());
+            match token {
+                "perft" => {
+                    let depth = number().unwrap_or(1) as u8;
+                    let count = perft(&self.chess, depth);
+                    self.send(&format!("info string perft {} nodes {}", depth, count));
+                    return;
+                }
+                "depth" => limits.depth = number().map(|d| d.min(u8::MAX as u64) as u8),
+                "nodes" => limits.nodes = number(),
+                "movetime" => limits.movetime = number().map(Duration::from_millis),
+                "wtime" | "btime" => {
+                    let ms = number();
+                    if (token == "wtime") == white {
+                        time = ms.map(Duration::from_millis);
+                    }
+                }
+                "winc" | "binc" => {
+                    let ms = number().unwrap_or(0);
+                    if (token == "winc") == white {
+                        inc = Duration::from_millis(ms);
+                    }
+                }
+                "movestogo" => moves_to_go = number().map(|n| n as u32),
+                "infinite" => infinite = true,
+                _ => {}
+            }
+        }
+
+        // our share of the remaining time, unless the GUI asks for a fixed time
+        if let (None, Some(time)) = (limits.movetime, time) {
+            limits.movetime = SearchLimits::for_clock(time, inc, moves_to_go).movetime;
+        }
+
+        if !infinite {
+            let book_move = self.book.as_ref().and_then(|b| b.pick_move(&self.chess));
+            if let Some(mov) = book_move {
+                self.send(&format!("bestmove {}", format_move(&mov)));
+                return;
+            }
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+        let (out, engine, stop) = (self.out.clone(), self.engine.clone(), self.stop.clone());
+        let chess = self.chess.clone();
+        self.search = Some(thread::spawn(move || {
+            let best = engine
+                .lock()
+                .unwrap()
+                .search(&chess, limits, |info| send(&out, &info_line(info)));
+            // `go infinite` must not answer before `stop`, even if the search is done
+            while infinite && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            let best = best.map(|m| format_move(&m));
+            send(
+                &out,
+                &format!("bestmove {}", best.as_deref().unwrap_or("0000")),
+            );
+        }));
+    }
+}
+
+fn send<W: Write>(out: &Mutex<W>, line: &str) {
+    log::debug!("uci > {}", line);
+    let mut out = out.lock().unwrap();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+fn info_line(info: &SearchInfo) -> String {
+    let mut line = format!(
+        "info depth {} score {} nodes {} time {} pv",
+        info.depth,
+        info.score,
+        info.nodes,
+        info.time.as_millis()
+    );
+    for mov in &info.pv {
+        line.push(' ');
+        line.push_str(&format_move(mov));
+    }
+    line
+}
+
+/// Parse the arguments of `position`: `startpos` or `fen <fen>`, optionally followed by
+/// `moves <move>...`. Returns `None` if the FEN is incomplete or a move is illegal.
+fn parse_position(tokens: &[&str]) -> Option<Chess> {
+    let moves_pos = tokens.iter().position(|&t| t == "moves");
+    let (setup, moves) = tokens.split_at(moves_pos.unwrap_or(tokens.len()));
+
+    let mut chess = match setup {
+        ["startpos"] => Chess::new(),
+        ["fen", fen @ ..] if fen.len() >= 4 => {
+            // GUIs sometimes leave out the move counters
+            let mut fields = fen.to_vec();
+            if fields.len() < 5 {
+                fields.push("0");
+            }
+            if fields.len() < 6 {
+                fields.push("1");
+            }
+            Chess::load_fen(&fields.join(" "))
+        }
+        _ => return None,
+    };
+
+    for token in moves.iter().skip(1) {
+        let mov = parse_move(token)?;
+        chess.make_move(mov).ok()?;
+    }
+    Some(chess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An output buffer the test can read while the frontend owns it.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn lines(&self) -> Vec<String> {
+            let bytes = self.0.lock().unwrap();
+            String::from_utf8_lossy(&bytes)
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+
+        /// Wait until the search thread has sent its move.
+        fn bestmove(&self) -> String {
+            for _ in 0..500 {
+                if let Some(l) = self.lines().iter().find(|l| l.starts_with("bestmove ")) {
+                    return l[9..].to_string();
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("no bestmove");
+        }
+    }
+
+    #[test]
+    fn test_parse_position() {
+        let chess = parse_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3"]).unwrap();
+        assert!(chess
+            .get_fen()
+            .starts_with("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 "));
+
+        let fen = "8/8/8/8/8/8/8/K6k w - -";
+        let chess = parse_position(&fen_tokens(fen)).unwrap();
+        assert_eq!(chess.get_fen(), "8/8/8/8/8/8/8/K6k w - - 0 1");
+
+        assert!(parse_position(&["startpos", "moves", "e2e5"]).is_none());
+        assert!(parse_position(&["fen", "8/8/8"]).is_none());
+    }
+
+    fn fen_tokens(fen: &str) -> Vec<&str> {
+        std::iter::once("fen").chain(fen.split(' ')).collect()
+    }
+
+    #[test]
+    fn test_uci_session() {
+        let out = Output::default();
+        let mut uci = UciFrontend::new(out.clone());
+
+        assert!(uci.handle("uci"));
+        assert!(uci.handle("setoption name Hash value 4"));
+        assert!(uci.handle("isready"));
+        assert!(uci.handle("position fen 6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"));
+        assert!(uci.handle("go depth 2"));
+        assert_eq!(out.bestmove(), "a1a8");
+        assert!(!uci.handle("quit"));
+
+        let lines = out.lines();
+        assert!(lines[0].starts_with("id name "));
+        assert!(lines.contains(&"uciok".to_string()));
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("info depth 1 score mate 1 ") && l.ends_with(" pv a1a8")));
+    }
+
+    #[test]
+    fn test_go_infinite_waits_for_stop() {
+        let out = Output::default();
+        let mut uci = UciFrontend::new(out.clone());
+
+        uci.handle("position startpos moves e2e4");
+        uci.handle("go infinite");
+        thread::sleep(Duration::from_millis(50));
+        assert!(!out.lines().iter().any(|l| l.starts_with("bestmove")));
+
+        uci.handle("stop");
+        let lines = out.lines();
+        let best = lines.last().unwrap().strip_prefix("bestmove ").unwrap();
+        let mut chess = parse_position(&["startpos", "moves", "e2e4"]).unwrap();
+        assert!(chess.make_move(parse_move(best).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_time_control() {
+        let out = Output::default();
+        let mut uci = UciFrontend::new(out.clone());
+
+        // 1 second for the whole game: the move must come well within it
+        let start = std::time::Instant::now();
+        uci.handle("position startpos");
+        uci.handle("go wtime 1000 btime 1000 winc 0 binc 0");
+        out.bestmove();
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        uci.handle("go perft 3");
+        assert_eq!(
+            out.lines().last().unwrap(),
+            "info string perft 3 nodes 8902"
+        );
+    }
+}
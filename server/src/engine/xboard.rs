@@ -0,0 +1,917 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::chess::chess::Chess;
+use crate::chess::san::San;
+use crate::engine::search::SearchInfo;
+use crate::engine::uci::{
+    format_move, parse_move, with_timeout, UciInfo, HANDSHAKE_TIMEOUT, STARTPOS,
+};
+use crate::engine::{Engine, SearchLimits};
+use chess_core::{ChessColor, ChessMove, Score};
+use smol::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use smol::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use smol::stream::StreamExt;
+
+/// Mate scores in thinking output are `100000 + N` for "mate in N moves", negative if mated.
+const MATE_SCORE: i32 = 100_000;
+
+/// Engines that don't know `protover 2` never send features.
+/// The protocol says to give them two seconds before going on without.
+const FEATURE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Features an engine may ask for that we can deal with. Everything else is rejected,
+/// e.g. `san=1`, so the engine has to send its moves in coordinate notation.
+const KNOWN_FEATURES: [&str; 14] = [
+    "myname",
+    "usermove",
+    "setboard",
+    "ping",
+    "done",
+    "sigint",
+    "sigterm",
+    "analyze",
+    "colors",
+    "time",
+    "reuse",
+    "draw",
+    "playother",
+    "debug",
+];
+
+/// Parse a `feature` line into its `name=value` pairs. Values may be quoted.
+pub fn parse_features(line: &str) -> Vec<(String, String)> {
+    let Some(mut rest) = line.trim().strip_prefix("feature") else {
+        return vec![];
+    };
+    let mut features = vec![];
+    loop {
+        rest = rest.trim_start();
+        let Some((name, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(' ').unwrap_or((after, "")),
+        };
+        features.push((name.to_string(), value.to_string()));
+        rest = after;
+    }
+    features
+}
+
+/// Parse a line of thinking output: `depth score time nodes pv...`, with the time in centiseconds.
+/// The PV is free text; we read moves in coordinate notation or SAN for as long as they are
+/// legal in `chess`. Returns `None` if the line isn't thinking output.
+pub fn parse_thinking(line: &str, chess: &Chess) -> Option<UciInfo> {
+    let mut tokens = line.split_whitespace();
+    // some engines write `12.` or `12&` for the depth
+    let depth = tokens
+        .next()?
+        .trim_end_matches(|c: char| !c.is_ascii_digit());
+    let depth = depth.parse().ok()?;
+    let score = tokens.next()?.parse().ok()?;
+    let _time: u64 = tokens.next()?.parse().ok()?;
+    let nodes = tokens.next()?.parse().ok()?;
+
+    let mut chess = chess.clone();
+    let mut pv = vec![];
+    for token in tokens {
+        // move numbers like `1.` or `12...`
+        let token = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if token.is_empty() {
+            continue;
+        }
+        let Some(mov) = parse_move(token).or_else(|| ChessMove::from_san(&chess, token)) else {
+            break;
+        };
+        if chess.make_move(mov).is_err() {
+            break;
+        }
+        pv.push(mov);
+    }
+
+    Some(UciInfo {
+        depth: Some(depth),
+        score: Some(score_from_xboard(score)),
+        nodes: Some(nodes),
+        pv,
+    })
+}
+
+fn score_from_xboard(score: i32) -> Score {
+    if score >= MATE_SCORE {
+        Score::Mate(score - MATE_SCORE)
+    } else if score <= -MATE_SCORE {
+        Score::Mate(score + MATE_SCORE)
+    } else {
+        Score::Cp(score)
+    }
+}
+
+fn score_to_xboard(score: Score) -> i32 {
+    match score {
+        Score::Cp(cp) => cp,
+        Score::Mate(n) if n >= 0 => MATE_SCORE + n,
+        Score::Mate(n) => -MATE_SCORE + n,
+    }
+}
+
+/// The result to claim if the game is over in this position, in the form of the `result` command.
+pub fn game_result(chess: &Chess) -> Option<&'static str> {
+    if chess.is_checkmate() {
+        Some(match chess.active_player {
+            ChessColor::White => "0-1 {Black mates}",
+            ChessColor::Black => "1-0 {White mates}",
+        })
+    } else if chess.is_stalemate() {
+        Some("1/2-1/2 {Stalemate}")
+    } else if chess.half_moves() >= 100 {
+        Some("1/2-1/2 {50 move rule}")
+    } else if chess.is_repetition() {
+        Some("1/2-1/2 {3-fold repetition}")
+    } else {
+        None
+    }
+}
+
+/// The `time` and `otim` of a game: what's left on our clock and on the opponent's.
+#[derive(Clone, Copy, Debug)]
+pub struct Clock {
+    pub time: Duration,
+    pub otim: Duration,
+}
+
+/// An external chess engine speaking CECP (the XBoard protocol), running as a child process.
+/// Like `UciEngine`, every `go()` sets up the whole position again, so the engine doesn't
+/// have to follow a game. The engine is killed when this is dropped.
+pub struct XBoardEngine {
+    pub name: String,
+    features: HashMap<String, String>, // the features we accepted
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    pings: u32,
+}
+
+impl XBoardEngine {
+    /// Start the engine binary and negotiate the `protover 2` features.
+    pub async fn start(path: &str) -> io::Result<XBoardEngine> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap(); // piped above
+        let stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+
+        let mut engine = XBoardEngine {
+            name: path.to_string(),
+            features: HashMap::new(),
+            child,
+            stdin,
+            stdout,
+            pings: 0,
+        };
+
+        engine.send("xboard").await?;
+        engine.send("protover 2").await?;
+        engine.negotiate_features().await?;
+        // no pondering, and we want to see the thinking output
+        engine.send("easy").await?;
+        engine.send("post").await?;
+        engine.sync().await?;
+
+        log::info!("started XBoard engine {}", engine.name);
+        Ok(engine)
+    }
+
+    /// Read `feature` lines until `done=1`.
+    /// `done=0` means the engine needs more time, up to the usual handshake timeout.
+    async fn negotiate_features(&mut self) -> io::Result<()> {
+        let mut deadline = Instant::now() + FEATURE_TIMEOUT;
+        loop {
+            // an old engine without features, or one that never got done
+            let Some(line) = self.read_line_until(deadline).await? else {
+                return Ok(());
+            };
+
+            for (name, value) in parse_features(&line) {
+                let known = KNOWN_FEATURES.contains(&name.as_str());
+                let reply = if known { "accepted" } else { "rejected" };
+                self.send(&format!("{} {}", reply, name)).await?;
+                match (name.as_str(), value.as_str()) {
+                    ("done", "0") => deadline = Instant::now() + HANDSHAKE_TIMEOUT,
+                    ("done", _) => return Ok(()),
+                    ("myname", _) => self.name = value.clone(),
+                    _ => {}
+                }
+                if known {
+                    self.features.insert(name, value);
+                }
+            }
+        }
+    }
+
+    fn has_feature(&self, name: &str) -> bool {
+        self.features.get(name).is_some_and(|v| v == "1")
+    }
+
+    async fn send(&mut self, cmd: &str) -> io::Result<()> {
+        log::debug!("xboard > {}", cmd);
+        self.stdin.write_all(cmd.as_bytes()).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await
+    }
+
+    async fn read_line(&mut self) -> io::Result<String> {
+        match self.stdout.next().await {
+            Some(line) => {
+                let line = line?;
+                log::debug!("xboard < {}", line);
+                Ok(line)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "engine closed its output",
+            )),
+        }
+    }
+
+    /// The next line, or `None` if the engine has nothing to say until the deadline.
+    async fn read_line_until(&mut self, deadline: Instant) -> io::Result<Option<String>> {
+        smol::future::or(async { self.read_line().await.map(Some) }, async {
+            smol::Timer::at(deadline).await;
+            Ok(None)
+        })
+        .await
+    }
+
+    /// Wait until the engine has processed everything we sent, if it supports `ping`.
+    pub async fn sync(&mut self) -> io::Result<()> {
+        if !self.has_feature("ping") {
+            return Ok(());
+        }
+        self.pings += 1;
+        let pong = format!("pong {}", self.pings);
+        self.send(&format!("ping {}", self.pings)).await?;
+        with_timeout(async {
+            while self.read_line().await?.trim() != pong {}
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set up a position in force mode, so the engine doesn't start to think on its own.
+    async fn set_position(&mut self, fen: &str, moves: &[ChessMove]) -> io::Result<()> {
+        self.send("new").await?;
+        self.send("force").await?;
+        if fen != STARTPOS {
+            if !self.has_feature("setboard") {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "engine can't set up positions",
+                ));
+            }
+            self.send(&format!("setboard {}", fen)).await?;
+        }
+        let prefix = if self.has_feature("usermove") {
+            "usermove "
+        } else {
+            ""
+        };
+        for mov in moves {
+            self.send(&format!("{}{}", prefix, format_move(mov)))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Let the engine play the side to move in a position, given as start position and the
+    /// moves played since. The limits are sent as `sd` and `st`, the clock as `time`/`otim`.
+    /// `on_info` is called for every line of thinking output.
+    /// Returns `None` if the engine has no move (mate, stalemate) or resigns.
+    pub async fn go(
+        &mut self,
+        fen: &str,
+        moves: &[ChessMove],
+        limits: &SearchLimits,
+        clock: Option<Clock>,
+        mut on_info: impl FnMut(&UciInfo),
+    ) -> io::Result<Option<ChessMove>> {
+        let chess = replay(fen, moves)?;
+        self.set_position(fen, moves).await?;
+        if let Some(depth) = limits.depth {
+            self.send(&format!("sd {}", depth)).await?;
+        }
+        if let Some(movetime) = limits.movetime {
+            // `st` only knows whole seconds
+            let secs = movetime.as_secs_f64().ceil().max(1.0);
+            self.send(&format!("st {}", secs)).await?;
+        }
+        if let Some(clock) = clock {
+            self.send(&format!("time {}", clock.time.as_millis() / 10))
+                .await?;
+            self.send(&format!("otim {}", clock.otim.as_millis() / 10))
+                .await?;
+        }
+        self.send("go").await?;
+
+        loop {
+            let line = self.read_line().await?;
+            let line = line.trim();
+            if let Some(info) = parse_thinking(line, &chess) {
+                on_info(&info);
+            } else if let Some(mov) = line
+                .strip_prefix("move ")
+                .or_else(|| line.strip_prefix("My move is: "))
+            {
+                let mov = mov.trim();
+                return Ok(parse_move(mov).or_else(|| ChessMove::from_san(&chess, mov)));
+            } else if line == "resign" || is_result(line) {
+                return Ok(None);
+            } else if line.starts_with("Illegal move") || line.starts_with("Error") {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, line.to_string()));
+            }
+        }
+    }
+
+    /// Analyse a position for the given time. `on_info` is called for every line of thinking output.
+    pub async fn analyse(
+        &mut self,
+        fen: &str,
+        moves: &[ChessMove],
+        time: Duration,
+        mut on_info: impl FnMut(&UciInfo),
+    ) -> io::Result<()> {
+        if self.features.get("analyze").is_some_and(|v| v == "0") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "engine can't analyse",
+            ));
+        }
+        let chess = replay(fen, moves)?;
+        self.set_position(fen, moves).await?;
+        self.send("post").await?;
+        self.send("analyze").await?;
+
+        let deadline = Instant::now() + time;
+        while let Some(line) = self.read_line_until(deadline).await? {
+            if let Some(info) = parse_thinking(&line, &chess) {
+                on_info(&info);
+            }
+        }
+        self.send("exit").await?;
+        self.sync().await
+    }
+
+    /// Tell the engine how the game ended, e.g. `result("1-0", "White mates")`.
+    pub async fn result(&mut self, result: &str, comment: &str) -> io::Result<()> {
+        self.send(&format!("result {} {{{}}}", result, comment))
+            .await
+    }
+
+    /// Tell the engine to quit and wait for it to exit.
+    pub async fn quit(mut self) {
+        if self.send("quit").await.is_ok() {
+            let _ = with_timeout(self.child.status()).await;
+        }
+    }
+}
+
+/// Lines like `1-0 {White mates}`, which an engine sends to claim a result.
+fn is_result(line: &str) -> bool {
+    ["1-0", "0-1", "1/2-1/2", "*"]
+        .iter()
+        .any(|r| line == *r || line.starts_with(&format!("{} ", r)))
+}
+
+fn replay(fen: &str, moves: &[ChessMove]) -> io::Result<Chess> {
+    let mut chess = Chess::load_fen(fen);
+    for &mov in moves {
+        chess
+            .make_move(mov)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    }
+    Ok(chess)
+}
+
+/// The game the frontend is playing. Shared with the search thread, which makes the engine's move.
+struct Game {
+    start_fen: String,
+    moves: Vec<ChessMove>,
+    chess: Chess,
+}
+
+impl Game {
+    fn new(chess: Chess) -> Self {
+        Game {
+            start_fen: chess.get_fen(),
+            moves: vec![],
+            chess,
+        }
+    }
+
+    fn push(&mut self, mov: ChessMove) -> bool {
+        if self.chess.make_move(mov).is_err() {
+            return false;
+        }
+        self.moves.push(mov);
+        true
+    }
+
+    /// Take back the last `n` moves, by replaying the game without them.
+    fn undo(&mut self, n: usize) {
+        let keep = self.moves.len().saturating_sub(n);
+        self.moves.truncate(keep);
+        self.chess = Chess::load_fen(&self.start_fen);
+        for &mov in &self.moves {
+            let _ = self.chess.apply_move(mov);
+        }
+    }
+}
+
+/// Time control as set by `level`, `st` and `sd`, and the clocks as sent by `time`/`otim`.
+#[derive(Clone, Copy, Default)]
+struct TimeControl {
+    moves_per_session: u32, // 0: the base time is for the whole game
+    inc: Duration,
+    time: Option<Duration>, // our clock
+    st: Option<Duration>,   // fixed time per move
+    sd: Option<u8>,         // depth limit
+}
+
+impl TimeControl {
+    fn limits(&self, moves_played: usize) -> SearchLimits {
+        let mut limits = match (self.st, self.time) {
+            (Some(st), _) => SearchLimits {
+                movetime: Some(st),
+                ..Default::default()
+            },
+            (None, Some(time)) => {
+                let moves_to_go = (self.moves_per_session > 0).then(|| {
+                    let ours = (moves_played / 2) as u32;
+                    self.moves_per_session - ours % self.moves_per_session
+                });
+                SearchLimits::for_clock(time, self.inc, moves_to_go)
+            }
+            (None, None) => SearchLimits::default(),
+        };
+        limits.depth = self.sd;
+        limits
+    }
+}
+
+/// The other side of `XBoardEngine`: our own engine, speaking CECP to a GUI.
+/// Commands are passed in one line at a time through `handle()`; replies are written to `out`.
+/// A search runs in its own thread, so the GUI can interrupt it with `?`, `force` etc.
+pub struct XBoardFrontend<W: Write + Send + 'static> {
+    out: Arc<Mutex<W>>,
+    engine: Arc<Mutex<Engine>>,
+    stop: Arc<AtomicBool>,  // the engine's stop flag
+    abort: Arc<AtomicBool>, // the GUI doesn't want the move of the stopped search
+    game: Arc<Mutex<Game>>,
+    force: bool, // only follow the moves, don't play
+    engine_color: ChessColor,
+    post: bool, // send thinking output
+    tc: TimeControl,
+    search: Option<JoinHandle<()>>,
+}
+
+impl<W: Write + Send + 'static> XBoardFrontend<W> {
+    pub fn new(out: W) -> Self {
+        let engine = Engine::new();
+        let stop = engine.stop_flag();
+        XBoardFrontend {
+            out: Arc::new(Mutex::new(out)),
+            engine: Arc::new(Mutex::new(engine)),
+            stop,
+            abort: Arc::new(AtomicBool::new(false)),
+            game: Arc::new(Mutex::new(Game::new(Chess::new()))),
+            force: false,
+            engine_color: ChessColor::Black,
+            post: false,
+            tc: TimeControl::default(),
+            search: None,
+        }
+    }
+
+    /// Handle one command line. Returns `false` after `quit`.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        let (cmd, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+        match cmd {
+            "xboard" | "accepted" | "rejected" | "hard" | "easy" | "random" | "computer"
+            | "name" | "ics" | "draw" | "" => {}
+            "protover" => {
+                if args.parse::<u32>().unwrap_or(1) >= 2 {
+                    self.send(&format!(
+                        "feature myname=\"chess-server {}\" usermove=1 setboard=1 ping=1 \
+                         playother=1 colors=0 sigint=0 sigterm=0 analyze=0 variants=\"normal\" \
+                         done=1",
+                        env!("CARGO_PKG_VERSION")
+                    ));
+                }
+            }
+            "new" => {
+                self.abort_search();
+                *self.game.lock().unwrap() = Game::new(Chess::new());
+                self.engine.lock().unwrap().clear();
+                self.force = false;
+                self.engine_color = ChessColor::Black;
+                self.tc.sd = None;
+            }
+            "force" => {
+                self.abort_search();
+                self.force = true;
+            }
+            "go" => {
+                self.abort_search();
+                self.force = false;
+                self.engine_color = self.game.lock().unwrap().chess.active_player;
+                self.think();
+            }
+            "playother" => {
+                self.abort_search();
+                self.force = false;
+                self.engine_color = !self.game.lock().unwrap().chess.active_player;
+            }
+            "setboard" => {
+                self.abort_search();
+                if args.split_whitespace().count() < 4 {
+                    self.send(&format!("tellusererror Illegal position: {}", args));
+                    return true;
+                }
+                *self.game.lock().unwrap() = Game::new(Chess::load_fen(args));
+            }
+            "usermove" => self.user_move(args),
+            "time" | "otim" => {
+                let cs: u64 = args.parse().unwrap_or(0);
+                if cmd == "time" {
+                    self.tc.time = Some(Duration::from_millis(cs * 10));
+                }
+            }
+            "level" => self.set_level(args),
+            "st" => {
+                let secs: f64 = args.parse().unwrap_or(1.0);
+                self.tc.st = Some(Duration::from_secs_f64(secs.max(0.01)));
+            }
+            "sd" => self.tc.sd = args.parse().ok(),
+            "ping" => self.send(&format!("pong {}", args)),
+            "result" => {
+                self.abort_search();
+                self.force = true;
+            }
+            // move now
+            "?" => self.wait_for_search(),
+            "undo" | "remove" => {
+                self.abort_search();
+                let n = if cmd == "undo" { 1 } else { 2 };
+                self.game.lock().unwrap().undo(n);
+            }
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "quit" => {
+                self.abort_search();
+                return false;
+            }
+            // protocol version 1 sends moves without `usermove`
+            _ if parse_move(cmd).is_some() => self.user_move(cmd),
+            _ => self.send(&format!("Error (unknown command): {}", cmd)),
+        }
+        true
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.out, line);
+    }
+
+    /// Stop the running search and play its move.
+    fn wait_for_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            let _ = search.join();
+        }
+    }
+
+    /// Stop the running search and forget about its move.
+    fn abort_search(&mut self) {
+        self.abort.store(true, Ordering::Relaxed);
+        self.wait_for_search();
+    }
+
+    /// `level MPS BASE INC`. We don't need the base time, `time` tells us what's left.
+    fn set_level(&mut self, args: &str) {
+        let parts: Vec<&str> = args.split_whitespace().collect();
+        let [mps, _base, inc] = parts[..] else {
+            self.send(&format!("Error (bad level): {}", args));
+            return;
+        };
+        self.tc.moves_per_session = mps.parse().unwrap_or(0);
+        self.tc.inc = Duration::from_secs_f64(inc.parse().unwrap_or(0.0));
+        self.tc.st = None;
+    }
+
+    fn user_move(&mut self, text: &str) {
+        // a move while we think (e.g. in analysis), the GUI shouldn't do that
+        self.abort_search();
+        let mut game = self.game.lock().unwrap();
+        let mov = parse_move(text).or_else(|| ChessMove::from_san(&game.chess, text));
+        if !mov.is_some_and(|mov| game.push(mov)) {
+            drop(game);
+            self.send(&format!("Illegal move: {}", text));
+            return;
+        }
+        let result = game_result(&game.chess);
+        let our_turn = game.chess.active_player == self.engine_color;
+        drop(game);
+
+        if let Some(result) = result {
+            self.send(result);
+        } else if !self.force && our_turn {
+            self.think();
+        }
+    }
+
+    /// Search a move for the side to move in a thread; the thread plays it when it's done.
+    fn think(&mut self) {
+        let game = self.game.clone();
+        let (chess, moves_played) = {
+            let game = game.lock().unwrap();
+            (game.chess.clone(), game.moves.len())
+        };
+        if game_result(&chess).is_some() {
+            return;
+        }
+        let limits = self.tc.limits(moves_played);
+
+        self.stop.store(false, Ordering::Relaxed);
+        self.abort.store(false, Ordering::Relaxed);
+        let (out, engine, abort) = (self.out.clone(), self.engine.clone(), self.abort.clone());
+        let post = self.post;
+        self.search = Some(thread::spawn(move || {
+            let best = engine.lock().unwrap().search(&chess, limits, |info| {
+                if post {
+                    send(&out, &thinking_line(info));
+                }
+            });
+            if abort.load(Ordering::Relaxed) {
+                return;
+            }
+            let Some(best) = best else {
+                return;
+            };
+
+            let mut game = game.lock().unwrap();
+            game.push(best);
+            send(&out, &format!("move {}", format_move(&best)));
+            if let Some(result) = game_result(&game.chess) {
+                send(&out, result);
+            }
+        }));
+    }
+}
+
+fn send<W: Write>(out: &Mutex<W>, line: &str) {
+    log::debug!("xboard > {}", line);
+    let mut out = out.lock().unwrap();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+fn thinking_line(info: &SearchInfo) -> String {
+    let mut line = format!(
+        "{} {} {} {}",
+        info.depth,
+        score_to_xboard(info.score),
+        info.time.as_millis() / 10,
+        info.nodes
+    );
+    for mov in &info.pv {
+        line.push(' ');
+        line.push_str(&format_move(mov));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_features() {
+        let features = parse_features(
+            "feature ping=1 myname=\"Fake Engine 1.0\" san=1 variants=\"normal,suicide\" done=1",
+        );
+        let expected = [
+            ("ping", "1"),
+            ("myname", "Fake Engine 1.0"),
+            ("san", "1"),
+            ("variants", "normal,suicide"),
+            ("done", "1"),
+        ];
+        assert_eq!(features.len(), expected.len());
+        for ((name, value), (n, v)) in features.iter().zip(expected) {
+            assert_eq!((name.as_str(), value.as_str()), (n, v));
+        }
+        assert!(parse_features("move e2e4").is_empty());
+    }
+
+    #[test]
+    fn test_parse_thinking() {
+        let chess = Chess::new();
+        let info = parse_thinking(" 9.  35  120  81532  1. e4 e5 2. Nf3 Nc6", &chess).unwrap();
+        assert_eq!(info.depth, Some(9));
+        assert_eq!(info.score, Some(Score::Cp(35)));
+        assert_eq!(info.nodes, Some(81532));
+        let pv: Vec<String> = info.pv.iter().map(format_move).collect();
+        assert_eq!(pv, ["e2e4", "e7e5", "g1f3", "b8c6"]);
+
+        let info = parse_thinking("4 100003 10 900 d2d4", &chess).unwrap();
+        assert_eq!(info.score, Some(Score::Mate(3)));
+        let info = parse_thinking("4 -100002 10 900", &chess).unwrap();
+        assert_eq!(info.score, Some(Score::Mate(-2)));
+
+        assert!(parse_thinking("move e2e4", &chess).is_none());
+        assert!(parse_thinking("feature done=1", &chess).is_none());
+    }
+
+    #[test]
+    fn test_scores() {
+        for score in [Score::Cp(-40), Score::Mate(3), Score::Mate(-1)] {
+            assert_eq!(score_from_xboard(score_to_xboard(score)), score);
+        }
+    }
+
+    /// An output buffer the test can read while the frontend owns it.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Write::write(&mut *self.0.lock().unwrap(), buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn lines(&self) -> Vec<String> {
+            let bytes = self.0.lock().unwrap();
+            String::from_utf8_lossy(&bytes)
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+
+        /// Wait until the search thread has sent `count` moves.
+        fn moves(&self, count: usize) -> Vec<String> {
+            for _ in 0..500 {
+                let moves: Vec<String> = self
+                    .lines()
+                    .iter()
+                    .filter_map(|l| l.strip_prefix("move ").map(String::from))
+                    .collect();
+                if moves.len() >= count {
+                    return moves;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("engine didn't move");
+        }
+    }
+
+    #[test]
+    fn test_xboard_session() {
+        let out = Output::default();
+        let mut xb = XBoardFrontend::new(out.clone());
+
+        for cmd in [
+            "xboard",
+            "protover 2",
+            "accepted usermove",
+            "new",
+            "level 40 5 0",
+        ] {
+            assert!(xb.handle(cmd));
+        }
+        assert!(out.lines()[0].starts_with("feature myname="));
+        assert!(out.lines()[0].ends_with("done=1"));
+
+        // we play black after `new`
+        xb.handle("time 1000");
+        xb.handle("otim 1000");
+        xb.handle("usermove e2e4");
+        let reply = &out.moves(1)[0];
+        let mut chess = Chess::new();
+        chess.make_move(parse_move("e2e4").unwrap()).unwrap();
+        assert!(chess.make_move(parse_move(reply).unwrap()).is_ok());
+
+        xb.handle("usermove e2e5");
+        assert_eq!(out.lines().last().unwrap(), "Illegal move: e2e5");
+
+        xb.handle("ping 7");
+        assert_eq!(out.lines().last().unwrap(), "pong 7");
+
+        // a mate in one for white, after we switch sides
+        xb.handle("setboard 6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1");
+        xb.handle("sd 2");
+        xb.handle("go");
+        assert_eq!(out.moves(2)[1], "a1a8");
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(out.lines().last().unwrap(), "1-0 {White mates}");
+
+        xb.handle("result 1-0 {White mates}");
+        assert!(!xb.handle("quit"));
+    }
+
+    #[test]
+    fn test_force_and_undo() {
+        let out = Output::default();
+        let mut xb = XBoardFrontend::new(out.clone());
+
+        xb.handle("new");
+        xb.handle("force");
+        xb.handle("e2e4");
+        xb.handle("usermove e7e5");
+        xb.handle("usermove g1f3");
+        xb.handle("remove");
+        assert_eq!(xb.game.lock().unwrap().moves.len(), 1);
+        assert!(out.moves(0).is_empty());
+
+        xb.handle("foo");
+        assert_eq!(out.lines().last().unwrap(), "Error (unknown command): foo");
+    }
+
+    /// A fake engine that knows just enough CECP to be talked to.
+    #[cfg(unix)]
+    const FAKE_ENGINE: &str = r#"#!/bin/sh
+while read -r line; do
+    case "$line" in
+        "protover 2") echo "feature myname=\"Fake XBoard 1.0\" san=1 usermove=1 ping=1"; echo "feature done=1";;
+        ping*) echo "pong ${line#ping }";;
+        go) echo "1 20 0 20 e2e4"; echo "2 100003 1 400 d2d4 d7d5"; echo "move d2d4";;
+        analyze) echo "3 -15 5 1000 e7e5";;
+        quit) exit 0;;
+    esac
+done
+"#;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fake_engine() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("fake-xboard-{}.sh", std::process::id()));
+        std::fs::write(&path, FAKE_ENGINE).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        smol::block_on(async {
+            let mut engine = XBoardEngine::start(path.to_str().unwrap()).await.unwrap();
+            assert_eq!(engine.name, "Fake XBoard 1.0");
+            assert!(engine.has_feature("usermove"));
+            assert!(!engine.has_feature("san"));
+
+            let mut infos = vec![];
+            let clock = Clock {
+                time: Duration::from_secs(60),
+                otim: Duration::from_secs(60),
+            };
+            let e4: ChessMove = "e2e4".parse().unwrap();
+            let best = engine
+                .go(
+                    STARTPOS,
+                    &[],
+                    &SearchLimits::level(1),
+                    Some(clock),
+                    |info| infos.push(info.clone()),
+                )
+                .await
+                .unwrap();
+            assert_eq!(best, Some("d2d4".parse().unwrap()));
+            assert_eq!(infos.len(), 2);
+            assert_eq!(infos[1].score, Some(Score::Mate(3)));
+
+            let mut infos = vec![];
+            let time = Duration::from_millis(100);
+            engine
+                .analyse(STARTPOS, &[e4], time, |info| infos.push(info.clone()))
+                .await
+                .unwrap();
+            assert_eq!(infos.len(), 1);
+            assert_eq!(infos[0].pv, vec!["e7e5".parse().unwrap()]);
+
+            engine.result("1-0", "White mates").await.unwrap();
+            engine.quit().await;
+        });
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod chess;
+pub mod engine;
+pub mod server;
+mod test;
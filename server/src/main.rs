@@ -1,16 +1,37 @@
-mod chess;
-mod server;
-mod test;
-
-use server::server::Server;
+use chess_server::chess::pgn::parse_pgn;
+use chess_server::chess::polyglot::BookWriter;
+use chess_server::server::server::Server;
 
 use smol_macros::main;
 use std::io;
 
+/// Builds a Polyglot opening book from a collection of PGN games.
+fn make_book(pgn_path: &str, book_path: &str, max_ply: usize) -> io::Result<()> {
+    let text = std::fs::read_to_string(pgn_path)?;
+    let mut writer = BookWriter::new(max_ply);
+    let mut count = 0;
+    for game in parse_pgn(&text) {
+        match writer.add_game(&game) {
+            Ok(()) => count += 1,
+            Err(san) => log::warn!("skipping game with illegal move: {}", san),
+        }
+    }
+    writer.write(book_path)?;
+    println!("Wrote {} from {} games.", book_path, count);
+    Ok(())
+}
+
 main! {
     async fn main() -> io::Result<()> {
         env_logger::init();
 
+        // chess-server make-book <games.pgn> <book.bin> [max-ply]
+        let args: Vec<String> = std::env::args().collect();
+        if args.len() >= 4 && args[1] == "make-book" {
+            let max_ply = args.get(4).and_then(|s| s.parse().ok()).unwrap_or(20);
+            return make_book(&args[2], &args[3], max_ply);
+        }
+
         let mut server = Server::new();
         let _ = server.run(7878).await;
 
@@ -0,0 +1,491 @@
+use crate::server::chessgame::Identity;
+use crate::server::manager::{GameManager, TaskResult};
+use crate::server::password::{hash_password, valid_hash, verify_password, DUMMY_HASH};
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::Credentials;
+use chess_core::{ClientId, UserId};
+use smol::io::AsyncWriteExt;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+pub const MIN_PASSWORD_LEN: usize = 6;
+const MAX_NAME_LEN: usize = 20;
+/// Wrong passwords a name may get before logins with it have to wait.
+pub const FREE_FAILED_LOGINS: u32 = 3;
+/// The first wait; it doubles with every further wrong password.
+pub const LOGIN_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_LOGIN_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
+/// A registered player.
+/// The ID stays the same over all connections, unlike the `ClientId` of a session.
+pub struct Account {
+    pub id: UserId,
+    pub name: String,
+    hash: String,
+}
+
+#[derive(Debug)]
+pub enum AccountError {
+    InvalidName,
+    PasswordTooShort,
+    NameTaken,
+    WrongLogin, // unknown name or wrong password, we don't tell which
+    AlreadyLoggedIn,
+    LoginPending,
+    TooManyAttempts,
+    Disabled,
+    Io(io::Error),
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountError::InvalidName => write!(
+                f,
+                "Names have 1 to {} letters, digits, '-' or '_'",
+                MAX_NAME_LEN
+            ),
+            AccountError::PasswordTooShort => {
+                write!(f, "Passwords need at least {} characters", MIN_PASSWORD_LEN)
+            }
+            AccountError::NameTaken => write!(f, "Name already taken"),
+            AccountError::WrongLogin => write!(f, "Wrong name or password"),
+            AccountError::AlreadyLoggedIn => write!(f, "Already logged in"),
+            AccountError::LoginPending => write!(f, "Still checking the last login"),
+            AccountError::TooManyAttempts => {
+                write!(f, "Too many failed logins, try again later")
+            }
+            AccountError::Disabled => write!(f, "Accounts are disabled on this server"),
+            AccountError::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AccountError {}
+
+pub type AccountResult<T> = std::result::Result<T, AccountError>;
+
+/// All accounts, kept in memory and in a local file with one account per line:
+/// `id:name:hash`, the hash as a PHC string.
+/// Passwords are only stored as salted Argon2 hashes, see `password`.
+pub struct AccountStore {
+    path: PathBuf,
+    accounts: Vec<Account>,
+}
+
+impl AccountStore {
+    /// Load the accounts from a file. A missing file is an empty store, it's created with the
+    /// first account.
+    pub fn open(path: &str) -> io::Result<AccountStore> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut accounts = vec![];
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            match parse_account(line) {
+                Some(account) => accounts.push(account),
+                None => log::warn!("skipping broken account line in {}: {}", path, line),
+            }
+        }
+        Ok(AccountStore {
+            path: PathBuf::from(path),
+            accounts,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn get(&self, id: UserId) -> Option<&Account> {
+        self.accounts.iter().find(|a| a.id == id)
+    }
+
+    /// Names are unique regardless of case.
+    pub fn find(&self, name: &str) -> Option<&Account> {
+        self.accounts
+            .iter()
+            .find(|a| a.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Check name and password of a new account, before the password is hashed.
+    pub fn check_new(&self, name: &str, password: &str) -> AccountResult<()> {
+        if !valid_name(name) {
+            return Err(AccountError::InvalidName);
+        }
+        if password.chars().count() < MIN_PASSWORD_LEN {
+            return Err(AccountError::PasswordTooShort);
+        }
+        if self.find(name).is_some() {
+            return Err(AccountError::NameTaken);
+        }
+        Ok(())
+    }
+
+    /// Register a new account with the hash of its password and return its ID.
+    /// The name is checked again, somebody may have taken it while the password was hashed.
+    pub async fn create(&mut self, name: &str, hash: String) -> AccountResult<UserId> {
+        if self.find(name).is_some() {
+            return Err(AccountError::NameTaken);
+        }
+
+        let account = Account {
+            id: self.accounts.iter().map(|a| a.id).max().unwrap_or(0) + 1,
+            name: name.to_string(),
+            hash,
+        };
+        let mut file = smol::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(AccountError::Io)?;
+        let line = format!("{}:{}:{}\n", account.id, account.name, account.hash);
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(AccountError::Io)?;
+        file.sync_all().await.map_err(AccountError::Io)?;
+
+        let id = account.id;
+        self.accounts.push(account);
+        Ok(id)
+    }
+
+    /// What a login with this name is checked against: the account and its password hash.
+    /// Unknown names get `DUMMY_HASH`, so the check takes as long as for a real account.
+    pub fn login_hash(&self, name: &str) -> (Option<UserId>, String) {
+        match self.find(name) {
+            Some(account) => (Some(account.id), account.hash.clone()),
+            None => (None, DUMMY_HASH.to_string()),
+        }
+    }
+}
+
+/// Recent wrong passwords for one name.
+struct FailedLogins {
+    count: u32,
+    last: Instant,
+}
+
+impl FailedLogins {
+    /// Until when logins with the name are refused, if they are.
+    fn blocked_until(&self) -> Option<Instant> {
+        let extra = self.count.checked_sub(FREE_FAILED_LOGINS)?;
+        let wait = LOGIN_BACKOFF.saturating_mul(1 << extra.min(16));
+        Some(self.last + wait.min(MAX_LOGIN_BACKOFF))
+    }
+}
+
+/// Counts the wrong passwords per name, over all connections, so guessing doesn't get faster
+/// by reconnecting. Unknown names are counted the same way, or the waits would tell which
+/// names exist.
+#[derive(Default)]
+pub struct LoginThrottle {
+    failed: HashMap<String, FailedLogins>,
+}
+
+impl LoginThrottle {
+    /// May a password for this name be checked now?
+    pub fn allow(&self, name: &str, now: Instant) -> bool {
+        let failed = self.failed.get(&name.to_ascii_lowercase());
+        failed
+            .and_then(|f| f.blocked_until())
+            .is_none_or(|until| now >= until)
+    }
+
+    /// Count a checked login: a wrong password makes the next wait longer, the right one
+    /// clears the count. Names without a wrong password for a while are forgotten.
+    pub fn record(&mut self, name: &str, result: &AccountResult<UserId>, now: Instant) {
+        let name = name.to_ascii_lowercase();
+        self.failed
+            .retain(|_, f| now.duration_since(f.last) < MAX_LOGIN_BACKOFF);
+        match result {
+            Ok(_) => {
+                self.failed.remove(&name);
+            }
+            Err(AccountError::WrongLogin) => {
+                let failed = self.failed.entry(name).or_insert(FailedLogins {
+                    count: 0,
+                    last: now,
+                });
+                failed.count += 1;
+                failed.last = now;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Check a password against what `AccountStore::login_hash` returned.
+/// This takes a while on purpose, run it with `smol::unblock`.
+pub fn check_login(uid: Option<UserId>, hash: &str, password: &str) -> AccountResult<UserId> {
+    let verified = verify_password(password, hash);
+    uid.filter(|_| verified).ok_or(AccountError::WrongLogin)
+}
+
+fn valid_name(name: &str) -> bool {
+    (1..=MAX_NAME_LEN).contains(&name.chars().count())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn parse_account(line: &str) -> Option<Account> {
+    let mut parts = line.trim().split(':');
+    let account = Account {
+        id: parts.next()?.parse().ok()?,
+        name: parts.next()?.to_string(),
+        hash: parts.next()?.to_string(),
+    };
+    (parts.next().is_none() && valid_hash(&account.hash)).then_some(account)
+}
+
+impl GameManager {
+    /// Create an account and log the client in to it.
+    /// The password is hashed in a task of its own; the hash comes back as
+    /// `TaskResult::PasswordHashed`.
+    pub(crate) async fn handle_create_account(&mut self, cid: ClientId, credentials: Credentials) {
+        let checked = self
+            .check_login_allowed(cid)
+            .and_then(|accounts| accounts.check_new(&credentials.name, &credentials.password));
+        if let Err(e) = checked {
+            self.finish_login(cid, Err(e)).await;
+            return;
+        }
+
+        self.set_login_pending(cid, true);
+        let tx = self.task_tx.clone();
+        smol::spawn(async move {
+            let Credentials { name, password } = credentials;
+            let hash = smol::unblock(move || hash_password(&password)).await;
+            let _ = tx.send(TaskResult::PasswordHashed(cid, name, hash)).await;
+        })
+        .detach();
+    }
+
+    /// The password of a new account is hashed, now the account can be stored.
+    pub(crate) async fn handle_password_hashed(
+        &mut self,
+        cid: ClientId,
+        name: String,
+        hash: String,
+    ) {
+        self.set_login_pending(cid, false);
+        let result = match self.accounts.as_mut() {
+            Some(accounts) => accounts
+                .create(&name, hash)
+                .await
+                .inspect(|uid| log::info!("new account #{}: {}", uid, name)),
+            None => Err(AccountError::Disabled),
+        };
+        self.finish_login(cid, result).await;
+    }
+
+    /// The password is checked in a task of its own; the result comes back as
+    /// `TaskResult::LoginChecked`.
+    pub(crate) async fn handle_login(&mut self, cid: ClientId, credentials: Credentials) {
+        let throttled = !self.login_throttle.allow(&credentials.name, Instant::now());
+        let (uid, hash) = match self.check_login_allowed(cid) {
+            Ok(_) if throttled => {
+                self.finish_login(cid, Err(AccountError::TooManyAttempts))
+                    .await;
+                return;
+            }
+            Ok(accounts) => accounts.login_hash(&credentials.name),
+            Err(e) => {
+                self.finish_login(cid, Err(e)).await;
+                return;
+            }
+        };
+
+        self.set_login_pending(cid, true);
+        let tx = self.task_tx.clone();
+        smol::spawn(async move {
+            let Credentials { name, password } = credentials;
+            let result = smol::unblock(move || check_login(uid, &hash, &password)).await;
+            let _ = tx.send(TaskResult::LoginChecked(cid, name, result)).await;
+        })
+        .detach();
+    }
+
+    pub(crate) async fn handle_login_checked(
+        &mut self,
+        cid: ClientId,
+        name: String,
+        result: AccountResult<UserId>,
+    ) {
+        self.set_login_pending(cid, false);
+        self.login_throttle.record(&name, &result, Instant::now());
+        let result = result.and_then(|uid| {
+            // one session per account, or we wouldn't know which one plays
+            let online = self.clients.values().any(|c| c.user == Some(uid));
+            if online {
+                Err(AccountError::AlreadyLoggedIn)
+            } else {
+                Ok(uid)
+            }
+        });
+        self.finish_login(cid, result).await;
+    }
+
+    /// The account store, if this client may log in.
+    /// A connection checks one password at a time.
+    fn check_login_allowed(&mut self, cid: ClientId) -> AccountResult<&mut AccountStore> {
+        if let Some(client) = self.clients.get(&cid) {
+            if client.user.is_some() {
+                return Err(AccountError::AlreadyLoggedIn);
+            }
+            if client.login_pending {
+                return Err(AccountError::LoginPending);
+            }
+        }
+        self.accounts.as_mut().ok_or(AccountError::Disabled)
+    }
+
+    fn set_login_pending(&mut self, cid: ClientId, pending: bool) {
+        if let Some(client) = self.clients.get_mut(&cid) {
+            client.login_pending = pending;
+        }
+    }
+
+    /// Tell the client how the login went. On success, the client goes by the account name
+    /// and gets back the seats that were kept for the account.
+    async fn finish_login(&mut self, cid: ClientId, result: AccountResult<UserId>) {
+        let uid = match result {
+            Ok(uid) => uid,
+            Err(e) => {
+                log::info!("login of client {} failed: {}", cid, e);
+                self.send_to(cid, ServerMessage::LoginFailed(e.to_string()))
+                    .await;
+                return;
+            }
+        };
+        let Some(name) = self
+            .accounts
+            .as_ref()
+            .and_then(|a| Some(a.get(uid)?.name.clone()))
+        else {
+            return;
+        };
+        let Some(client) = self.clients.get_mut(&cid) else {
+            return;
+        };
+        client.user = Some(uid);
+        client.name = name.clone();
+        log::info!("client {} logged in as #{} ({})", cid, uid, name);
+
+        self.send_to(cid, ServerMessage::LoggedIn(uid, name)).await;
+        self.reclaim_seats(cid, Identity::User(uid)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accounts() {
+        let path = std::env::temp_dir().join(format!("accounts-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let login = |store: &AccountStore, name: &str, password: &str| {
+            let (uid, hash) = store.login_hash(name);
+            check_login(uid, &hash, password)
+        };
+
+        smol::block_on(async {
+            let mut store = AccountStore::open(path).unwrap();
+            assert!(store.is_empty());
+
+            store.check_new("alice", "secret1").unwrap();
+            let alice = store
+                .create("alice", hash_password("secret1"))
+                .await
+                .unwrap();
+            let bob = store.create("bob", hash_password("secret2")).await.unwrap();
+            assert_ne!(alice, bob);
+            assert!(matches!(
+                store.check_new("Alice", "whatever"),
+                Err(AccountError::NameTaken)
+            ));
+            assert!(matches!(
+                store.create("Alice", hash_password("whatever")).await,
+                Err(AccountError::NameTaken)
+            ));
+            assert!(matches!(
+                store.check_new("carol", "short"),
+                Err(AccountError::PasswordTooShort)
+            ));
+            assert!(matches!(
+                store.check_new("no spaces", "secret3"),
+                Err(AccountError::InvalidName)
+            ));
+
+            // the accounts are still there after reopening, the passwords are not
+            let store = AccountStore::open(path).unwrap();
+            assert_eq!(store.len(), 2);
+            assert!(!std::fs::read_to_string(path).unwrap().contains("secret"));
+            assert_eq!(login(&store, "alice", "secret1").unwrap(), alice);
+            assert_eq!(login(&store, "BOB", "secret2").unwrap(), bob);
+            assert!(matches!(
+                login(&store, "alice", "secret2"),
+                Err(AccountError::WrongLogin)
+            ));
+            assert_eq!(store.get(bob).unwrap().name, "bob");
+
+            // unknown names are checked against the dummy hash, which no password matches
+            let (uid, hash) = store.login_hash("carol");
+            assert_eq!(uid, None);
+            assert_eq!(hash, DUMMY_HASH);
+            assert!(matches!(
+                login(&store, "carol", "secret1"),
+                Err(AccountError::WrongLogin)
+            ));
+        });
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_login_throttle() {
+        let mut throttle = LoginThrottle::default();
+        let start = Instant::now();
+        let wrong = Err(AccountError::WrongLogin);
+        for _ in 0..FREE_FAILED_LOGINS {
+            assert!(throttle.allow("alice", start));
+            throttle.record("alice", &wrong, start);
+        }
+        // the name is blocked for everyone, in any case; other names are not
+        assert!(!throttle.allow("Alice", start + Duration::from_secs(1)));
+        assert!(throttle.allow("bob", start));
+
+        // every wrong password doubles the wait
+        let later = start + LOGIN_BACKOFF;
+        assert!(throttle.allow("alice", later));
+        throttle.record("alice", &wrong, later);
+        assert!(!throttle.allow("alice", later + LOGIN_BACKOFF));
+        assert!(throttle.allow("alice", later + 2 * LOGIN_BACKOFF));
+
+        // other failures don't count, the right password clears the count
+        throttle.record("bob", &Err(AccountError::AlreadyLoggedIn), start);
+        assert!(!throttle.failed.contains_key("bob"));
+        throttle.record("alice", &Ok(1), later + 2 * LOGIN_BACKOFF);
+        assert!(throttle.failed.is_empty());
+
+        // a wrong password long ago is forgotten
+        throttle.record("carol", &wrong, start);
+        throttle.record("alice", &wrong, start + MAX_LOGIN_BACKOFF);
+        assert!(!throttle.failed.contains_key("carol"));
+    }
+}
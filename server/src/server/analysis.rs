@@ -0,0 +1,237 @@
+use crate::chess::chess::Chess;
+use crate::chess::san::San;
+use crate::engine::tablebase::Tablebase;
+use crate::engine::uci::{UciEngine, UciInfo};
+use crate::engine::xboard::XBoardEngine;
+use crate::engine::{Engine, ExternalEngine, SearchLimits};
+use crate::server::manager::{GameManager, TaskResult};
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::states::ChessGameState;
+use chess_core::*;
+use smol::channel::Sender;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long the engine thinks about a position for an analysis request.
+const ANALYSIS_TIME: Duration = Duration::from_secs(2);
+
+/// Analyse a position for a client.
+/// Every finished search depth is sent to the client as `ServerMessage::Analysis`.
+/// Uses the external engine if one is given (and starts up), the built-in engine otherwise.
+/// Meant to run in its own task, so the `GameManager` doesn't have to wait for the engine.
+pub async fn analyse(
+    gid: GameId,
+    chess: Chess,
+    engine: Option<ExternalEngine>,
+    tx: Sender<ServerMessage>,
+) {
+    let limits = SearchLimits {
+        movetime: Some(ANALYSIS_TIME),
+        ..Default::default()
+    };
+
+    if let Some(engine) = engine {
+        let result = match &engine {
+            ExternalEngine::Uci(path) => analyse_uci(gid, &chess, path, &limits, &tx).await,
+            ExternalEngine::XBoard(path) => analyse_xboard(gid, &chess, path, &tx).await,
+        };
+        match result {
+            Ok(()) => return,
+            Err(e) => log::warn!(
+                "analysis with engine {:?} failed ({}), using the built-in engine",
+                engine,
+                e
+            ),
+        }
+    }
+
+    // the search takes a while, so it must not block the executor
+    smol::unblock(move || {
+        let mut engine = Engine::new();
+        engine.search(&chess, limits, |info| {
+            let pv = pv_to_san(&chess, &info.pv);
+            let _ = tx.try_send(ServerMessage::Analysis(gid, info.depth, info.score, pv));
+        });
+    })
+    .await;
+}
+
+async fn analyse_uci(
+    gid: GameId,
+    chess: &Chess,
+    path: &str,
+    limits: &SearchLimits,
+    tx: &Sender<ServerMessage>,
+) -> std::io::Result<()> {
+    let mut engine = UciEngine::start(path).await?;
+    engine.set_option("UCI_AnalyseMode", "true").await?;
+    engine
+        .go(&chess.get_fen(), &[], limits, |info| {
+            send_info(gid, chess, info, tx)
+        })
+        .await?;
+    engine.quit().await;
+    Ok(())
+}
+
+async fn analyse_xboard(
+    gid: GameId,
+    chess: &Chess,
+    path: &str,
+    tx: &Sender<ServerMessage>,
+) -> std::io::Result<()> {
+    let mut engine = XBoardEngine::start(path).await?;
+    engine
+        .analyse(&chess.get_fen(), &[], ANALYSIS_TIME, |info| {
+            send_info(gid, chess, info, tx)
+        })
+        .await?;
+    engine.quit().await;
+    Ok(())
+}
+
+fn send_info(gid: GameId, chess: &Chess, info: &UciInfo, tx: &Sender<ServerMessage>) {
+    // engines also send `info` lines without a score, e.g. `currmove` updates
+    if let (Some(depth), Some(score)) = (info.depth, info.score) {
+        let pv = pv_to_san(chess, &info.pv);
+        let _ = tx.try_send(ServerMessage::Analysis(gid, depth, score, pv));
+    }
+}
+
+/// Look up a position in the tablebase for a client, which gets `ServerMessage::TablebaseResult`.
+/// Like `analyse`, meant to run in its own task: the prober is an external program.
+pub async fn probe_tablebase(
+    gid: GameId,
+    chess: Chess,
+    tablebase: Arc<Tablebase>,
+    tx: Sender<ServerMessage>,
+) {
+    let entry = tablebase.probe(&chess).await.unwrap_or_else(|e| {
+        log::warn!("tablebase probe failed: {}", e);
+        None
+    });
+    let result = entry.map(|entry| {
+        let best = entry.best.map(|mov| mov.to_san(&chess));
+        (entry.wdl, entry.dtz, best.unwrap_or_default())
+    });
+    let _ = tx.send(ServerMessage::TablebaseResult(gid, result)).await;
+}
+
+/// Convert a principal variation to SAN, as long as the moves are legal.
+fn pv_to_san(chess: &Chess, pv: &[ChessMove]) -> Vec<String> {
+    let mut chess = chess.clone();
+    let mut san = vec![];
+    for &mov in pv {
+        let s = mov.to_san(&chess);
+        if chess.make_move(mov).is_err() {
+            break;
+        }
+        san.push(s);
+    }
+    san
+}
+
+impl GameManager {
+    /// The client asked for an engine analysis of the current position of a game.
+    /// The analysis runs in its own task and sends its results directly to the client.
+    pub(crate) async fn handle_analyse(&self, cid: ClientId, gid: GameId) {
+        let (Some(game), Some(client)) = (self.games.get(&gid), self.clients.get(&cid)) else {
+            return;
+        };
+        let task = analyse(
+            gid,
+            game.chess.clone(),
+            self.engine.clone(),
+            client.tx.clone(),
+        );
+        smol::spawn(task).detach();
+    }
+
+    /// The client asked for the opening book moves of the current position of a game.
+    /// Without a loaded book, or out of book, the answer is an empty list.
+    pub(crate) async fn handle_query_book_moves(&self, cid: ClientId, gid: GameId) {
+        let Some(game) = self.games.get(&gid) else {
+            return;
+        };
+        let moves = match &self.book {
+            Some(book) => book
+                .lookup(&game.chess)
+                .into_iter()
+                .map(|(mov, weight)| (mov.to_san(&game.chess), weight))
+                .collect(),
+            None => vec![],
+        };
+        let msg = ServerMessage::BookMoves(gid, moves);
+        self.send_to(cid, msg).await;
+    }
+
+    /// The client asked for the tablebase result of the current position of a game.
+    /// Without tablebases, or with too many pieces on the board, the answer is empty.
+    /// The probe runs in its own task and answers the client directly.
+    pub(crate) async fn handle_query_tablebase(&self, cid: ClientId, gid: GameId) {
+        let (Some(game), Some(client)) = (self.games.get(&gid), self.clients.get(&cid)) else {
+            return;
+        };
+        match &self.tablebase {
+            Some(tb) => {
+                let task = probe_tablebase(gid, game.chess.clone(), tb.clone(), client.tx.clone());
+                smol::spawn(task).detach();
+            }
+            None => {
+                let msg = ServerMessage::TablebaseResult(gid, None);
+                self.send_to(cid, msg).await;
+            }
+        }
+    }
+
+    /// In adjudication mode, look up the position of a game between two computer players,
+    /// so `ChessGame::get_game_state` can end the game as soon as the tablebase knows the result.
+    /// The probe runs in its own task; the result comes back as `TaskResult::Adjudication`.
+    pub(crate) fn probe_for_adjudication(&self, gid: GameId) {
+        let (Some(tb), true) = (self.tablebase.clone(), self.adjudicate) else {
+            return;
+        };
+        let Some(game) = self.games.get(&gid) else {
+            return;
+        };
+        let players = game.get_players();
+        let computers = players.len() == 2
+            && players
+                .iter()
+                .all(|cid| self.clients.get(cid).is_some_and(|c| c.is_computer()));
+        if !computers || !tb.can_probe(&game.chess) {
+            return;
+        }
+
+        let chess = game.chess.clone();
+        let tx = self.task_tx.clone();
+        smol::spawn(async move {
+            match tb.probe(&chess).await {
+                Ok(entry) => {
+                    let result =
+                        TaskResult::Adjudication(gid, chess.get_fen(), entry.map(|e| e.wdl));
+                    let _ = tx.send(result).await;
+                }
+                Err(e) => log::warn!("tablebase probe failed: {}", e),
+            }
+        })
+        .detach();
+    }
+
+    /// The tablebase result for a game between computer players is in. It only counts if the
+    /// game is still in the probed position; then the game may be over.
+    pub(crate) async fn handle_adjudication(&mut self, gid: GameId, fen: String, wdl: Option<Wdl>) {
+        let Some(game) = self.games.get_mut(&gid) else {
+            return;
+        };
+        if game.chess.get_fen() != fen {
+            return;
+        }
+        game.tablebase = wdl;
+        if let ChessGameState::Finished(reason) = game.get_game_state() {
+            self.broadcast(gid, ServerMessage::GameOver(gid, reason))
+                .await;
+            self.close_game(gid, reason).await;
+        }
+    }
+}
@@ -0,0 +1,495 @@
+use crate::server::chessgame::{ChessGame, Identity};
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{ArenaParams, ArenaStanding, NewGameParams};
+use chess_core::states::GameOverReason;
+use chess_core::{ChessColor, ClientId, GameId, TournamentId};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Points for a win and for a draw. On a streak of two wins or more, they count double.
+const WIN_POINTS: u16 = 2;
+const DRAW_POINTS: u16 = 1;
+const STREAK: u8 = 2;
+
+/// A player of an arena. Players are never removed, so their points stay on the leaderboard.
+/// Players are known by their identity; `cid` is their latest connection.
+#[derive(Debug, Clone)]
+pub struct ArenaPlayer {
+    pub cid: ClientId,
+    pub identity: Identity,
+    pub name: String,
+    pub score: u16,
+    pub games: u16,
+    pub streak: u8, // wins in a row
+    pub paused: bool,
+    pub withdrawn: bool,
+    whites: u16,
+    last_opponent: Option<usize>,
+}
+
+impl ArenaPlayer {
+    fn is_playing(&self) -> bool {
+        !self.paused && !self.withdrawn
+    }
+}
+
+/// A time-boxed tournament: as soon as a game ends, its players wait for new opponents.
+pub struct Arena {
+    pub id: TournamentId,
+    pub organizer: ClientId,
+    pub params: ArenaParams,
+    pub ends: Instant,
+    pub players: Vec<ArenaPlayer>,
+    games: HashMap<GameId, (usize, usize)>, // white and black, indices into `players`
+    waiting: Vec<usize>,                    // longest waiting first
+    finished: bool,
+}
+
+impl Arena {
+    /// The arena starts right away and runs for `params.minutes`.
+    pub fn new(id: TournamentId, organizer: ClientId, params: ArenaParams, now: Instant) -> Self {
+        let ends = now + Duration::from_secs(params.minutes as u64 * 60);
+        Arena {
+            id,
+            organizer,
+            params,
+            ends,
+            players: vec![],
+            games: HashMap::new(),
+            waiting: vec![],
+            finished: false,
+        }
+    }
+
+    /// Whether the time is up. Games that are still running count nonetheless.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Stop pairing: nobody waits for a game anymore.
+    pub fn finish(&mut self) {
+        self.finished = true;
+        self.waiting.clear();
+    }
+
+    fn index_of(&self, cid: ClientId) -> Option<usize> {
+        self.players.iter().position(|p| p.cid == cid)
+    }
+
+    fn in_game(&self, player: usize) -> bool {
+        self.games
+            .values()
+            .any(|&(white, black)| white == player || black == player)
+    }
+
+    /// Join the arena, or come back after a pause or withdrawal, also from a new connection.
+    /// Returns false if that isn't possible anymore.
+    pub fn join(&mut self, cid: ClientId, identity: Identity, name: String) -> bool {
+        if self.finished {
+            return false;
+        }
+        let player = match self.players.iter().position(|p| p.identity == identity) {
+            Some(index) => {
+                self.players[index].cid = cid;
+                self.players[index].paused = false;
+                self.players[index].withdrawn = false;
+                index
+            }
+            None => {
+                self.players.push(ArenaPlayer {
+                    cid,
+                    identity,
+                    name,
+                    score: 0,
+                    games: 0,
+                    streak: 0,
+                    paused: false,
+                    withdrawn: false,
+                    whites: 0,
+                    last_opponent: None,
+                });
+                self.players.len() - 1
+            }
+        };
+        if !self.in_game(player) && !self.waiting.contains(&player) {
+            self.waiting.push(player);
+        }
+        true
+    }
+
+    /// A player is back with a new connection, which takes their place; they stay paused
+    /// until they join again. Returns whether they take part.
+    pub fn reconnect(&mut self, identity: &Identity, cid: ClientId) -> bool {
+        match self.players.iter_mut().find(|p| &p.identity == identity) {
+            Some(player) => {
+                player.cid = cid;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take a break: the current game is played out, but no new ones follow.
+    /// Returns whether the client takes part.
+    pub fn pause(&mut self, cid: ClientId) -> bool {
+        let Some(index) = self.index_of(cid) else {
+            return false;
+        };
+        self.players[index].paused = true;
+        self.waiting.retain(|&p| p != index);
+        true
+    }
+
+    /// Leave the arena; the points stay on the leaderboard.
+    /// Returns whether the client took part.
+    pub fn withdraw(&mut self, cid: ClientId) -> bool {
+        let Some(index) = self.index_of(cid) else {
+            return false;
+        };
+        self.players[index].withdrawn = true;
+        self.waiting.retain(|&p| p != index);
+        true
+    }
+
+    /// Pair the waiting players, longest waiting first, as white and black.
+    /// Nobody plays the same opponent twice in a row, unless there is nobody else who
+    /// could become available.
+    pub fn pair(&mut self) -> Vec<(ClientId, ClientId)> {
+        let others_playing = !self.games.is_empty();
+        let mut pairs = vec![];
+        let mut i = 0;
+        while i < self.waiting.len() {
+            let a = self.waiting[i];
+            let fresh = |b: usize| self.players[a].last_opponent != Some(b);
+            let candidates = self.waiting.iter().skip(i + 1);
+            let opponent = match candidates.clone().position(|&b| fresh(b)) {
+                Some(j) => Some(j),
+                None if !others_playing && self.waiting.len() == 2 => Some(0),
+                None => None,
+            };
+            let Some(j) = opponent else {
+                i += 1;
+                continue;
+            };
+            let b = self.waiting.remove(i + 1 + j);
+            self.waiting.remove(i);
+            // fewer games as white gets white, ties go to who waited longest
+            let (white, black) = if self.players[b].whites < self.players[a].whites {
+                (b, a)
+            } else {
+                (a, b)
+            };
+            self.players[white].whites += 1;
+            pairs.push((self.players[white].cid, self.players[black].cid));
+        }
+        pairs
+    }
+
+    /// Remember the game two paired players play.
+    pub fn set_game(&mut self, gid: GameId, white: ClientId, black: ClientId) {
+        if let (Some(white), Some(black)) = (self.index_of(white), self.index_of(black)) {
+            self.games.insert(gid, (white, black));
+        }
+    }
+
+    /// Score a game of the arena. Its players wait for the next opponent, unless they paused
+    /// or the time is up. Returns false if the game isn't one of the arena.
+    pub fn record_result(&mut self, gid: GameId, white_points: f32) -> bool {
+        let Some((white, black)) = self.games.remove(&gid) else {
+            return false;
+        };
+        for (player, opponent, points) in [
+            (white, black, white_points),
+            (black, white, 1.0 - white_points),
+        ] {
+            let p = &mut self.players[player];
+            let bonus = if p.streak >= STREAK { 2 } else { 1 };
+            if points == 1.0 {
+                p.score += WIN_POINTS * bonus;
+                p.streak = p.streak.saturating_add(1);
+            } else {
+                if points == 0.5 {
+                    p.score += DRAW_POINTS * bonus;
+                }
+                p.streak = 0;
+            }
+            p.games += 1;
+            p.last_opponent = Some(opponent);
+            if p.is_playing() && !self.finished {
+                self.waiting.push(player);
+            }
+        }
+        true
+    }
+
+    /// Everyone who gets the leaderboard: the players that haven't withdrawn.
+    pub fn recipients(&self) -> Vec<ClientId> {
+        self.players
+            .iter()
+            .filter(|p| !p.withdrawn)
+            .map(|p| p.cid)
+            .collect()
+    }
+
+    /// The players by score; with the same score, fewer games ranks higher.
+    pub fn leaderboard(&self) -> Vec<ArenaStanding> {
+        let mut standings: Vec<ArenaStanding> = self
+            .players
+            .iter()
+            .map(|p| ArenaStanding {
+                player: p.cid,
+                name: p.name.clone(),
+                score: p.score,
+                games: p.games,
+                streak: p.streak,
+                playing: p.is_playing(),
+            })
+            .collect();
+        standings.sort_by(|a, b| b.score.cmp(&a.score).then(a.games.cmp(&b.games)));
+        standings
+    }
+}
+
+impl GameManager {
+    /// Open an arena. It runs right away, players join and leave while it does.
+    pub(crate) async fn handle_create_arena(&mut self, cid: ClientId, mut params: ArenaParams) {
+        params.name = params.name.trim().to_string();
+        if params.name.is_empty() || params.minutes == 0 {
+            log::warn!("client {} can't create arena {:?}", cid, params.name);
+            return;
+        }
+        params.rated &= self.ratings.is_some();
+
+        let tid = self.next_tournament_id;
+        self.next_tournament_id += 1;
+        log::info!("client {} creates arena {} ({})", cid, tid, params.name);
+        let msg = ServerMessage::ArenaCreated(tid, cid, params.clone());
+        let arena = Arena::new(tid, cid, params, Instant::now());
+        self.arenas.insert(tid, arena);
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+        }
+    }
+
+    /// Join an arena, or come back to it. Rated arenas are for players with accounts.
+    pub(crate) async fn handle_join_arena(&mut self, cid: ClientId, tid: TournamentId) {
+        let (Some(client), Some(arena)) = (self.clients.get(&cid), self.arenas.get_mut(&tid))
+        else {
+            return;
+        };
+        let identity = match client.identity() {
+            Some(identity) if !arena.params.rated || client.user.is_some() => identity,
+            _ => {
+                log::warn!("client {} can't play in arena {}", cid, tid);
+                return;
+            }
+        };
+        if !arena.join(cid, identity, client.name.clone()) {
+            log::warn!("client {} is too late for arena {}", cid, tid);
+            return;
+        }
+        self.send_leaderboard(tid).await;
+        self.pair_arena(tid).await;
+    }
+
+    /// Pause, or leave an arena for good. The game in progress is played out.
+    pub(crate) async fn handle_pause_arena(
+        &mut self,
+        cid: ClientId,
+        tid: TournamentId,
+        withdraw: bool,
+    ) {
+        let Some(arena) = self.arenas.get_mut(&tid) else {
+            return;
+        };
+        let took_part = match withdraw {
+            true => arena.withdraw(cid),
+            false => arena.pause(cid),
+        };
+        if took_part {
+            self.send_leaderboard(tid).await;
+        }
+    }
+
+    /// A player is back: their place in the arenas goes to the new connection.
+    pub(crate) async fn rejoin_arenas(&mut self, cid: ClientId, identity: &Identity) {
+        let tids: Vec<TournamentId> = self
+            .arenas
+            .iter_mut()
+            .filter_map(|(&tid, a)| a.reconnect(identity, cid).then_some(tid))
+            .collect();
+        for tid in tids {
+            log::info!("client {} is back in arena {}", cid, tid);
+            self.send_leaderboard(tid).await;
+        }
+    }
+
+    /// Start games for the players of an arena that wait for an opponent.
+    async fn pair_arena(&mut self, tid: TournamentId) {
+        let Some(arena) = self.arenas.get_mut(&tid) else {
+            return;
+        };
+        let pairs = arena.pair();
+        let params = NewGameParams {
+            mode: 0,
+            rated: arena.params.rated,
+            time: arena.params.time,
+            time_inc: arena.params.time_inc,
+        };
+        let variant = arena.params.variant;
+        for (white, black) in pairs {
+            let gid = self
+                .start_paired_game(white, black, params.clone(), variant)
+                .await;
+            if let Some(game) = self.games.get_mut(&gid) {
+                game.tournament = Some(tid);
+            }
+            if let Some(arena) = self.arenas.get_mut(&tid) {
+                arena.set_game(gid, white, black);
+            }
+        }
+    }
+
+    /// An arena game ended: the players get their points and, time permitting, new opponents.
+    pub(crate) async fn record_arena_result(
+        &mut self,
+        tid: TournamentId,
+        game: &ChessGame,
+        reason: GameOverReason,
+    ) {
+        let white_points = match reason.get_winner() {
+            Some(ChessColor::White) => 1.0,
+            Some(ChessColor::Black) => 0.0,
+            None => 0.5,
+        };
+        let Some(arena) = self.arenas.get_mut(&tid) else {
+            return;
+        };
+        if arena.record_result(game.id, white_points) {
+            self.send_leaderboard(tid).await;
+            self.pair_arena(tid).await;
+        }
+    }
+
+    /// Stop pairing in the arenas whose time is up. Running games still count.
+    pub(crate) async fn end_arenas(&mut self, now: Instant) {
+        let mut ended = vec![];
+        for arena in self.arenas.values_mut() {
+            if !arena.is_finished() && arena.ends <= now {
+                arena.finish();
+                ended.push(arena.id);
+            }
+        }
+        for tid in ended {
+            log::info!("arena {} is over", tid);
+            self.send_leaderboard(tid).await;
+        }
+    }
+
+    /// Push the leaderboard of an arena to its players.
+    async fn send_leaderboard(&self, tid: TournamentId) {
+        let Some(arena) = self.arenas.get(&tid) else {
+            return;
+        };
+        let seconds_left = match arena.is_finished() {
+            true => 0,
+            false => arena
+                .ends
+                .saturating_duration_since(Instant::now())
+                .as_secs() as u32,
+        };
+        let msg = ServerMessage::Leaderboard(tid, seconds_left, arena.leaderboard());
+        for cid in arena.recipients() {
+            self.send_to(cid, msg.clone()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_core::Variant;
+
+    fn arena() -> Arena {
+        let params = ArenaParams {
+            name: "Blitz arena".to_string(),
+            minutes: 30,
+            time: 180,
+            time_inc: 0,
+            variant: Variant::Standard,
+            rated: false,
+        };
+        Arena::new(1, 1, params, Instant::now())
+    }
+
+    fn guest(cid: ClientId) -> Identity {
+        Identity::Guest(format!("token{}", cid))
+    }
+
+    #[test]
+    fn test_arena_pairing() {
+        let mut arena = arena();
+        for cid in 1..=3 {
+            arena.join(cid, guest(cid), format!("player {}", cid));
+        }
+        assert_eq!(arena.pair(), vec![(1, 2)]);
+        arena.set_game(10, 1, 2);
+
+        // as soon as the game ends, its players meet somebody else
+        arena.record_result(10, 1.0);
+        assert_eq!(arena.pair(), vec![(3, 1)]);
+        arena.set_game(11, 3, 1);
+        assert!(arena.pair().is_empty());
+
+        // unless nobody else could come
+        arena.pause(3);
+        arena.record_result(11, 0.5);
+        assert_eq!(arena.pair(), vec![(2, 1)]);
+
+        arena.join(3, guest(3), String::new());
+        arena.finish();
+        assert!(arena.pair().is_empty());
+        assert!(!arena.join(4, guest(4), "late".to_string()));
+    }
+
+    #[test]
+    fn test_arena_streaks() {
+        let mut arena = arena();
+        arena.join(1, guest(1), "winner".to_string());
+        arena.join(2, guest(2), "loser".to_string());
+        // three wins: the third counts double, then a draw on the streak
+        for (gid, points) in [(1, 1.0), (2, 1.0), (3, 1.0), (4, 0.5)] {
+            let (white, black) = arena.pair()[0];
+            arena.set_game(gid, white, black);
+            let white_points = if white == 1 { points } else { 1.0 - points };
+            arena.record_result(gid, white_points);
+        }
+        let leaderboard = arena.leaderboard();
+        assert_eq!(leaderboard[0].player, 1);
+        assert_eq!(
+            (leaderboard[0].score, leaderboard[0].streak),
+            (2 + 2 + 4 + 2, 0)
+        );
+        assert_eq!((leaderboard[1].score, leaderboard[1].games), (1, 4));
+    }
+
+    #[test]
+    fn test_arena_rejoin() {
+        let mut arena = arena();
+        arena.join(1, guest(1), "alice".to_string());
+        arena.join(2, guest(2), "bob".to_string());
+        let (white, black) = arena.pair()[0];
+        arena.set_game(1, white, black);
+        arena.record_result(1, if white == 1 { 1.0 } else { 0.0 });
+
+        // the connection of player 1 drops; client 5 comes back with the same points
+        arena.pause(1);
+        assert!(arena.reconnect(&guest(1), 5));
+        assert!(arena.join(5, guest(1), "alice".to_string()));
+        assert_eq!(arena.players.len(), 2);
+        let leaderboard = arena.leaderboard();
+        assert_eq!((leaderboard[0].player, leaderboard[0].score), (5, 2));
+        assert_eq!(arena.pair().len(), 1);
+    }
+}
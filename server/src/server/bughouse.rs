@@ -0,0 +1,566 @@
+use crate::server::chessgame::ChessGame;
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{BughouseBoard, BughouseParams, NewGameParams};
+use chess_core::states::GameOverReason;
+use chess_core::{
+    BughouseId, ChessColor, ChessDrop, ChessError, ChessMove, ChessPiece, ClientId, GameId, Tile,
+    Variant, WoodPiece,
+};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BughouseError {
+    AlreadyStarted,
+    NoSuchSeat,
+    SeatTaken,
+    AlreadySeated,
+}
+
+impl std::fmt::Display for BughouseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BughouseError::AlreadyStarted => write!(f, "The match has already started"),
+            BughouseError::NoSuchSeat => write!(f, "There are four seats"),
+            BughouseError::SeatTaken => write!(f, "The seat is taken"),
+            BughouseError::AlreadySeated => write!(f, "Already seated"),
+        }
+    }
+}
+
+/// Index of a side in the clocks: white first.
+pub(crate) fn side(color: ChessColor) -> usize {
+    match color {
+        ChessColor::White => 0,
+        ChessColor::Black => 1,
+    }
+}
+
+/// A bughouse match: two boards, two teams of two. The seats are white and black on the
+/// first board, then white and black on the second. White on the first board plays with
+/// black on the second (the first team), and black on the first with white on the second.
+pub struct Bughouse {
+    pub id: BughouseId,
+    pub creator: ClientId,
+    pub params: BughouseParams,
+    pub seats: [Option<ClientId>; 4],
+    pub boards: Vec<GameId>,    // both boards, once the match started
+    pub result: Option<f32>,    // points of the first team
+    clocks: [[Duration; 2]; 2], // per board, white and black
+    to_move: [ChessColor; 2],
+    turn_started: [Instant; 2],
+    promoted: [Vec<Tile>; 2], // where promoted pieces stand, per board
+}
+
+impl Bughouse {
+    pub fn new(id: BughouseId, creator: ClientId, params: BughouseParams) -> Self {
+        let time = Duration::from_secs(params.time as u64);
+        let now = Instant::now();
+        Bughouse {
+            id,
+            creator,
+            params,
+            seats: [None; 4],
+            boards: vec![],
+            result: None,
+            clocks: [[time; 2]; 2],
+            to_move: [ChessColor::White; 2],
+            turn_started: [now; 2],
+            promoted: [vec![], vec![]],
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        !self.boards.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.seats.iter().all(|s| s.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seats.iter().all(|s| s.is_none())
+    }
+
+    pub fn sit(&mut self, cid: ClientId, seat: u8) -> Result<(), BughouseError> {
+        if self.is_started() {
+            return Err(BughouseError::AlreadyStarted);
+        }
+        if self.seats.contains(&Some(cid)) {
+            return Err(BughouseError::AlreadySeated);
+        }
+        match self.seats.get_mut(seat as usize) {
+            None => Err(BughouseError::NoSuchSeat),
+            Some(Some(_)) => Err(BughouseError::SeatTaken),
+            Some(seat) => {
+                *seat = Some(cid);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns whether the client had a seat. After the start, players resign instead.
+    pub fn leave(&mut self, cid: ClientId) -> bool {
+        if self.is_started() {
+            return false;
+        }
+        match self.seats.iter_mut().find(|s| **s == Some(cid)) {
+            Some(seat) => {
+                *seat = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// White and black of a board.
+    pub fn players(&self, board: usize) -> (Option<ClientId>, Option<ClientId>) {
+        (self.seats[2 * board], self.seats[2 * board + 1])
+    }
+
+    /// Both boards start at once; so do their clocks.
+    pub fn start(&mut self, boards: [GameId; 2], now: Instant) {
+        self.boards = boards.to_vec();
+        self.turn_started = [now; 2];
+    }
+
+    /// Which board a game is, the first or the second.
+    pub fn board_of(&self, gid: GameId) -> Option<usize> {
+        self.boards.iter().position(|&g| g == gid)
+    }
+
+    /// The side to move on a board moved: its clock stops and the other one runs.
+    pub fn punch_clock(&mut self, board: usize, now: Instant) {
+        let color = self.to_move[board];
+        let clock = &mut self.clocks[board][side(color)];
+        let elapsed = now.saturating_duration_since(self.turn_started[board]);
+        *clock = clock.saturating_sub(elapsed) + Duration::from_secs(self.params.time_inc as u64);
+        self.to_move[board] = !color;
+        self.turn_started[board] = now;
+    }
+
+    /// Milliseconds left for white and black on a board, counting the running clock.
+    pub fn clock_millis(&self, board: usize, now: Instant) -> [u32; 2] {
+        let mut millis = [0; 2];
+        for color in [ChessColor::White, ChessColor::Black] {
+            let mut left = self.clocks[board][side(color)];
+            if color == self.to_move[board] && self.result.is_none() {
+                let running = now.saturating_duration_since(self.turn_started[board]);
+                left = left.saturating_sub(running);
+            }
+            millis[side(color)] = left.as_millis() as u32;
+        }
+        millis
+    }
+
+    /// When the first clock runs out, if the match has a clock and runs.
+    pub fn flag_deadline(&self) -> Option<Instant> {
+        if self.params.time == 0 || !self.is_started() || self.result.is_some() {
+            return None;
+        }
+        (0..2)
+            .map(|board| self.turn_started[board] + self.clocks[board][side(self.to_move[board])])
+            .min()
+    }
+
+    /// The board and side whose time is up.
+    pub fn flagged(&self, now: Instant) -> Option<(usize, ChessColor)> {
+        self.flag_deadline().filter(|&t| t <= now)?;
+        (0..2)
+            .find(|&board| self.clock_millis(board, now)[side(self.to_move[board])] == 0)
+            .map(|board| (board, self.to_move[board]))
+    }
+
+    /// Follow a move on a board and return the piece that goes to the pocket of the other
+    /// board, if the move captured one. Promoted pieces go back as pawns.
+    pub fn captured(
+        &mut self,
+        board: usize,
+        mov: &ChessMove,
+        captured: Option<WoodPiece>,
+    ) -> Option<WoodPiece> {
+        let promoted = &mut self.promoted[board];
+        let was_promoted = promoted.contains(&mov.dst);
+        promoted.retain(|&t| t != mov.dst);
+        match promoted.iter_mut().find(|t| **t == mov.src) {
+            Some(tile) => *tile = mov.dst,
+            None if mov.special.is_some() => promoted.push(mov.dst),
+            None => {}
+        }
+        captured.map(|piece| match was_promoted {
+            true => WoodPiece::new(ChessPiece::Pawn, piece.color),
+            false => piece,
+        })
+    }
+
+    /// A board ended with that winner, `None` for a draw: the points of the first team,
+    /// which plays white on the first board and black on the second.
+    pub fn team_points(board: usize, winner: Option<ChessColor>) -> f32 {
+        let first_team = match board {
+            0 => ChessColor::White,
+            _ => ChessColor::Black,
+        };
+        match winner {
+            Some(color) if color == first_team => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        }
+    }
+
+    /// The winner on a board, `None` for a draw, given the points of the first team.
+    pub fn winner_on(board: usize, team_points: f32) -> Option<ChessColor> {
+        let first_team = match board {
+            0 => ChessColor::White,
+            _ => ChessColor::Black,
+        };
+        match team_points {
+            1.0 => Some(first_team),
+            0.0 => Some(!first_team),
+            _ => None,
+        }
+    }
+}
+
+impl GameManager {
+    /// Open a bughouse match. It starts as soon as all four seats are taken.
+    pub(crate) async fn handle_create_bughouse(&mut self, cid: ClientId, params: BughouseParams) {
+        let bid = self.next_bughouse_id;
+        self.next_bughouse_id += 1;
+        log::info!("client {} opens bughouse match {}", cid, bid);
+        let bughouse = Bughouse::new(bid, cid, params.clone());
+        let seats = ServerMessage::BughouseSeats(bid, bughouse.seats);
+        self.bughouses.insert(bid, bughouse);
+        let msg = ServerMessage::BughouseCreated(bid, cid, params);
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+            let _ = c.tx.send(seats.clone()).await;
+        }
+    }
+
+    pub(crate) async fn handle_join_bughouse(&mut self, cid: ClientId, bid: BughouseId, seat: u8) {
+        let (Some(client), Some(bughouse)) = (self.clients.get(&cid), self.bughouses.get_mut(&bid))
+        else {
+            return;
+        };
+        // computer players don't know about drops
+        if client.is_computer() {
+            log::warn!("client {} can't play bughouse", cid);
+            return;
+        }
+        if let Err(e) = bughouse.sit(cid, seat) {
+            log::warn!("client {} can't sit in bughouse match {}: {}", cid, bid, e);
+            return;
+        }
+        let full = bughouse.is_full();
+        self.announce_bughouse_seats(bid).await;
+        if full {
+            self.start_bughouse(bid).await;
+        }
+    }
+
+    /// Get up before the match starts. A match nobody sits at anymore is closed.
+    pub(crate) async fn handle_leave_bughouse(&mut self, cid: ClientId, bid: BughouseId) {
+        let Some(bughouse) = self.bughouses.get_mut(&bid) else {
+            return;
+        };
+        if !bughouse.leave(cid) {
+            return;
+        }
+        self.announce_bughouse_seats(bid).await;
+        if self.bughouses.get(&bid).is_some_and(|b| b.is_empty()) {
+            log::info!("bughouse match {} closed", bid);
+            self.bughouses.remove(&bid);
+        }
+    }
+
+    /// Both boards start at the same time, and so do their clocks.
+    async fn start_bughouse(&mut self, bid: BughouseId) {
+        let Some(bughouse) = self.bughouses.get(&bid) else {
+            return;
+        };
+        let params = NewGameParams {
+            mode: 0,
+            rated: false,
+            time: bughouse.params.time,
+            time_inc: bughouse.params.time_inc,
+        };
+        let pairs = [bughouse.players(0), bughouse.players(1)];
+        let mut boards = [0; 2];
+        for (board, pair) in pairs.into_iter().enumerate() {
+            let (Some(white), Some(black)) = pair else {
+                return;
+            };
+            let gid = self
+                .start_paired_game(white, black, params.clone(), Variant::Standard)
+                .await;
+            if let Some(game) = self.games.get_mut(&gid) {
+                game.bughouse = Some(bid);
+            }
+            // it got stored when it started, but won't be kept over a restart
+            if let Some(store) = &self.store {
+                let _ = store.remove(gid).await;
+            }
+            boards[board] = gid;
+        }
+        log::info!("bughouse match {} starts in games {:?}", bid, boards);
+        if let Some(bughouse) = self.bughouses.get_mut(&bid) {
+            bughouse.start(boards, Instant::now());
+        }
+        self.update_bughouse(boards[0]).await;
+    }
+
+    /// Handle a drop of a piece from the pocket, in a bughouse game. Like a move, it is
+    /// accepted or rejected.
+    pub(crate) async fn handle_drop(&mut self, cid: ClientId, gid: GameId, drop: ChessDrop) {
+        let Some(game) = self.games.get_mut(&gid) else {
+            let msg = ServerMessage::IllegalMove(ChessError::IllegalDrop(drop));
+            self.send_to(cid, msg).await;
+            return;
+        };
+        match game.drop_piece(drop, cid) {
+            Ok(changes) => {
+                let san = game.move_history.last().unwrap().san.clone();
+                game.in_book.push(false);
+                game.draw_offer_white = false;
+                game.draw_offer_black = false;
+                game.takeback_offer = None;
+
+                let changes = changes
+                    .iter()
+                    .map(|(t, p)| (*t, p.map(|piece| piece.piece)))
+                    .collect();
+                let msg = ServerMessage::MoveAccepted(gid, san.len() as u8, san, changes);
+                self.broadcast(gid, msg).await;
+                self.bughouse_move(gid, None, None);
+                self.after_move(gid).await;
+            }
+            Err(e) => self.send_to(cid, ServerMessage::IllegalMove(e)).await,
+        }
+    }
+
+    /// A move or drop on a bughouse board: its clock switches sides, and a captured piece
+    /// goes to the pocket of the other board.
+    pub(crate) fn bughouse_move(
+        &mut self,
+        gid: GameId,
+        mov: Option<&ChessMove>,
+        captured: Option<WoodPiece>,
+    ) {
+        let Some(bid) = self.games.get(&gid).and_then(|g| g.bughouse) else {
+            return;
+        };
+        let Some(bughouse) = self.bughouses.get_mut(&bid) else {
+            return;
+        };
+        let Some(board) = bughouse.board_of(gid) else {
+            return;
+        };
+        bughouse.punch_clock(board, Instant::now());
+        let Some(mov) = mov else {
+            return;
+        };
+        let piece = bughouse.captured(board, mov, captured);
+        let other = bughouse.boards[1 - board];
+        if let (Some(piece), Some(game)) = (piece, self.games.get_mut(&other)) {
+            game.pocket.push(piece);
+        }
+    }
+
+    /// After a move on a bughouse board, everybody in the match sees both boards again.
+    pub(crate) async fn update_bughouse(&self, gid: GameId) {
+        let Some(bid) = self.games.get(&gid).and_then(|g| g.bughouse) else {
+            return;
+        };
+        let Some(bughouse) = self.bughouses.get(&bid) else {
+            return;
+        };
+        let (Some(first), Some(second)) = (
+            self.games.get(&bughouse.boards[0]),
+            self.games.get(&bughouse.boards[1]),
+        ) else {
+            return;
+        };
+        self.send_bughouse_state(bid, [first, second]).await;
+    }
+
+    /// A board of a bughouse match ended, and so does the match: the other board ends
+    /// with the same result for each team.
+    pub(crate) async fn record_bughouse_result(
+        &mut self,
+        bid: BughouseId,
+        game: &ChessGame,
+        reason: GameOverReason,
+    ) {
+        let Some(bughouse) = self.bughouses.get_mut(&bid) else {
+            return;
+        };
+        let Some(board) = bughouse.board_of(game.id) else {
+            return;
+        };
+        let points = Bughouse::team_points(board, reason.get_winner());
+        bughouse.result = Some(points);
+        log::info!(
+            "bughouse match {} is over, {} to {}",
+            bid,
+            points,
+            1.0 - points
+        );
+
+        let other = bughouse.boards[1 - board];
+        let other_reason = GameOverReason::Adjudication(Bughouse::winner_on(1 - board, points));
+        self.broadcast(other, ServerMessage::GameOver(other, other_reason))
+            .await;
+        if let Some(other_game) = self.remove_game(other).await {
+            let _ = self.save_game(&other_game).await;
+            let games = match board {
+                0 => [game, &other_game],
+                _ => [&other_game, game],
+            };
+            self.send_bughouse_state(bid, games).await;
+        }
+        self.bughouses.remove(&bid);
+    }
+
+    /// End the bughouse matches where a clock ran out: that side loses its board.
+    pub(crate) async fn end_flagged_bughouses(&mut self, now: Instant) {
+        let flagged: Vec<(GameId, ChessColor)> = self
+            .bughouses
+            .values()
+            .filter_map(|b| {
+                let (board, color) = b.flagged(now)?;
+                Some((b.boards[board], color))
+            })
+            .collect();
+        for (gid, color) in flagged {
+            log::info!("{} ran out of time in game {}", color, gid);
+            let reason = GameOverReason::TimeOut(!color);
+            self.broadcast(gid, ServerMessage::GameOver(gid, reason))
+                .await;
+            self.close_game(gid, reason).await;
+        }
+    }
+
+    /// Tell everyone who sits at a bughouse match so far.
+    async fn announce_bughouse_seats(&self, bid: BughouseId) {
+        let Some(bughouse) = self.bughouses.get(&bid) else {
+            return;
+        };
+        let msg = ServerMessage::BughouseSeats(bid, bughouse.seats);
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+        }
+    }
+
+    /// Send both boards of a bughouse match, with their pockets and clocks, to the players
+    /// and spectators of either board.
+    async fn send_bughouse_state(&self, bid: BughouseId, games: [&ChessGame; 2]) {
+        let Some(bughouse) = self.bughouses.get(&bid) else {
+            return;
+        };
+        let now = Instant::now();
+        let boards = [0, 1].map(|board| {
+            let (white, black) = bughouse.players(board);
+            BughouseBoard {
+                game: games[board].id,
+                white: white.unwrap_or_default(),
+                black: black.unwrap_or_default(),
+                fen: games[board].chess.get_fen(),
+                pocket: games[board].pocket.clone(),
+                clocks: bughouse.clock_millis(board, now),
+            }
+        });
+        let msg = ServerMessage::BughouseState(bid, bughouse.result, boards);
+        let mut recipients = games[0].get_all_participants();
+        for cid in games[1].get_all_participants() {
+            if !recipients.contains(&cid) {
+                recipients.push(cid);
+            }
+        }
+        for cid in recipients {
+            self.send_to(cid, msg.clone()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bughouse() -> Bughouse {
+        let params = BughouseParams {
+            time: 60,
+            time_inc: 0,
+        };
+        Bughouse::new(1, 10, params)
+    }
+
+    #[test]
+    fn test_bughouse_seats() {
+        let mut bughouse = bughouse();
+        assert_eq!(bughouse.sit(10, 4), Err(BughouseError::NoSuchSeat));
+        assert_eq!(bughouse.sit(10, 0), Ok(()));
+        assert_eq!(bughouse.sit(10, 1), Err(BughouseError::AlreadySeated));
+        assert_eq!(bughouse.sit(20, 0), Err(BughouseError::SeatTaken));
+        for (cid, seat) in [(20, 1), (30, 2), (40, 3)] {
+            assert_eq!(bughouse.sit(cid, seat), Ok(()));
+        }
+        assert!(bughouse.leave(40));
+        assert!(!bughouse.is_full());
+        assert_eq!(bughouse.sit(40, 3), Ok(()));
+        assert!(bughouse.is_full());
+
+        bughouse.start([100, 101], Instant::now());
+        assert!(!bughouse.leave(40));
+        assert_eq!(bughouse.sit(50, 0), Err(BughouseError::AlreadyStarted));
+        assert_eq!(bughouse.board_of(101), Some(1));
+        assert_eq!(bughouse.players(1), (Some(30), Some(40)));
+
+        // white mates on the first board: the first team wins, so black on the second
+        let points = Bughouse::team_points(0, Some(ChessColor::White));
+        assert_eq!(points, 1.0);
+        assert_eq!(Bughouse::winner_on(1, points), Some(ChessColor::Black));
+        assert_eq!(Bughouse::winner_on(1, 0.5), None);
+    }
+
+    #[test]
+    fn test_bughouse_captures() {
+        let mut bughouse = bughouse();
+        let mov = |s: &str| s.parse::<ChessMove>().unwrap();
+        let queen = WoodPiece::new(ChessPiece::Queen, ChessColor::Black);
+
+        // a promoted queen moves on and gets taken: it goes over as a pawn
+        assert_eq!(bughouse.captured(0, &mov("b7a8Q"), None), None);
+        assert_eq!(bughouse.captured(0, &mov("a8a5"), None), None);
+        let taken = bughouse.captured(0, &mov("a6a5"), Some(queen));
+        assert_eq!(
+            taken,
+            Some(WoodPiece::new(ChessPiece::Pawn, ChessColor::Black))
+        );
+        // a real queen stays a queen
+        assert_eq!(bughouse.captured(0, &mov("d1d8"), Some(queen)), Some(queen));
+    }
+
+    #[test]
+    fn test_bughouse_clocks() {
+        let mut bughouse = bughouse();
+        let start = Instant::now();
+        bughouse.start([100, 101], start);
+        assert_eq!(
+            bughouse.flag_deadline(),
+            Some(start + Duration::from_secs(60))
+        );
+
+        // white on the first board takes 10 seconds, black then has the full minute
+        bughouse.punch_clock(0, start + Duration::from_secs(10));
+        let later = start + Duration::from_secs(20);
+        assert_eq!(bughouse.clock_millis(0, later), [50_000, 50_000]);
+        assert_eq!(bughouse.clock_millis(1, later), [40_000, 60_000]);
+        assert_eq!(
+            bughouse.flag_deadline(),
+            Some(start + Duration::from_secs(60))
+        );
+        assert_eq!(bughouse.flagged(later), None);
+        let over = start + Duration::from_secs(61);
+        assert_eq!(bughouse.flagged(over), Some((1, ChessColor::White)));
+    }
+}
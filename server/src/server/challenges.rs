@@ -0,0 +1,236 @@
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{
+    ChallengeOutcome, ChallengeParams, DeclineReason, NewGameParams, UserRoleSelection,
+};
+use chess_core::{ChallengeId, ClientId};
+use rand::RngExt;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long a challenge waits for an answer.
+pub const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A game proposed by one player to another, waiting for an answer.
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub id: ChallengeId,
+    pub challenger: ClientId,
+    pub challenged: ClientId,
+    pub params: ChallengeParams,
+    pub expires: Instant,
+}
+
+/// The open challenges.
+#[derive(Default)]
+pub struct Challenges {
+    next_id: ChallengeId,
+    open: HashMap<ChallengeId, Challenge>,
+}
+
+impl Challenges {
+    /// Open a new challenge that expires `CHALLENGE_TIMEOUT` after `now`.
+    pub fn add(
+        &mut self,
+        challenger: ClientId,
+        challenged: ClientId,
+        params: ChallengeParams,
+        now: Instant,
+    ) -> &Challenge {
+        self.next_id += 1;
+        let challenge = Challenge {
+            id: self.next_id,
+            challenger,
+            challenged,
+            params,
+            expires: now + CHALLENGE_TIMEOUT,
+        };
+        self.open.entry(challenge.id).or_insert(challenge)
+    }
+
+    pub fn get(&self, id: ChallengeId) -> Option<&Challenge> {
+        self.open.get(&id)
+    }
+
+    pub fn remove(&mut self, id: ChallengeId) -> Option<Challenge> {
+        self.open.remove(&id)
+    }
+
+    /// Remove all challenges from or to a client.
+    pub fn remove_client(&mut self, cid: ClientId) -> Vec<Challenge> {
+        self.remove_where(|c| c.challenger == cid || c.challenged == cid)
+    }
+
+    /// Remove the challenges that have not been answered in time.
+    pub fn remove_expired(&mut self, now: Instant) -> Vec<Challenge> {
+        self.remove_where(|c| c.expires <= now)
+    }
+
+    /// When the next challenge expires, if there is any.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.open.values().map(|c| c.expires).min()
+    }
+
+    fn remove_where(&mut self, f: impl Fn(&Challenge) -> bool) -> Vec<Challenge> {
+        let ids: Vec<ChallengeId> = self.open.values().filter(|c| f(c)).map(|c| c.id).collect();
+        ids.iter().filter_map(|id| self.open.remove(id)).collect()
+    }
+}
+
+impl GameManager {
+    /// Challenge another player to a game.
+    pub(crate) async fn handle_challenge(
+        &mut self,
+        cid: ClientId,
+        challenged: ClientId,
+        mut params: ChallengeParams,
+    ) {
+        let known = self
+            .clients
+            .get(&challenged)
+            .is_some_and(|c| !c.is_computer());
+        if cid == challenged || !known {
+            log::warn!("client {} can't challenge client {}", cid, challenged);
+            return;
+        }
+        if !matches!(
+            params.color,
+            UserRoleSelection::White | UserRoleSelection::Black | UserRoleSelection::Random
+        ) {
+            log::warn!("client {} challenges to play as {}", cid, params.color);
+            return;
+        }
+        // without ratings, there are only casual games; rated ones need two accounts
+        params.rated &= self.ratings.is_some();
+        let has_account = |cid| self.clients.get(&cid).is_some_and(|c| c.user.is_some());
+        if params.rated && !(has_account(cid) && has_account(challenged)) {
+            log::warn!(
+                "rated challenge of {} to {} without accounts",
+                cid,
+                challenged
+            );
+            return;
+        }
+
+        let challenge = self.challenges.add(cid, challenged, params, Instant::now());
+        log::info!(
+            "client {} challenges {} (challenge {})",
+            cid,
+            challenged,
+            challenge.id
+        );
+        let msg =
+            ServerMessage::ChallengeIssued(challenge.id, cid, challenged, challenge.params.clone());
+        self.send_to(cid, msg.clone()).await;
+        self.send_to(challenged, msg).await;
+    }
+
+    /// The challenged player takes the challenge: the game starts with the agreed colours.
+    pub(crate) async fn handle_accept_challenge(&mut self, cid: ClientId, id: ChallengeId) {
+        if self.challenges.get(id).is_none_or(|c| c.challenged != cid) {
+            log::warn!("client {} can't accept challenge {}", cid, id);
+            return;
+        }
+        let Some(challenge) = self.challenges.remove(id) else {
+            return;
+        };
+        self.close_challenge(&challenge, ChallengeOutcome::Accepted)
+            .await;
+
+        let challenger_white = match challenge.params.color {
+            UserRoleSelection::White => true,
+            UserRoleSelection::Black => false,
+            _ => rand::rng().random_bool(0.5),
+        };
+        let (white, black) = if challenger_white {
+            (challenge.challenger, challenge.challenged)
+        } else {
+            (challenge.challenged, challenge.challenger)
+        };
+        let params = NewGameParams {
+            mode: 0,
+            rated: challenge.params.rated,
+            time: challenge.params.time,
+            time_inc: challenge.params.time_inc,
+        };
+        let gid = self
+            .start_paired_game(white, black, params, challenge.params.variant)
+            .await;
+        log::info!("challenge {} accepted, game {}", id, gid);
+    }
+
+    pub(crate) async fn handle_decline_challenge(
+        &mut self,
+        cid: ClientId,
+        id: ChallengeId,
+        reason: DeclineReason,
+    ) {
+        if self.challenges.get(id).is_none_or(|c| c.challenged != cid) {
+            log::warn!("client {} can't decline challenge {}", cid, id);
+            return;
+        }
+        if let Some(challenge) = self.challenges.remove(id) {
+            self.close_challenge(&challenge, ChallengeOutcome::Declined(reason))
+                .await;
+        }
+    }
+
+    pub(crate) async fn handle_cancel_challenge(&mut self, cid: ClientId, id: ChallengeId) {
+        if self.challenges.get(id).is_none_or(|c| c.challenger != cid) {
+            log::warn!("client {} can't cancel challenge {}", cid, id);
+            return;
+        }
+        if let Some(challenge) = self.challenges.remove(id) {
+            self.close_challenge(&challenge, ChallengeOutcome::Cancelled)
+                .await;
+        }
+    }
+
+    /// Tell both players how a challenge ended.
+    pub(crate) async fn close_challenge(&self, challenge: &Challenge, outcome: ChallengeOutcome) {
+        let msg = ServerMessage::ChallengeClosed(challenge.id, outcome);
+        self.send_to(challenge.challenger, msg.clone()).await;
+        self.send_to(challenge.challenged, msg).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_core::protocol::UserRoleSelection;
+    use chess_core::Variant;
+
+    #[test]
+    fn test_challenges() {
+        let params = ChallengeParams {
+            time: 600,
+            time_inc: 0,
+            variant: Variant::Standard,
+            rated: false,
+            color: UserRoleSelection::Random,
+        };
+        let start = Instant::now();
+        let mut challenges = Challenges::default();
+        assert_eq!(challenges.next_expiry(), None);
+
+        let first = challenges.add(1, 2, params.clone(), start).id;
+        let later = start + Duration::from_secs(10);
+        let second = challenges.add(3, 1, params.clone(), later).id;
+        let third = challenges.add(3, 4, params, later).id;
+        assert_eq!(challenges.next_expiry(), Some(start + CHALLENGE_TIMEOUT));
+
+        // client 1 leaves: its challenges go, either way
+        let mut removed: Vec<ChallengeId> =
+            challenges.remove_client(1).iter().map(|c| c.id).collect();
+        removed.sort();
+        assert_eq!(removed, vec![first, second]);
+
+        assert!(challenges
+            .remove_expired(start + CHALLENGE_TIMEOUT)
+            .is_empty());
+        let expired = challenges.remove_expired(later + CHALLENGE_TIMEOUT);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, third);
+        assert!(challenges.get(third).is_none());
+    }
+}
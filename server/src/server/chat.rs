@@ -0,0 +1,306 @@
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{ChatChannel, UserRoleSelection};
+use chess_core::{ClientId, GameId};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The longest chat line, in characters.
+pub const MAX_CHAT_LEN: usize = 140;
+/// How many lines of a channel newcomers get to see.
+pub const CHAT_REPLAY: usize = 20;
+/// A client may send `RATE_LIMIT` lines per `RATE_WINDOW`.
+const RATE_LIMIT: usize = 5;
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// A line of chat, as it went out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatLine {
+    pub channel: ChatChannel,
+    pub sender: ClientId,
+    pub name: String,
+    pub text: String,
+}
+
+impl ChatLine {
+    pub fn to_message(&self) -> ServerMessage {
+        ServerMessage::Chat(
+            self.channel,
+            self.sender,
+            self.name.clone(),
+            self.text.clone(),
+        )
+    }
+}
+
+/// `[game] alice: good luck`, for game records.
+impl fmt::Display for ChatLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let channel = match self.channel {
+            ChatChannel::Lobby => "lobby",
+            ChatChannel::Game(_) => "game",
+            ChatChannel::Kibitz(_) => "kibitz",
+            ChatChannel::Team(..) => "team",
+        };
+        write!(f, "[{}] {}: {}", channel, self.name, self.text)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChatError {
+    Empty,
+    TooLong,
+    TooFast,
+    NotAllowed, // not a player (game) or not a spectator (kibitz)
+}
+
+impl fmt::Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatError::Empty => write!(f, "Empty message"),
+            ChatError::TooLong => write!(f, "Messages have at most {} characters", MAX_CHAT_LEN),
+            ChatError::TooFast => write!(f, "Too many messages, slow down"),
+            ChatError::NotAllowed => write!(f, "You can't talk in this channel"),
+        }
+    }
+}
+
+/// Tidy up a chat line: no control characters, no surrounding whitespace.
+pub fn clean_text(text: &str) -> Result<String, ChatError> {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    let text = text.trim();
+    if text.is_empty() {
+        Err(ChatError::Empty)
+    } else if text.chars().count() > MAX_CHAT_LEN {
+        Err(ChatError::TooLong)
+    } else {
+        Ok(text.to_string())
+    }
+}
+
+/// The last lines of the lobby, for newcomers.
+#[derive(Default)]
+pub struct ChatHistory {
+    lines: VecDeque<ChatLine>,
+}
+
+impl ChatHistory {
+    pub fn push(&mut self, line: ChatLine) {
+        if self.lines.len() == CHAT_REPLAY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &ChatLine> {
+        self.lines.iter()
+    }
+}
+
+/// The last lines of a channel in a full chat log, oldest first.
+pub fn replay(lines: &[ChatLine], channel: ChatChannel) -> Vec<&ChatLine> {
+    let mut replay: Vec<&ChatLine> = lines
+        .iter()
+        .rev()
+        .filter(|l| l.channel == channel)
+        .take(CHAT_REPLAY)
+        .collect();
+    replay.reverse();
+    replay
+}
+
+/// Counts the recent lines of every client.
+#[derive(Default)]
+pub struct RateLimiter {
+    sent: HashMap<ClientId, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    /// May the client send another line now? If so, the line is counted.
+    pub fn allow(&mut self, cid: ClientId, now: Instant) -> bool {
+        let sent = self.sent.entry(cid).or_default();
+        while sent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= RATE_LIMIT {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+
+    pub fn remove(&mut self, cid: ClientId) {
+        self.sent.remove(&cid);
+    }
+}
+
+impl GameManager {
+    /// A chat line from a client: players talk in their game, spectators kibitz,
+    /// everyone can talk in the lobby.
+    pub(crate) async fn handle_chat(&mut self, cid: ClientId, channel: ChatChannel, text: String) {
+        let line = match self.check_chat(cid, channel, &text) {
+            Ok(line) => line,
+            Err(e) => {
+                log::warn!("chat of client {} in {} rejected: {}", cid, channel, e);
+                self.send_to(cid, ServerMessage::ChatRejected(e.to_string()))
+                    .await;
+                return;
+            }
+        };
+
+        let msg = line.to_message();
+        let mut recipients = match channel {
+            ChatChannel::Lobby => {
+                self.lobby_chat.push(line);
+                let clients = self.clients.iter();
+                clients
+                    .filter(|(_, c)| !c.is_computer())
+                    .map(|(cid, _)| *cid)
+                    .collect()
+            }
+            ChatChannel::Game(gid) | ChatChannel::Kibitz(gid) | ChatChannel::Team(gid, _) => {
+                let Some(game) = self.games.get_mut(&gid) else {
+                    return;
+                };
+                game.chat.push(line);
+                match (channel, &game.consultation) {
+                    (ChatChannel::Game(_), _) => game.get_players(),
+                    (ChatChannel::Team(_, color), Some(consultation)) => {
+                        consultation.team(color).to_vec()
+                    }
+                    (ChatChannel::Team(..), None) => vec![],
+                    _ => game.spectators.clone(),
+                }
+            }
+        };
+        recipients.dedup(); // one client can play both sides
+        for recipient in recipients {
+            self.send_to(recipient, msg.clone()).await;
+        }
+    }
+
+    /// Check who may say what where, and how often.
+    fn check_chat(
+        &mut self,
+        cid: ClientId,
+        channel: ChatChannel,
+        text: &str,
+    ) -> Result<ChatLine, ChatError> {
+        let text = clean_text(text)?;
+        let Some(client) = self.clients.get(&cid) else {
+            return Err(ChatError::NotAllowed);
+        };
+        let allowed = match channel {
+            ChatChannel::Lobby => !client.is_computer(),
+            ChatChannel::Game(gid) => self
+                .games
+                .get(&gid)
+                .is_some_and(|g| g.get_players().contains(&cid)),
+            ChatChannel::Kibitz(gid) => self
+                .games
+                .get(&gid)
+                .is_some_and(|g| g.spectators.contains(&cid)),
+            ChatChannel::Team(gid, color) => self
+                .games
+                .get(&gid)
+                .and_then(|g| g.consultation.as_ref())
+                .is_some_and(|c| c.side_of(cid) == Some(color)),
+        };
+        if !allowed {
+            return Err(ChatError::NotAllowed);
+        }
+        if !self.chat_limiter.allow(cid, Instant::now()) {
+            return Err(ChatError::TooFast);
+        }
+        Ok(ChatLine {
+            channel,
+            sender: cid,
+            name: client.name.clone(),
+            text,
+        })
+    }
+
+    /// Show a client that just joined a game what was said before: the game chat to
+    /// players, the kibitz to spectators, and to a member of a team what the team said.
+    pub(crate) async fn replay_chat(&self, cid: ClientId, gid: GameId, side: UserRoleSelection) {
+        let Some(game) = self.games.get(&gid) else {
+            return;
+        };
+        let mut channels = vec![match side {
+            UserRoleSelection::Spectator => ChatChannel::Kibitz(gid),
+            _ => ChatChannel::Game(gid),
+        }];
+        if let Some(color) = game.consultation.as_ref().and_then(|c| c.side_of(cid)) {
+            channels.push(ChatChannel::Team(gid, color));
+        }
+        for channel in channels {
+            for line in replay(&game.chat, channel) {
+                self.send_to(cid, line.to_message()).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(channel: ChatChannel, text: &str) -> ChatLine {
+        ChatLine {
+            channel,
+            sender: 1,
+            name: "alice".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_clean_text() {
+        assert_eq!(clean_text("  hi\u{7}  ").unwrap(), "hi");
+        assert_eq!(clean_text(" \n "), Err(ChatError::Empty));
+        assert!(clean_text(&"ä".repeat(MAX_CHAT_LEN)).is_ok());
+        assert_eq!(
+            clean_text(&"a".repeat(MAX_CHAT_LEN + 1)),
+            Err(ChatError::TooLong)
+        );
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+        for _ in 0..RATE_LIMIT {
+            assert!(limiter.allow(1, start));
+        }
+        assert!(!limiter.allow(1, start + Duration::from_secs(1)));
+        assert!(limiter.allow(2, start));
+        assert!(limiter.allow(1, start + RATE_WINDOW));
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut lines = vec![line(ChatChannel::Kibitz(1), "spectators only")];
+        for i in 0..CHAT_REPLAY + 5 {
+            lines.push(line(ChatChannel::Game(1), &i.to_string()));
+        }
+        let game = replay(&lines, ChatChannel::Game(1));
+        assert_eq!(game.len(), CHAT_REPLAY);
+        assert_eq!(game[0].text, "5");
+        assert_eq!(replay(&lines, ChatChannel::Kibitz(1)).len(), 1);
+
+        let mut lobby = ChatHistory::default();
+        for l in lines {
+            lobby.push(l);
+        }
+        assert_eq!(lobby.lines().count(), CHAT_REPLAY);
+        assert_eq!(lobby.lines().next().unwrap().text, "5");
+        assert_eq!(
+            line(ChatChannel::Game(1), "gl").to_string(),
+            "[game] alice: gl"
+        );
+    }
+}
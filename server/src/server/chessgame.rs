@@ -1,9 +1,35 @@
 use crate::chess::chess::Chess;
 use crate::chess::pieces::Piece;
-use chess_core::protocol::UserRoleSelection;
+use crate::chess::san::{drop_to_san, San};
+use crate::engine::tablebase::wdl_winner;
+use crate::server::bughouse::side;
+use crate::server::chat::ChatLine;
+use crate::server::consultation::Consultation;
+use chess_core::protocol::{MoveRecord, UserRoleSelection};
 use chess_core::states::{ChessGameState, GameOverReason};
 use chess_core::*;
-use std::time::{SystemTime, UNIX_EPOCH};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How long a game may stay without anybody in it before it is removed.
+pub const EMPTY_GAME_GRACE: Duration = Duration::from_secs(60);
+
+/// Who a player is, beyond the current connection.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Identity {
+    User(UserId),  // logged in to an account
+    Guest(String), // by the SHA-256 of the secret token of their connection, see `guest`
+}
+
+impl Identity {
+    /// The identity of a guest with this reconnect token. Only a hash of the token is kept,
+    /// so the game store and the logs don't give away what takes over a guest's seat.
+    /// The token is random and long, a plain hash is enough.
+    pub fn guest(token: &str) -> Identity {
+        let hash = Sha256::digest(token.as_bytes());
+        Identity::Guest(hash.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
 
 /// A `ChessGame` represents a real chess game between two players.
 /// It wraps the 'raw' `Chess` struct, which is basically only the board and the rules,
@@ -11,22 +37,88 @@ use std::time::{SystemTime, UNIX_EPOCH};
 pub struct ChessGame {
     pub id: GameId,
     pub chess: Chess,
+    pub start_fen: String, // the position before `move_history`
 
     pub _started: bool,
     pub white_player: Option<ClientId>,
     pub black_player: Option<ClientId>,
     pub spectators: Vec<ClientId>,
+    // empty seats kept for players that are gone for now (restart, lost connection)
+    pub white_reserved: Option<Identity>,
+    pub black_reserved: Option<Identity>,
+    // since when a player has been gone from their seat, and since when nobody is in the game
+    pub white_gone: Option<Instant>,
+    pub black_gone: Option<Instant>,
+    pub empty_since: Option<Instant>,
 
     pub _time: u32,
     pub _time_inc: u32,
+    pub rated: bool,
+    pub variant: Variant,
+    pub tournament: Option<TournamentId>, // or arena
+    pub simul: Option<SimulId>,
+    pub bughouse: Option<BughouseId>,
+    pub pocket: Vec<WoodPiece>, // bughouse: pieces to drop, of both sides
+    pub consultation: Option<Consultation>, // teams that vote on their moves
 
     pub draw_offer_white: bool,
     pub draw_offer_black: bool,
 
-    pub move_history: Vec<String>,
+    pub move_history: Vec<MoveRecord>,
+    pub in_book: Vec<bool>, // for each move in `move_history`: was it a book move?
+    pub turn_started: Instant, // when the side to move got the move
+    pub clocks: [Duration; 2], // time left for white and black, not counting the running move
+
+    // tablebase result of the current position, only probed in adjudication mode
+    pub tablebase: Option<Wdl>,
+
+    pub takeback_offer: Option<(ChessColor, u8)>, // who asked, and for how many plies
+
+    pub chat: Vec<ChatLine>, // of the players and the spectators, for the game record
 }
 
 impl ChessGame {
+    /// A game without players, starting from the given position.
+    pub fn new(id: GameId, chess: Chess, time: u32, time_inc: u32) -> Self {
+        ChessGame {
+            id,
+            start_fen: chess.get_fen(),
+            chess,
+            _started: false,
+            white_player: None,
+            black_player: None,
+            spectators: vec![],
+            white_reserved: None,
+            black_reserved: None,
+            white_gone: None,
+            black_gone: None,
+            empty_since: Some(Instant::now()),
+            _time: time,
+            _time_inc: time_inc,
+            rated: false,
+            variant: Variant::Standard,
+            tournament: None,
+            simul: None,
+            bughouse: None,
+            pocket: vec![],
+            consultation: None,
+            draw_offer_white: false,
+            draw_offer_black: false,
+            move_history: vec![],
+            in_book: vec![],
+            turn_started: Instant::now(),
+            clocks: [Duration::from_secs(time as u64); 2],
+            tablebase: None,
+            takeback_offer: None,
+            chat: vec![],
+        }
+    }
+
+    /// Ratings are kept per time category.
+    pub fn time_category(&self) -> TimeCategory {
+        TimeCategory::from_clock(self._time, self._time_inc)
+    }
+
     /// Starts a chess game.
     /// Chess game can only start when two players are joined.
     pub fn _start_game(&mut self) -> GameManagerResult<()> {
@@ -51,16 +143,70 @@ impl ChessGame {
 
     /// Adds a client (player) to a chess game.
     /// Can be White, Black, Random, Both (for analysis) and Spectator.
-    /// TODO: instead of Role 'Both', we might want a Role 'Analysis' instead. This way, we could've multiple people analysing.
-    /// TODO: But maybe it'd be better to have a GameMode for Analysis instead, so we can swap the behavior of the chess game.
+    /// Several people analysing together do so in an `AnalysisRoom`, not in a game.
     pub fn add_player(
         &mut self,
         client_id: ClientId,
         side: UserRoleSelection,
+    ) -> GameManagerResult<UserRoleSelection> {
+        if self.consultation.is_some() && side != UserRoleSelection::Spectator {
+            return Err(GameManagerError::InvalidGameStatus(
+                "Join a team to play in a consultation game".to_string(),
+            ));
+        }
+        let side = self.take_seat(client_id, side)?;
+        match side {
+            UserRoleSelection::White => self.white_gone = None,
+            UserRoleSelection::Black => self.black_gone = None,
+            UserRoleSelection::Both => {
+                self.white_gone = None;
+                self.black_gone = None;
+            }
+            _ => {}
+        }
+        self.empty_since = None;
+        Ok(side)
+    }
+
+    /// Join a team of a consultation game. The first member of a team is its captain and
+    /// takes the seat.
+    pub fn join_team(
+        &mut self,
+        client_id: ClientId,
+        color: ChessColor,
+    ) -> GameManagerResult<UserRoleSelection> {
+        let Some(consultation) = self.consultation.as_mut() else {
+            return Err(GameManagerError::InvalidGameStatus(
+                "Not a consultation game".to_string(),
+            ));
+        };
+        consultation
+            .join(client_id, color)
+            .map_err(|e| GameManagerError::InvalidGameStatus(e.to_string()))?;
+        let (seat, gone) = match color {
+            ChessColor::White => (&mut self.white_player, &mut self.white_gone),
+            ChessColor::Black => (&mut self.black_player, &mut self.black_gone),
+        };
+        if seat.is_none() {
+            *seat = Some(client_id);
+            *gone = None;
+        }
+        self.spectators.retain(|&id| id != client_id);
+        self.empty_since = None;
+        Ok(match color {
+            ChessColor::White => UserRoleSelection::White,
+            ChessColor::Black => UserRoleSelection::Black,
+        })
+    }
+
+    fn take_seat(
+        &mut self,
+        client_id: ClientId,
+        side: UserRoleSelection,
     ) -> GameManagerResult<UserRoleSelection> {
         match side {
             UserRoleSelection::Black => {
-                if self.black_player.is_some() {
+                if self.black_taken() {
                     Err(GameManagerError::InvalidGameStatus(
                         "Black side already taken".to_string(),
                     ))
@@ -70,7 +216,7 @@ impl ChessGame {
                 }
             }
             UserRoleSelection::White => {
-                if self.white_player.is_some() {
+                if self.white_taken() {
                     Err(GameManagerError::InvalidGameStatus(
                         "White side already taken".to_string(),
                     ))
@@ -80,7 +226,7 @@ impl ChessGame {
                 }
             }
             UserRoleSelection::Random => {
-                if self.white_player.is_some() && self.black_player.is_some() {
+                if self.white_taken() && self.black_taken() {
                     return Err(GameManagerError::InvalidGameStatus(
                         "Game already full".to_string(),
                     ));
@@ -95,7 +241,7 @@ impl ChessGame {
 
                 match side {
                     false => {
-                        if self.black_player.is_none() {
+                        if !self.black_taken() {
                             self.black_player = Some(client_id);
                             Ok(UserRoleSelection::Black)
                         } else {
@@ -104,7 +250,7 @@ impl ChessGame {
                         }
                     }
                     true => {
-                        if self.white_player.is_none() {
+                        if !self.white_taken() {
                             self.white_player = Some(client_id);
                             Ok(UserRoleSelection::White)
                         } else {
@@ -119,7 +265,7 @@ impl ChessGame {
                 Ok(UserRoleSelection::Spectator)
             }
             UserRoleSelection::Both => {
-                if self.white_player.is_some() || self.black_player.is_some() {
+                if self.white_taken() || self.black_taken() {
                     return Err(GameManagerError::InvalidGameStatus(
                         "Cannot control both colors in non-empty games".to_string(),
                     ));
@@ -131,23 +277,155 @@ impl ChessGame {
         }
     }
 
+    fn white_taken(&self) -> bool {
+        self.white_player.is_some() || self.white_reserved.is_some()
+    }
+
+    fn black_taken(&self) -> bool {
+        self.black_player.is_some() || self.black_reserved.is_some()
+    }
+
+    /// Give a returning player back the seat that was kept for them.
+    /// Returns the side, or `None` if nothing was reserved for this player.
+    pub fn reclaim_seat(
+        &mut self,
+        client_id: ClientId,
+        identity: &Identity,
+    ) -> Option<UserRoleSelection> {
+        let both = Some(identity);
+        let side = if self.white_reserved.as_ref() == both && self.black_reserved.as_ref() == both {
+            self.white_reserved = None;
+            self.black_reserved = None;
+            self.white_player = Some(client_id);
+            self.black_player = Some(client_id);
+            self.white_gone = None;
+            self.black_gone = None;
+            UserRoleSelection::Both
+        } else if self.white_reserved.as_ref() == both {
+            self.white_reserved = None;
+            self.white_player = Some(client_id);
+            self.white_gone = None;
+            UserRoleSelection::White
+        } else if self.black_reserved.as_ref() == both {
+            self.black_reserved = None;
+            self.black_player = Some(client_id);
+            self.black_gone = None;
+            UserRoleSelection::Black
+        } else {
+            return None;
+        };
+        self.empty_since = None;
+        Some(side)
+    }
+
+    /// Keep the seat of a player that lost the connection, so they can come back.
+    pub fn reserve_seat(&mut self, client_id: ClientId, identity: &Identity) {
+        // a team goes on without the member
+        if self.consultation.is_some() {
+            return;
+        }
+        if self.white_player == Some(client_id) {
+            self.white_reserved = Some(identity.clone());
+        }
+        if self.black_player == Some(client_id) {
+            self.black_reserved = Some(identity.clone());
+        }
+    }
+
+    /// Keep a seat for a player that isn't connected, as if they had just left.
+    pub fn keep_seat(&mut self, color: ChessColor, identity: Identity) {
+        let now = Instant::now();
+        match color {
+            ChessColor::White => {
+                self.white_reserved = Some(identity);
+                self.white_gone = Some(now);
+            }
+            ChessColor::Black => {
+                self.black_reserved = Some(identity);
+                self.black_gone = Some(now);
+            }
+        }
+    }
+
+    /// Take a client out of the game. A player's seat stays empty (or reserved), and the
+    /// time they left counts towards forfeiting.
     pub fn remove_player(&mut self, client_id: ClientId) -> Option<UserRoleSelection> {
+        let now = Instant::now();
         let mut side = None;
         if let Some(id) = self.white_player {
             if id == client_id {
                 side = Some(UserRoleSelection::White);
                 self.white_player = None;
+                self.white_gone = Some(now);
             }
         }
         if let Some(id) = self.black_player {
             if id == client_id {
                 side = Some(UserRoleSelection::Black);
                 self.black_player = None;
+                self.black_gone = Some(now);
+            }
+        }
+        // the next member of the team takes over the seat
+        if let Some(consultation) = self.consultation.as_mut() {
+            if let Some(color) = consultation.leave(client_id) {
+                let next = consultation.team(color).first().copied();
+                let (seat, gone) = match color {
+                    ChessColor::White => (&mut self.white_player, &mut self.white_gone),
+                    ChessColor::Black => (&mut self.black_player, &mut self.black_gone),
+                };
+                if next.is_some() {
+                    *seat = next;
+                    *gone = None;
+                }
             }
         }
+        self.spectators.retain(|&id| id != client_id);
+        if self.get_all_participants().is_empty()
+            && self.white_reserved.is_none()
+            && self.black_reserved.is_none()
+        {
+            self.empty_since = Some(now);
+        }
         side
     }
 
+    /// A game can be aborted, without a result, until both sides have made a move.
+    /// Tournament, simul and bughouse games need a result.
+    pub fn can_abort(&self) -> bool {
+        self.tournament.is_none()
+            && self.simul.is_none()
+            && self.bughouse.is_none()
+            && self.move_history.len() < 2
+    }
+
+    /// When the side to move forfeits for staying away, if its player is gone.
+    /// Like a clock, only the time on the move counts.
+    pub fn forfeit_deadline(&self, limit: Duration) -> Option<Instant> {
+        let gone = match self.chess.active_player {
+            ChessColor::White => self.white_gone,
+            ChessColor::Black => self.black_gone,
+        }?;
+        Some(gone.max(self.turn_started) + limit)
+    }
+
+    /// When the team to move has to play its leading proposal, in a consultation game.
+    pub fn vote_deadline(&self) -> Option<Instant> {
+        self.consultation.as_ref()?.vote_deadline(self.turn_started)
+    }
+
+    /// When the game gets removed for being empty, if nobody is in it.
+    /// Tournament and bughouse games, and games with a kept seat, stay until somebody forfeits.
+    pub fn reap_deadline(&self) -> Option<Instant> {
+        if self.tournament.is_some() || self.bughouse.is_some() {
+            return None;
+        }
+        if self.white_reserved.is_some() || self.black_reserved.is_some() {
+            return None;
+        }
+        self.empty_since.map(|since| since + EMPTY_GAME_GRACE)
+    }
+
     /// Returns all players and spectators of a chess game.
     pub fn get_all_participants(&self) -> Vec<ClientId> {
         let mut participants = Vec::new();
@@ -159,7 +437,8 @@ impl ChessGame {
                 participants.push(id);
             }
         }
-        for &id in &self.spectators {
+        let members = self.consultation.iter().flat_map(|c| c.members());
+        for id in members.chain(self.spectators.iter().copied()) {
             if !participants.contains(&id) {
                 participants.push(id);
             }
@@ -176,6 +455,11 @@ impl ChessGame {
         if let Some(id) = self.black_player {
             players.push(id);
         }
+        for id in self.consultation.iter().flat_map(|c| c.members()) {
+            if !players.contains(&id) {
+                players.push(id);
+            }
+        }
         players
     }
 
@@ -202,19 +486,138 @@ impl ChessGame {
         if !is_current_player {
             return Err(ChessError::NotYourTurn);
         }
+        // the SAN depends on the position before the move
+        let san = mov.to_san(&self.chess);
         match self.chess.make_move(mov) {
-            Ok(ret) => Ok(ret),
+            Ok(ret) => {
+                // they were about the previous position
+                self.tablebase = None;
+                if let Some(consultation) = self.consultation.as_mut() {
+                    consultation.clear();
+                }
+                self.record_move(Some(mov), san);
+                Ok(ret)
+            }
             Err(e) => Err(e),
         }
     }
 
+    /// Drop a piece from the pocket (bughouse).
+    pub fn drop_piece(
+        &mut self,
+        drop: ChessDrop,
+        client_id: ClientId,
+    ) -> ChessResult<Vec<(Tile, Option<Piece>)>> {
+        let color = self.chess.active_player;
+        let is_current_player = match color {
+            ChessColor::White => self.white_player == Some(client_id),
+            ChessColor::Black => self.black_player == Some(client_id),
+        };
+        if !is_current_player {
+            return Err(ChessError::NotYourTurn);
+        }
+        let piece = WoodPiece::new(drop.typ, color);
+        let Some(index) = self.pocket.iter().position(|&p| p == piece) else {
+            return Err(ChessError::IllegalDrop(drop));
+        };
+        let san = drop_to_san(&drop, &self.chess);
+        let changes = self.chess.drop_piece(drop)?;
+        self.pocket.remove(index);
+        self.tablebase = None;
+        self.record_move(None, san);
+        Ok(changes)
+    }
+
+    /// Note a move that was just made, with the time it took. The clock of the side that
+    /// moved stops and gets the increment, and the other side's turn starts.
+    fn record_move(&mut self, mov: Option<ChessMove>, san: String) {
+        let now = Instant::now();
+        let think_time = now.saturating_duration_since(self.turn_started);
+        let clock = (self._time > 0).then(|| {
+            let clock = &mut self.clocks[side(!self.chess.active_player)];
+            *clock = clock.saturating_sub(think_time) + Duration::from_secs(self._time_inc as u64);
+            clock.as_millis() as u32
+        });
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        self.move_history.push(MoveRecord {
+            mov,
+            san,
+            fen: self.chess.get_fen(),
+            timestamp,
+            clock,
+            think_time: think_time.as_millis() as u32,
+        });
+        self.turn_started = now;
+    }
+
+    /// Set the clocks to what they showed after the last move of each side, e.g. after a
+    /// takeback or a restart.
+    pub fn restore_clocks(&mut self) {
+        let start = Duration::from_secs(self._time as u64);
+        self.clocks = [start; 2];
+        for record in &self.move_history {
+            if let Some(millis) = record.clock {
+                self.clocks[side(record.color())] = Duration::from_millis(millis as u64);
+            }
+        }
+    }
+
+    /// The moves played so far, in SAN.
+    pub fn sans(&self) -> Vec<String> {
+        self.move_history.iter().map(|r| r.san.clone()).collect()
+    }
+
+    /// Whether the side to move could drop a piece from the pocket somewhere.
+    fn has_legal_drop(&self) -> bool {
+        let color = self.chess.active_player;
+        self.pocket.iter().filter(|p| p.color == color).any(|p| {
+            Tile::all()
+                .into_iter()
+                .any(|dst| self.chess.is_legal_drop(&ChessDrop { typ: p.typ, dst }))
+        })
+    }
+
+    /// Undo the last `plies` half-moves. There is no unmake, so the position is rebuilt from
+    /// the start position; that also rebuilds the hashes for repetition detection.
+    pub fn take_back(&mut self, plies: usize) -> Result<(), String> {
+        if plies == 0 || plies > self.move_history.len() {
+            return Err(format!(
+                "can't take back {} of {} plies",
+                plies,
+                self.move_history.len()
+            ));
+        }
+        let keep = self.move_history.len() - plies;
+        self.chess = replay(&self.start_fen, &mut self.move_history[..keep])?;
+        self.move_history.truncate(keep);
+        self.in_book.truncate(keep);
+        self.restore_clocks();
+        self.tablebase = None;
+        self.draw_offer_white = false;
+        self.draw_offer_black = false;
+        self.takeback_offer = None;
+        if let Some(consultation) = self.consultation.as_mut() {
+            consultation.clear();
+        }
+        self.turn_started = Instant::now();
+        Ok(())
+    }
+
     pub fn get_game_state(&self) -> ChessGameState {
-        if self.chess.is_checkmate() {
+        // in bughouse, a drop from the pocket may still get out of it
+        let no_drop = || !self.has_legal_drop();
+        if self.chess.is_checkmate() && no_drop() {
             return ChessGameState::Finished(GameOverReason::Checkmate(!self.chess.active_player));
         }
-        if self.chess.is_stalemate() {
+        if self.chess.is_stalemate() && no_drop() {
             return ChessGameState::Finished(GameOverReason::Stalemate);
         }
+        // material comes and goes with the other board, so bughouse doesn't count these
+        if self.bughouse.is_some() {
+            return ChessGameState::Running;
+        }
         if self.chess.is_fifty_moves_rule() {
             return ChessGameState::Finished(GameOverReason::FiftyMovesRule);
         }
@@ -223,6 +626,12 @@ impl ChessGame {
             return ChessGameState::Finished(GameOverReason::ThreefoldRepetition);
         }
 
+        // adjudication: the tablebase knows how this ends
+        if let Some(wdl) = self.tablebase {
+            let winner = wdl_winner(wdl, self.chess.active_player);
+            return ChessGameState::Finished(GameOverReason::Adjudication(winner));
+        }
+
         ChessGameState::Running
     }
 
@@ -237,3 +646,74 @@ impl ChessGame {
         }
     }
 }
+
+/// The position after playing the moves from a start position. Moves only known by their
+/// SAN, e.g. from a stored game, get their `ChessMove` and FEN filled in on the way.
+/// Fails with the first move that doesn't fit.
+pub fn replay(start_fen: &str, moves: &mut [MoveRecord]) -> Result<Chess, String> {
+    let mut chess = Chess::load_fen(start_fen);
+    for record in moves {
+        let san = &record.san;
+        let mov = match record.mov {
+            Some(mov) => mov,
+            None => ChessMove::from_san(&chess, san).ok_or_else(|| san.clone())?,
+        };
+        chess.make_move(mov).map_err(|_| san.clone())?;
+        record.mov = Some(mov);
+        record.fen = chess.get_fen();
+    }
+    Ok(chess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abandonment() {
+        let limit = Duration::from_secs(30);
+        let mut game = ChessGame::new(1, Chess::new(), 300, 0);
+        assert!(game.reap_deadline().is_some());
+        game.add_player(1, UserRoleSelection::White).unwrap();
+        game.add_player(2, UserRoleSelection::Black).unwrap();
+        assert_eq!(game.reap_deadline(), None);
+        assert_eq!(game.forfeit_deadline(limit), None);
+
+        // black leaves while white is to move: nothing counts yet
+        game.remove_player(2);
+        assert!(game.black_gone.is_some());
+        assert_eq!(game.forfeit_deadline(limit), None);
+        game.make_move("e2e4".parse().unwrap(), 1).unwrap();
+        assert_eq!(
+            game.forfeit_deadline(limit),
+            Some(game.turn_started + limit)
+        );
+        assert!(game.can_abort());
+
+        // somebody takes the seat
+        game.add_player(3, UserRoleSelection::Black).unwrap();
+        assert_eq!(game.forfeit_deadline(limit), None);
+
+        // a kept seat means somebody may come back
+        game.white_reserved = Some(Identity::Guest("9c1f".to_string()));
+        game.remove_player(1);
+        game.remove_player(3);
+        assert_eq!(game.reap_deadline(), None);
+        game.white_reserved = None;
+        game.add_player(4, UserRoleSelection::Spectator).unwrap();
+        game.remove_player(4);
+        assert!(game.reap_deadline().is_some());
+
+        // a restored game starts empty, with the seats kept
+        let mut game = ChessGame::new(2, Chess::new(), 300, 0);
+        let identity = Identity::User(7);
+        game.white_reserved = Some(identity.clone());
+        assert_eq!(game.reap_deadline(), None);
+        assert_eq!(
+            game.reclaim_seat(5, &identity),
+            Some(UserRoleSelection::White)
+        );
+        assert_eq!(game.empty_since, None);
+        assert_eq!(game.reap_deadline(), None);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::chess::chess::Chess;
+use crate::chess::polyglot::OpeningBook;
+use crate::chess::san::San;
+use crate::engine::search::MAX_LEVEL;
+use crate::engine::tablebase::Tablebase;
+use crate::engine::uci::UciEngine;
+use crate::engine::xboard::XBoardEngine;
+use crate::engine::{Engine, ExternalEngine, SearchLimits};
+use crate::server::manager::{ClientEndpoint, GameManager};
+use chess_core::protocol::messages::{ClientMessage, ServerMessage};
+use chess_core::protocol::UserRoleSelection;
+use chess_core::*;
+use smol::channel::{unbounded, Receiver, Sender};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// How a computer player is set up. The `GameManager` fills this in from the server config.
+#[derive(Clone)]
+pub struct ComputerSettings {
+    pub level: u8,
+    pub book: Option<Arc<OpeningBook>>,
+    pub tablebase: Option<Arc<Tablebase>>,
+    pub engine: Option<ExternalEngine>, // else the built-in engine plays
+}
+
+/// Where the moves of a computer player come from.
+enum Brain {
+    Builtin(Arc<Mutex<Engine>>),
+    Uci(UciEngine),
+    XBoard(XBoardEngine),
+}
+
+/// A computer player that occupies a seat in a `ChessGame`.
+/// For the `GameManager`, a computer player is just another client: it has a `ClientEndpoint`,
+/// receives the broadcasts of its game, and sends its moves as `ClientMessage::Move`, the same
+/// way a `ClientSession` forwards the moves of a remote client.
+/// The computer player keeps its own copy of the board, which it updates from `MoveAccepted`.
+pub struct ComputerPlayer {
+    id: ClientId,
+    gid: GameId,
+    color: ChessColor,
+    settings: ComputerSettings,
+    start_fen: String,                     // the position we joined the game in ...
+    moves: Vec<ChessMove>,                 // ... and the moves since, for external engines
+    chess: Chess,                          // the current position
+    opponent_seated: bool,                 // only play when there is someone to play against
+    rx: Receiver<ServerMessage>,           // broadcasts of our game
+    tx: Sender<(ClientId, ClientMessage)>, // to the `GameManager`
+}
+
+impl ComputerPlayer {
+    pub fn new(
+        id: ClientId,
+        gid: GameId,
+        color: ChessColor,
+        chess: Chess,
+        settings: ComputerSettings,
+        rx: Receiver<ServerMessage>,
+        tx: Sender<(ClientId, ClientMessage)>,
+    ) -> Self {
+        ComputerPlayer {
+            id,
+            gid,
+            color,
+            settings,
+            start_fen: chess.get_fen(),
+            moves: vec![],
+            chess,
+            opponent_seated: false,
+            rx,
+            tx,
+        }
+    }
+
+    /// Play the game until it is over.
+    /// Also ends when the `GameManager` drops our endpoint.
+    pub async fn run(mut self) {
+        let mut brain = self.start_brain().await;
+        // the game may already be going, e.g. when it was restored after a restart;
+        // find out if there is an opponent, instead of waiting for the next join
+        self.send(ClientMessage::QueryGameDetails(self.gid)).await;
+
+        while let Ok(msg) = self.rx.recv().await {
+            match msg {
+                // someone came or went (including ourselves); see who is seated now
+                ServerMessage::GameJoined(gid, _, _) | ServerMessage::GameLeft(gid, _)
+                    if gid == self.gid =>
+                {
+                    self.send(ClientMessage::QueryGameDetails(gid)).await;
+                }
+                ServerMessage::GameDetails(gid, white, black, ..) if gid == self.gid => {
+                    self.opponent_seated = white.is_some() && black.is_some();
+                    self.play_if_our_turn(&mut brain).await;
+                }
+                ServerMessage::MoveAccepted(gid, _, san, _) if gid == self.gid => {
+                    let Some(mov) = ChessMove::from_san(&self.chess, &san) else {
+                        log::error!("computer #{}: can't follow move {}", self.id, san);
+                        break;
+                    };
+                    if let Err(e) = self.chess.make_move(mov) {
+                        log::error!("computer #{}: can't follow move {}: {}", self.id, san, e);
+                        break;
+                    }
+                    self.moves.push(mov);
+                    self.play_if_our_turn(&mut brain).await;
+                }
+                ServerMessage::IllegalMove(e) => {
+                    log::warn!("computer #{} made an illegal move: {}", self.id, e);
+                }
+                ServerMessage::GameOver(gid, _) if gid == self.gid => break,
+                _ => {}
+            }
+        }
+
+        match brain {
+            Brain::Uci(engine) => engine.quit().await,
+            Brain::XBoard(engine) => engine.quit().await,
+            Brain::Builtin(_) => {}
+        }
+        log::info!("computer #{} leaves game {}", self.id, self.gid);
+    }
+
+    /// The external engine, if one is configured and starts up; the built-in engine otherwise.
+    async fn start_brain(&self) -> Brain {
+        let started = match &self.settings.engine {
+            Some(ExternalEngine::Uci(path)) => UciEngine::start(path).await.map(Brain::Uci),
+            Some(ExternalEngine::XBoard(path)) => {
+                XBoardEngine::start(path).await.map(Brain::XBoard)
+            }
+            None => return Brain::Builtin(Arc::new(Mutex::new(Engine::new()))),
+        };
+        match started {
+            Ok(brain) => return brain,
+            Err(e) => log::warn!(
+                "computer #{}: failed to start engine {:?} ({}), using the built-in engine",
+                self.id,
+                self.settings.engine,
+                e
+            ),
+        }
+        Brain::Builtin(Arc::new(Mutex::new(Engine::new())))
+    }
+
+    async fn send(&self, msg: ClientMessage) {
+        let _ = self.tx.send((self.id, msg)).await;
+    }
+
+    async fn play_if_our_turn(&mut self, brain: &mut Brain) {
+        if !self.opponent_seated || self.chess.active_player != self.color {
+            return;
+        }
+        let Some(mov) = self.choose_move(brain).await else {
+            return;
+        };
+        self.send(ClientMessage::Move(self.gid, mov)).await;
+    }
+
+    /// The tablebase move in the endgame, a book move in the opening, otherwise ask the engine.
+    async fn choose_move(&self, brain: &mut Brain) -> Option<ChessMove> {
+        if let Some(tb) = &self.settings.tablebase {
+            match tb.probe(&self.chess).await {
+                Ok(Some(entry)) if entry.best.is_some() => return entry.best,
+                Ok(_) => {}
+                Err(e) => log::warn!("computer #{}: tablebase probe failed: {}", self.id, e),
+            }
+        }
+
+        let book = self.settings.book.as_ref();
+        if let Some(mov) = book.and_then(|b| b.pick_move(&self.chess)) {
+            return Some(mov);
+        }
+
+        let limits = SearchLimits::level(self.settings.level);
+        let id = self.id;
+        match brain {
+            Brain::Builtin(engine) => {
+                // the search takes a while, so it must not block the executor
+                let chess = self.chess.clone();
+                let engine = engine.clone();
+                smol::unblock(move || {
+                    let mut engine = engine.lock().unwrap();
+                    engine.search(&chess, limits, |info| {
+                        log::debug!("computer #{}: {}", id, info);
+                    })
+                })
+                .await
+            }
+            Brain::Uci(engine) => {
+                let result = engine
+                    .go(&self.start_fen, &self.moves, &limits, |_| {})
+                    .await;
+                self.check_engine_move(result)
+            }
+            Brain::XBoard(engine) => {
+                // we don't know the clocks, so the engine only gets the limits
+                let result = engine
+                    .go(&self.start_fen, &self.moves, &limits, None, |_| {})
+                    .await;
+                self.check_engine_move(result)
+            }
+        }
+    }
+
+    /// Don't trust an external engine blindly, the game would be stuck on an illegal move.
+    fn check_engine_move(&self, result: io::Result<Option<ChessMove>>) -> Option<ChessMove> {
+        match result {
+            Ok(Some(mov)) if self.chess.is_legal_move(&mov) => Some(mov),
+            Ok(mov) => {
+                log::warn!(
+                    "computer #{}: engine has no legal move ({:?})",
+                    self.id,
+                    mov
+                );
+                None
+            }
+            Err(e) => {
+                log::error!("computer #{}: engine failed: {}", self.id, e);
+                None
+            }
+        }
+    }
+}
+
+impl GameManager {
+    /// Seat a computer player in a game.
+    /// The computer player gets its own client ID and endpoint and runs in its own task.
+    pub(crate) async fn handle_add_computer(
+        &mut self,
+        cid: ClientId,
+        gid: GameId,
+        side: UserRoleSelection,
+        level: u8,
+    ) {
+        if !matches!(
+            side,
+            UserRoleSelection::White | UserRoleSelection::Black | UserRoleSelection::Random
+        ) {
+            log::warn!("client {} asked for a computer playing {}", cid, side);
+            return;
+        }
+        if self.games.get(&gid).is_some_and(|g| g.rated) {
+            log::warn!("client {} asked for a computer in rated game {}", cid, gid);
+            return;
+        }
+        let level = level.clamp(1, MAX_LEVEL);
+        let computer_id = self.next_computer_id;
+
+        let side = match self.add_player_to_game(gid, computer_id, side) {
+            Ok(side) => side,
+            Err(e) => {
+                log::warn!(
+                    "AddComputer failed for client {} in game {}: {}",
+                    cid,
+                    gid,
+                    e
+                );
+                return;
+            }
+        };
+        self.next_computer_id -= 1;
+
+        let color = if side == UserRoleSelection::White {
+            ChessColor::White
+        } else {
+            ChessColor::Black
+        };
+        self.start_computer(computer_id, gid, color, level);
+        log::info!(
+            "computer #{} (level {}) joined game {} as {}",
+            computer_id,
+            level,
+            gid,
+            side
+        );
+
+        let msg = ServerMessage::GameJoined(gid, computer_id, side);
+        self.broadcast(gid, msg).await;
+        self.persist(gid).await;
+    }
+
+    /// Create the endpoint of a computer player that is already seated in a game,
+    /// and start its task.
+    pub(crate) fn start_computer(
+        &mut self,
+        computer_id: ClientId,
+        gid: GameId,
+        color: ChessColor,
+        level: u8,
+    ) {
+        let (tx, rx) = unbounded();
+        self.clients
+            .insert(computer_id, ClientEndpoint::computer(tx, level));
+
+        let settings = ComputerSettings {
+            level,
+            book: self.book.clone(),
+            tablebase: self.tablebase.clone(),
+            engine: self.engine.clone(),
+        };
+        let computer = ComputerPlayer::new(
+            computer_id,
+            gid,
+            color,
+            self.games[&gid].chess.clone(),
+            settings,
+            rx,
+            self.tx.clone(),
+        );
+        smol::spawn(computer.run()).detach();
+    }
+}
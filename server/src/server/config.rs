@@ -0,0 +1,88 @@
+use crate::engine::ExternalEngine;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+/// Server settings, read from a simple `key=value` file.
+/// Every setting is optional; a missing file just means defaults everywhere.
+#[derive(Clone, Default)]
+pub struct Config {
+    /// Path to a Polyglot opening book (.bin).
+    pub book: Option<String>,
+    /// Path to an external UCI engine for computer players and analysis.
+    pub uci_engine: Option<String>,
+    /// The same for a CECP engine, if there is no UCI engine.
+    pub xboard_engine: Option<String>,
+    /// Directory with Syzygy tablebases.
+    pub syzygy_path: Option<String>,
+    /// The program that reads them, `fathom` by default. It isn't part of the server and has to
+    /// be installed separately. It is called as `<prober> --path=<dir> <fen>` and answers with
+    /// `WDL`, `DTZ` and `WinningMoves`/`DrawingMoves`/`LosingMoves` PGN tags, like Fathom.
+    pub syzygy_prober: String,
+    /// End games between computer players as soon as the tablebase knows the result.
+    pub tablebase_adjudication: bool,
+    /// Directory to keep running games in, so they survive a restart. Off if unset.
+    pub game_store: Option<String>,
+    /// File with the player accounts. Without it, everyone plays as a guest.
+    pub accounts: Option<String>,
+    /// File with the ratings of the accounts. Without it (or accounts), all games are casual.
+    pub ratings: Option<String>,
+    /// Allow takebacks in rated games as well, not only in casual ones.
+    pub rated_takebacks: bool,
+    /// Seconds a player may be gone while it's their move before they lose (120 by default).
+    pub forfeit_after: u64,
+    /// File with the puzzles. Without it, there are none.
+    pub puzzles: Option<String>,
+    /// A puzzle database in the Lichess CSV format, added to the puzzles at the start.
+    pub puzzle_import: Option<String>,
+    /// File with the puzzle ratings and attempts of the accounts. Without it, puzzles are unrated.
+    pub puzzle_ratings: Option<String>,
+}
+
+impl Config {
+    pub fn read(file_path: &str) -> Config {
+        let mut settings: HashMap<String, String> = HashMap::new();
+        match read_to_string(file_path) {
+            Ok(content) => {
+                for line in content.lines() {
+                    let mut parts = line.split('=');
+                    if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+                        settings.insert(key.trim().to_string(), value.trim().to_string());
+                    }
+                }
+            }
+            Err(e) => {
+                log::info!("no config file {} ({}), using defaults", file_path, e);
+            }
+        }
+        Config {
+            book: settings.get("book").cloned(),
+            uci_engine: settings.get("uci_engine").cloned(),
+            xboard_engine: settings.get("xboard_engine").cloned(),
+            syzygy_path: settings.get("syzygy_path").cloned(),
+            syzygy_prober: settings
+                .get("syzygy_prober")
+                .cloned()
+                .unwrap_or_else(|| "fathom".to_string()),
+            tablebase_adjudication: settings
+                .get("tablebase_adjudication")
+                .is_some_and(|v| v == "true"),
+            game_store: settings.get("game_store").cloned(),
+            accounts: settings.get("accounts").cloned(),
+            ratings: settings.get("ratings").cloned(),
+            rated_takebacks: settings.get("rated_takebacks").is_some_and(|v| v == "true"),
+            forfeit_after: settings
+                .get("forfeit_after")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(120),
+            puzzles: settings.get("puzzles").cloned(),
+            puzzle_import: settings.get("puzzle_import").cloned(),
+            puzzle_ratings: settings.get("puzzle_ratings").cloned(),
+        }
+    }
+
+    /// The external engine to use instead of the built-in one, if any.
+    pub fn external_engine(&self) -> Option<ExternalEngine> {
+        let uci = self.uci_engine.clone().map(ExternalEngine::Uci);
+        uci.or_else(|| self.xboard_engine.clone().map(ExternalEngine::XBoard))
+    }
+}
@@ -0,0 +1,372 @@
+use crate::chess::chess::Chess;
+use crate::chess::san::San;
+use crate::server::chessgame::ChessGame;
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{ConsultationParams, VotingMode};
+use chess_core::{ChessColor, ChessError, ChessMove, ClientId, GameId};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConsultationError {
+    AlreadyInTeam,
+    NotInTeam,
+    NotYourTurn,
+}
+
+impl std::fmt::Display for ConsultationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConsultationError::AlreadyInTeam => write!(f, "Already in a team"),
+            ConsultationError::NotInTeam => write!(f, "Not in a team"),
+            ConsultationError::NotYourTurn => write!(f, "The other team is to move"),
+        }
+    }
+}
+
+/// The teams of a consultation game and the moves they propose. The first member of a
+/// team is its captain, who holds the seat of the side and speaks for the team when it
+/// resigns or offers a draw.
+pub struct Consultation {
+    pub params: ConsultationParams,
+    pub white: Vec<ClientId>,
+    pub black: Vec<ClientId>,
+    proposals: Vec<(ClientId, ChessMove)>, // of the team to move, the first proposed first
+}
+
+impl Consultation {
+    pub fn new(params: ConsultationParams) -> Self {
+        Consultation {
+            params,
+            white: vec![],
+            black: vec![],
+            proposals: vec![],
+        }
+    }
+
+    pub fn team(&self, color: ChessColor) -> &[ClientId] {
+        match color {
+            ChessColor::White => &self.white,
+            ChessColor::Black => &self.black,
+        }
+    }
+
+    pub fn side_of(&self, cid: ClientId) -> Option<ChessColor> {
+        [ChessColor::White, ChessColor::Black]
+            .into_iter()
+            .find(|&color| self.team(color).contains(&cid))
+    }
+
+    /// Both teams, white first.
+    pub fn members(&self) -> Vec<ClientId> {
+        self.white.iter().chain(&self.black).copied().collect()
+    }
+
+    pub fn join(&mut self, cid: ClientId, color: ChessColor) -> Result<(), ConsultationError> {
+        if self.side_of(cid).is_some() {
+            return Err(ConsultationError::AlreadyInTeam);
+        }
+        match color {
+            ChessColor::White => self.white.push(cid),
+            ChessColor::Black => self.black.push(cid),
+        }
+        Ok(())
+    }
+
+    /// Take a member out of their team, with their proposal. Returns the side.
+    pub fn leave(&mut self, cid: ClientId) -> Option<ChessColor> {
+        let color = self.side_of(cid)?;
+        self.white.retain(|&c| c != cid);
+        self.black.retain(|&c| c != cid);
+        self.proposals.retain(|&(c, _)| c != cid);
+        Some(color)
+    }
+
+    /// A member of the team to move proposes a move, which replaces what they proposed
+    /// before. Returns the move the team decided on, if the proposal settles it.
+    pub fn propose(
+        &mut self,
+        cid: ClientId,
+        to_move: ChessColor,
+        mov: ChessMove,
+    ) -> Result<Option<ChessMove>, ConsultationError> {
+        match self.side_of(cid) {
+            None => return Err(ConsultationError::NotInTeam),
+            Some(color) if color != to_move => return Err(ConsultationError::NotYourTurn),
+            Some(_) => {}
+        }
+        match self.proposals.iter_mut().find(|(c, _)| *c == cid) {
+            Some(proposal) => proposal.1 = mov,
+            None => self.proposals.push((cid, mov)),
+        }
+
+        let team = self.team(to_move);
+        let decided = match self.params.mode {
+            VotingMode::FirstProposal => Some(mov),
+            VotingMode::Majority => Some(mov).filter(|m| 2 * self.votes(m) > team.len()),
+            VotingMode::Captain => Some(mov).filter(|_| team.first() == Some(&cid)),
+        };
+        Ok(decided)
+    }
+
+    fn votes(&self, mov: &ChessMove) -> usize {
+        self.proposals.iter().filter(|(_, m)| m == mov).count()
+    }
+
+    pub fn proposals(&self) -> &[(ClientId, ChessMove)] {
+        &self.proposals
+    }
+
+    /// The move with the most proposals; of those with as many, the first proposed.
+    pub fn leading(&self) -> Option<ChessMove> {
+        let mut leading: Option<(ChessMove, usize)> = None;
+        for (_, mov) in &self.proposals {
+            let votes = self.votes(mov);
+            if leading.is_none_or(|(_, most)| votes > most) {
+                leading = Some((*mov, votes));
+            }
+        }
+        leading.map(|(mov, _)| mov)
+    }
+
+    /// A move has been played: the other team starts over.
+    pub fn clear(&mut self) {
+        self.proposals.clear();
+    }
+
+    /// When the team to move has to play its leading proposal, if there is a time limit
+    /// and something to play.
+    pub fn vote_deadline(&self, turn_started: Instant) -> Option<Instant> {
+        if self.params.vote_time == 0 || self.proposals.is_empty() {
+            return None;
+        }
+        Some(turn_started + Duration::from_secs(self.params.vote_time as u64))
+    }
+}
+
+impl GameManager {
+    /// Open a game for two teams. Clients join a side with `JoinTeam`, everybody else
+    /// can watch.
+    pub(crate) async fn handle_create_consultation(
+        &mut self,
+        cid: ClientId,
+        params: ConsultationParams,
+    ) {
+        let gid = self.next_game_id;
+        self.next_game_id += 1;
+        log::info!(
+            "client {} opens consultation game {} ({})",
+            cid,
+            gid,
+            params.mode
+        );
+        let mut game = ChessGame::new(gid, Chess::new(), params.time, params.time_inc);
+        let teams = ServerMessage::Teams(gid, params.mode, vec![], vec![]);
+        game.consultation = Some(Consultation::new(params));
+        self.games.insert(gid, game);
+        for c in self.clients.values() {
+            let _ = c.tx.send(ServerMessage::GameCreated(gid, cid)).await;
+            let _ = c.tx.send(teams.clone()).await;
+        }
+    }
+
+    pub(crate) async fn handle_join_team(&mut self, cid: ClientId, gid: GameId, color: ChessColor) {
+        // computers play alone
+        if self.clients.get(&cid).is_none_or(|c| c.is_computer()) {
+            return;
+        }
+        let Some(game) = self.games.get_mut(&gid) else {
+            return;
+        };
+        match game.join_team(cid, color) {
+            Ok(side) => {
+                let msg = ServerMessage::GameJoined(gid, cid, side);
+                self.broadcast(gid, msg).await;
+                self.replay_chat(cid, gid, side).await;
+                self.announce_teams(gid).await;
+                self.send_proposals(gid).await;
+            }
+            Err(e) => log::warn!("client {} can't join a team in game {}: {}", cid, gid, e),
+        }
+    }
+
+    /// A member of the team to move proposes a move. The team sees all proposals, and
+    /// once they settle the move, it is played from the captain's seat.
+    pub(crate) async fn handle_proposal(&mut self, cid: ClientId, gid: GameId, mov: ChessMove) {
+        let Some(game) = self.games.get_mut(&gid) else {
+            return;
+        };
+        if !game.chess.is_legal_move(&mov) {
+            let msg = ServerMessage::IllegalMove(ChessError::IllegalMove(mov));
+            self.send_to(cid, msg).await;
+            return;
+        }
+        let to_move = game.chess.active_player;
+        let Some(consultation) = game.consultation.as_mut() else {
+            return;
+        };
+        match consultation.propose(cid, to_move, mov) {
+            Ok(decided) => {
+                self.send_proposals(gid).await;
+                if let Some(mov) = decided {
+                    self.play_team_move(gid, mov).await;
+                }
+            }
+            Err(e) => {
+                log::warn!("proposal of client {} in game {}: {}", cid, gid, e);
+                let msg = ServerMessage::IllegalMove(ChessError::NotYourTurn);
+                self.send_to(cid, msg).await;
+            }
+        }
+    }
+
+    /// The team to move decided on a move, or ran out of time to: its captain plays it.
+    async fn play_team_move(&mut self, gid: GameId, mov: ChessMove) {
+        let Some(game) = self.games.get(&gid) else {
+            return;
+        };
+        let seat = match game.chess.active_player {
+            ChessColor::White => game.white_player,
+            ChessColor::Black => game.black_player,
+        };
+        if let Some(captain) = seat {
+            self.play_move(captain, gid, mov).await;
+        }
+    }
+
+    /// Teams that took too long to agree play their leading proposal.
+    pub(crate) async fn end_votes(&mut self, now: Instant) {
+        let due: Vec<(GameId, ChessMove)> = self
+            .games
+            .values()
+            .filter(|g| g.vote_deadline().is_some_and(|t| t <= now))
+            .filter_map(|g| Some((g.id, g.consultation.as_ref()?.leading()?)))
+            .collect();
+        for (gid, mov) in due {
+            log::info!("vote in game {} is over, playing {}", gid, mov);
+            self.play_team_move(gid, mov).await;
+        }
+    }
+
+    pub(crate) async fn announce_teams(&self, gid: GameId) {
+        let Some(consultation) = self.games.get(&gid).and_then(|g| g.consultation.as_ref()) else {
+            return;
+        };
+        let msg = ServerMessage::Teams(
+            gid,
+            consultation.params.mode,
+            consultation.white.clone(),
+            consultation.black.clone(),
+        );
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+        }
+    }
+
+    /// Show the team to move what its members propose.
+    async fn send_proposals(&self, gid: GameId) {
+        let Some(game) = self.games.get(&gid) else {
+            return;
+        };
+        let Some(consultation) = &game.consultation else {
+            return;
+        };
+        let proposals = consultation
+            .proposals()
+            .iter()
+            .map(|(cid, mov)| (*cid, mov.to_san(&game.chess)))
+            .collect();
+        let msg = ServerMessage::Proposals(gid, proposals);
+        for cid in consultation.team(game.chess.active_player) {
+            self.send_to(*cid, msg.clone()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consultation(mode: VotingMode) -> Consultation {
+        let params = ConsultationParams {
+            time: 0,
+            time_inc: 0,
+            mode,
+            vote_time: 30,
+        };
+        let mut consultation = Consultation::new(params);
+        for (cid, color) in [
+            (10, ChessColor::White),
+            (20, ChessColor::White),
+            (30, ChessColor::White),
+            (40, ChessColor::Black),
+        ] {
+            assert_eq!(consultation.join(cid, color), Ok(()));
+        }
+        consultation
+    }
+
+    fn mov(s: &str) -> ChessMove {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_consultation_teams() {
+        let mut consultation = consultation(VotingMode::FirstProposal);
+        assert_eq!(
+            consultation.join(20, ChessColor::Black),
+            Err(ConsultationError::AlreadyInTeam)
+        );
+        assert_eq!(consultation.side_of(40), Some(ChessColor::Black));
+        assert_eq!(consultation.members(), vec![10, 20, 30, 40]);
+        assert_eq!(
+            consultation.propose(40, ChessColor::White, mov("e7e5")),
+            Err(ConsultationError::NotYourTurn)
+        );
+        assert_eq!(
+            consultation.propose(50, ChessColor::White, mov("e2e4")),
+            Err(ConsultationError::NotInTeam)
+        );
+        // the first proposal is the move
+        assert_eq!(
+            consultation.propose(20, ChessColor::White, mov("d2d4")),
+            Ok(Some(mov("d2d4")))
+        );
+        assert_eq!(consultation.leave(10), Some(ChessColor::White));
+        assert_eq!(consultation.team(ChessColor::White), &[20, 30]);
+    }
+
+    #[test]
+    fn test_consultation_votes() {
+        let start = Instant::now();
+        let mut majority = consultation(VotingMode::Majority);
+        assert_eq!(majority.vote_deadline(start), None);
+        let white = ChessColor::White;
+        assert_eq!(majority.propose(10, white, mov("e2e4")), Ok(None));
+        assert_eq!(majority.propose(20, white, mov("d2d4")), Ok(None));
+        assert_eq!(
+            majority.vote_deadline(start),
+            Some(start + Duration::from_secs(30))
+        );
+        // on a tie, the first proposed leads
+        assert_eq!(majority.leading(), Some(mov("e2e4")));
+        // two of three is a majority; changing one's mind counts once
+        assert_eq!(majority.propose(20, white, mov("c2c4")), Ok(None));
+        assert_eq!(
+            majority.propose(20, white, mov("e2e4")),
+            Ok(Some(mov("e2e4")))
+        );
+        majority.clear();
+        assert!(majority.proposals().is_empty());
+
+        // only the captain decides, the others advise
+        let mut captain = consultation(VotingMode::Captain);
+        assert_eq!(captain.propose(20, white, mov("g1f3")), Ok(None));
+        assert_eq!(captain.propose(30, white, mov("g1f3")), Ok(None));
+        assert_eq!(captain.leading(), Some(mov("g1f3")));
+        assert_eq!(
+            captain.propose(10, white, mov("e2e4")),
+            Ok(Some(mov("e2e4")))
+        );
+    }
+}
@@ -1,15 +1,36 @@
 use crate::chess::chess::Chess;
-use crate::chess::san::San;
-use crate::server::chessgame::ChessGame;
+use crate::chess::polyglot::OpeningBook;
+use crate::engine::tablebase::Tablebase;
+use crate::engine::ExternalEngine;
+use crate::server::accounts::{AccountResult, AccountStore, LoginThrottle};
+use crate::server::arenas::Arena;
+use crate::server::bughouse::Bughouse;
+use crate::server::challenges::Challenges;
+use crate::server::chat::{ChatHistory, RateLimiter};
+use crate::server::chessgame::{ChessGame, Identity};
+use crate::server::config::Config;
+use crate::server::puzzleratings::PuzzleRatings;
+use crate::server::puzzles::{PuzzleAttempt, PuzzleStore};
+use crate::server::ratings::RatingStore;
+use crate::server::rematches::Rematches;
+use crate::server::rooms::{AnalysisRoom, FinishedGames};
+use crate::server::seeks::SeekPool;
+use crate::server::simuls::Simul;
+use crate::server::store::GameStore;
+use crate::server::tournaments::Tournament;
 use chess_core::protocol::messages::{ClientMessage, ServerMessage};
-use chess_core::protocol::{JoinGameParams, NewGameParams, UserRoleSelection};
+use chess_core::protocol::{ChallengeOutcome, JoinGameParams, NewGameParams, UserRoleSelection};
 use chess_core::states::{ChessGameState, GameOverReason};
 use chess_core::*;
 use chrono::prelude::*;
-use smol::channel::{Receiver, Sender};
+use rand::RngExt;
+use smol::channel::{unbounded, Receiver, RecvError, Sender};
 use smol::fs::File;
 use smol::io::AsyncWriteExt;
+use smol::Timer;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// The endpoint of a client for the `GameManager`.
 /// Those are used by the `GameManager` to keep a connection
@@ -21,6 +42,10 @@ use std::collections::HashMap;
 pub struct ClientEndpoint {
     pub tx: Sender<ServerMessage>,
     pub name: String,
+    pub user: Option<UserId>, // the account of the client; `None` for guests
+    pub computer_level: Option<u8>, // `None` for remote clients
+    pub token: String,        // secret a guest gets their seats back with after a drop
+    pub login_pending: bool,  // a password of this client is being checked
 }
 
 impl ClientEndpoint {
@@ -28,8 +53,59 @@ impl ClientEndpoint {
         ClientEndpoint {
             tx,
             name: String::from(""),
+            user: None,
+            computer_level: None,
+            token: new_token(),
+            login_pending: false,
         }
     }
+
+    /// Endpoint of a `ComputerPlayer`. The channel leads to the task of the computer player
+    /// instead of a `ClientSession`.
+    pub fn computer(tx: Sender<ServerMessage>, level: u8) -> Self {
+        ClientEndpoint {
+            tx,
+            name: format!("Computer level {}", level),
+            user: None,
+            computer_level: Some(level),
+            token: String::new(),
+            login_pending: false,
+        }
+    }
+
+    pub fn is_computer(&self) -> bool {
+        self.computer_level.is_some()
+    }
+
+    /// How we recognize the client when it comes back with a new connection.
+    /// Guests are recognized by their token, not by the nickname anybody could take.
+    pub fn identity(&self) -> Option<Identity> {
+        match self.user {
+            Some(uid) => Some(Identity::User(uid)),
+            None if self.is_computer() => None,
+            None => Some(Identity::guest(&self.token)),
+        }
+    }
+}
+
+/// A fresh reconnect token; hard enough to guess that only the client it was sent to has it.
+fn new_token() -> String {
+    format!("{:032x}", rand::rng().random::<u128>())
+}
+
+/// The result of work the `GameManager` hands to a task of its own, so that its loop never
+/// waits for an external program or a password hash. Tasks send them back on `task_tx`.
+pub(crate) enum TaskResult {
+    Adjudication(GameId, String, Option<Wdl>), // tablebase result of the position (FEN)
+    PasswordHashed(ClientId, String, String),  // account name and password hash
+    LoginChecked(ClientId, String, AccountResult<UserId>), // name of the account
+}
+
+/// What the `GameManager` waits for.
+enum Event {
+    Client(Result<(ClientId, ClientMessage), RecvError>),
+    Task(TaskResult),
+    Deadline,
 }
 
 /// The `GameManager` is responsible for managing all games and communicating
@@ -41,22 +117,202 @@ impl ClientEndpoint {
 /// (i.e., letting clients join games), querying games, and making moves. It will also send
 /// back `ServerMessages` for whatever happened in games, i.e., if a game was created, joined
 /// or a move was successfully executed (or rejected).
+///
+/// The messages of each subsystem (accounts, tournaments, puzzles, ...) are handled in an
+/// `impl GameManager` block in the module of that subsystem; this module keeps the loop and
+/// what they all share.
 pub struct GameManager {
-    games: HashMap<GameId, ChessGame>,
+    pub(crate) games: HashMap<GameId, ChessGame>,
     pub clients: HashMap<ClientId, ClientEndpoint>, // Maps ClientId to their outbound message channel
     rx: Receiver<(ClientId, ClientMessage)>,        // receives messages from clients
-    next_game_id: GameId,
+    pub(crate) tx: Sender<(ClientId, ClientMessage)>, // the sending side of `rx`, for computer players
+    task_rx: Receiver<TaskResult>,
+    pub(crate) task_tx: Sender<TaskResult>, // for the tasks the manager spawns
+    pub(crate) next_game_id: GameId,
+    pub(crate) next_computer_id: ClientId,
+    pub(crate) book: Option<Arc<OpeningBook>>,
+    pub(crate) engine: Option<ExternalEngine>, // for computer players and analysis
+    pub(crate) tablebase: Option<Arc<Tablebase>>,
+    pub(crate) adjudicate: bool, // end games between computers by the tablebase
+    pub(crate) store: Option<GameStore>, // running games on disk, if enabled
+    pub(crate) accounts: Option<AccountStore>,
+    pub(crate) ratings: Option<RatingStore>, // only with accounts
+    pub(crate) rated_takebacks: bool,
+    pub(crate) forfeit_after: Duration, // for players gone on their move
+    pub(crate) seeks: SeekPool,
+    pub(crate) challenges: Challenges,
+    pub(crate) rematches: Rematches,
+    pub(crate) tournaments: HashMap<TournamentId, Tournament>,
+    pub(crate) arenas: HashMap<TournamentId, Arena>,
+    pub(crate) next_tournament_id: TournamentId, // tournaments and arenas share the IDs
+    pub(crate) simuls: HashMap<SimulId, Simul>,
+    pub(crate) next_simul_id: SimulId,
+    pub(crate) bughouses: HashMap<BughouseId, Bughouse>,
+    pub(crate) next_bughouse_id: BughouseId,
+    pub(crate) rooms: HashMap<RoomId, AnalysisRoom>,
+    pub(crate) next_room_id: RoomId,
+    pub(crate) finished: FinishedGames, // the last games that ended, to analyse
+    pub(crate) puzzles: Option<PuzzleStore>,
+    pub(crate) puzzle_attempts: HashMap<ClientId, PuzzleAttempt>, // one puzzle at a time per client
+    pub(crate) puzzle_ratings: Option<PuzzleRatings>,             // only with accounts and puzzles
+    pub(crate) lobby_chat: ChatHistory,
+    pub(crate) chat_limiter: RateLimiter,
+    pub(crate) login_throttle: LoginThrottle,
 }
 
 impl GameManager {
     /// constructor
-    pub fn new(recv: Receiver<(ClientId, ClientMessage)>) -> Self {
-        GameManager {
+    pub fn new(
+        recv: Receiver<(ClientId, ClientMessage)>,
+        send: Sender<(ClientId, ClientMessage)>,
+        config: &Config,
+    ) -> Self {
+        let book = config
+            .book
+            .as_ref()
+            .and_then(|path| match OpeningBook::open(path) {
+                Ok(book) => {
+                    log::info!("loaded opening book {} ({} entries)", path, book.len());
+                    Some(Arc::new(book))
+                }
+                Err(e) => {
+                    log::warn!("failed to load opening book {}: {}", path, e);
+                    None
+                }
+            });
+
+        let tablebase = config.syzygy_path.as_ref().and_then(|path| {
+            match Tablebase::open(path, &config.syzygy_prober) {
+                Ok(tb) => {
+                    log::info!(
+                        "found {} Syzygy tables in {} (up to {} pieces)",
+                        tb.table_count(),
+                        path,
+                        tb.max_pieces()
+                    );
+                    Some(Arc::new(tb))
+                }
+                Err(e) => {
+                    log::warn!("failed to open tablebases in {}: {}", path, e);
+                    None
+                }
+            }
+        });
+
+        let store = config
+            .game_store
+            .as_ref()
+            .and_then(|dir| match GameStore::open(dir) {
+                Ok(store) => Some(store),
+                Err(e) => {
+                    log::warn!("failed to open game store {}: {}", dir, e);
+                    None
+                }
+            });
+
+        let accounts = config
+            .accounts
+            .as_ref()
+            .and_then(|path| match AccountStore::open(path) {
+                Ok(accounts) => {
+                    log::info!("loaded {} accounts from {}", accounts.len(), path);
+                    Some(accounts)
+                }
+                Err(e) => {
+                    log::warn!("failed to load accounts from {}: {}", path, e);
+                    None
+                }
+            });
+
+        let ratings = match (&config.ratings, &accounts) {
+            (Some(path), Some(_)) => match RatingStore::open(path) {
+                Ok(ratings) => Some(ratings),
+                Err(e) => {
+                    log::warn!("failed to load ratings from {}: {}", path, e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let mut puzzles = config.puzzles.as_ref().and_then(|path| {
+            let mut puzzles = match PuzzleStore::open(path) {
+                Ok(puzzles) => puzzles,
+                Err(e) => {
+                    log::warn!("failed to load puzzles from {}: {}", path, e);
+                    return None;
+                }
+            };
+            if let Some(csv) = &config.puzzle_import {
+                match puzzles.import(csv) {
+                    Ok(added) => log::info!("imported {} new puzzles from {}", added, csv),
+                    Err(e) => log::warn!("failed to import puzzles from {}: {}", csv, e),
+                }
+            }
+            log::info!("loaded {} puzzles from {}", puzzles.len(), path);
+            Some(puzzles)
+        });
+
+        let puzzle_ratings = match (&config.puzzle_ratings, &accounts, &mut puzzles) {
+            (Some(path), Some(_), Some(puzzles)) => match PuzzleRatings::open(path) {
+                Ok(ratings) => {
+                    // the puzzles are served by their ratings now
+                    for (id, glicko) in ratings.puzzle_ratings() {
+                        puzzles.set_rating(id, glicko.to_rating().rating);
+                    }
+                    Some(ratings)
+                }
+                Err(e) => {
+                    log::warn!("failed to load puzzle ratings from {}: {}", path, e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let (task_tx, task_rx) = unbounded();
+        let mut manager = GameManager {
             games: HashMap::new(),
             clients: HashMap::new(),
             rx: recv,
+            tx: send,
+            task_rx,
+            task_tx,
             next_game_id: 1,
-        }
+            // the `Server` counts client IDs up from 1, computer players count down from the top.
+            // IDs go over the network as u32, so that's the top.
+            next_computer_id: u32::MAX as ClientId,
+            book,
+            engine: config.external_engine(),
+            tablebase,
+            adjudicate: config.tablebase_adjudication,
+            store,
+            accounts,
+            ratings,
+            rated_takebacks: config.rated_takebacks,
+            forfeit_after: Duration::from_secs(config.forfeit_after),
+            seeks: SeekPool::default(),
+            challenges: Challenges::default(),
+            rematches: Rematches::default(),
+            tournaments: HashMap::new(),
+            arenas: HashMap::new(),
+            simuls: HashMap::new(),
+            next_simul_id: 1,
+            bughouses: HashMap::new(),
+            next_bughouse_id: 1,
+            rooms: HashMap::new(),
+            next_room_id: 1,
+            finished: FinishedGames::default(),
+            puzzles,
+            puzzle_attempts: HashMap::new(),
+            puzzle_ratings,
+            next_tournament_id: 1,
+            lobby_chat: ChatHistory::default(),
+            chat_limiter: RateLimiter::default(),
+            login_throttle: LoginThrottle::default(),
+        };
+        manager.restore_games();
+        manager
     }
 
     /// Creates a new `ChessGame` and returns it.
@@ -66,19 +322,10 @@ impl GameManager {
         self.next_game_id += 1;
 
         log::info!("create game with id: {} (mode: {})", id, game_params.mode);
-        ChessGame {
-            id,
-            chess: Chess::new(),
-            _started: false,
-            white_player: None,
-            black_player: None,
-            spectators: vec![],
-            _time: game_params.time,
-            _time_inc: game_params.time_inc,
-            draw_offer_white: false,
-            draw_offer_black: false,
-            move_history: vec![],
-        }
+        let mut game = ChessGame::new(id, Chess::new(), game_params.time, game_params.time_inc);
+        // without ratings, there are only casual games
+        game.rated = game_params.rated && self.ratings.is_some();
+        game
     }
 
     /// The main loop of the `GameManager`.
@@ -86,7 +333,7 @@ impl GameManager {
     /// It will then process the message and send back `ServerMessages` to the `ClientSessions`.
     pub async fn run(&mut self) {
         loop {
-            match self.rx.recv().await {
+            match self.next_message().await {
                 Ok((cid, cmd)) => {
                     // the actual command
                     match cmd {
@@ -118,6 +365,9 @@ impl GameManager {
                             log::info!("Set nickname for client {} to {}", cid, name);
                             self.handle_set_nickname(cid, name).await;
                         }
+                        ClientMessage::Reconnect(token) => {
+                            self.handle_reconnect(cid, token).await;
+                        }
                         ClientMessage::QueryBoard(gid) => {
                             self.handle_query_board(cid, gid).await;
                         }
@@ -130,6 +380,155 @@ impl GameManager {
                         ClientMessage::OfferDraw(gid) => {
                             self.handle_offer_draw(cid, gid).await;
                         }
+                        ClientMessage::QueryBookMoves(gid) => {
+                            self.handle_query_book_moves(cid, gid).await;
+                        }
+                        ClientMessage::AddComputer(gid, side, level) => {
+                            self.handle_add_computer(cid, gid, side, level).await;
+                        }
+                        ClientMessage::Analyse(gid) => {
+                            self.handle_analyse(cid, gid).await;
+                        }
+                        ClientMessage::QueryTablebase(gid) => {
+                            self.handle_query_tablebase(cid, gid).await;
+                        }
+                        ClientMessage::CreateAccount(credentials) => {
+                            self.handle_create_account(cid, credentials).await;
+                        }
+                        ClientMessage::Login(credentials) => {
+                            self.handle_login(cid, credentials).await;
+                        }
+                        ClientMessage::QueryRatingHistory(uid) => {
+                            self.handle_query_rating_history(cid, uid).await;
+                        }
+                        ClientMessage::Seek(params) => {
+                            self.handle_seek(cid, params).await;
+                        }
+                        ClientMessage::CancelSeek => {
+                            if self.seeks.remove(cid) {
+                                let msg = ServerMessage::SeekCancelled("Cancelled".to_string());
+                                self.send_to(cid, msg).await;
+                            }
+                        }
+                        ClientMessage::Challenge(challenged, params) => {
+                            self.handle_challenge(cid, challenged, params).await;
+                        }
+                        ClientMessage::AcceptChallenge(id) => {
+                            self.handle_accept_challenge(cid, id).await;
+                        }
+                        ClientMessage::DeclineChallenge(id, reason) => {
+                            self.handle_decline_challenge(cid, id, reason).await;
+                        }
+                        ClientMessage::CancelChallenge(id) => {
+                            self.handle_cancel_challenge(cid, id).await;
+                        }
+                        ClientMessage::Chat(channel, text) => {
+                            self.handle_chat(cid, channel, text).await;
+                        }
+                        ClientMessage::RequestTakeback(gid, plies) => {
+                            self.handle_request_takeback(cid, gid, plies).await;
+                        }
+                        ClientMessage::AnswerTakeback(gid, accept) => {
+                            self.handle_answer_takeback(cid, gid, accept).await;
+                        }
+                        ClientMessage::Abort(gid) => {
+                            self.handle_abort(cid, gid).await;
+                        }
+                        ClientMessage::Rematch(gid) => {
+                            self.handle_rematch(cid, gid).await;
+                        }
+                        ClientMessage::CreateTournament(params) => {
+                            self.handle_create_tournament(cid, params).await;
+                        }
+                        ClientMessage::JoinTournament(tid) => {
+                            self.handle_join_tournament(cid, tid).await;
+                        }
+                        ClientMessage::WithdrawTournament(tid) => {
+                            self.handle_withdraw_tournament(cid, tid).await;
+                        }
+                        ClientMessage::StartTournament(tid) => {
+                            self.handle_start_tournament(cid, tid).await;
+                        }
+                        ClientMessage::QueryStandings(tid) => {
+                            if let Some(msg) = self.standings(tid) {
+                                self.send_to(cid, msg).await;
+                            }
+                        }
+                        ClientMessage::ExportTournament(tid, format) => {
+                            self.handle_export_tournament(cid, tid, format).await;
+                        }
+                        ClientMessage::CreateArena(params) => {
+                            self.handle_create_arena(cid, params).await;
+                        }
+                        ClientMessage::JoinArena(tid) => {
+                            self.handle_join_arena(cid, tid).await;
+                        }
+                        ClientMessage::PauseArena(tid) => {
+                            self.handle_pause_arena(cid, tid, false).await;
+                        }
+                        ClientMessage::WithdrawArena(tid) => {
+                            self.handle_pause_arena(cid, tid, true).await;
+                        }
+                        ClientMessage::CreateSimul(params) => {
+                            self.handle_create_simul(cid, params).await;
+                        }
+                        ClientMessage::JoinSimul(sid) => {
+                            self.handle_join_simul(cid, sid).await;
+                        }
+                        ClientMessage::LeaveSimul(sid) => {
+                            self.handle_leave_simul(cid, sid).await;
+                        }
+                        ClientMessage::StartSimul(sid) => {
+                            self.handle_start_simul(cid, sid).await;
+                        }
+                        ClientMessage::CreateBughouse(params) => {
+                            self.handle_create_bughouse(cid, params).await;
+                        }
+                        ClientMessage::JoinBughouse(bid, seat) => {
+                            self.handle_join_bughouse(cid, bid, seat).await;
+                        }
+                        ClientMessage::LeaveBughouse(bid) => {
+                            self.handle_leave_bughouse(cid, bid).await;
+                        }
+                        ClientMessage::Drop(gid, drop) => {
+                            self.handle_drop(cid, gid, drop).await;
+                        }
+                        ClientMessage::CreateConsultation(params) => {
+                            self.handle_create_consultation(cid, params).await;
+                        }
+                        ClientMessage::JoinTeam(gid, color) => {
+                            self.handle_join_team(cid, gid, color).await;
+                        }
+                        ClientMessage::CreateRoom(source) => {
+                            self.handle_create_room(cid, source).await;
+                        }
+                        ClientMessage::JoinRoom(rid) => {
+                            self.handle_join_room(cid, rid).await;
+                        }
+                        ClientMessage::LeaveRoom(rid) => {
+                            self.handle_leave_room(cid, rid).await;
+                        }
+                        ClientMessage::RoomMove(rid, mov) => {
+                            self.handle_room_move(cid, rid, mov).await;
+                        }
+                        ClientMessage::RoomSetup(rid, fen) => {
+                            self.handle_room_setup(cid, rid, fen).await;
+                        }
+                        ClientMessage::RoomGoTo(rid, node) => {
+                            self.handle_room_go_to(cid, rid, node).await;
+                        }
+                        ClientMessage::RoomEngine(rid, on) => {
+                            self.handle_room_engine(cid, rid, on).await;
+                        }
+                        ClientMessage::RequestPuzzle(params) => {
+                            self.handle_request_puzzle(cid, params).await;
+                        }
+                        ClientMessage::PuzzleMove(mov) => {
+                            self.handle_puzzle_move(cid, mov).await;
+                        }
+                        ClientMessage::QueryPuzzleStats(uid) => {
+                            self.handle_query_puzzle_stats(cid, uid).await;
+                        }
                     }
                 }
                 Err(_) => {
@@ -138,26 +537,134 @@ impl GameManager {
             }
         }
     }
+    /// Wait for the next message of a client. In the meantime, challenges that aren't
+    /// answered in time expire, rematch offers run out, games that nobody plays
+    /// anymore end and so do arenas and bughouse matches whose clock ran out. Teams
+    /// that took too long to vote play their leading proposal, and the results of
+    /// spawned tasks are taken in.
+    async fn next_message(&mut self) -> Result<(ClientId, ClientMessage), RecvError> {
+        loop {
+            let games = self.games.values().flat_map(|g| {
+                [
+                    g.reap_deadline(),
+                    g.forfeit_deadline(self.forfeit_after),
+                    g.vote_deadline(),
+                ]
+            });
+            let arenas = self
+                .arenas
+                .values()
+                .filter(|a| !a.is_finished())
+                .map(|a| Some(a.ends));
+            let flags = self.bughouses.values().map(|b| b.flag_deadline());
+            let deadlines = games
+                .chain([self.challenges.next_expiry(), self.rematches.next_expiry()])
+                .chain(arenas)
+                .chain(flags);
+            let deadline = deadlines.flatten().min();
+            let (rx, task_rx) = (self.rx.clone(), self.task_rx.clone());
+            let client = async { Event::Client(rx.recv().await) };
+            // `self` keeps a sender, so the task channel never closes
+            let task = async { Event::Task(task_rx.recv().await.unwrap()) };
+            let timer = async {
+                match deadline {
+                    Some(deadline) => Timer::at(deadline).await,
+                    None => Timer::never().await,
+                };
+                Event::Deadline
+            };
+            match smol::future::or(client, smol::future::or(task, timer)).await {
+                Event::Client(received) => return received,
+                Event::Task(TaskResult::Adjudication(gid, fen, wdl)) => {
+                    self.handle_adjudication(gid, fen, wdl).await;
+                }
+                Event::Task(TaskResult::PasswordHashed(cid, name, hash)) => {
+                    self.handle_password_hashed(cid, name, hash).await;
+                }
+                Event::Task(TaskResult::LoginChecked(cid, name, result)) => {
+                    self.handle_login_checked(cid, name, result).await;
+                }
+                Event::Deadline => {
+                    let now = Instant::now();
+                    for challenge in self.challenges.remove_expired(now) {
+                        self.close_challenge(&challenge, ChallengeOutcome::Expired)
+                            .await;
+                    }
+                    self.rematches.remove_expired(now);
+                    self.end_abandoned_games(now).await;
+                    self.end_arenas(now).await;
+                    self.end_flagged_bughouses(now).await;
+                    self.end_votes(now).await;
+                }
+            }
+        }
+    }
+
     /// Create a new game and inform all `ClientSessions` about it.
     async fn handle_new_game(&mut self, cid: ClientId, game_params: NewGameParams) {
         let game = self.create_game(game_params);
         let gid = game.id;
         self.games.insert(gid, game);
+        self.persist(gid).await;
         // inform all clients of new game created by $cid
         for c in &self.clients {
             let _ = c.1.tx.send(ServerMessage::GameCreated(gid, cid)).await;
         }
     }
 
+    /// Remove the games nobody is in anymore, and end the games where the player to move
+    /// has been gone too long: they lose, or the game is aborted if it hasn't really started.
+    async fn end_abandoned_games(&mut self, now: Instant) {
+        let mut empty = vec![];
+        let mut abandoned = vec![];
+        for game in self.games.values() {
+            if game.reap_deadline().is_some_and(|t| t <= now) {
+                empty.push(game.id);
+            } else if game
+                .forfeit_deadline(self.forfeit_after)
+                .is_some_and(|t| t <= now)
+            {
+                abandoned.push((game.id, game.chess.active_player, game.can_abort()));
+            }
+        }
+
+        for gid in empty {
+            log::info!("removing empty game {}", gid);
+            self.remove_game(gid).await;
+        }
+        for (gid, side, abort) in abandoned {
+            log::info!("{} abandoned game {}", side, gid);
+            if abort {
+                self.abort_game(gid).await;
+            } else {
+                let reason = GameOverReason::Abandoned(!side);
+                self.broadcast(gid, ServerMessage::GameOver(gid, reason))
+                    .await;
+                self.close_game(gid, reason).await;
+            }
+        }
+    }
+
     /// Assign a client to a game and inform it and all other clients about the join.
     async fn handle_join_game(&mut self, cid: ClientId, join_params: JoinGameParams) {
         let gid = join_params.game_id;
         let side = join_params.side;
 
+        // rated games are for players with accounts, and one player per side
+        let rated = self.games.get(&gid).is_some_and(|g| g.rated);
+        let guest = self.clients.get(&cid).is_none_or(|c| c.user.is_none());
+        let player = side != UserRoleSelection::Spectator;
+        if rated && player && (guest || side == UserRoleSelection::Both) {
+            log::warn!("client {} can't play rated game {} as {}", cid, gid, side);
+            return;
+        }
+
         match self.add_player_to_game(gid, cid, side) {
             Ok(side) => {
                 let msg = ServerMessage::GameJoined(gid, cid, side);
                 self.broadcast(gid, msg).await;
+                self.replay_chat(cid, gid, side).await;
+                self.persist(gid).await;
             }
 
             Err(e) => {
@@ -171,8 +678,45 @@ impl GameManager {
         // leave all games that the client is part of. Used for sudden disconnects.
         let mut gids = vec![];
         if gid == 0 {
+            self.seeks.remove(cid);
+            self.chat_limiter.remove(cid);
+            self.rematches.remove_client(cid);
+            self.puzzle_attempts.remove(&cid);
+            // nobody can start the tournaments of an organizer that left; the players stay
+            // in the others, like in their games, until they come back
+            self.tournaments
+                .retain(|_, t| t.is_started() || t.organizer != cid);
+            // arena players take a break until they are back
+            let tids: Vec<TournamentId> = self.arenas.keys().copied().collect();
+            for tid in tids {
+                self.handle_pause_arena(cid, tid, false).await;
+            }
+            // a simul that hasn't started goes with its host
+            self.simuls.retain(|_, s| s.is_started() || s.host != cid);
+            let sids: Vec<SimulId> = self.simuls.keys().copied().collect();
+            for sid in sids {
+                self.handle_leave_simul(cid, sid).await;
+            }
+            let bids: Vec<BughouseId> = self.bughouses.keys().copied().collect();
+            for bid in bids {
+                self.handle_leave_bughouse(cid, bid).await;
+            }
+            let rids: Vec<RoomId> = self.rooms.keys().copied().collect();
+            for rid in rids {
+                self.handle_leave_room(cid, rid).await;
+            }
+            for challenge in self.challenges.remove_client(cid) {
+                self.close_challenge(&challenge, ChallengeOutcome::Cancelled)
+                    .await;
+            }
+
+            // a player we can recognize can come back to the game, keep the seat for them
+            let identity = self.clients.get(&cid).and_then(|c| c.identity());
             for game in self.games.values_mut() {
                 if game.get_all_participants().contains(&cid) {
+                    if let Some(identity) = &identity {
+                        game.reserve_seat(cid, identity);
+                    }
                     gids.push(game.id);
                 }
             }
@@ -183,21 +727,113 @@ impl GameManager {
             self.remove_player_from_game(gid, cid);
             let msg = ServerMessage::GameLeft(gid, cid);
             self.broadcast(gid, msg).await;
+            self.persist(gid).await;
+            self.announce_teams(gid).await;
+        }
+        if gid == 0 {
+            // the connection is gone; its account and token are free for the next one
+            self.clients.remove(&cid);
         }
     }
 
-    /// register a client after a new connection is accepted
-    /// TODO: this is quite a dummy as long we don't have persistent accounts.
-    /// TODO: later, we probably need to work here when we have real accounts.
+    /// Create a game with both players seated, for a seek or a challenge that found
+    /// its opponent. Returns the ID of the game.
+    pub(crate) async fn start_paired_game(
+        &mut self,
+        white: ClientId,
+        black: ClientId,
+        params: NewGameParams,
+        variant: Variant,
+    ) -> GameId {
+        let mut game = self.create_game(params);
+        game.variant = variant;
+        let gid = game.id;
+        self.games.insert(gid, game);
+
+        for c in self.clients.values() {
+            let _ = c.tx.send(ServerMessage::GameCreated(gid, white)).await;
+        }
+        for (cid, side) in [
+            (white, UserRoleSelection::White),
+            (black, UserRoleSelection::Black),
+        ] {
+            // a tournament player may be away; the caller keeps their seat
+            if !self.clients.contains_key(&cid) {
+                continue;
+            }
+            match self.add_player_to_game(gid, cid, side) {
+                Ok(side) => {
+                    let msg = ServerMessage::GameJoined(gid, cid, side);
+                    self.broadcast(gid, msg).await;
+                }
+                Err(e) => log::warn!("seating client {} in game {} failed: {}", cid, gid, e),
+            }
+        }
+        self.persist(gid).await;
+        gid
+    }
+
+    /// register a client after a new connection is accepted.
+    /// Every client starts as a guest; `Login` or `CreateAccount` tie the session to an account.
     async fn handle_register(&mut self, cid: ClientId, tx: Sender<ServerMessage>) {
         let client = ClientEndpoint::new(tx);
+        let token = client.token.clone();
         self.clients.insert(cid, client);
+        self.send_to(cid, ServerMessage::ReconnectToken(token))
+            .await;
+        for line in self.lobby_chat.lines() {
+            self.send_to(cid, line.to_message()).await;
+        }
+        // tournaments still open for registration
+        for t in self.tournaments.values().filter(|t| !t.is_started()) {
+            let msg = ServerMessage::TournamentCreated(t.id, t.organizer, t.params.clone());
+            self.send_to(cid, msg).await;
+            let players = t.players.iter().map(|p| p.cid).collect();
+            self.send_to(cid, ServerMessage::TournamentPlayers(t.id, players))
+                .await;
+        }
+        for a in self.arenas.values().filter(|a| !a.is_finished()) {
+            let msg = ServerMessage::ArenaCreated(a.id, a.organizer, a.params.clone());
+            self.send_to(cid, msg).await;
+        }
+        for s in self.simuls.values().filter(|s| !s.is_started()) {
+            let msg = ServerMessage::SimulCreated(s.id, s.host, s.params.clone());
+            self.send_to(cid, msg).await;
+            let msg = ServerMessage::SimulPlayers(s.id, s.opponents.clone());
+            self.send_to(cid, msg).await;
+        }
+        for b in self.bughouses.values().filter(|b| !b.is_started()) {
+            let msg = ServerMessage::BughouseCreated(b.id, b.creator, b.params.clone());
+            self.send_to(cid, msg).await;
+            self.send_to(cid, ServerMessage::BughouseSeats(b.id, b.seats))
+                .await;
+        }
+        for r in self.rooms.values() {
+            self.send_to(cid, ServerMessage::RoomCreated(r.id, r.creator))
+                .await;
+            let msg = ServerMessage::RoomMembers(r.id, r.members.clone());
+            self.send_to(cid, msg).await;
+        }
+    }
+
+    /// Handle a `ChessMove` from a `ClientSession`. In a consultation game, it is what a
+    /// member of the team proposes.
+    async fn handle_move(&mut self, cid: ClientId, gid: GameId, mov: ChessMove) {
+        if self
+            .games
+            .get(&gid)
+            .is_some_and(|g| g.consultation.is_some())
+        {
+            self.handle_proposal(cid, gid, mov).await;
+        } else {
+            self.play_move(cid, gid, mov).await;
+        }
     }
 
-    /// Handle a `ChessMove` from a `ClientSession`.
+    /// Make a move for a player.
     /// Moves can be accepted (when legal) and rejected (when illegal).
     /// Will also send separate `ServerMessages` for checkmate and stalemate.
-    async fn handle_move(&mut self, cid: ClientId, gid: GameId, mov: ChessMove) {
+    pub(crate) async fn play_move(&mut self, cid: ClientId, gid: GameId, mov: ChessMove) {
         let game = match self.games.get_mut(&gid) {
             Some(g) => g,
             None => {
@@ -208,20 +844,25 @@ impl GameManager {
             }
         };
 
-        // convert `ChessMove` to SAN notation to send it back to the client.
-        // we have to make this conversion before make_move() as we need the current board state
-        let san = mov.to_san(&game.chess);
-        let san_len = san.len() as u8;
+        // the book lookup needs the position before the move
+        let in_book = self
+            .book
+            .as_ref()
+            .is_some_and(|book| book.contains(&game.chess, mov));
+        // and for what the move captures, which goes to the other board in bughouse
+        let captured = game.chess.captured_piece(&mov);
 
         match game.make_move(mov, cid) {
-            // a legal move was made and accepted:
-            // Update move history, clear any draw offers
-            // and send the updated squares to all clients in the game.
+            // a legal move was made and accepted (the game recorded it):
+            // clear any draw offers and send the SAN and the updated squares to all
+            // clients in the game.
             Ok(changes) => {
-                game.move_history.push(san.clone());
+                let san = game.move_history.last().unwrap().san.clone();
+                game.in_book.push(in_book);
 
                 game.draw_offer_white = false;
                 game.draw_offer_black = false;
+                game.takeback_offer = None;
 
                 // convert `Piece` to `WoodPiece`. A `Piece` includes all the server side logic for
                 // movement, which the client should not need to know about. A `WoodPiece` is merely
@@ -231,23 +872,10 @@ impl GameManager {
                     .map(|(t, p)| (*t, p.map(|piece| piece.piece)))
                     .collect();
 
-                let msg = ServerMessage::MoveAccepted(san_len, san.clone(), changes.clone());
+                let msg = ServerMessage::MoveAccepted(gid, san.len() as u8, san, changes.clone());
                 self.broadcast(gid, msg).await;
-                // The move has been executed. Now we check if the game is over,
-                // e.g., checkmate or stalemate.
-                match self.get_game_state(gid).await {
-                    Some(ChessGameState::Running) => {}
-                    Some(ChessGameState::Finished(outcome)) => match outcome {
-                        reason => {
-                            let msg = ServerMessage::GameOver(gid, reason);
-                            self.broadcast(gid, msg).await;
-                            self.close_game(gid).await;
-                        }
-                    },
-                    None => {
-                        log::warn!("Invalid ChessGameState query for game: {}", gid)
-                    }
-                }
+                self.bughouse_move(gid, Some(&mov), captured);
+                self.after_move(gid).await;
             }
 
             // The move was illegal and thus rejected
@@ -259,6 +887,27 @@ impl GameManager {
         }
     }
 
+    /// The move has been executed. Now we check if the game is over, e.g., checkmate or
+    /// stalemate; if not, the game is kept and everyone following it learns about the move.
+    pub(crate) async fn after_move(&mut self, gid: GameId) {
+        self.probe_for_adjudication(gid);
+        match self.get_game_state(gid).await {
+            Some(ChessGameState::Running) => {
+                self.persist(gid).await;
+                self.update_simul(gid).await;
+                self.update_bughouse(gid).await;
+            }
+            Some(ChessGameState::Finished(reason)) => {
+                let msg = ServerMessage::GameOver(gid, reason);
+                self.broadcast(gid, msg).await;
+                self.close_game(gid, reason).await;
+            }
+            None => {
+                log::warn!("Invalid ChessGameState query for game: {}", gid)
+            }
+        }
+    }
+
     /// The client asked for listing all games.
     async fn handle_query_games(&self, cid: ClientId) {
         let game_ids: Vec<GameId> = self.games.keys().cloned().collect();
@@ -278,6 +927,9 @@ impl GameManager {
                     game.black_player,
                     game._time,
                     game._time_inc,
+                    game.rated,
+                    self.player_rating(game, game.white_player),
+                    self.player_rating(game, game.black_player),
                 );
                 self.send_to(cid, msg).await;
             }
@@ -301,10 +953,66 @@ impl GameManager {
         self.send_to(cid, msg).await;
     }
 
-    /// Setting the nickname of a client.
+    /// Setting the nickname of a guest.
+    /// Logged in clients go by their account name, and guests can't use the name of an account.
+    /// A returning guest gets their seats back with `Reconnect`, not by the name.
     async fn handle_set_nickname(&mut self, cid: ClientId, nickname: String) {
-        if let Some(c) = self.clients.get_mut(&cid) {
-            c.name = nickname;
+        let taken = self
+            .accounts
+            .as_ref()
+            .is_some_and(|a| a.find(&nickname).is_some());
+        let Some(c) = self.clients.get_mut(&cid) else {
+            return;
+        };
+        if c.user.is_some() || taken {
+            log::warn!("client {} can't go by the name {}", cid, nickname);
+            return;
+        }
+        c.name = nickname;
+    }
+
+    /// A guest is back with the token of an earlier connection. The token becomes the one of
+    /// this connection, and the seats kept for it are given back.
+    /// A token still in use by a connected client is refused.
+    async fn handle_reconnect(&mut self, cid: ClientId, token: String) {
+        let in_use = self
+            .clients
+            .iter()
+            .any(|(&other, c)| other != cid && c.token == token);
+        let Some(c) = self.clients.get_mut(&cid) else {
+            return;
+        };
+        if c.user.is_some() || c.is_computer() || token.is_empty() || in_use {
+            log::warn!("client {} can't reconnect with that token", cid);
+        } else {
+            let identity = Identity::guest(&token);
+            c.token = token;
+            self.reclaim_seats(cid, identity).await;
+        }
+        // the client keeps whatever token is valid now
+        if let Some(c) = self.clients.get(&cid) {
+            let msg = ServerMessage::ReconnectToken(c.token.clone());
+            self.send_to(cid, msg).await;
+        }
+    }
+
+    /// Seat a client in all games where a seat was kept for it, and give it back its place
+    /// in tournaments and arenas.
+    pub(crate) async fn reclaim_seats(&mut self, cid: ClientId, identity: Identity) {
+        self.rejoin_tournaments(cid, &identity).await;
+        self.rejoin_arenas(cid, &identity).await;
+        let mut reclaimed = vec![];
+        for game in self.games.values_mut() {
+            if let Some(side) = game.reclaim_seat(cid, &identity) {
+                reclaimed.push((game.id, side));
+            }
+        }
+        for (gid, side) in reclaimed {
+            log::info!("client {} ({:?}) is back in game {}", cid, identity, gid);
+            let msg = ServerMessage::GameJoined(gid, cid, side);
+            self.broadcast(gid, msg).await;
+            self.replay_chat(cid, gid, side).await;
+            self.persist(gid).await;
         }
     }
 
@@ -329,10 +1037,11 @@ impl GameManager {
         let Some(side) = self.get_player_side(gid, cid).await else {
             return;
         };
-        let msg = ServerMessage::GameOver(gid, GameOverReason::Resignation(!side));
+        let reason = GameOverReason::Resignation(!side);
+        let msg = ServerMessage::GameOver(gid, reason);
 
         self.broadcast(gid, msg).await;
-        self.close_game(gid).await;
+        self.close_game(gid, reason).await;
     }
 
     pub async fn handle_offer_draw(&mut self, cid: ClientId, gid: GameId) {
@@ -360,11 +1069,118 @@ impl GameManager {
         if self.get_white_draw_offer(gid) && self.get_black_draw_offer(gid) {
             let msg = ServerMessage::GameOver(gid, GameOverReason::DrawAgreement);
             self.broadcast(gid, msg).await;
-            self.close_game(gid).await;
+            self.close_game(gid, GameOverReason::DrawAgreement).await;
+        } else {
+            self.persist(gid).await;
         }
     }
 
-    fn is_full(&self, gid: GameId) -> bool {
+    /// A player asks to take back the last half-move (`plies` = 1) or full move (2).
+    /// The opponent has to agree; someone playing both sides takes back right away.
+    async fn handle_request_takeback(&mut self, cid: ClientId, gid: GameId, plies: u8) {
+        let Some(game) = self.games.get_mut(&gid) else {
+            return;
+        };
+        let Some(color) = game.get_side(cid) else {
+            return;
+        };
+        if !(1..=2).contains(&plies) || plies as usize > game.move_history.len() {
+            log::warn!(
+                "client {} can't take back {} plies in game {}",
+                cid,
+                plies,
+                gid
+            );
+            return;
+        }
+        if game.rated && !self.rated_takebacks {
+            log::warn!("client {} asked for a takeback in rated game {}", cid, gid);
+            return;
+        }
+        // the captures already went to the other board
+        if game.bughouse.is_some() {
+            log::warn!(
+                "client {} asked for a takeback in bughouse game {}",
+                cid,
+                gid
+            );
+            return;
+        }
+
+        let opponent = game.get_opponent(cid);
+        if opponent == Some(cid) {
+            self.take_back(gid, plies).await;
+            return;
+        }
+        // computer players don't answer
+        let human = |c: ClientId| self.clients.get(&c).is_some_and(|c| !c.is_computer());
+        if !opponent.is_some_and(human) {
+            log::warn!(
+                "client {} has nobody to ask for a takeback in game {}",
+                cid,
+                gid
+            );
+            return;
+        }
+        if let Some(game) = self.games.get_mut(&gid) {
+            game.takeback_offer = Some((color, plies));
+        }
+        let msg = ServerMessage::TakebackOffered(gid, plies);
+        self.broadcast(gid, msg).await;
+    }
+
+    /// The opponent of the player who asked for a takeback answers.
+    async fn handle_answer_takeback(&mut self, cid: ClientId, gid: GameId, accept: bool) {
+        let Some(game) = self.games.get_mut(&gid) else {
+            return;
+        };
+        let Some((color, plies)) = game.takeback_offer else {
+            log::warn!(
+                "client {} answers a takeback in game {} nobody asked for",
+                cid,
+                gid
+            );
+            return;
+        };
+        let opponent = match color {
+            ChessColor::White => game.black_player,
+            ChessColor::Black => game.white_player,
+        };
+        if opponent != Some(cid) {
+            log::warn!("client {} can't answer the takeback in game {}", cid, gid);
+            return;
+        }
+
+        game.takeback_offer = None;
+        if accept {
+            self.take_back(gid, plies).await;
+        } else {
+            let msg = ServerMessage::TakebackDeclined(gid);
+            self.broadcast(gid, msg).await;
+        }
+    }
+
+    /// Undo moves and bring all participants back in sync with the game.
+    async fn take_back(&mut self, gid: GameId, plies: u8) {
+        let Some(game) = self.games.get_mut(&gid) else {
+            return;
+        };
+        if let Err(e) = game.take_back(plies as usize) {
+            log::warn!("takeback in game {} failed: {}", gid, e);
+            return;
+        }
+        log::info!("took back {} plies in game {}", plies, gid);
+        let fen = game.chess.get_fen();
+        let history = game.move_history.clone();
+        self.broadcast(gid, ServerMessage::BoardState(gid, fen))
+            .await;
+        self.broadcast(gid, ServerMessage::MoveHistory(gid, history))
+            .await;
+        self.persist(gid).await;
+        self.update_simul(gid).await;
+    }
+
+    pub(crate) fn is_full(&self, gid: GameId) -> bool {
         let game = self.games.get(&gid);
         match game {
             Some(game) => game.get_players().len() == 2,
@@ -420,7 +1236,7 @@ impl GameManager {
         }
     }
 
-    fn add_player_to_game(
+    pub(crate) fn add_player_to_game(
         &mut self,
         gid: GameId,
         cid: ClientId,
@@ -460,44 +1276,115 @@ impl GameManager {
     }
 
     /// Broadcast a message to all clients (players and spectators) of a game.
-    async fn broadcast(&mut self, gid: GameId, message: ServerMessage) {
+    pub(crate) async fn broadcast(&mut self, gid: GameId, message: ServerMessage) {
         if let Some(game) = self.games.get(&gid) {
             for c in game.get_all_participants() {
-                let _ = self.clients.get(&c).unwrap().tx.send(message.clone()).await;
+                if let Some(client) = self.clients.get(&c) {
+                    let _ = client.tx.send(message.clone()).await;
+                }
             }
         }
     }
 
-    async fn send_to(&self, cid: ClientId, message: ServerMessage) {
+    pub(crate) async fn send_to(&self, cid: ClientId, message: ServerMessage) {
         if let Some(client) = self.clients.get(&cid) {
             let _ = client.tx.send(message).await;
         }
     }
 
     /// Remove game from `GameManager` and save game history to disk.
-    async fn close_game(&mut self, gid: GameId) {
-        if let Some(game) = self.games.remove(&gid) {
-            let _ = self.save_game(&game).await;
+    /// The history is saved first, `remove_game` takes the computer players and their names.
+    pub(crate) async fn close_game(&mut self, gid: GameId, reason: GameOverReason) {
+        if let Some(game) = self.games.get(&gid) {
+            let _ = self.save_game(game).await;
         }
+        if let Some(game) = self.remove_game(gid).await {
+            // drops don't fit on the free board of a room
+            if game.bughouse.is_none() {
+                self.finished.add(game.id, &game.start_fen, &game.sans());
+            }
+            if game.rated {
+                self.rate_game(&game, reason).await;
+            }
+            if let Some(sid) = game.simul {
+                self.record_simul_result(sid, &game, reason).await;
+                return;
+            }
+            if let Some(bid) = game.bughouse {
+                self.record_bughouse_result(bid, &game, reason).await;
+                return;
+            }
+            match game.tournament {
+                Some(tid) if self.arenas.contains_key(&tid) => {
+                    self.record_arena_result(tid, &game, reason).await
+                }
+                Some(tid) => self.record_tournament_result(tid, &game, reason).await,
+                None => self.keep_for_rematch(&game),
+            }
+        }
+    }
+
+    /// A player ends the game before it really started; there is no result.
+    async fn handle_abort(&mut self, cid: ClientId, gid: GameId) {
+        let Some(game) = self.games.get(&gid) else {
+            return;
+        };
+        if game.get_side(cid).is_none() {
+            return;
+        }
+        if !game.can_abort() {
+            log::warn!("client {} can't abort game {} anymore", cid, gid);
+            return;
+        }
+        self.abort_game(gid).await;
+    }
+
+    /// End a game without a result: not saved, not rated.
+    async fn abort_game(&mut self, gid: GameId) {
+        log::info!("game {} aborted", gid);
+        self.broadcast(gid, ServerMessage::GameAborted(gid)).await;
+        self.remove_game(gid).await;
+    }
+
+    /// Remove a game from the manager and the game store.
+    /// Computer players only exist for their game, so they are removed as well.
+    pub(crate) async fn remove_game(&mut self, gid: GameId) -> Option<ChessGame> {
+        let game = self.games.remove(&gid)?;
+        if let Some(store) = &self.store {
+            if let Err(e) = store.remove(gid).await {
+                log::warn!("failed to remove stored game {}: {}", gid, e);
+            }
+        }
+        for cid in game.get_players() {
+            if self.clients.get(&cid).is_some_and(|c| c.is_computer()) {
+                self.clients.remove(&cid);
+            }
+        }
+        Some(game)
     }
 
     /// save game history to disk.
-    async fn save_game(&self, game: &ChessGame) -> std::io::Result<()> {
+    pub(crate) async fn save_game(&self, game: &ChessGame) -> std::io::Result<()> {
         let date = Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string();
 
-        let white = game.white_player.map_or("Unknown".to_string(), |p| {
-            self.clients.get(&p).unwrap().name.clone()
-        });
-        let black = game.black_player.map_or("Unknown".to_string(), |p| {
-            self.clients.get(&p).unwrap().name.clone()
-        });
+        // players who left have no client anymore
+        let name = |player: Option<ClientId>| {
+            player
+                .and_then(|p| self.clients.get(&p))
+                .map_or("Unknown".to_string(), |c| c.name.clone())
+        };
+        let white = name(game.white_player);
+        let black = name(game.black_player);
 
         let filename = format!("{}-vs-{}_{}.txt", white, black, date);
         let mut file = File::create(filename).await?;
 
         let mut fullmove = false;
-        for mov in game.move_history.iter() {
-            file.write_all(mov.to_string().as_bytes()).await?;
+        for (i, record) in game.move_history.iter().enumerate() {
+            file.write_all(record.san.as_bytes()).await?;
+            if game.in_book.get(i) == Some(&true) {
+                file.write_all(b" {book}").await?;
+            }
             if !fullmove {
                 file.write_all(b" ").await?;
                 fullmove = true;
@@ -506,6 +1393,12 @@ impl GameManager {
                 fullmove = false;
             }
         }
+        if !game.chat.is_empty() {
+            file.write_all(b"\n").await?;
+            for line in &game.chat {
+                file.write_all(format!("{}\n", line).as_bytes()).await?;
+            }
+        }
         file.sync_all().await?;
 
         Ok(())
@@ -1,4 +1,23 @@
+pub mod accounts;
+pub mod analysis;
+pub mod arenas;
+pub mod bughouse;
+pub mod challenges;
+pub mod chat;
 pub mod chessgame;
+pub mod computer;
+pub mod config;
+pub mod consultation;
 pub mod manager;
+pub mod password;
+pub mod puzzleratings;
+pub mod puzzles;
+pub mod ratings;
+pub mod rematches;
+pub mod rooms;
+pub mod seeks;
 pub mod server;
 pub mod session;
+pub mod simuls;
+pub mod store;
+pub mod tournaments;
@@ -0,0 +1,66 @@
+//! Salted password hashes with Argon2id, stored as PHC strings
+//! (`$argon2id$v=19$m=...,t=...,p=...$salt$hash`), which carry their own salt and parameters.
+//! The parameters are the defaults of the `argon2` crate, the current OWASP recommendation.
+//! Hashing takes a while on purpose, so callers run it with `smol::unblock`.
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::RngExt;
+
+const SALT_LEN: usize = 16;
+
+/// The hash of a random password that was thrown away, with the same parameters as
+/// `hash_password`. Logins with unknown names are checked against it, so they take as long
+/// as logins with a wrong password and don't tell which names exist.
+pub const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$Fb8hELr8XbeQrCvFhu3Vfw$AlxlW4aKFc2qSYIHU4bD+iDJL5R3kFP10EGb7Po4TTc";
+
+/// Hash a password with a new random salt.
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; SALT_LEN];
+    rand::rng().fill(&mut salt);
+    let salt = SaltString::encode_b64(&salt).expect("16 bytes are a valid salt");
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("the default parameters are valid")
+        .to_string()
+}
+
+/// Check a password against a stored hash. A broken hash matches no password.
+/// The comparison takes the same time however much of the hash is right.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// Whether a stored hash is a hash we can check passwords against.
+pub fn valid_hash(hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_password() {
+        let hash = hash_password("secret");
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("secret", &hash));
+        assert!(!verify_password("Secret", &hash));
+
+        // the same password gets a different salt every time
+        let other = hash_password("secret");
+        assert_ne!(hash, other);
+        assert!(verify_password("secret", &other));
+
+        assert!(valid_hash(&hash));
+        assert!(valid_hash(DUMMY_HASH));
+        assert!(!verify_password("secret", DUMMY_HASH));
+        assert!(!valid_hash("0123abcd"));
+        assert!(!verify_password("secret", "0123abcd"));
+    }
+}
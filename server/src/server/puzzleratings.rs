@@ -0,0 +1,298 @@
+use crate::server::puzzles::{Puzzle, PuzzleStore};
+use crate::server::ratings::Glicko2;
+use chess_core::protocol::ThemeStats;
+use chess_core::UserId;
+use smol::io::AsyncWriteExt;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many of a solver's last attempts keep their puzzles from being served again.
+const RECENT: usize = 500;
+/// Imported puzzles were played a lot on Lichess; their ratings are about this sure.
+const IMPORTED_DEVIATION: f64 = 80.0;
+
+/// A puzzle a solver tried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempt {
+    pub puzzle: String,
+    pub time: u32, // seconds since the Unix epoch
+    pub solved: bool,
+    pub rating: u16, // of the puzzle, after the attempt
+}
+
+/// The puzzle ratings of the solvers and of the puzzles, and what each solver tried.
+/// Every attempt is a game between the solver and the puzzle, which wins if it isn't solved.
+/// On disk, this is a log with a line for every attempt:
+/// `user puzzle time solved rating deviation volatility rating deviation volatility`, with the
+/// new ratings of the solver and then the puzzle.
+pub struct PuzzleRatings {
+    path: PathBuf,
+    solvers: HashMap<UserId, Glicko2>,
+    puzzles: HashMap<String, Glicko2>,
+    attempts: HashMap<UserId, Vec<Attempt>>, // oldest first
+}
+
+impl PuzzleRatings {
+    /// Load the ratings from a file. A missing file means nobody has tried a puzzle yet.
+    pub fn open(path: &str) -> io::Result<PuzzleRatings> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut ratings = PuzzleRatings {
+            path: PathBuf::from(path),
+            solvers: HashMap::new(),
+            puzzles: HashMap::new(),
+            attempts: HashMap::new(),
+        };
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            match parse_line(line) {
+                Some((uid, attempt, solver, puzzle)) => {
+                    ratings.insert(uid, attempt, solver, puzzle)
+                }
+                None => log::warn!("skipping broken puzzle rating line in {}: {}", path, line),
+            }
+        }
+        Ok(ratings)
+    }
+
+    fn insert(&mut self, uid: UserId, attempt: Attempt, solver: Glicko2, puzzle: Glicko2) {
+        self.solvers.insert(uid, solver);
+        self.puzzles.insert(attempt.puzzle.clone(), puzzle);
+        self.attempts.entry(uid).or_default().push(attempt);
+    }
+
+    /// The rating of a solver; the default rating before the first attempt.
+    pub fn solver(&self, uid: UserId) -> Glicko2 {
+        self.solvers.get(&uid).copied().unwrap_or_default()
+    }
+
+    /// The rating of a puzzle; the one it was imported with before the first attempt.
+    pub fn puzzle(&self, puzzle: &Puzzle) -> Glicko2 {
+        self.puzzles.get(&puzzle.id).copied().unwrap_or(Glicko2 {
+            rating: puzzle.rating as f64,
+            deviation: IMPORTED_DEVIATION,
+            ..Default::default()
+        })
+    }
+
+    /// The puzzles that were rated, with their ratings now.
+    pub fn puzzle_ratings(&self) -> impl Iterator<Item = (&String, &Glicko2)> {
+        self.puzzles.iter()
+    }
+
+    /// What a solver tried, oldest first.
+    pub fn attempts(&self, uid: UserId) -> &[Attempt] {
+        self.attempts.get(&uid).map_or(&[], |a| a.as_slice())
+    }
+
+    /// The puzzles of a solver's last attempts, not to be served again so soon.
+    pub fn recent(&self, uid: UserId) -> HashSet<&str> {
+        let attempts = self.attempts(uid);
+        attempts[attempts.len().saturating_sub(RECENT)..]
+            .iter()
+            .map(|a| a.puzzle.as_str())
+            .collect()
+    }
+
+    /// Rate an attempt. Returns the new ratings of the solver and the puzzle.
+    pub async fn rate(
+        &mut self,
+        uid: UserId,
+        puzzle: &Puzzle,
+        solved: bool,
+    ) -> io::Result<(Glicko2, Glicko2)> {
+        let old_solver = self.solver(uid);
+        let old_puzzle = self.puzzle(puzzle);
+        let score = if solved { 1.0 } else { 0.0 };
+        let new_solver = old_solver.update(&[(old_puzzle, score)]);
+        let new_puzzle = old_puzzle.update(&[(old_solver, 1.0 - score)]);
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+        let attempt = Attempt {
+            puzzle: puzzle.id.clone(),
+            time,
+            solved,
+            rating: new_puzzle.to_rating().rating,
+        };
+        let line = format!(
+            "{} {} {} {} {} {} {} {} {} {}\n",
+            uid,
+            attempt.puzzle,
+            time,
+            solved as u8,
+            new_solver.rating,
+            new_solver.deviation,
+            new_solver.volatility,
+            new_puzzle.rating,
+            new_puzzle.deviation,
+            new_puzzle.volatility
+        );
+        self.insert(uid, attempt, new_solver, new_puzzle);
+
+        let mut file = smol::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.sync_all().await?;
+        Ok((new_solver, new_puzzle))
+    }
+
+    /// How a solver does per theme, the strongest first. Puzzles that are gone from the
+    /// store don't count.
+    pub fn theme_stats(&self, uid: UserId, store: &PuzzleStore) -> Vec<ThemeStats> {
+        // theme: (attempts, solved, sum of the puzzle ratings)
+        let mut themes: HashMap<&str, (u32, u32, f64)> = HashMap::new();
+        for attempt in self.attempts(uid) {
+            let Some(puzzle) = store.get(&attempt.puzzle) else {
+                continue;
+            };
+            for theme in &puzzle.themes {
+                let entry = themes.entry(theme).or_default();
+                entry.0 += 1;
+                entry.1 += attempt.solved as u32;
+                entry.2 += attempt.rating as f64;
+            }
+        }
+        let mut stats: Vec<ThemeStats> = themes
+            .into_iter()
+            .map(|(theme, (attempts, solved, ratings))| {
+                let failed = attempts - solved;
+                let performance =
+                    (ratings + 400.0 * (solved as f64 - failed as f64)) / attempts as f64;
+                ThemeStats {
+                    theme: theme.to_string(),
+                    attempts,
+                    solved,
+                    performance: performance.round().clamp(0.0, u16::MAX as f64) as u16,
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| {
+            b.performance
+                .cmp(&a.performance)
+                .then_with(|| a.theme.cmp(&b.theme))
+        });
+        stats
+    }
+}
+
+fn parse_line(line: &str) -> Option<(UserId, Attempt, Glicko2, Glicko2)> {
+    let mut parts = line.split_whitespace();
+    let uid = parts.next()?.parse().ok()?;
+    let puzzle = parts.next()?.to_string();
+    let time = parts.next()?.parse().ok()?;
+    let solved = parts.next()? == "1";
+    let mut glicko = || -> Option<Glicko2> {
+        Some(Glicko2 {
+            rating: parts.next()?.parse().ok()?,
+            deviation: parts.next()?.parse().ok()?,
+            volatility: parts.next()?.parse().ok()?,
+        })
+    };
+    let solver = glicko()?;
+    let rated = glicko()?;
+    let attempt = Attempt {
+        puzzle,
+        time,
+        solved,
+        rating: rated.to_rating().rating,
+    };
+    Some((uid, attempt, solver, rated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puzzle(id: &str, rating: u16, themes: &[&str]) -> Puzzle {
+        Puzzle {
+            id: id.to_string(),
+            fen: String::new(),
+            moves: vec![],
+            rating,
+            themes: themes.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_puzzle_ratings() {
+        smol::block_on(async {
+            let path = std::env::temp_dir().join(format!("puzzle-ratings-{}", std::process::id()));
+            let path = path.to_str().unwrap();
+            let _ = std::fs::remove_file(path);
+
+            let fork = puzzle("a", 1500, &["fork", "short"]);
+            let pin = puzzle("b", 1500, &["pin", "short"]);
+            let mut ratings = PuzzleRatings::open(path).unwrap();
+            assert_eq!(ratings.puzzle(&fork).rating, 1500.0);
+
+            // solving wins rating from the puzzle, failing loses it
+            let (solver, rated) = ratings.rate(7, &fork, true).await.unwrap();
+            assert!(solver.rating > 1500.0 && rated.rating < 1500.0);
+            let (after, rated) = ratings.rate(7, &pin, false).await.unwrap();
+            assert!(after.rating < solver.rating && rated.rating > 1500.0);
+            assert_eq!(ratings.recent(7), HashSet::from(["a", "b"]));
+            assert!(ratings.recent(8).is_empty());
+
+            // everything comes back from the file
+            let reloaded = PuzzleRatings::open(path).unwrap();
+            assert_eq!(reloaded.solver(7), after);
+            assert_eq!(reloaded.puzzle(&pin), rated);
+            assert_eq!(reloaded.attempts(7), ratings.attempts(7));
+            let _ = std::fs::remove_file(path);
+        });
+    }
+
+    #[test]
+    fn test_theme_stats() {
+        let dir = std::env::temp_dir().join(format!("puzzle-stats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("lichess.csv");
+        std::fs::write(
+            &csv,
+            "a,7k/8/8/8/8/8/R7/1R4K1 b - - 0 1,h8g8 b1b7 g8f8 a2a8,1200,75,90,100,mate mateIn2,,\n\
+             b,6k1/p4ppp/8/8/8/8/5PPP/3RR1K1 b - - 0 1,a7a6 e1e8,900,75,90,100,mate mateIn1,,\n",
+        )
+        .unwrap();
+        let store_path = dir.join("puzzles.txt");
+        let _ = std::fs::remove_file(&store_path);
+        let mut store = PuzzleStore::open(store_path.to_str().unwrap()).unwrap();
+        store.import(csv.to_str().unwrap()).unwrap();
+
+        let mut ratings = PuzzleRatings::open(dir.join("none").to_str().unwrap()).unwrap();
+        let attempt = |puzzle: &str, solved, rating| Attempt {
+            puzzle: puzzle.to_string(),
+            time: 0,
+            solved,
+            rating,
+        };
+        let glicko = Glicko2::default();
+        ratings.insert(1, attempt("a", false, 1200), glicko, glicko);
+        ratings.insert(1, attempt("b", true, 900), glicko, glicko);
+        ratings.insert(1, attempt("gone", true, 900), glicko, glicko);
+
+        let stats = ratings.theme_stats(1, &store);
+        let themes: Vec<(&str, u32, u32, u16)> = stats
+            .iter()
+            .map(|s| (s.theme.as_str(), s.attempts, s.solved, s.performance))
+            .collect();
+        assert_eq!(
+            themes,
+            vec![
+                ("mateIn1", 1, 1, 1300),
+                ("mate", 2, 1, 1050),
+                ("mateIn2", 1, 0, 800),
+            ]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
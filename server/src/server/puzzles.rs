@@ -0,0 +1,573 @@
+use crate::chess::chess::Chess;
+use crate::chess::san::San;
+use crate::server::manager::GameManager;
+use crate::server::rooms::setup_position;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{PuzzleParams, PuzzleState, PuzzleStatus};
+use chess_core::{ChessColor, ChessError, ChessMove, ClientId, UserId};
+use rand::RngExt;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// Puzzles are picked within this of the solver's rating, or twice as far and so on if
+/// there are none.
+const NEAR: u16 = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PuzzleError {
+    NotRunning,
+    IllegalMove,
+}
+
+impl std::fmt::Display for PuzzleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PuzzleError::NotRunning => write!(f, "The puzzle is over"),
+            PuzzleError::IllegalMove => write!(f, "Illegal move"),
+        }
+    }
+}
+
+/// A puzzle in the way of the Lichess puzzle database: the position before the opponent's
+/// move that sets the puzzle, then that move and the solution, the opponent's replies in
+/// between. The solver always has the last move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    pub moves: Vec<ChessMove>,
+    pub rating: u16,
+    pub themes: Vec<String>,
+}
+
+impl Puzzle {
+    /// A puzzle from a line of the Lichess CSV export:
+    /// `PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags`.
+    pub fn from_lichess_csv(line: &str) -> Option<Puzzle> {
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() < 8 {
+            return None;
+        }
+        Puzzle::new(fields[0], fields[1], fields[2], fields[3], fields[7])
+    }
+
+    /// A puzzle from a line of the store: `id,fen,moves,rating,themes`.
+    fn from_line(line: &str) -> Option<Puzzle> {
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != 5 {
+            return None;
+        }
+        Puzzle::new(fields[0], fields[1], fields[2], fields[3], fields[4])
+    }
+
+    fn to_line(&self) -> String {
+        let moves: Vec<String> = self.moves.iter().map(|m| m.to_string()).collect();
+        format!(
+            "{},{},{},{},{}",
+            self.id,
+            self.fen,
+            moves.join(" "),
+            self.rating,
+            self.themes.join(" ")
+        )
+    }
+
+    /// Checks that the moves can be played from the position, and that there are any
+    /// for the solver.
+    fn new(id: &str, fen: &str, moves: &str, rating: &str, themes: &str) -> Option<Puzzle> {
+        if id.is_empty() {
+            return None;
+        }
+        let moves = moves
+            .split_whitespace()
+            .map(parse_uci)
+            .collect::<Option<Vec<ChessMove>>>()?;
+        if moves.len() < 2 || !moves.len().is_multiple_of(2) {
+            return None;
+        }
+        let mut chess = setup_position(fen)?;
+        for mov in &moves {
+            if !chess.is_legal_move(mov) {
+                return None;
+            }
+            chess.make_move(*mov).ok()?;
+        }
+        Some(Puzzle {
+            id: id.to_string(),
+            fen: fen.to_string(),
+            moves,
+            rating: rating.parse().ok()?,
+            themes: themes.split_whitespace().map(str::to_string).collect(),
+        })
+    }
+
+    pub fn has_theme(&self, theme: &str) -> bool {
+        self.themes.iter().any(|t| t == theme)
+    }
+}
+
+/// A move in UCI notation, only if it names squares of the board.
+fn parse_uci(s: &str) -> Option<ChessMove> {
+    let b = s.as_bytes();
+    let on_board =
+        |file: u8, rank: u8| (b'a'..=b'h').contains(&file) && (b'1'..=b'8').contains(&rank);
+    if !(4..=5).contains(&b.len()) || !on_board(b[0], b[1]) || !on_board(b[2], b[3]) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// The puzzles of the server. On disk, a line per puzzle: `id,fen,moves,rating,themes`,
+/// the moves in UCI notation and the themes apart by spaces, as in the Lichess export.
+pub struct PuzzleStore {
+    path: PathBuf,
+    puzzles: Vec<Puzzle>,
+    index: HashMap<String, usize>, // ID to position in `puzzles`
+}
+
+impl PuzzleStore {
+    /// Load the puzzles from a file. A missing file means there are none yet.
+    pub fn open(path: &str) -> io::Result<PuzzleStore> {
+        let mut store = PuzzleStore {
+            path: PathBuf::from(path),
+            puzzles: vec![],
+            index: HashMap::new(),
+        };
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(store),
+            Err(e) => return Err(e),
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match Puzzle::from_line(&line) {
+                Some(puzzle) => {
+                    store.insert(puzzle);
+                }
+                None => log::warn!("skipping broken puzzle line in {}: {}", path, line),
+            }
+        }
+        Ok(store)
+    }
+
+    fn insert(&mut self, puzzle: Puzzle) -> bool {
+        if self.index.contains_key(&puzzle.id) {
+            return false;
+        }
+        self.index.insert(puzzle.id.clone(), self.puzzles.len());
+        self.puzzles.push(puzzle);
+        true
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Puzzle> {
+        self.index.get(id).map(|&i| &self.puzzles[i])
+    }
+
+    /// The rating of a puzzle changes as it is solved or failed; the store keeps the one it
+    /// was imported with.
+    pub fn set_rating(&mut self, id: &str, rating: u16) {
+        if let Some(&i) = self.index.get(id) {
+            self.puzzles[i].rating = rating;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.puzzles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.puzzles.is_empty()
+    }
+
+    /// Add the puzzles of a Lichess CSV export that aren't in the store yet.
+    /// Lines that don't make a puzzle are skipped. Returns how many were added.
+    pub fn import(&mut self, csv: &str) -> io::Result<usize> {
+        let reader = BufReader::new(File::open(csv)?);
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut added = 0;
+        let mut skipped = 0;
+        for line in reader.lines() {
+            let line = line?;
+            if line.starts_with("PuzzleId") || line.trim().is_empty() {
+                continue; // the header
+            }
+            let Some(puzzle) = Puzzle::from_lichess_csv(&line) else {
+                skipped += 1;
+                continue;
+            };
+            let stored = puzzle.to_line();
+            if self.insert(puzzle) {
+                writeln!(file, "{}", stored)?;
+                added += 1;
+            }
+        }
+        file.sync_all()?;
+        if skipped > 0 {
+            log::warn!("skipped {} broken puzzles in {}", skipped, csv);
+        }
+        Ok(added)
+    }
+
+    /// A random puzzle of the rating and theme asked for, one the solver hasn't tried lately
+    /// if there is any. Without a rating asked for, it is as close to the solver's rating
+    /// (`near`) as there are puzzles.
+    pub fn pick(
+        &self,
+        params: &PuzzleParams,
+        near: Option<u16>,
+        recent: &HashSet<&str>,
+    ) -> Option<&Puzzle> {
+        let fits: Vec<&Puzzle> = self
+            .puzzles
+            .iter()
+            .filter(|p| params.min_rating == 0 || p.rating >= params.min_rating)
+            .filter(|p| params.max_rating == 0 || p.rating <= params.max_rating)
+            .filter(|p| params.theme.is_empty() || p.has_theme(&params.theme))
+            .collect();
+        let fresh: Vec<&Puzzle> = fits
+            .iter()
+            .copied()
+            .filter(|p| !recent.contains(p.id.as_str()))
+            .collect();
+        let mut fits = if fresh.is_empty() { fits } else { fresh };
+
+        if let (Some(near), 0, 0) = (near, params.min_rating, params.max_rating) {
+            let mut window = NEAR;
+            while !fits.is_empty() {
+                let close: Vec<&Puzzle> = fits
+                    .iter()
+                    .copied()
+                    .filter(|p| p.rating.abs_diff(near) <= window)
+                    .collect();
+                if !close.is_empty() {
+                    fits = close;
+                    break;
+                }
+                window = window.saturating_mul(2);
+            }
+        }
+        if fits.is_empty() {
+            return None;
+        }
+        Some(fits[rand::rng().random_range(0..fits.len())])
+    }
+}
+
+/// A client solving a puzzle. The server plays the opponent's moves; the solver has to
+/// find the moves of the solution, but any move that mates is as good.
+pub struct PuzzleAttempt {
+    pub puzzle: Puzzle,
+    color: ChessColor, // of the solver
+    chess: Chess,
+    next: usize, // the move of the solution that's due
+    played: Vec<String>,
+    status: PuzzleStatus,
+    solution: Vec<String>, // what the solver missed, once failed
+}
+
+impl PuzzleAttempt {
+    /// Set up the puzzle: the opponent makes the first move.
+    pub fn start(puzzle: Puzzle) -> Self {
+        // the puzzle was checked when it was loaded
+        let chess = setup_position(&puzzle.fen).unwrap_or_default();
+        let mut attempt = PuzzleAttempt {
+            puzzle,
+            color: !chess.active_player,
+            chess,
+            next: 0,
+            played: vec![],
+            status: PuzzleStatus::Running,
+            solution: vec![],
+        };
+        attempt.reply();
+        attempt
+    }
+
+    /// The opponent plays the next move of the solution.
+    fn reply(&mut self) {
+        let mov = self.puzzle.moves[self.next];
+        self.played.push(mov.to_san(&self.chess));
+        let _ = self.chess.make_move(mov);
+        self.next += 1;
+    }
+
+    /// A move of the solver. The right move brings the opponent's reply, or ends the puzzle
+    /// if it was the last one; a mate ends it anyway. A wrong move fails it.
+    pub fn play(&mut self, mov: ChessMove) -> Result<PuzzleStatus, PuzzleError> {
+        if self.status != PuzzleStatus::Running {
+            return Err(PuzzleError::NotRunning);
+        }
+        if !self.chess.is_legal_move(&mov) {
+            return Err(PuzzleError::IllegalMove);
+        }
+        let before = self.chess.clone();
+        self.played.push(mov.to_san(&self.chess));
+        self.chess
+            .make_move(mov)
+            .map_err(|_| PuzzleError::IllegalMove)?;
+
+        if self.chess.is_checkmate() {
+            self.status = PuzzleStatus::Solved;
+        } else if mov != self.puzzle.moves[self.next] {
+            self.status = PuzzleStatus::Failed;
+            let mut chess = before;
+            for mov in &self.puzzle.moves[self.next..] {
+                self.solution.push(mov.to_san(&chess));
+                let _ = chess.make_move(*mov);
+            }
+        } else if self.next + 1 == self.puzzle.moves.len() {
+            self.status = PuzzleStatus::Solved;
+        } else {
+            self.next += 1;
+            self.reply();
+        }
+        Ok(self.status)
+    }
+
+    pub fn state(&self) -> PuzzleState {
+        PuzzleState {
+            id: self.puzzle.id.clone(),
+            rating: self.puzzle.rating,
+            themes: self.puzzle.themes.clone(),
+            color: self.color,
+            fen: self.chess.get_fen(),
+            played: self.played.clone(),
+            status: self.status,
+            solution: self.solution.clone(),
+        }
+    }
+}
+
+impl GameManager {
+    /// Set up a puzzle of the rating and theme asked for; a puzzle the client was still
+    /// solving is given up. Solvers with an account get one near their puzzle rating, and
+    /// none they tried lately.
+    pub(crate) async fn handle_request_puzzle(&mut self, cid: ClientId, params: PuzzleParams) {
+        let Some(puzzles) = &self.puzzles else {
+            let msg = ServerMessage::NoPuzzle("There are no puzzles on this server".to_string());
+            self.send_to(cid, msg).await;
+            return;
+        };
+        let user = self.clients.get(&cid).and_then(|c| c.user);
+        let (near, recent) = match (user, &self.puzzle_ratings) {
+            (Some(uid), Some(ratings)) => (
+                Some(ratings.solver(uid).to_rating().rating),
+                ratings.recent(uid),
+            ),
+            _ => (None, Default::default()),
+        };
+        let Some(puzzle) = puzzles.pick(&params, near, &recent) else {
+            let msg = ServerMessage::NoPuzzle("No puzzle fits".to_string());
+            self.send_to(cid, msg).await;
+            return;
+        };
+        log::info!("client {} gets puzzle {}", cid, puzzle.id);
+        let attempt = PuzzleAttempt::start(puzzle.clone());
+        let msg = ServerMessage::PuzzleState(attempt.state());
+        self.puzzle_attempts.insert(cid, attempt);
+        self.send_to(cid, msg).await;
+    }
+
+    /// A move of the solver; the state of the puzzle comes back, with the opponent's reply.
+    pub(crate) async fn handle_puzzle_move(&mut self, cid: ClientId, mov: ChessMove) {
+        let Some(attempt) = self.puzzle_attempts.get_mut(&cid) else {
+            return;
+        };
+        match attempt.play(mov) {
+            Ok(status) => {
+                log::info!("client {} on puzzle {}: {}", cid, attempt.puzzle.id, status);
+                let msg = ServerMessage::PuzzleState(attempt.state());
+                let puzzle = attempt.puzzle.clone();
+                self.send_to(cid, msg).await;
+                if status != PuzzleStatus::Running {
+                    self.rate_puzzle(cid, &puzzle, status == PuzzleStatus::Solved)
+                        .await;
+                }
+            }
+            Err(e) => {
+                log::warn!("client {} can't play {} in a puzzle: {}", cid, mov, e);
+                let msg = ServerMessage::IllegalMove(ChessError::IllegalMove(mov));
+                self.send_to(cid, msg).await;
+            }
+        }
+    }
+
+    /// A finished puzzle counts for the solver's puzzle rating and the puzzle's, if the
+    /// solver has an account; the solver gets to see the new rating.
+    async fn rate_puzzle(&mut self, cid: ClientId, puzzle: &Puzzle, solved: bool) {
+        let Some(uid) = self.clients.get(&cid).and_then(|c| c.user) else {
+            return;
+        };
+        let Some(ratings) = &mut self.puzzle_ratings else {
+            return;
+        };
+        match ratings.rate(uid, puzzle, solved).await {
+            Ok((solver, rated)) => {
+                log::info!(
+                    "puzzle {} rated: #{} {:.0}, puzzle {:.0}",
+                    puzzle.id,
+                    uid,
+                    solver.rating,
+                    rated.rating
+                );
+                if let Some(puzzles) = &mut self.puzzles {
+                    puzzles.set_rating(&puzzle.id, rated.to_rating().rating);
+                }
+            }
+            Err(e) => log::warn!("failed to store the rating of puzzle {}: {}", puzzle.id, e),
+        }
+        self.handle_query_puzzle_stats(cid, uid).await;
+    }
+
+    /// The puzzle rating of a user and how they do per theme.
+    pub(crate) async fn handle_query_puzzle_stats(&self, cid: ClientId, uid: UserId) {
+        let msg = match (&self.puzzle_ratings, &self.puzzles) {
+            (Some(ratings), Some(puzzles)) => ServerMessage::PuzzleStats(
+                uid,
+                Some(ratings.solver(uid).to_rating()),
+                ratings.theme_stats(uid, puzzles),
+            ),
+            _ => ServerMessage::PuzzleStats(uid, None, vec![]),
+        };
+        self.send_to(cid, msg).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mov(s: &str) -> ChessMove {
+        s.parse().unwrap()
+    }
+
+    fn puzzle(fen: &str, moves: &str) -> Puzzle {
+        Puzzle::new("test", fen, moves, "1500", "mate").unwrap()
+    }
+
+    #[test]
+    fn test_lichess_csv() {
+        let line = "00sHx,q3k1nr/1pp1nQpp/3p4/1P2p3/4P3/B1PP1b2/B5PP/5K2 b k - 0 17,\
+                    e8d7 a2e6 d7d8 f7f8,1760,80,83,72,mate mateIn2 middlegame short,\
+                    https://lichess.org/yyznGmXs/black#34,Italian_Game";
+        let puzzle = Puzzle::from_lichess_csv(line).unwrap();
+        assert_eq!(puzzle.id, "00sHx");
+        assert_eq!(puzzle.moves.len(), 4);
+        assert_eq!(puzzle.rating, 1760);
+        assert!(puzzle.has_theme("mateIn2"));
+        assert!(!puzzle.has_theme("fork"));
+        assert_eq!(Puzzle::from_line(&puzzle.to_line()), Some(puzzle));
+
+        // the header, an illegal move, and the solver without a move
+        let header = "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,\
+                      GameUrl,OpeningTags";
+        assert!(Puzzle::from_lichess_csv(header).is_none());
+        assert!(Puzzle::from_lichess_csv(&line.replace("e8d7", "e8e6")).is_none());
+        assert!(Puzzle::from_lichess_csv(&line.replace("e8d7 a2e6 d7d8 f7f8", "e8d7")).is_none());
+    }
+
+    #[test]
+    fn test_puzzle_solved() {
+        // a rook ladder: the opponent steps out of the corner, the rooks mate
+        let mut attempt = PuzzleAttempt::start(puzzle(
+            "7k/8/8/8/8/8/R7/1R4K1 b - - 0 1",
+            "h8g8 b1b7 g8f8 a2a8",
+        ));
+        assert_eq!(attempt.state().played, vec!["Kg8"]);
+        assert_eq!(attempt.state().color, ChessColor::White);
+        assert_eq!(attempt.play(mov("b1b2")), Ok(PuzzleStatus::Failed));
+        assert_eq!(attempt.play(mov("b2b7")), Err(PuzzleError::NotRunning));
+
+        let mut attempt = PuzzleAttempt::start(attempt.puzzle.clone());
+        assert_eq!(attempt.play(mov("a2b3")), Err(PuzzleError::IllegalMove));
+        assert_eq!(attempt.play(mov("b1b7")), Ok(PuzzleStatus::Running));
+        assert_eq!(attempt.state().played, vec!["Kg8", "Rb7", "Kf8"]);
+        assert_eq!(attempt.play(mov("a2a8")), Ok(PuzzleStatus::Solved));
+        let state = attempt.state();
+        assert_eq!(state.status, PuzzleStatus::Solved);
+        assert!(state.solution.is_empty());
+    }
+
+    #[test]
+    fn test_puzzle_failed() {
+        let mut attempt = PuzzleAttempt::start(puzzle(
+            "7k/8/8/8/8/8/R7/1R4K1 b - - 0 1",
+            "h8g8 b1b7 g8f8 a2a8",
+        ));
+        assert_eq!(attempt.play(mov("a2a7")), Ok(PuzzleStatus::Failed));
+        let state = attempt.state();
+        assert_eq!(state.played, vec!["Kg8", "Ra7"]);
+        assert_eq!(state.solution, vec!["Rb7", "Kf8", "Ra8#"]);
+    }
+
+    #[test]
+    fn test_other_mate_in_one() {
+        // either rook mates on the back rank
+        let p = puzzle("6k1/p4ppp/8/8/8/8/5PPP/3RR1K1 b - - 0 1", "a7a6 e1e8");
+        let mut attempt = PuzzleAttempt::start(p.clone());
+        assert_eq!(attempt.play(mov("d1d8")), Ok(PuzzleStatus::Solved));
+        let mut attempt = PuzzleAttempt::start(p);
+        assert_eq!(attempt.play(mov("e1e8")), Ok(PuzzleStatus::Solved));
+    }
+
+    #[test]
+    fn test_puzzle_store() {
+        let dir = std::env::temp_dir().join(format!("chess-puzzles-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("lichess.csv");
+        let path = dir.join("puzzles.txt");
+        let _ = std::fs::remove_file(&path);
+        std::fs::write(
+            &csv,
+            "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags\n\
+             a,7k/8/8/8/8/8/R7/1R4K1 b - - 0 1,h8g8 b1b7 g8f8 a2a8,1200,75,90,100,mate mateIn2,,\n\
+             b,6k1/p4ppp/8/8/8/8/5PPP/3RR1K1 b - - 0 1,a7a6 e1e8,900,75,90,100,mate mateIn1 backRankMate,,\n\
+             c,not a position,e2e4 e7e5,1000,75,90,100,mate,,\n",
+        )
+        .unwrap();
+
+        let mut store = PuzzleStore::open(path.to_str().unwrap()).unwrap();
+        assert!(store.is_empty());
+        assert_eq!(store.import(csv.to_str().unwrap()).unwrap(), 2);
+        // known puzzles aren't added twice
+        assert_eq!(store.import(csv.to_str().unwrap()).unwrap(), 0);
+
+        let mut store = PuzzleStore::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(store.len(), 2);
+        let any = HashSet::new();
+        let theme = |theme: &str| PuzzleParams {
+            theme: theme.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(store.pick(&theme("mateIn1"), None, &any).unwrap().id, "b");
+        assert!(store.pick(&theme("fork"), None, &any).is_none());
+        let rating = PuzzleParams {
+            min_rating: 1000,
+            max_rating: 1500,
+            theme: "mate".to_string(),
+        };
+        assert_eq!(store.pick(&rating, None, &any).unwrap().id, "a");
+
+        // near the solver's rating, and not the one tried just now unless there is no other
+        assert_eq!(store.pick(&theme(""), Some(1150), &any).unwrap().id, "a");
+        assert_eq!(store.pick(&theme(""), Some(1400), &any).unwrap().id, "a");
+        let recent = HashSet::from(["a"]);
+        assert_eq!(store.pick(&theme(""), Some(1150), &recent).unwrap().id, "b");
+        assert_eq!(
+            store.pick(&theme("mateIn2"), None, &recent).unwrap().id,
+            "a"
+        );
+        store.set_rating("b", 1600);
+        assert_eq!(store.get("b").unwrap().rating, 1600);
+        assert_eq!(store.pick(&theme(""), Some(1650), &any).unwrap().id, "b");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,397 @@
+use crate::server::chessgame::{ChessGame, Identity};
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::states::GameOverReason;
+use chess_core::{ChessColor, ClientId, Rating, RatingRecord, TimeCategory, UserId, Variant};
+use smol::io::AsyncWriteExt;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_RATING: f64 = 1500.0;
+const DEFAULT_DEVIATION: f64 = 350.0;
+const DEFAULT_VOLATILITY: f64 = 0.06;
+const TAU: f64 = 0.5; // how much the volatility may change
+const SCALE: f64 = 173.7178; // between the Glicko and the Glicko-2 scale
+const EPSILON: f64 = 0.000001;
+
+/// A Glicko-2 rating, on the Glicko scale (1500 for a new player).
+/// See Mark Glickman, "Example of the Glicko-2 system".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glicko2 {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Glicko2 {
+    fn default() -> Self {
+        Glicko2 {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+}
+
+impl Glicko2 {
+    /// The rating after a rating period with the given games: (opponent, score),
+    /// where the score is 1 for a win, 0.5 for a draw and 0 for a loss.
+    /// We rate after every game, so a period is usually one game.
+    pub fn update(&self, games: &[(Glicko2, f64)]) -> Glicko2 {
+        let mu = (self.rating - DEFAULT_RATING) / SCALE;
+        let phi = self.deviation / SCALE;
+        let sigma = self.volatility;
+
+        if games.is_empty() {
+            // only the uncertainty grows
+            let phi = (phi * phi + sigma * sigma).sqrt();
+            return Glicko2 {
+                deviation: (phi * SCALE).min(DEFAULT_DEVIATION),
+                ..*self
+            };
+        }
+
+        // the estimated variance and improvement from the game results
+        let mut v_inv = 0.0;
+        let mut sum = 0.0;
+        for (opponent, score) in games {
+            let mu_j = (opponent.rating - DEFAULT_RATING) / SCALE;
+            let g = g(opponent.deviation / SCALE);
+            let e = 1.0 / (1.0 + (-g * (mu - mu_j)).exp());
+            v_inv += g * g * e * (1.0 - e);
+            sum += g * (score - e);
+        }
+        let v = 1.0 / v_inv;
+        let delta = v * sum;
+
+        let sigma = new_volatility(phi, sigma, v, delta);
+        let phi_star = (phi * phi + sigma * sigma).sqrt();
+        let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let mu = mu + phi * phi * sum;
+
+        Glicko2 {
+            rating: mu * SCALE + DEFAULT_RATING,
+            deviation: (phi * SCALE).min(DEFAULT_DEVIATION),
+            volatility: sigma,
+        }
+    }
+
+    /// Rounded for clients.
+    pub fn to_rating(self) -> Rating {
+        Rating {
+            rating: self.rating.round().clamp(0.0, u16::MAX as f64) as u16,
+            deviation: self.deviation.round() as u16,
+        }
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+/// Step 5 of the algorithm: find the new volatility with the Illinois algorithm.
+fn new_volatility(phi: f64, sigma: f64, v: f64, delta: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let d = phi * phi + v + ex;
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * d * d) - (x - a) / (TAU * TAU)
+    };
+
+    let mut a_ = a;
+    let mut b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+    let mut fa = f(a_);
+    let mut fb = f(b);
+    while (b - a_).abs() > EPSILON {
+        let c = a_ + (a_ - b) * fa / (fb - fa);
+        let fc = f(c);
+        if fc * fb <= 0.0 {
+            a_ = b;
+            fa = fb;
+        } else {
+            fa /= 2.0;
+        }
+        b = c;
+        fb = fc;
+    }
+    (a_ / 2.0).exp()
+}
+
+/// The ratings of all players, per variant and time category, with their history.
+/// On disk, this is a log with a line for every new rating:
+/// `user variant category time rating deviation volatility`; the last line of a player,
+/// variant and category is the current rating.
+pub struct RatingStore {
+    path: PathBuf,
+    current: HashMap<(UserId, Variant, TimeCategory), Glicko2>,
+    history: HashMap<UserId, Vec<RatingRecord>>,
+}
+
+impl RatingStore {
+    /// Load the ratings from a file. A missing file means nobody has played a rated game yet.
+    pub fn open(path: &str) -> io::Result<RatingStore> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut store = RatingStore {
+            path: PathBuf::from(path),
+            current: HashMap::new(),
+            history: HashMap::new(),
+        };
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            match parse_line(line) {
+                Some((uid, record, glicko)) => store.insert(uid, record, glicko),
+                None => log::warn!("skipping broken rating line in {}: {}", path, line),
+            }
+        }
+        Ok(store)
+    }
+
+    fn insert(&mut self, uid: UserId, record: RatingRecord, glicko: Glicko2) {
+        self.current
+            .insert((uid, record.variant, record.category), glicko);
+        self.history.entry(uid).or_default().push(record);
+    }
+
+    /// The current rating; the default rating if the player hasn't played yet.
+    pub fn get(&self, uid: UserId, variant: Variant, category: TimeCategory) -> Glicko2 {
+        self.current
+            .get(&(uid, variant, category))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// All ratings of a player, oldest first.
+    pub fn history(&self, uid: UserId) -> Vec<RatingRecord> {
+        self.history.get(&uid).cloned().unwrap_or_default()
+    }
+
+    /// Rate a game. `white_score` is 1 if white won, 0.5 for a draw, 0 if black won.
+    /// Returns the new ratings of white and black.
+    pub async fn rate_game(
+        &mut self,
+        white: UserId,
+        black: UserId,
+        variant: Variant,
+        category: TimeCategory,
+        white_score: f64,
+    ) -> io::Result<(Glicko2, Glicko2)> {
+        let old_white = self.get(white, variant, category);
+        let old_black = self.get(black, variant, category);
+        let new_white = old_white.update(&[(old_black, white_score)]);
+        let new_black = old_black.update(&[(old_white, 1.0 - white_score)]);
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+        let mut lines = String::new();
+        for (uid, glicko) in [(white, new_white), (black, new_black)] {
+            let record = RatingRecord {
+                variant,
+                category,
+                time,
+                rating: glicko.to_rating(),
+            };
+            lines += &format!(
+                "{} {} {} {} {} {} {}\n",
+                uid,
+                variant as u8,
+                category as u8,
+                time,
+                glicko.rating,
+                glicko.deviation,
+                glicko.volatility
+            );
+            self.insert(uid, record, glicko);
+        }
+
+        let mut file = smol::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(lines.as_bytes()).await?;
+        file.sync_all().await?;
+        Ok((new_white, new_black))
+    }
+}
+
+fn parse_line(line: &str) -> Option<(UserId, RatingRecord, Glicko2)> {
+    let mut parts = line.split_whitespace();
+    let uid = parts.next()?.parse().ok()?;
+    let variant = Variant::from_u8(parts.next()?.parse().ok()?)?;
+    let category = TimeCategory::from_u8(parts.next()?.parse().ok()?)?;
+    let time = parts.next()?.parse().ok()?;
+    let glicko = Glicko2 {
+        rating: parts.next()?.parse().ok()?,
+        deviation: parts.next()?.parse().ok()?,
+        volatility: parts.next()?.parse().ok()?,
+    };
+    let record = RatingRecord {
+        variant,
+        category,
+        time,
+        rating: glicko.to_rating(),
+    };
+    Some((uid, record, glicko))
+}
+
+impl GameManager {
+    /// The rating of a player of a game, in the variant and time category of the game.
+    pub(crate) fn player_rating(
+        &self,
+        game: &ChessGame,
+        player: Option<ClientId>,
+    ) -> Option<Rating> {
+        let uid = self.clients.get(&player?)?.user?;
+        let ratings = self.ratings.as_ref()?;
+        Some(
+            ratings
+                .get(uid, game.variant, game.time_category())
+                .to_rating(),
+        )
+    }
+
+    /// The client asked for all ratings of a player over time.
+    pub(crate) async fn handle_query_rating_history(&self, cid: ClientId, uid: UserId) {
+        let history = match &self.ratings {
+            Some(ratings) => ratings.history(uid),
+            None => vec![],
+        };
+        let msg = ServerMessage::RatingHistory(uid, history);
+        self.send_to(cid, msg).await;
+    }
+
+    /// Update the ratings of both players after a rated game.
+    pub(crate) async fn rate_game(&mut self, game: &ChessGame, reason: GameOverReason) {
+        // a player may have lost the connection before the end; the seat still tells who it was
+        let user = |player: Option<ClientId>, reserved: &Option<Identity>| match player {
+            Some(cid) => self.clients.get(&cid).and_then(|c| c.user),
+            None => match reserved {
+                Some(Identity::User(uid)) => Some(*uid),
+                _ => None,
+            },
+        };
+        let white = user(game.white_player, &game.white_reserved);
+        let black = user(game.black_player, &game.black_reserved);
+        let (Some(white), Some(black), Some(ratings)) = (white, black, self.ratings.as_mut())
+        else {
+            log::warn!("rated game {} without two accounts, not rated", game.id);
+            return;
+        };
+
+        let white_score = match reason.get_winner() {
+            Some(ChessColor::White) => 1.0,
+            Some(ChessColor::Black) => 0.0,
+            None => 0.5,
+        };
+        let category = game.time_category();
+        match ratings
+            .rate_game(white, black, game.variant, category, white_score)
+            .await
+        {
+            Ok((w, b)) => log::info!(
+                "game {} rated ({} {}): #{} {:.0}, #{} {:.0}",
+                game.id,
+                game.variant,
+                category,
+                white,
+                w.rating,
+                black,
+                b.rating
+            ),
+            Err(e) => log::warn!("failed to store the ratings of game {}: {}", game.id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glicko(rating: f64, deviation: f64) -> Glicko2 {
+        Glicko2 {
+            rating,
+            deviation,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+
+    #[test]
+    fn test_glickman_example() {
+        // the example from Glickman's paper
+        let player = glicko(1500.0, 200.0);
+        let games = [
+            (glicko(1400.0, 30.0), 1.0),
+            (glicko(1550.0, 100.0), 0.0),
+            (glicko(1700.0, 300.0), 0.0),
+        ];
+        let new = player.update(&games);
+        assert!((new.rating - 1464.06).abs() < 0.01, "{}", new.rating);
+        assert!((new.deviation - 151.52).abs() < 0.01, "{}", new.deviation);
+        assert!(
+            (new.volatility - 0.05999).abs() < 0.00001,
+            "{}",
+            new.volatility
+        );
+
+        // without games, only the deviation grows
+        let idle = player.update(&[]);
+        assert_eq!(idle.rating, 1500.0);
+        assert!(idle.deviation > 200.0);
+    }
+
+    #[test]
+    fn test_rating_store() {
+        let path = std::env::temp_dir().join(format!("ratings-{}.log", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let blitz = TimeCategory::Blitz;
+
+        smol::block_on(async {
+            let mut store = RatingStore::open(path).unwrap();
+            assert_eq!(store.get(1, Variant::Standard, blitz), Glicko2::default());
+
+            let (white, black) = store
+                .rate_game(1, 2, Variant::Standard, blitz, 1.0)
+                .await
+                .unwrap();
+            assert!(white.rating > 1500.0 && black.rating < 1500.0);
+            assert_eq!(white.rating - 1500.0, 1500.0 - black.rating);
+            store
+                .rate_game(2, 1, Variant::Standard, blitz, 0.5)
+                .await
+                .unwrap();
+
+            // other categories are not affected
+            assert_eq!(
+                store.get(1, Variant::Standard, TimeCategory::Rapid),
+                Glicko2::default()
+            );
+
+            let reopened = RatingStore::open(path).unwrap();
+            let current = store.get(1, Variant::Standard, blitz);
+            assert_eq!(reopened.get(1, Variant::Standard, blitz), current);
+            let history = reopened.history(1);
+            assert_eq!(history.len(), 2);
+            assert_eq!(history[1].rating, current.to_rating());
+            assert!(reopened.history(3).is_empty());
+        });
+
+        let _ = std::fs::remove_file(path);
+    }
+}
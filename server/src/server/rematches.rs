@@ -0,0 +1,186 @@
+use crate::server::chessgame::ChessGame;
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::NewGameParams;
+use chess_core::{ClientId, GameId, Variant};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long after a game the players can still ask for a rematch.
+pub const REMATCH_WINDOW: Duration = Duration::from_secs(60);
+
+/// A finished game between two players, open for a rematch.
+#[derive(Debug, Clone)]
+pub struct Rematch {
+    pub white: ClientId,
+    pub black: ClientId,
+    pub params: NewGameParams,
+    pub variant: Variant,
+    pub offered_by: Option<ClientId>,
+    pub expires: Instant,
+}
+
+impl Rematch {
+    /// White and black of the rematch: the colours swap.
+    pub fn colors(&self) -> (ClientId, ClientId) {
+        (self.black, self.white)
+    }
+}
+
+/// What came of asking for a rematch.
+#[derive(Debug)]
+pub enum RematchStatus {
+    Waiting,         // for the other player
+    Agreed(Rematch), // by both, ready to start
+    NotOpen,         // not a player of the game, or too late
+}
+
+/// The finished games that can still be played again, by the ID of the finished game.
+#[derive(Default)]
+pub struct Rematches {
+    open: HashMap<GameId, Rematch>,
+}
+
+impl Rematches {
+    /// Keep a finished game around for `REMATCH_WINDOW` after `now`.
+    pub fn add(
+        &mut self,
+        gid: GameId,
+        white: ClientId,
+        black: ClientId,
+        params: NewGameParams,
+        variant: Variant,
+        now: Instant,
+    ) {
+        let rematch = Rematch {
+            white,
+            black,
+            params,
+            variant,
+            offered_by: None,
+            expires: now + REMATCH_WINDOW,
+        };
+        self.open.insert(gid, rematch);
+    }
+
+    pub fn get(&self, gid: GameId) -> Option<&Rematch> {
+        self.open.get(&gid)
+    }
+
+    /// A player of the finished game asks for a rematch. Once the other one asked as well,
+    /// the rematch leaves the list.
+    pub fn offer(&mut self, gid: GameId, cid: ClientId) -> RematchStatus {
+        let Some(rematch) = self.open.get_mut(&gid) else {
+            return RematchStatus::NotOpen;
+        };
+        if cid != rematch.white && cid != rematch.black {
+            return RematchStatus::NotOpen;
+        }
+        match rematch.offered_by {
+            Some(other) if other != cid => match self.open.remove(&gid) {
+                Some(rematch) => RematchStatus::Agreed(rematch),
+                None => RematchStatus::NotOpen,
+            },
+            _ => {
+                rematch.offered_by = Some(cid);
+                RematchStatus::Waiting
+            }
+        }
+    }
+
+    /// Forget the games of a client that left.
+    pub fn remove_client(&mut self, cid: ClientId) {
+        self.open.retain(|_, r| r.white != cid && r.black != cid);
+    }
+
+    /// Forget the games that have been over for too long.
+    pub fn remove_expired(&mut self, now: Instant) {
+        self.open.retain(|_, r| r.expires > now);
+    }
+
+    /// When the next rematch window closes, if there is any.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.open.values().map(|r| r.expires).min()
+    }
+}
+
+impl GameManager {
+    /// Two different players, neither a computer, can play the game again for a while.
+    pub(crate) fn keep_for_rematch(&mut self, game: &ChessGame) {
+        let (Some(white), Some(black)) = (game.white_player, game.black_player) else {
+            return;
+        };
+        let human = |cid| self.clients.get(&cid).is_some_and(|c| !c.is_computer());
+        if white == black || !human(white) || !human(black) {
+            return;
+        }
+        let params = NewGameParams {
+            mode: 0,
+            rated: game.rated,
+            time: game._time,
+            time_inc: game._time_inc,
+        };
+        self.rematches
+            .add(game.id, white, black, params, game.variant, Instant::now());
+    }
+
+    /// A player wants to play the game that just ended again. When both want it, the new
+    /// game starts right away, with the colours swapped.
+    pub(crate) async fn handle_rematch(&mut self, cid: ClientId, gid: GameId) {
+        match self.rematches.offer(gid, cid) {
+            RematchStatus::NotOpen => {
+                log::warn!("client {} can't ask for a rematch of game {}", cid, gid)
+            }
+            RematchStatus::Waiting => {
+                log::info!("client {} asks for a rematch of game {}", cid, gid);
+                let Some(rematch) = self.rematches.get(gid) else {
+                    return;
+                };
+                let msg = ServerMessage::RematchOffered(gid, cid);
+                self.send_to(rematch.white, msg.clone()).await;
+                self.send_to(rematch.black, msg).await;
+            }
+            RematchStatus::Agreed(rematch) => {
+                let (white, black) = rematch.colors();
+                let new_gid = self
+                    .start_paired_game(white, black, rematch.params, rematch.variant)
+                    .await;
+                log::info!("rematch of game {} in game {}", gid, new_gid);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rematches() {
+        let params = NewGameParams {
+            mode: 0,
+            rated: false,
+            time: 300,
+            time_inc: 2,
+        };
+        let start = Instant::now();
+        let mut rematches = Rematches::default();
+        rematches.add(1, 10, 20, params.clone(), Variant::Standard, start);
+        rematches.add(2, 30, 40, params, Variant::Standard, start);
+        assert_eq!(rematches.next_expiry(), Some(start + REMATCH_WINDOW));
+
+        // only the players can ask, and it takes both
+        assert!(matches!(rematches.offer(1, 30), RematchStatus::NotOpen));
+        assert!(matches!(rematches.offer(1, 10), RematchStatus::Waiting));
+        assert!(matches!(rematches.offer(1, 10), RematchStatus::Waiting));
+        match rematches.offer(1, 20) {
+            RematchStatus::Agreed(rematch) => assert_eq!(rematch.colors(), (20, 10)),
+            status => panic!("Expected the rematch, got {:?}", status),
+        }
+        assert!(matches!(rematches.offer(1, 10), RematchStatus::NotOpen));
+
+        rematches.remove_expired(start + REMATCH_WINDOW);
+        assert!(matches!(rematches.offer(2, 30), RematchStatus::NotOpen));
+        assert_eq!(rematches.next_expiry(), None);
+    }
+}
@@ -0,0 +1,532 @@
+use crate::chess::chess::Chess;
+use crate::chess::san::San;
+use crate::server::analysis::analyse;
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{RoomSource, RoomTree};
+use chess_core::{ChessColor, ChessError, ChessMove, ChessPiece, ClientId, GameId, RoomId, Tile};
+use smol::channel::{unbounded, Sender};
+use std::collections::VecDeque;
+
+/// How many finished games are kept for analysis rooms.
+const FINISHED_GAMES: usize = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RoomError {
+    IllegalMove,
+    BadPosition,
+    NoSuchNode,
+}
+
+impl std::fmt::Display for RoomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoomError::IllegalMove => write!(f, "Illegal move"),
+            RoomError::BadPosition => write!(f, "Not a position to analyse"),
+            RoomError::NoSuchNode => write!(f, "No such node"),
+        }
+    }
+}
+
+/// A position of the tree: the start position, or the position after a move.
+struct Node {
+    parent: usize,
+    mov: Option<ChessMove>, // `None` for the start position
+    san: String,
+    chess: Chess,
+}
+
+impl Node {
+    fn start(chess: Chess) -> Self {
+        Node {
+            parent: 0,
+            mov: None,
+            san: String::new(),
+            chess,
+        }
+    }
+}
+
+/// A free board that a group of clients analyses together. Every move somebody makes goes
+/// into a shared tree of variations; anybody can go to any position of the tree, or set up
+/// a new one to start over.
+pub struct AnalysisRoom {
+    pub id: RoomId,
+    pub creator: ClientId,
+    pub members: Vec<ClientId>, // in the order they joined
+    pub engine: bool,           // analyse every position the room goes to
+    nodes: Vec<Node>,
+    current: usize,
+}
+
+impl AnalysisRoom {
+    /// The creator is the first member.
+    pub fn new(id: RoomId, creator: ClientId, chess: Chess) -> Self {
+        AnalysisRoom {
+            id,
+            creator,
+            members: vec![creator],
+            engine: false,
+            nodes: vec![Node::start(chess)],
+            current: 0,
+        }
+    }
+
+    /// A room on the moves of a game, at the end of the game.
+    /// Fails with the first move that doesn't fit.
+    pub fn from_game(
+        id: RoomId,
+        creator: ClientId,
+        start_fen: &str,
+        moves: &[String],
+    ) -> Result<Self, String> {
+        let mut room = AnalysisRoom::new(id, creator, Chess::load_fen(start_fen));
+        for san in moves {
+            let mov = ChessMove::from_san(room.position(), san).ok_or_else(|| san.clone())?;
+            room.play(mov).map_err(|_| san.clone())?;
+        }
+        Ok(room)
+    }
+
+    /// Returns false for a member that is already in.
+    pub fn join(&mut self, cid: ClientId) -> bool {
+        if self.members.contains(&cid) {
+            return false;
+        }
+        self.members.push(cid);
+        true
+    }
+
+    /// Returns false for a client that isn't a member.
+    pub fn leave(&mut self, cid: ClientId) -> bool {
+        let before = self.members.len();
+        self.members.retain(|&c| c != cid);
+        self.members.len() != before
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// The position everybody looks at.
+    pub fn position(&self) -> &Chess {
+        &self.nodes[self.current].chess
+    }
+
+    /// Make a move in the current position. A move that was played here before leads to
+    /// the same node again, any other starts a new variation. Returns the node of the move.
+    pub fn play(&mut self, mov: ChessMove) -> Result<usize, RoomError> {
+        let known = (1..self.nodes.len())
+            .find(|&n| self.nodes[n].parent == self.current && self.nodes[n].mov == Some(mov));
+        if let Some(node) = known {
+            self.current = node;
+            return Ok(node);
+        }
+
+        let mut chess = self.position().clone();
+        if !chess.is_legal_move(&mov) {
+            return Err(RoomError::IllegalMove);
+        }
+        let san = mov.to_san(&chess);
+        chess.make_move(mov).map_err(|_| RoomError::IllegalMove)?;
+        self.nodes.push(Node {
+            parent: self.current,
+            mov: Some(mov),
+            san,
+            chess,
+        });
+        self.current = self.nodes.len() - 1;
+        Ok(self.current)
+    }
+
+    /// Start over from a position given as FEN; the tree goes.
+    pub fn setup(&mut self, fen: &str) -> Result<(), RoomError> {
+        let chess = setup_position(fen).ok_or(RoomError::BadPosition)?;
+        self.nodes = vec![Node::start(chess)];
+        self.current = 0;
+        Ok(())
+    }
+
+    /// Go to any position of the tree, e.g. back to an earlier move.
+    pub fn go_to(&mut self, node: usize) -> Result<(), RoomError> {
+        if node >= self.nodes.len() {
+            return Err(RoomError::NoSuchNode);
+        }
+        self.current = node;
+        Ok(())
+    }
+
+    /// The tree as the members see it.
+    pub fn tree(&self) -> RoomTree {
+        RoomTree {
+            start_fen: self.nodes[0].chess.get_fen(),
+            nodes: self
+                .nodes
+                .iter()
+                .map(|n| (n.parent as u32, n.san.clone()))
+                .collect(),
+            current: self.current as u32,
+            fen: self.position().get_fen(),
+            engine: self.engine,
+        }
+    }
+}
+
+/// A position from a FEN, for a room. It doesn't have to come up in a game: pawns may stand
+/// on the back ranks and there may be ten queens. But each side needs its king, and the side
+/// that just moved must not be in check, or the king could be taken. Castling rights without
+/// the king and rook at home are dropped. The move counters may be left out.
+pub fn setup_position(fen: &str) -> Option<Chess> {
+    let mut fields: Vec<&str> = fen.split_whitespace().collect();
+    if !(2..=6).contains(&fields.len()) {
+        return None;
+    }
+    for (i, default) in ["-", "-", "0", "1"].into_iter().enumerate() {
+        if fields.len() <= i + 2 {
+            fields.push(default);
+        }
+    }
+
+    let ranks: Vec<&str> = fields[0].split('/').collect();
+    let files = |rank: &str| {
+        rank.chars().try_fold(0, |files, c| match c {
+            '1'..='8' => Some(files + c.to_digit(10)?),
+            'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => {
+                Some(files + 1)
+            }
+            _ => None,
+        })
+    };
+    if ranks.len() != 8 || !ranks.iter().all(|rank| files(rank) == Some(8)) {
+        return None;
+    }
+    let kings = |king| fields[0].chars().filter(|&c| c == king).count();
+    if kings('K') != 1 || kings('k') != 1 {
+        return None;
+    }
+    if !matches!(fields[1], "w" | "b") {
+        return None;
+    }
+    if fields[2] != "-" && !fields[2].chars().all(|c| "KQkq".contains(c)) {
+        return None;
+    }
+    let en_passant = fields[3].as_bytes();
+    if fields[3] != "-"
+        && !(en_passant.len() == 2
+            && (b'a'..=b'h').contains(&en_passant[0])
+            && matches!(en_passant[1], b'3' | b'6'))
+    {
+        return None;
+    }
+    if fields[4].parse::<usize>().is_err() || fields[5].parse::<usize>().is_err() {
+        return None;
+    }
+
+    let chess = Chess::load_fen(&fields.join(" "));
+    if chess.is_in_check(!chess.active_player) {
+        return None;
+    }
+    let at_home = |tile: &str, typ, color| {
+        chess[Tile::from(tile)].is_some_and(|p| p.typ == typ && p.color == color)
+    };
+    let castling: String = [
+        ('K', "e1", "h1", ChessColor::White),
+        ('Q', "e1", "a1", ChessColor::White),
+        ('k', "e8", "h8", ChessColor::Black),
+        ('q', "e8", "a8", ChessColor::Black),
+    ]
+    .into_iter()
+    .filter(|&(right, king, rook, color)| {
+        fields[2].contains(right)
+            && at_home(king, ChessPiece::King, color)
+            && at_home(rook, ChessPiece::Rook, color)
+    })
+    .map(|(right, ..)| right)
+    .collect();
+    // the rights are part of the hash, so the position is loaded again with the right ones
+    fields[2] = if castling.is_empty() { "-" } else { &castling };
+    Some(Chess::load_fen(&fields.join(" ")))
+}
+
+/// The last games that ended, so a room can be opened on one of them.
+#[derive(Default)]
+pub struct FinishedGames {
+    games: VecDeque<(GameId, String, Vec<String>)>, // start FEN, moves in SAN; the latest last
+}
+
+impl FinishedGames {
+    pub fn add(&mut self, gid: GameId, start_fen: &str, moves: &[String]) {
+        if self.games.len() == FINISHED_GAMES {
+            self.games.pop_front();
+        }
+        self.games
+            .push_back((gid, start_fen.to_string(), moves.to_vec()));
+    }
+
+    /// A room on a finished game, at its end.
+    pub fn open_room(
+        &self,
+        rid: RoomId,
+        creator: ClientId,
+        gid: GameId,
+    ) -> Option<Result<AnalysisRoom, String>> {
+        let (_, start_fen, moves) = self.games.iter().find(|(g, _, _)| *g == gid)?;
+        Some(AnalysisRoom::from_game(rid, creator, start_fen, moves))
+    }
+}
+
+impl GameManager {
+    /// Open an analysis room on the initial position, a position of the creator's choosing
+    /// or a game that ended. The creator is the first member.
+    pub(crate) async fn handle_create_room(&mut self, cid: ClientId, source: RoomSource) {
+        if self.clients.get(&cid).is_none_or(|c| c.is_computer()) {
+            return;
+        }
+        let rid = self.next_room_id;
+        let room = match &source {
+            RoomSource::Empty => Ok(AnalysisRoom::new(rid, cid, Chess::new())),
+            RoomSource::Fen(fen) => setup_position(fen)
+                .map(|chess| AnalysisRoom::new(rid, cid, chess))
+                .ok_or_else(|| RoomError::BadPosition.to_string()),
+            RoomSource::Game(gid) => match self.finished.open_room(rid, cid, *gid) {
+                Some(room) => room.map_err(|san| format!("bad move {}", san)),
+                None => Err("not a game that ended".to_string()),
+            },
+        };
+        let room = match room {
+            Ok(room) => room,
+            Err(e) => {
+                log::warn!("client {} can't open a room on {:?}: {}", cid, source, e);
+                return;
+            }
+        };
+        self.next_room_id += 1;
+        log::info!("client {} opens analysis room {}", cid, rid);
+        self.rooms.insert(rid, room);
+        let msg = ServerMessage::RoomCreated(rid, cid);
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+        }
+        self.announce_room_members(rid).await;
+        self.room_changed(rid).await;
+    }
+
+    pub(crate) async fn handle_join_room(&mut self, cid: ClientId, rid: RoomId) {
+        if self.clients.get(&cid).is_none_or(|c| c.is_computer()) {
+            return;
+        }
+        let Some(room) = self.rooms.get_mut(&rid) else {
+            return;
+        };
+        if room.join(cid) {
+            let msg = ServerMessage::RoomState(rid, room.tree());
+            self.send_to(cid, msg).await;
+            self.announce_room_members(rid).await;
+        }
+    }
+
+    /// The last member to leave closes the room.
+    pub(crate) async fn handle_leave_room(&mut self, cid: ClientId, rid: RoomId) {
+        let Some(room) = self.rooms.get_mut(&rid) else {
+            return;
+        };
+        if !room.leave(cid) {
+            return;
+        }
+        if room.members.is_empty() {
+            log::info!("analysis room {} closed", rid);
+            self.rooms.remove(&rid);
+        }
+        self.announce_room_members(rid).await;
+    }
+
+    /// The room of a member, to change it.
+    fn member_room(&mut self, cid: ClientId, rid: RoomId) -> Option<&mut AnalysisRoom> {
+        self.rooms
+            .get_mut(&rid)
+            .filter(|room| room.members.contains(&cid))
+    }
+
+    /// A member moves on the board of the room, in the position everybody looks at.
+    pub(crate) async fn handle_room_move(&mut self, cid: ClientId, rid: RoomId, mov: ChessMove) {
+        let Some(room) = self.member_room(cid, rid) else {
+            return;
+        };
+        match room.play(mov) {
+            Ok(_) => self.room_changed(rid).await,
+            Err(_) => {
+                let msg = ServerMessage::IllegalMove(ChessError::IllegalMove(mov));
+                self.send_to(cid, msg).await;
+            }
+        }
+    }
+
+    /// A member sets up a new position, which needn't be legal; the tree starts over.
+    pub(crate) async fn handle_room_setup(&mut self, cid: ClientId, rid: RoomId, fen: String) {
+        let Some(room) = self.member_room(cid, rid) else {
+            return;
+        };
+        match room.setup(&fen) {
+            Ok(()) => self.room_changed(rid).await,
+            Err(e) => log::warn!(
+                "client {} can't set up {:?} in room {}: {}",
+                cid,
+                fen,
+                rid,
+                e
+            ),
+        }
+    }
+
+    /// A member goes to another position of the tree, e.g. back in the history.
+    pub(crate) async fn handle_room_go_to(&mut self, cid: ClientId, rid: RoomId, node: u32) {
+        let Some(room) = self.member_room(cid, rid) else {
+            return;
+        };
+        match room.go_to(node as usize) {
+            Ok(()) => self.room_changed(rid).await,
+            Err(e) => log::warn!(
+                "client {} can't go to node {} in room {}: {}",
+                cid,
+                node,
+                rid,
+                e
+            ),
+        }
+    }
+
+    pub(crate) async fn handle_room_engine(&mut self, cid: ClientId, rid: RoomId, on: bool) {
+        let Some(room) = self.member_room(cid, rid) else {
+            return;
+        };
+        room.engine = on;
+        self.room_changed(rid).await;
+    }
+
+    /// Who is in a room, to all; nobody for a room that closed.
+    async fn announce_room_members(&self, rid: RoomId) {
+        let members = self
+            .rooms
+            .get(&rid)
+            .map_or(vec![], |room| room.members.clone());
+        let msg = ServerMessage::RoomMembers(rid, members);
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+        }
+    }
+
+    /// Every member sees the room as it is now. With the engine on, it analyses the
+    /// position for all of them.
+    async fn room_changed(&self, rid: RoomId) {
+        let Some(room) = self.rooms.get(&rid) else {
+            return;
+        };
+        let members: Vec<Sender<ServerMessage>> = room
+            .members
+            .iter()
+            .filter_map(|cid| self.clients.get(cid))
+            .map(|c| c.tx.clone())
+            .collect();
+        let msg = ServerMessage::RoomState(rid, room.tree());
+        for tx in &members {
+            let _ = tx.send(msg.clone()).await;
+        }
+        if !room.engine {
+            return;
+        }
+
+        // the results carry the node, so the members can tell them from those of a
+        // position the room has left in the meantime
+        let node = room.current() as u32;
+        let (tx, rx) = unbounded();
+        let task = analyse(rid, room.position().clone(), self.engine.clone(), tx);
+        smol::spawn(task).detach();
+        smol::spawn(async move {
+            while let Ok(ServerMessage::Analysis(_, depth, score, pv)) = rx.recv().await {
+                let msg = ServerMessage::RoomAnalysis(rid, node, depth, score, pv);
+                for tx in &members {
+                    let _ = tx.send(msg.clone()).await;
+                }
+            }
+        })
+        .detach();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mov(s: &str) -> ChessMove {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_room_tree() {
+        let mut room = AnalysisRoom::new(1, 10, Chess::new());
+        assert!(!room.join(10));
+        assert!(room.join(20));
+        assert_eq!(room.play(mov("e2e4")), Ok(1));
+        assert_eq!(room.play(mov("e7e5")), Ok(2));
+        assert_eq!(room.play(mov("e5e4")), Err(RoomError::IllegalMove));
+        // back to the first move, and a variation
+        assert_eq!(room.go_to(1), Ok(()));
+        assert_eq!(room.play(mov("c7c5")), Ok(3));
+        // the same move again follows the tree
+        assert_eq!(room.go_to(1), Ok(()));
+        assert_eq!(room.play(mov("e7e5")), Ok(2));
+        assert_eq!(room.go_to(4), Err(RoomError::NoSuchNode));
+
+        let tree = room.tree();
+        assert_eq!(tree.line(), vec!["e4", "e5"]);
+        assert_eq!(tree.children(1), vec![2, 3]);
+        assert_eq!(tree.nodes[3], (1, "c5".to_string()));
+        assert_eq!(tree.fen, room.position().get_fen());
+        assert!(room.leave(10));
+        assert!(!room.leave(10));
+        assert_eq!(room.members, vec![20]);
+    }
+
+    #[test]
+    fn test_room_setup() {
+        let mut room = AnalysisRoom::new(1, 10, Chess::new());
+        room.engine = true;
+        room.play(mov("d2d4")).unwrap();
+        // seven white queens and a pawn on the last rank; no castling rights without rooks,
+        // no move counters
+        let fen = "4k2P/8/8/8/8/8/8/QQQQKQQQ w KQkq";
+        assert_eq!(room.setup(fen), Ok(()));
+        assert_eq!(room.tree().start_fen, "4k2P/8/8/8/8/8/8/QQQQKQQQ w - - 0 1");
+        assert_eq!(room.tree().nodes.len(), 1);
+        assert_eq!(room.members, vec![10]);
+        assert!(room.engine);
+
+        // no king, two kings, the side that just moved in check, a broken rank
+        for fen in [
+            "8/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+            "4k3/4R3/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/9/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+        ] {
+            assert!(setup_position(fen).is_none(), "{}", fen);
+        }
+        // but the side to move may be
+        assert!(setup_position("4k3/4R3/8/8/8/8/8/4K3 b - - 0 1").is_some());
+    }
+
+    #[test]
+    fn test_room_from_game() {
+        let mut finished = FinishedGames::default();
+        let moves: Vec<String> = ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        finished.add(7, &Chess::new().get_fen(), &moves);
+        assert!(finished.open_room(1, 10, 8).is_none());
+        let room = finished.open_room(1, 10, 7).unwrap().unwrap();
+        assert_eq!(room.current(), 7);
+        assert_eq!(room.tree().line(), moves);
+        assert!(room.position().is_checkmate());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{NewGameParams, SeekParams, UserRoleSelection};
+use chess_core::{ClientId, TimeCategory};
+use rand::RngExt;
+
+/// A seek in the pool, with the rating of the seeking player in the variant and time
+/// category of the seek (`None` for guests).
+#[derive(Debug, Clone)]
+pub struct Seek {
+    pub client: ClientId,
+    pub params: SeekParams,
+    pub rating: Option<u16>,
+}
+
+impl Seek {
+    /// Would this seek take an opponent with that rating?
+    fn accepts(&self, rating: Option<u16>) -> bool {
+        match rating {
+            Some(rating) => (self.params.rating_min..=self.params.rating_max).contains(&rating),
+            None => self.params.is_open(),
+        }
+    }
+
+    /// Two seeks match if they ask for the same game, their colours fit together and
+    /// both players are in the rating range of the other.
+    pub fn matches(&self, other: &Seek) -> bool {
+        let (a, b) = (&self.params, &other.params);
+        self.client != other.client
+            && a.time == b.time
+            && a.time_inc == b.time_inc
+            && a.variant == b.variant
+            && a.rated == b.rated
+            && (a.color != b.color || a.color == UserRoleSelection::Random)
+            && self.accepts(other.rating)
+            && other.accepts(self.rating)
+    }
+}
+
+/// The open seeks, oldest first, at most one per client.
+#[derive(Default)]
+pub struct SeekPool {
+    seeks: Vec<Seek>,
+}
+
+impl SeekPool {
+    pub fn len(&self) -> usize {
+        self.seeks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seeks.is_empty()
+    }
+
+    /// Add a seek, replacing an earlier seek of the same client.
+    /// If a waiting seek matches, that one leaves the pool and is returned instead;
+    /// the longest waiting seek gets paired first.
+    pub fn add(&mut self, seek: Seek) -> Option<Seek> {
+        self.remove(seek.client);
+        match self.seeks.iter().position(|s| s.matches(&seek)) {
+            Some(index) => Some(self.seeks.remove(index)),
+            None => {
+                self.seeks.push(seek);
+                None
+            }
+        }
+    }
+
+    /// Remove the seek of a client; returns whether there was one.
+    pub fn remove(&mut self, cid: ClientId) -> bool {
+        let len = self.seeks.len();
+        self.seeks.retain(|s| s.client != cid);
+        self.seeks.len() != len
+    }
+}
+
+/// White and black for two matching seeks.
+/// A player who asked for a colour gets it, if both left it open we toss a coin.
+pub fn pair_colors(a: &Seek, b: &Seek) -> (ClientId, ClientId) {
+    let a_white = match (a.params.color, b.params.color) {
+        (UserRoleSelection::White, _) | (_, UserRoleSelection::Black) => true,
+        (UserRoleSelection::Black, _) | (_, UserRoleSelection::White) => false,
+        _ => rand::rng().random_bool(0.5),
+    };
+    if a_white {
+        (a.client, b.client)
+    } else {
+        (b.client, a.client)
+    }
+}
+
+impl GameManager {
+    /// Put a seek into the pool, or start a game right away if a waiting seek matches.
+    pub(crate) async fn handle_seek(&mut self, cid: ClientId, mut params: SeekParams) {
+        let Some(client) = self.clients.get(&cid) else {
+            return;
+        };
+        if !matches!(
+            params.color,
+            UserRoleSelection::White | UserRoleSelection::Black | UserRoleSelection::Random
+        ) {
+            log::warn!("client {} seeks a game as {}", cid, params.color);
+            return;
+        }
+        // without ratings, there are only casual games
+        params.rated &= self.ratings.is_some();
+        let user = client.user;
+        if params.rated && user.is_none() {
+            let msg = ServerMessage::SeekCancelled("Rated games need an account".to_string());
+            self.send_to(cid, msg).await;
+            return;
+        }
+
+        let category = TimeCategory::from_clock(params.time, params.time_inc);
+        let rating = match (user, &self.ratings) {
+            (Some(uid), Some(ratings)) => Some(
+                ratings
+                    .get(uid, params.variant, category)
+                    .to_rating()
+                    .rating,
+            ),
+            _ => None,
+        };
+        let seek = Seek {
+            client: cid,
+            params,
+            rating,
+        };
+        match self.seeks.add(seek.clone()) {
+            Some(waiting) => {
+                let (white, black) = pair_colors(&waiting, &seek);
+                let params = NewGameParams {
+                    mode: 0,
+                    rated: seek.params.rated,
+                    time: seek.params.time,
+                    time_inc: seek.params.time_inc,
+                };
+                let gid = self
+                    .start_paired_game(white, black, params, seek.params.variant)
+                    .await;
+                log::info!("paired seeks of {} and {} in game {}", white, black, gid);
+            }
+            None => self.send_to(cid, ServerMessage::SeekPending).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_core::Variant;
+
+    fn seek(client: ClientId, color: UserRoleSelection, rating: Option<u16>) -> Seek {
+        Seek {
+            client,
+            params: SeekParams {
+                time: 300,
+                time_inc: 3,
+                variant: Variant::Standard,
+                rated: false,
+                rating_min: 0,
+                rating_max: u16::MAX,
+                color,
+            },
+            rating,
+        }
+    }
+
+    #[test]
+    fn test_matching() {
+        let white = seek(1, UserRoleSelection::White, Some(1500));
+        assert!(white.matches(&seek(2, UserRoleSelection::Random, None)));
+        assert!(white.matches(&seek(2, UserRoleSelection::Black, None)));
+        assert!(!white.matches(&seek(2, UserRoleSelection::White, None)));
+        assert!(!white.matches(&seek(1, UserRoleSelection::Black, None)));
+
+        let mut faster = seek(2, UserRoleSelection::Random, None);
+        faster.params.time = 60;
+        assert!(!white.matches(&faster));
+        let mut rated = seek(2, UserRoleSelection::Random, Some(1500));
+        rated.params.rated = true;
+        assert!(!white.matches(&rated));
+
+        // the rating range must fit both ways, and guests only take open seeks
+        let mut picky = seek(2, UserRoleSelection::Random, Some(1800));
+        picky.params.rating_min = 1600;
+        assert!(!white.matches(&picky));
+        picky.params.rating_min = 1400;
+        assert!(white.matches(&picky));
+        assert!(!picky.matches(&seek(3, UserRoleSelection::Random, None)));
+        let mut narrow = white.clone();
+        narrow.params.rating_max = 1700;
+        assert!(!narrow.matches(&picky));
+    }
+
+    #[test]
+    fn test_pool() {
+        let mut pool = SeekPool::default();
+        assert!(pool.add(seek(1, UserRoleSelection::White, None)).is_none());
+        assert!(pool.add(seek(2, UserRoleSelection::White, None)).is_none());
+        // a new seek replaces the old one
+        let mut bullet = seek(2, UserRoleSelection::Black, None);
+        bullet.params.time = 60;
+        assert!(pool.add(bullet).is_none());
+        assert_eq!(pool.len(), 2);
+
+        let new = seek(3, UserRoleSelection::Random, None);
+        let old = pool.add(new.clone()).unwrap();
+        assert_eq!(old.client, 1);
+        assert_eq!(pair_colors(&old, &new), (1, 3));
+        assert_eq!(pool.len(), 1);
+
+        assert!(pool.remove(2));
+        assert!(!pool.remove(2));
+        assert!(pool.is_empty());
+    }
+}
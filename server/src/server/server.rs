@@ -1,3 +1,4 @@
+use super::config::Config;
 use super::manager::GameManager;
 use super::session::ClientSession;
 use chess_core::protocol::messages::ClientMessage;
@@ -17,6 +18,12 @@ pub struct Server {
     client_id_counter: ClientId,
 }
 
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Server {
     pub fn new() -> Server {
         Server {
@@ -35,10 +42,16 @@ impl Server {
         ClientSession::new(self.client_id_counter, socket, tx_channel).await
     }
 
+    /// run the server with the settings from `server.cfg`.
+    pub async fn run(&mut self, port: u16) -> Result<()> {
+        self.run_with_config(port, &Config::read("server.cfg"))
+            .await
+    }
+
     /// run the server.
     /// this creates the GameManager task and listens for incoming connections,
     /// which will then be converted to client tasks and linked to the Game Manager.
-    pub async fn run(&mut self, port: u16) -> Result<()> {
+    pub async fn run_with_config(&mut self, port: u16, config: &Config) -> Result<()> {
         // N-to-1 client-Server channel
         // server sets up the channel through which clients communicate to server.
         // client_tx: transmitter for the client to the server.
@@ -46,7 +59,7 @@ impl Server {
         let (client_tx, srv_rx) = unbounded();
 
         // Game Manager gets the receiver of the channel
-        let mut game_manager = GameManager::new(srv_rx);
+        let mut game_manager = GameManager::new(srv_rx, client_tx.clone(), config);
         smol::spawn(async move {
             game_manager.run().await;
         })
@@ -0,0 +1,345 @@
+use crate::server::chessgame::ChessGame;
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{NewGameParams, SimulBoard, SimulParams};
+use chess_core::states::GameOverReason;
+use chess_core::{ChessColor, ClientId, GameId, SimulId};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SimulError {
+    AlreadyStarted,
+    AlreadyJoined,
+    Full,
+    IsHost,
+    NoOpponents,
+}
+
+impl std::fmt::Display for SimulError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulError::AlreadyStarted => write!(f, "The simul has already started"),
+            SimulError::AlreadyJoined => write!(f, "Already registered"),
+            SimulError::Full => write!(f, "All boards are taken"),
+            SimulError::IsHost => write!(f, "The host can't be an opponent"),
+            SimulError::NoOpponents => write!(f, "Nobody to play"),
+        }
+    }
+}
+
+/// A simultaneous exhibition: one game between the host and each opponent.
+pub struct Simul {
+    pub id: SimulId,
+    pub host: ClientId,
+    pub params: SimulParams,
+    pub opponents: Vec<ClientId>,
+    pub boards: Vec<(GameId, ClientId, Option<f32>)>, // game, opponent, points of the host
+    started: bool,
+}
+
+impl Simul {
+    pub fn new(id: SimulId, host: ClientId, params: SimulParams) -> Self {
+        Simul {
+            id,
+            host,
+            params,
+            opponents: vec![],
+            boards: vec![],
+            started: false,
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    pub fn join(&mut self, cid: ClientId) -> Result<(), SimulError> {
+        if self.started {
+            return Err(SimulError::AlreadyStarted);
+        }
+        if cid == self.host {
+            return Err(SimulError::IsHost);
+        }
+        if self.opponents.contains(&cid) {
+            return Err(SimulError::AlreadyJoined);
+        }
+        if self.params.boards != 0 && self.opponents.len() >= self.params.boards as usize {
+            return Err(SimulError::Full);
+        }
+        self.opponents.push(cid);
+        Ok(())
+    }
+
+    /// Returns whether the client was an opponent. After the start, opponents resign instead.
+    pub fn leave(&mut self, cid: ClientId) -> bool {
+        let before = self.opponents.len();
+        if !self.started {
+            self.opponents.retain(|&c| c != cid);
+        }
+        self.opponents.len() != before
+    }
+
+    /// Close the registration; a board per opponent follows.
+    pub fn start(&mut self) -> Result<(), SimulError> {
+        if self.started {
+            return Err(SimulError::AlreadyStarted);
+        }
+        if self.opponents.is_empty() {
+            return Err(SimulError::NoOpponents);
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    /// White and black of the board against an opponent.
+    pub fn colors(&self, opponent: ClientId) -> (ClientId, ClientId) {
+        match self.params.host_white {
+            true => (self.host, opponent),
+            false => (opponent, self.host),
+        }
+    }
+
+    pub fn add_board(&mut self, gid: GameId, opponent: ClientId) {
+        self.boards.push((gid, opponent, None));
+    }
+
+    /// Note the result of a board, in points of the host.
+    /// Returns false if the game isn't one of the simul.
+    pub fn record_result(&mut self, gid: GameId, host_points: f32) -> bool {
+        match self
+            .boards
+            .iter_mut()
+            .find(|(g, _, result)| *g == gid && result.is_none())
+        {
+            Some(board) => {
+                board.2 = Some(host_points);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.started && self.boards.iter().all(|(_, _, result)| result.is_some())
+    }
+
+    /// The points of the host and of all opponents together, over the finished boards.
+    pub fn score(&self) -> (f32, f32) {
+        self.boards
+            .iter()
+            .filter_map(|(_, _, result)| *result)
+            .fold((0.0, 0.0), |(host, opponents), points| {
+                (host + points, opponents + 1.0 - points)
+            })
+    }
+
+    /// The host and the opponents.
+    pub fn participants(&self) -> Vec<ClientId> {
+        let mut participants = vec![self.host];
+        participants.extend(&self.opponents);
+        participants
+    }
+}
+
+impl GameManager {
+    /// Open a simul for opponents; the host starts it once enough of them are in.
+    pub(crate) async fn handle_create_simul(&mut self, cid: ClientId, mut params: SimulParams) {
+        params.name = params.name.trim().to_string();
+        let computer = self.clients.get(&cid).is_none_or(|c| c.is_computer());
+        if params.name.is_empty() || computer {
+            log::warn!("client {} can't host simul {:?}", cid, params.name);
+            return;
+        }
+        let sid = self.next_simul_id;
+        self.next_simul_id += 1;
+        log::info!("client {} hosts simul {} ({})", cid, sid, params.name);
+        let msg = ServerMessage::SimulCreated(sid, cid, params.clone());
+        self.simuls.insert(sid, Simul::new(sid, cid, params));
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+        }
+    }
+
+    pub(crate) async fn handle_join_simul(&mut self, cid: ClientId, sid: SimulId) {
+        let (Some(client), Some(simul)) = (self.clients.get(&cid), self.simuls.get_mut(&sid))
+        else {
+            return;
+        };
+        if client.is_computer() {
+            log::warn!("client {} can't play in simul {}", cid, sid);
+            return;
+        }
+        match simul.join(cid) {
+            Ok(()) => self.announce_simul_players(sid).await,
+            Err(e) => log::warn!("client {} can't join simul {}: {}", cid, sid, e),
+        }
+    }
+
+    pub(crate) async fn handle_leave_simul(&mut self, cid: ClientId, sid: SimulId) {
+        let Some(simul) = self.simuls.get_mut(&sid) else {
+            return;
+        };
+        if simul.leave(cid) {
+            self.announce_simul_players(sid).await;
+        }
+    }
+
+    /// The host starts the simul: a game against every opponent, all at once.
+    pub(crate) async fn handle_start_simul(&mut self, cid: ClientId, sid: SimulId) {
+        let Some(simul) = self.simuls.get_mut(&sid) else {
+            return;
+        };
+        if simul.host != cid {
+            log::warn!("client {} can't start simul {}", cid, sid);
+            return;
+        }
+        if let Err(e) = simul.start() {
+            log::warn!("simul {} can't start: {}", sid, e);
+            return;
+        }
+        log::info!("simul {} starts on {} boards", sid, simul.opponents.len());
+        let params = NewGameParams {
+            mode: 0,
+            rated: false,
+            time: simul.params.time,
+            time_inc: simul.params.time_inc,
+        };
+        let variant = simul.params.variant;
+        let boards: Vec<_> = simul
+            .opponents
+            .iter()
+            .map(|&opponent| (opponent, simul.colors(opponent)))
+            .collect();
+        for (opponent, (white, black)) in boards {
+            let gid = self
+                .start_paired_game(white, black, params.clone(), variant)
+                .await;
+            if let Some(game) = self.games.get_mut(&gid) {
+                game.simul = Some(sid);
+            }
+            if let Some(simul) = self.simuls.get_mut(&sid) {
+                simul.add_board(gid, opponent);
+            }
+        }
+        self.send_simul_boards(sid).await;
+    }
+
+    /// A move or takeback in a simul game: the host sees where it's their turn now.
+    pub(crate) async fn update_simul(&self, gid: GameId) {
+        if let Some(sid) = self.games.get(&gid).and_then(|g| g.simul) {
+            self.send_simul_boards(sid).await;
+        }
+    }
+
+    /// A simul game ended. The last one ends the simul.
+    pub(crate) async fn record_simul_result(
+        &mut self,
+        sid: SimulId,
+        game: &ChessGame,
+        reason: GameOverReason,
+    ) {
+        let Some(simul) = self.simuls.get_mut(&sid) else {
+            return;
+        };
+        let host_color = match simul.params.host_white {
+            true => ChessColor::White,
+            false => ChessColor::Black,
+        };
+        let host_points = match reason.get_winner() {
+            Some(color) if color == host_color => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+        if !simul.record_result(game.id, host_points) {
+            return;
+        }
+        if simul.is_finished() {
+            let (host, opponents) = simul.score();
+            log::info!("simul {} is over, {} to {}", sid, host, opponents);
+        }
+        self.send_simul_boards(sid).await;
+        if self.simuls.get(&sid).is_some_and(|s| s.is_finished()) {
+            self.simuls.remove(&sid);
+        }
+    }
+
+    /// Tell everyone who wants to play against the host of a simul.
+    async fn announce_simul_players(&self, sid: SimulId) {
+        let Some(simul) = self.simuls.get(&sid) else {
+            return;
+        };
+        let msg = ServerMessage::SimulPlayers(sid, simul.opponents.clone());
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+        }
+    }
+
+    /// Send the boards of a simul, with whose turn it is and the results so far.
+    async fn send_simul_boards(&self, sid: SimulId) {
+        let Some(simul) = self.simuls.get(&sid) else {
+            return;
+        };
+        let host_color = match simul.params.host_white {
+            true => ChessColor::White,
+            false => ChessColor::Black,
+        };
+        let boards = simul
+            .boards
+            .iter()
+            .map(|&(gid, opponent, result)| SimulBoard {
+                game: gid,
+                opponent,
+                host_to_move: result.is_none()
+                    && self
+                        .games
+                        .get(&gid)
+                        .is_some_and(|g| g.chess.active_player == host_color),
+                result,
+            })
+            .collect();
+        let msg = ServerMessage::SimulBoards(sid, boards);
+        for cid in simul.participants() {
+            self.send_to(cid, msg.clone()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_core::Variant;
+
+    #[test]
+    fn test_simul() {
+        let params = SimulParams {
+            name: "Exhibition".to_string(),
+            host_white: true,
+            boards: 2,
+            time: 0,
+            time_inc: 0,
+            variant: Variant::Standard,
+        };
+        let mut simul = Simul::new(1, 10, params);
+        assert_eq!(simul.start(), Err(SimulError::NoOpponents));
+        assert_eq!(simul.join(10), Err(SimulError::IsHost));
+        assert_eq!(simul.join(20), Ok(()));
+        assert_eq!(simul.join(20), Err(SimulError::AlreadyJoined));
+        assert_eq!(simul.join(30), Ok(()));
+        assert_eq!(simul.join(40), Err(SimulError::Full));
+        assert!(simul.leave(30));
+        assert_eq!(simul.join(40), Ok(()));
+
+        assert_eq!(simul.start(), Ok(()));
+        assert_eq!(simul.join(50), Err(SimulError::AlreadyStarted));
+        assert!(!simul.leave(20));
+        assert_eq!(simul.colors(20), (10, 20));
+        simul.add_board(100, 20);
+        simul.add_board(101, 40);
+
+        assert!(simul.record_result(100, 1.0));
+        assert!(!simul.record_result(100, 0.0));
+        assert!(!simul.is_finished());
+        assert!(simul.record_result(101, 0.5));
+        assert!(simul.is_finished());
+        assert_eq!(simul.score(), (1.5, 0.5));
+    }
+}
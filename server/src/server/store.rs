@@ -0,0 +1,413 @@
+use crate::chess::chess::Chess;
+use crate::server::chessgame::{replay, ChessGame, Identity};
+use crate::server::manager::GameManager;
+use chess_core::protocol::MoveRecord;
+use chess_core::{ChessColor, ClientId, GameId, Variant};
+use std::io;
+use std::path::PathBuf;
+
+/// Who sits on one side of a stored game.
+/// Client IDs only live as long as a connection, so players are remembered by account or,
+/// for guests, by the hash of their reconnect token.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Seat {
+    Player(Identity),
+    Computer(u8), // level
+}
+
+/// Everything needed to bring a running `ChessGame` back after a restart.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SavedGame {
+    pub id: GameId,
+    pub start_fen: String,
+    pub white: Option<Seat>,
+    pub black: Option<Seat>,
+    pub time: u32,
+    pub time_inc: u32,
+    pub rated: bool,
+    pub variant: Variant,
+    pub draw_offer_white: bool,
+    pub draw_offer_black: bool,
+    pub moves: Vec<MoveRecord>, // only the SAN and the times are stored
+    pub in_book: Vec<bool>,
+}
+
+impl SavedGame {
+    /// The current position: the start position with all moves replayed, which also works
+    /// out the moves and positions of the records.
+    /// Fails if a move doesn't fit, e.g. because the file has been edited.
+    pub fn replay(&mut self) -> Result<Chess, String> {
+        replay(&self.start_fen, &mut self.moves)
+    }
+
+    /// One `key=value` per line, like the server config.
+    pub fn to_text(&self) -> String {
+        let seat = |seat: &Option<Seat>| match seat {
+            Some(Seat::Player(Identity::User(uid))) => format!("user {}", uid),
+            Some(Seat::Player(Identity::Guest(token))) => format!("guest {}", token),
+            Some(Seat::Computer(level)) => format!("computer {}", level),
+            None => String::new(),
+        };
+        let in_book: Vec<&str> = self
+            .in_book
+            .iter()
+            .map(|&b| if b { "1" } else { "0" })
+            .collect();
+        let sans: Vec<&str> = self.moves.iter().map(|r| r.san.as_str()).collect();
+        // when the move was made / what was left on the clock / how long it took, in ms
+        let times: Vec<String> = self
+            .moves
+            .iter()
+            .map(|r| {
+                let clock = r.clock.map_or("-".to_string(), |c| c.to_string());
+                format!("{}/{}/{}", r.timestamp, clock, r.think_time)
+            })
+            .collect();
+        [
+            format!("id={}", self.id),
+            format!("start={}", self.start_fen),
+            format!("white={}", seat(&self.white)),
+            format!("black={}", seat(&self.black)),
+            format!("time={}", self.time),
+            format!("time_inc={}", self.time_inc),
+            format!("rated={}", self.rated),
+            format!("variant={}", self.variant as u8),
+            format!("draw_offer_white={}", self.draw_offer_white),
+            format!("draw_offer_black={}", self.draw_offer_black),
+            format!("moves={}", sans.join(" ")),
+            format!("times={}", times.join(" ")),
+            format!("in_book={}", in_book.join(" ")),
+        ]
+        .join("\n")
+            + "\n"
+    }
+
+    pub fn from_text(text: &str) -> Option<SavedGame> {
+        let mut game = SavedGame::default();
+        let mut times = vec![];
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let seat = || match value.split_once(' ') {
+                Some(("user", uid)) => uid
+                    .parse()
+                    .ok()
+                    .map(|uid| Seat::Player(Identity::User(uid))),
+                Some(("guest", token)) => Some(Seat::Player(Identity::Guest(token.to_string()))),
+                Some(("computer", level)) => level.parse().ok().map(Seat::Computer),
+                _ => None,
+            };
+            match key {
+                "id" => game.id = value.parse().ok()?,
+                "start" => game.start_fen = value.to_string(),
+                "white" => game.white = seat(),
+                "black" => game.black = seat(),
+                "time" => game.time = value.parse().ok()?,
+                "time_inc" => game.time_inc = value.parse().ok()?,
+                "rated" => game.rated = value == "true",
+                // a variant this server doesn't know can't be played on
+                "variant" => game.variant = Variant::from_u8(value.parse().ok()?)?,
+                "draw_offer_white" => game.draw_offer_white = value == "true",
+                "draw_offer_black" => game.draw_offer_black = value == "true",
+                "moves" => {
+                    game.moves = value
+                        .split_whitespace()
+                        .map(|san| MoveRecord {
+                            san: san.to_string(),
+                            ..MoveRecord::default()
+                        })
+                        .collect()
+                }
+                "times" => times = value.split_whitespace().collect(),
+                "in_book" => game.in_book = value.split_whitespace().map(|b| b == "1").collect(),
+                _ => {}
+            }
+        }
+        if game.id == 0 || game.start_fen.is_empty() {
+            return None;
+        }
+        // games stored before the times were kept have none
+        for (record, time) in game.moves.iter_mut().zip(times) {
+            let mut parts = time.split('/');
+            record.timestamp = parts.next()?.parse().ok()?;
+            record.clock = parts.next()?.parse().ok();
+            record.think_time = parts.next()?.parse().ok()?;
+        }
+        Some(game)
+    }
+}
+
+/// Running games on disk, one file per game, so they survive a restart of the server.
+pub struct GameStore {
+    dir: PathBuf,
+}
+
+impl GameStore {
+    /// Use (and create, if needed) the given directory.
+    pub fn open(dir: &str) -> io::Result<GameStore> {
+        std::fs::create_dir_all(dir)?;
+        Ok(GameStore {
+            dir: PathBuf::from(dir),
+        })
+    }
+
+    fn path(&self, gid: GameId) -> PathBuf {
+        self.dir.join(format!("{}.game", gid))
+    }
+
+    /// All stored games. Broken files are skipped.
+    /// Only used at startup, before the `GameManager` runs, so this doesn't need to be async.
+    pub fn load_all(&self) -> io::Result<Vec<SavedGame>> {
+        let mut games = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "game") {
+                continue;
+            }
+            match SavedGame::from_text(&std::fs::read_to_string(&path)?) {
+                Some(game) => games.push(game),
+                None => log::warn!("skipping broken game file {}", path.display()),
+            }
+        }
+        games.sort_by_key(|g| g.id);
+        Ok(games)
+    }
+
+    /// Write a game. The file is replaced in one step, so a crash can't leave half a game behind.
+    pub async fn save(&self, game: &SavedGame) -> io::Result<()> {
+        let path = self.path(game.id);
+        let tmp = path.with_extension("tmp");
+        smol::fs::write(&tmp, game.to_text()).await?;
+        smol::fs::rename(&tmp, &path).await
+    }
+
+    pub async fn remove(&self, gid: GameId) -> io::Result<()> {
+        smol::fs::remove_file(self.path(gid)).await
+    }
+}
+
+impl GameManager {
+    /// Bring back the games that were running when the server stopped.
+    /// Computer players take their seats again right away, the seats of remote players are
+    /// kept for them until they come back (same account, or the reconnect token for guests).
+    pub(crate) fn restore_games(&mut self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let saved_games = match store.load_all() {
+            Ok(games) => games,
+            Err(e) => {
+                log::warn!("failed to load stored games: {}", e);
+                return;
+            }
+        };
+
+        for mut saved in saved_games {
+            let chess = match saved.replay() {
+                Ok(chess) => chess,
+                Err(san) => {
+                    log::warn!("can't restore game {}: bad move {}", saved.id, san);
+                    continue;
+                }
+            };
+            let mut game = ChessGame::new(saved.id, chess, saved.time, saved.time_inc);
+            game.start_fen = saved.start_fen;
+            game.rated = saved.rated;
+            game.variant = saved.variant;
+            game.move_history = saved.moves;
+            game.restore_clocks();
+            game.in_book = saved.in_book;
+            game.draw_offer_white = saved.draw_offer_white;
+            game.draw_offer_black = saved.draw_offer_black;
+            // nobody is in yet, but the players' seats are kept for them
+            game.empty_since = None;
+            self.games.insert(game.id, game);
+            self.next_game_id = self.next_game_id.max(saved.id + 1);
+
+            for (seat, color) in [
+                (saved.white, ChessColor::White),
+                (saved.black, ChessColor::Black),
+            ] {
+                match seat {
+                    Some(Seat::Player(identity)) => {
+                        let game = self.games.get_mut(&saved.id).unwrap();
+                        game.keep_seat(color, identity);
+                    }
+                    Some(Seat::Computer(level)) => {
+                        let computer_id = self.next_computer_id;
+                        self.next_computer_id -= 1;
+                        let game = self.games.get_mut(&saved.id).unwrap();
+                        match color {
+                            ChessColor::White => game.white_player = Some(computer_id),
+                            ChessColor::Black => game.black_player = Some(computer_id),
+                        }
+                        self.start_computer(computer_id, saved.id, color, level);
+                    }
+                    None => {}
+                }
+            }
+            log::info!("restored game {}", saved.id);
+        }
+    }
+
+    /// Write the current state of a running game to the game store, so it survives a restart.
+    pub(crate) async fn persist(&self, gid: GameId) {
+        let (Some(store), Some(game)) = (&self.store, self.games.get(&gid)) else {
+            return;
+        };
+        // bughouse boards depend on each other and on the pockets, they don't outlive a
+        // restart; nor do the teams of consultation games
+        if game.bughouse.is_some() || game.consultation.is_some() {
+            return;
+        }
+        // remote players are remembered by account, guests by the hash of their reconnect token;
+        // whoever comes back with the token gets the seat
+        let seat = |player: Option<ClientId>, reserved: &Option<Identity>| match player {
+            Some(cid) => self.clients.get(&cid).and_then(|c| match c.computer_level {
+                Some(level) => Some(Seat::Computer(level)),
+                None => c.identity().map(Seat::Player),
+            }),
+            None => reserved.clone().map(Seat::Player),
+        };
+        let saved = SavedGame {
+            id: gid,
+            start_fen: game.start_fen.clone(),
+            white: seat(game.white_player, &game.white_reserved),
+            black: seat(game.black_player, &game.black_reserved),
+            time: game._time,
+            time_inc: game._time_inc,
+            rated: game.rated,
+            variant: game.variant,
+            draw_offer_white: game.draw_offer_white,
+            draw_offer_black: game.draw_offer_black,
+            moves: game.move_history.clone(),
+            in_book: game.in_book.clone(),
+        };
+        if let Err(e) = store.save(&saved).await {
+            log::warn!("failed to store game {}: {}", gid, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_game() -> SavedGame {
+        SavedGame {
+            id: 7,
+            start_fen: Chess::new().get_fen(),
+            white: Some(Seat::Player(Identity::guest("3f9a0c7d"))),
+            black: Some(Seat::Computer(3)),
+            time: 300,
+            time_inc: 2,
+            rated: true,
+            variant: Variant::Standard,
+            draw_offer_white: true,
+            draw_offer_black: false,
+            moves: [("e4", Some(299_000)), ("e5", None), ("Nf3", Some(297_500))]
+                .into_iter()
+                .map(|(san, clock)| MoveRecord {
+                    san: san.to_string(),
+                    timestamp: 1_700_000_000_000,
+                    clock,
+                    think_time: 2500,
+                    ..MoveRecord::default()
+                })
+                .collect(),
+            in_book: vec![true, true, false],
+        }
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let game = saved_game();
+        assert_eq!(SavedGame::from_text(&game.to_text()), Some(game.clone()));
+
+        let user = SavedGame {
+            black: Some(Seat::Player(Identity::User(12))),
+            ..game.clone()
+        };
+        assert_eq!(SavedGame::from_text(&user.to_text()), Some(user));
+
+        let empty_seats = SavedGame {
+            white: None,
+            black: None,
+            moves: vec![],
+            in_book: vec![],
+            ..game
+        };
+        assert_eq!(
+            SavedGame::from_text(&empty_seats.to_text()),
+            Some(empty_seats)
+        );
+        assert!(SavedGame::from_text("garbage").is_none());
+    }
+
+    #[test]
+    fn test_variant_roundtrip() {
+        let game = saved_game();
+        let text = game.to_text();
+        assert!(text.contains(&format!("variant={}\n", Variant::Standard as u8)));
+        assert_eq!(SavedGame::from_text(&text).unwrap().variant, game.variant);
+
+        // games stored before the variant was kept are standard games
+        let old = text.replace(&format!("variant={}\n", Variant::Standard as u8), "");
+        assert_eq!(SavedGame::from_text(&old), Some(game));
+        // and a game in a variant we don't know is not restored as something else
+        let unknown = old + "variant=255\n";
+        assert!(SavedGame::from_text(&unknown).is_none());
+    }
+
+    #[test]
+    fn test_token_not_stored() {
+        let game = SavedGame {
+            white: Some(Seat::Player(Identity::guest("9c1f3e0a77b2"))),
+            ..saved_game()
+        };
+        let text = game.to_text();
+        assert!(!text.contains("9c1f3e0a77b2"));
+        assert_eq!(SavedGame::from_text(&text), Some(game));
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut game = saved_game();
+        let chess = game.replay().unwrap();
+        assert!(chess
+            .get_fen()
+            .starts_with("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 "));
+
+        assert_eq!(game.moves[2].mov, Some("g1f3".parse().unwrap()));
+        assert_eq!(game.moves[2].fen, chess.get_fen());
+
+        game.moves.push(MoveRecord {
+            san: "Ke3".to_string(),
+            ..MoveRecord::default()
+        });
+        assert_eq!(game.replay().err(), Some("Ke3".to_string()));
+    }
+
+    #[test]
+    fn test_store() {
+        let dir = std::env::temp_dir().join(format!("games-{}", std::process::id()));
+        let store = GameStore::open(dir.to_str().unwrap()).unwrap();
+        let game = saved_game();
+        let other = SavedGame {
+            id: 3,
+            ..saved_game()
+        };
+
+        smol::block_on(async {
+            store.save(&game).await.unwrap();
+            store.save(&other).await.unwrap();
+            assert_eq!(store.load_all().unwrap(), vec![other.clone(), game.clone()]);
+
+            store.remove(3).await.unwrap();
+            assert_eq!(store.load_all().unwrap(), vec![game.clone()]);
+        });
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,1043 @@
+use crate::server::chessgame::{ChessGame, Identity};
+use crate::server::manager::GameManager;
+use chess_core::protocol::messages::ServerMessage;
+use chess_core::protocol::{
+    ExportFormat, MoveRecord, NewGameParams, Standing, TournamentParams, TournamentSystem,
+};
+use chess_core::states::GameOverReason;
+use chess_core::{
+    ChessColor, ClientId, GameId, GameNode, GameTree, TimeCategory, TournamentId, START_FEN,
+};
+use chrono::prelude::*;
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+/// Pairing a Swiss round gives up on avoiding rematches after that many tries.
+const PAIRING_BUDGET: usize = 100_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TournamentError {
+    AlreadyStarted,
+    NotStarted,
+    AlreadyJoined,
+    NotEnoughPlayers,
+}
+
+impl std::fmt::Display for TournamentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TournamentError::AlreadyStarted => write!(f, "The tournament has already started"),
+            TournamentError::NotStarted => write!(f, "The tournament hasn't started yet"),
+            TournamentError::AlreadyJoined => write!(f, "Already registered"),
+            TournamentError::NotEnoughPlayers => write!(f, "Not enough players"),
+        }
+    }
+}
+
+/// A registered player. After the start, the players are ordered by seed.
+/// Players are known by their identity; `cid` is their latest connection.
+#[derive(Debug, Clone)]
+pub struct Entrant {
+    pub cid: ClientId,
+    pub identity: Identity,
+    pub name: String,
+    pub rating: Option<u16>, // for seeding, in the variant and time category of the tournament
+    pub withdrawn: bool,
+}
+
+/// A board of a round, or a bye if there is no black player.
+/// Players are indices into `Tournament::players`.
+#[derive(Debug, Clone)]
+pub struct Pairing {
+    pub white: usize,
+    pub black: Option<usize>,
+    pub game: Option<GameId>,       // `None` for byes and forfeits
+    pub result: Option<(f32, f32)>, // points of white and black
+    pub moves: Vec<MoveRecord>,     // for the PGN export
+}
+
+impl Pairing {
+    fn new(white: usize, black: Option<usize>) -> Self {
+        Pairing {
+            white,
+            black,
+            game: None,
+            result: None,
+            moves: vec![],
+        }
+    }
+
+    /// The opponent of a player on this board, and the points of both.
+    fn for_player(&self, player: usize) -> Option<(Option<usize>, Option<f32>)> {
+        if self.white == player {
+            Some((self.black, self.result.map(|r| r.0)))
+        } else if self.black == Some(player) {
+            Some((Some(self.white), self.result.map(|r| r.1)))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Tournament {
+    pub id: TournamentId,
+    pub organizer: ClientId,
+    pub params: TournamentParams,
+    pub players: Vec<Entrant>,
+    pub rounds: Vec<Vec<Pairing>>,
+    pub date: String, // of the start, `YYYY.MM.DD` like in PGN
+    schedule: Vec<Vec<(usize, Option<usize>)>>, // round robin: all rounds, fixed at the start
+    started: bool,
+}
+
+impl Tournament {
+    pub fn new(id: TournamentId, organizer: ClientId, params: TournamentParams) -> Self {
+        Tournament {
+            id,
+            organizer,
+            params,
+            players: vec![],
+            rounds: vec![],
+            date: String::new(),
+            schedule: vec![],
+            started: false,
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    /// The client IDs of the players that still play.
+    pub fn active_players(&self) -> Vec<ClientId> {
+        self.players
+            .iter()
+            .filter(|p| !p.withdrawn)
+            .map(|p| p.cid)
+            .collect()
+    }
+
+    fn index_of(&self, cid: ClientId) -> Option<usize> {
+        self.players.iter().position(|p| p.cid == cid)
+    }
+
+    pub fn entrant(&self, cid: ClientId) -> Option<&Entrant> {
+        self.players.iter().find(|p| p.cid == cid)
+    }
+
+    pub fn join(&mut self, entrant: Entrant) -> Result<(), TournamentError> {
+        if self.started {
+            return Err(TournamentError::AlreadyStarted);
+        }
+        if self.players.iter().any(|p| p.identity == entrant.identity) {
+            return Err(TournamentError::AlreadyJoined);
+        }
+        self.players.push(entrant);
+        Ok(())
+    }
+
+    /// A player is back with a new connection, which takes their place.
+    /// Returns whether they take part.
+    pub fn reconnect(&mut self, identity: &Identity, cid: ClientId) -> bool {
+        match self.players.iter_mut().find(|p| &p.identity == identity) {
+            Some(player) => {
+                player.cid = cid;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Before the start, a player just leaves. Later, they keep their results but aren't
+    /// paired anymore; their remaining round robin games are lost by forfeit.
+    /// Returns whether the client took part.
+    pub fn withdraw(&mut self, cid: ClientId) -> bool {
+        let Some(index) = self.index_of(cid) else {
+            return false;
+        };
+        if self.started {
+            self.players[index].withdrawn = true;
+        } else {
+            self.players.remove(index);
+        }
+        true
+    }
+
+    /// Seed the players by rating and fix the schedule of a round robin.
+    pub fn start(&mut self, date: String) -> Result<(), TournamentError> {
+        if self.started {
+            return Err(TournamentError::AlreadyStarted);
+        }
+        if self.players.len() < 2 {
+            return Err(TournamentError::NotEnoughPlayers);
+        }
+        // unrated players last, otherwise in the order they came
+        self.players
+            .sort_by_key(|p| std::cmp::Reverse(p.rating.map_or(0, |r| r as u32 + 1)));
+        if self.params.system == TournamentSystem::RoundRobin {
+            self.schedule = round_robin(self.players.len());
+        }
+        self.date = date;
+        self.started = true;
+        Ok(())
+    }
+
+    pub fn total_rounds(&self) -> usize {
+        match self.params.system {
+            TournamentSystem::RoundRobin => self.schedule.len(),
+            TournamentSystem::Swiss => self.params.rounds as usize,
+        }
+    }
+
+    /// Are all boards of the current round decided?
+    pub fn is_round_over(&self) -> bool {
+        self.rounds
+            .last()
+            .is_none_or(|round| round.iter().all(|p| p.result.is_some()))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.started && self.rounds.len() >= self.total_rounds() && self.is_round_over()
+    }
+
+    /// Pair the next round. Byes and forfeits are decided right away, the other boards
+    /// wait for their games. Returns the number of the round, counting from 1.
+    pub fn pair_next_round(&mut self) -> Result<usize, TournamentError> {
+        if !self.started {
+            return Err(TournamentError::NotStarted);
+        }
+        if self.is_finished() || !self.is_round_over() {
+            return Err(TournamentError::AlreadyStarted);
+        }
+        let pairs = match self.params.system {
+            TournamentSystem::RoundRobin => self.schedule[self.rounds.len()].clone(),
+            TournamentSystem::Swiss => self.swiss_pairs(),
+        };
+
+        let round = pairs
+            .into_iter()
+            .map(|(white, black)| {
+                let mut pairing = Pairing::new(white, black);
+                let gone = |p: usize| self.players[p].withdrawn;
+                pairing.result = match black {
+                    None if gone(white) => Some((0.0, 0.0)),
+                    None => Some((1.0, 0.0)),
+                    Some(black) => match (gone(white), gone(black)) {
+                        (true, true) => Some((0.0, 0.0)),
+                        (true, false) => Some((0.0, 1.0)),
+                        (false, true) => Some((1.0, 0.0)),
+                        (false, false) => None,
+                    },
+                };
+                pairing
+            })
+            .collect();
+        self.rounds.push(round);
+        Ok(self.rounds.len())
+    }
+
+    /// The boards of the current round that need a game: (board, white, black).
+    pub fn boards_to_play(&self) -> Vec<(usize, ClientId, ClientId)> {
+        let Some(round) = self.rounds.last() else {
+            return vec![];
+        };
+        round
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.result.is_none() && p.game.is_none())
+            .filter_map(|(board, p)| {
+                let black = p.black?;
+                Some((board, self.players[p.white].cid, self.players[black].cid))
+            })
+            .collect()
+    }
+
+    pub fn set_game(&mut self, board: usize, gid: GameId) {
+        if let Some(pairing) = self.rounds.last_mut().and_then(|r| r.get_mut(board)) {
+            pairing.game = Some(gid);
+        }
+    }
+
+    /// Who has the bye in the current round.
+    pub fn bye(&self) -> Option<ClientId> {
+        let round = self.rounds.last()?;
+        let bye = round.iter().find(|p| p.black.is_none())?;
+        Some(self.players[bye.white].cid)
+    }
+
+    /// Enter the result of a tournament game. Returns whether the game was part of it.
+    pub fn record_result(
+        &mut self,
+        gid: GameId,
+        white_points: f32,
+        moves: Vec<MoveRecord>,
+    ) -> bool {
+        for round in self.rounds.iter_mut() {
+            if let Some(pairing) = round.iter_mut().find(|p| p.game == Some(gid)) {
+                pairing.result = Some((white_points, 1.0 - white_points));
+                pairing.moves = moves;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The points of every player so far.
+    pub fn scores(&self) -> Vec<f32> {
+        let mut scores = vec![0.0; self.players.len()];
+        for pairing in self.rounds.iter().flatten() {
+            if let Some((white, black)) = pairing.result {
+                scores[pairing.white] += white;
+                if let Some(b) = pairing.black {
+                    scores[b] += black;
+                }
+            }
+        }
+        scores
+    }
+
+    /// The standings, best first: by score, then Buchholz, then Sonneborn-Berger, then seed.
+    pub fn standings(&self) -> Vec<Standing> {
+        let scores = self.scores();
+        let mut tiebreaks = vec![(0.0, 0.0); self.players.len()];
+        for pairing in self.rounds.iter().flatten() {
+            let (Some(black), Some((w, b))) = (pairing.black, pairing.result) else {
+                continue;
+            };
+            for (player, opponent, points) in [(pairing.white, black, w), (black, pairing.white, b)]
+            {
+                tiebreaks[player].0 += scores[opponent];
+                tiebreaks[player].1 += points * scores[opponent];
+            }
+        }
+
+        let mut order: Vec<usize> = (0..self.players.len()).collect();
+        order.sort_by(|&a, &b| {
+            let key = |p: usize| [scores[p], tiebreaks[p].0, tiebreaks[p].1];
+            key(b)
+                .partial_cmp(&key(a))
+                .unwrap_or(Ordering::Equal)
+                .then(a.cmp(&b))
+        });
+        order
+            .into_iter()
+            .map(|p| Standing {
+                player: self.players[p].cid,
+                name: self.players[p].name.clone(),
+                score: scores[p],
+                buchholz: tiebreaks[p].0,
+                sonneborn_berger: tiebreaks[p].1,
+            })
+            .collect()
+    }
+
+    /// Dutch system: players sorted by score and seed, the upper half of every score group
+    /// against the lower half. No rematches if that can be helped, and the lowest ranked
+    /// player without a bye sits out if the number is odd.
+    fn swiss_pairs(&self) -> Vec<(usize, Option<usize>)> {
+        let scores = self.scores();
+        let mut order: Vec<usize> = (0..self.players.len())
+            .filter(|&p| !self.players[p].withdrawn)
+            .collect();
+        order.sort_by(|&a, &b| {
+            scores[b]
+                .partial_cmp(&scores[a])
+                .unwrap_or(Ordering::Equal)
+                .then(a.cmp(&b))
+        });
+
+        let mut pairs = vec![];
+        if order.len() % 2 == 1 {
+            let had_bye = |p: usize| {
+                self.rounds
+                    .iter()
+                    .flatten()
+                    .any(|pairing| pairing.white == p && pairing.black.is_none())
+            };
+            let index = order
+                .iter()
+                .rposition(|&p| !had_bye(p))
+                .unwrap_or(order.len() - 1);
+            pairs.push((order.remove(index), None));
+        }
+
+        let met = |a: usize, b: usize| {
+            self.rounds.iter().flatten().any(|p| {
+                (p.white == a && p.black == Some(b)) || (p.white == b && p.black == Some(a))
+            })
+        };
+        let mut budget = PAIRING_BUDGET;
+        let mut unlimited = usize::MAX;
+        let boards = pair_dutch(&order, &scores, &met, &mut budget)
+            .or_else(|| pair_dutch(&order, &scores, &|_, _| false, &mut unlimited))
+            .unwrap_or_default();
+        for (a, b) in boards {
+            let (white, black) = if self.gets_white(a, b) {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            pairs.push((white, Some(black)));
+        }
+        // boards first, the bye last
+        pairs.sort_by_key(|(_, black)| black.is_none());
+        pairs
+    }
+
+    /// Colours for a Swiss board, `a` being the higher ranked player: whoever had white
+    /// less often gets it, else whoever had black last, else it alternates by round.
+    fn gets_white(&self, a: usize, b: usize) -> bool {
+        let history = |p: usize| -> Vec<bool> {
+            self.rounds
+                .iter()
+                .flatten()
+                .filter(|pairing| pairing.black.is_some() && pairing.for_player(p).is_some())
+                .map(|pairing| pairing.white == p)
+                .collect()
+        };
+        let (ha, hb) = (history(a), history(b));
+        let balance = |h: &[bool]| h.iter().map(|&w| if w { 1 } else { -1 }).sum::<i32>();
+        match balance(&ha).cmp(&balance(&hb)) {
+            Ordering::Less => true,
+            Ordering::Greater => false,
+            Ordering::Equal => match (ha.last(), hb.last()) {
+                (Some(&last_a), Some(&last_b)) if last_a != last_b => !last_a,
+                _ => self.rounds.len().is_multiple_of(2),
+            },
+        }
+    }
+
+    /// The standings with every player's results round by round, e.g. `+3w` for a win
+    /// with white against the player ranked third.
+    pub fn crosstable(&self) -> String {
+        let standings = self.standings();
+        let rank = |cid: ClientId| standings.iter().position(|s| s.player == cid).unwrap_or(0) + 1;
+        let mut text = String::new();
+        let _ = writeln!(text, "{}", self.params.name);
+        let _ = writeln!(
+            text,
+            "{}, {} rounds, {}+{}, {}{}\n",
+            self.params.system,
+            self.total_rounds(),
+            self.params.time / 60,
+            self.params.time_inc,
+            self.params.variant,
+            if self.params.rated { ", rated" } else { "" }
+        );
+
+        let _ = write!(text, "Rank  Name              Score  Buchholz     SB");
+        for round in 1..=self.rounds.len() {
+            let _ = write!(text, "  {:>4}", format!("R{}", round));
+        }
+        text.push('\n');
+        for (i, standing) in standings.iter().enumerate() {
+            let _ = write!(
+                text,
+                "{:>4}  {:<16}  {:>5.1}  {:>8.1}  {:>5.2}",
+                i + 1,
+                standing.name,
+                standing.score,
+                standing.buchholz,
+                standing.sonneborn_berger
+            );
+            let player = self.index_of(standing.player).unwrap_or(0);
+            for round in &self.rounds {
+                let entry = round
+                    .iter()
+                    .find_map(|pairing| pairing.for_player(player).map(|r| (pairing, r)))
+                    .map_or(String::new(), |(pairing, (opponent, points))| {
+                        let sign = match points {
+                            None => " ",
+                            Some(1.0) => "+",
+                            Some(0.5) => "=",
+                            Some(_) => "-",
+                        };
+                        match opponent {
+                            None => "bye".to_string(),
+                            Some(o) => {
+                                let color = match (pairing.game, pairing.white == player) {
+                                    (None, _) => "f", // forfeit, not played
+                                    (_, true) => "w",
+                                    (_, false) => "b",
+                                };
+                                format!("{}{}{}", sign, rank(self.players[o].cid), color)
+                            }
+                        }
+                    });
+                let _ = write!(text, "  {:>4}", entry);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// All games played so far, as PGN.
+    pub fn pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (r, round) in self.rounds.iter().enumerate() {
+            for pairing in round {
+                let (Some(black), Some(_), Some(result)) =
+                    (pairing.black, pairing.game, pairing.result)
+                else {
+                    continue;
+                };
+                let result = match result {
+                    (1.0, _) => "1-0",
+                    (0.0, _) => "0-1",
+                    _ => "1/2-1/2",
+                };
+                let mut tree = GameTree::new(START_FEN);
+                tree.tags = [
+                    ("Event", self.params.name.as_str()),
+                    ("Site", "chess-server"),
+                    ("Date", self.date.as_str()),
+                    ("Round", &(r + 1).to_string()),
+                    ("White", &self.players[pairing.white].name),
+                    ("Black", &self.players[black].name),
+                    ("Result", result),
+                ]
+                .iter()
+                .map(|(tag, value)| (tag.to_string(), value.to_string()))
+                .collect();
+                tree.result = result.to_string();
+
+                // the clocks go along as comments
+                let mut node = GameTree::ROOT;
+                for record in &pairing.moves {
+                    let move_node = GameNode {
+                        mov: record.mov,
+                        san: record.san.clone(),
+                        fen: record.fen.clone(),
+                        clock: record.clock,
+                        ..GameNode::default()
+                    };
+                    node = tree.add_move(node, move_node).unwrap();
+                }
+                let _ = writeln!(pgn, "{}", tree.to_pgn());
+            }
+        }
+        pgn
+    }
+}
+
+/// The rounds of a round robin for `n` players by the circle method: the first player stays,
+/// the others rotate. With an odd number, the player meeting the missing one has a bye.
+/// Everyone meets everyone once, with colours about even.
+pub fn round_robin(n: usize) -> Vec<Vec<(usize, Option<usize>)>> {
+    let mut circle: Vec<Option<usize>> = (0..n).map(Some).collect();
+    if n % 2 == 1 {
+        circle.push(None);
+    }
+    let size = circle.len();
+    let mut rounds = vec![];
+    for r in 0..size - 1 {
+        let mut round = vec![];
+        for i in 0..size / 2 {
+            let (a, b) = (circle[i], circle[size - 1 - i]);
+            // the fixed player alternates, the others take white in the upper half
+            let (white, black) = if i == 0 && r % 2 == 1 { (b, a) } else { (a, b) };
+            match (white, black) {
+                (Some(w), Some(b)) => round.push((w, Some(b))),
+                (Some(p), None) | (None, Some(p)) => round.push((p, None)),
+                (None, None) => {}
+            }
+        }
+        round.sort_by_key(|(_, black)| black.is_none());
+        rounds.push(round);
+        circle[1..].rotate_right(1);
+    }
+    rounds
+}
+
+/// Pair players sorted by score and seed: the first player gets the opponent in the same
+/// position of the lower half of their score group, or the next best one they haven't met.
+/// A player left alone in their group floats down to the next one.
+fn pair_dutch(
+    order: &[usize],
+    scores: &[f32],
+    met: &dyn Fn(usize, usize) -> bool,
+    budget: &mut usize,
+) -> Option<Vec<(usize, usize)>> {
+    let Some((&first, rest)) = order.split_first() else {
+        return Some(vec![]);
+    };
+    let group = 1 + rest
+        .iter()
+        .take_while(|&&p| scores[p] == scores[first])
+        .count();
+    let half = group / 2;
+    // S2 of the group from its middle, then the rest of S1 bottom up, then the lower groups
+    let mut candidates: Vec<usize> = vec![];
+    if group > 1 {
+        candidates.extend(half - 1..group - 1);
+        candidates.extend((0..half - 1).rev());
+    }
+    candidates.extend(group - 1..rest.len());
+
+    for index in candidates {
+        if *budget == 0 {
+            return None;
+        }
+        *budget -= 1;
+        let opponent = rest[index];
+        if met(first, opponent) {
+            continue;
+        }
+        let remaining: Vec<usize> = rest
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != index)
+            .map(|(_, &p)| p)
+            .collect();
+        if let Some(mut pairs) = pair_dutch(&remaining, scores, met, budget) {
+            pairs.insert(0, (first, opponent));
+            return Some(pairs);
+        }
+    }
+    None
+}
+
+impl GameManager {
+    /// Open a tournament for registration. The organizer starts it once everybody is in.
+    pub(crate) async fn handle_create_tournament(
+        &mut self,
+        cid: ClientId,
+        mut params: TournamentParams,
+    ) {
+        params.name = params.name.trim().to_string();
+        let no_rounds = params.system == TournamentSystem::Swiss && params.rounds == 0;
+        if params.name.is_empty() || no_rounds {
+            log::warn!("client {} can't create tournament {:?}", cid, params.name);
+            return;
+        }
+        // without ratings, there are only casual games
+        params.rated &= self.ratings.is_some();
+
+        let tid = self.next_tournament_id;
+        self.next_tournament_id += 1;
+        log::info!(
+            "client {} creates tournament {} ({})",
+            cid,
+            tid,
+            params.name
+        );
+        let msg = ServerMessage::TournamentCreated(tid, cid, params.clone());
+        self.tournaments
+            .insert(tid, Tournament::new(tid, cid, params));
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+        }
+    }
+
+    /// Register for a tournament; rated tournaments are for players with accounts.
+    pub(crate) async fn handle_join_tournament(&mut self, cid: ClientId, tid: TournamentId) {
+        let (Some(client), Some(t)) = (self.clients.get(&cid), self.tournaments.get(&tid)) else {
+            return;
+        };
+        let identity = match client.identity() {
+            Some(identity) if !t.params.rated || client.user.is_some() => identity,
+            _ => {
+                log::warn!("client {} can't play in tournament {}", cid, tid);
+                return;
+            }
+        };
+        let category = TimeCategory::from_clock(t.params.time, t.params.time_inc);
+        let rating = match (&self.ratings, client.user) {
+            (Some(ratings), Some(uid)) => Some(
+                ratings
+                    .get(uid, t.params.variant, category)
+                    .to_rating()
+                    .rating,
+            ),
+            _ => None,
+        };
+        let entrant = Entrant {
+            cid,
+            identity,
+            name: client.name.clone(),
+            rating,
+            withdrawn: false,
+        };
+
+        let Some(t) = self.tournaments.get_mut(&tid) else {
+            return;
+        };
+        match t.join(entrant) {
+            Ok(()) => self.announce_players(tid).await,
+            Err(e) => log::warn!("client {} can't join tournament {}: {}", cid, tid, e),
+        }
+    }
+
+    /// Leave a tournament. Once it runs, the player keeps their results but isn't paired
+    /// anymore.
+    pub(crate) async fn handle_withdraw_tournament(&mut self, cid: ClientId, tid: TournamentId) {
+        let Some(t) = self.tournaments.get_mut(&tid) else {
+            return;
+        };
+        if t.is_finished() || !t.withdraw(cid) {
+            return;
+        }
+        log::info!("client {} withdraws from tournament {}", cid, tid);
+        if !t.is_started() {
+            self.announce_players(tid).await;
+        }
+    }
+
+    pub(crate) async fn handle_start_tournament(&mut self, cid: ClientId, tid: TournamentId) {
+        let Some(t) = self.tournaments.get_mut(&tid) else {
+            return;
+        };
+        if t.organizer != cid {
+            log::warn!("client {} can't start tournament {}", cid, tid);
+            return;
+        }
+        let date = Utc::now().format("%Y.%m.%d").to_string();
+        if let Err(e) = t.start(date) {
+            log::warn!("tournament {} can't start: {}", tid, e);
+            return;
+        }
+        log::info!("tournament {} starts with {} players", tid, t.players.len());
+        self.next_round(tid).await;
+    }
+
+    /// Pair the next round of a tournament and start its games, or announce the final
+    /// standings when it's over. A round without games (only byes and forfeits) is over
+    /// right away, so the next one follows.
+    async fn next_round(&mut self, tid: TournamentId) {
+        loop {
+            let Some(t) = self.tournaments.get_mut(&tid) else {
+                return;
+            };
+            if !t.rounds.is_empty() {
+                self.send_standings(tid).await;
+            }
+            let Some(t) = self.tournaments.get_mut(&tid) else {
+                return;
+            };
+            if t.is_finished() {
+                log::info!("tournament {} finished", tid);
+                return;
+            }
+            let round = match t.pair_next_round() {
+                Ok(round) => round,
+                Err(e) => {
+                    log::warn!("can't pair tournament {}: {}", tid, e);
+                    return;
+                }
+            };
+            let boards = t.boards_to_play();
+            let msg = ServerMessage::TournamentRound(tid, round as u8, t.bye());
+            let players = t.active_players();
+            let params = NewGameParams {
+                mode: 0,
+                rated: t.params.rated,
+                time: t.params.time,
+                time_inc: t.params.time_inc,
+            };
+            let variant = t.params.variant;
+            log::info!(
+                "tournament {}: round {} with {} games",
+                tid,
+                round,
+                boards.len()
+            );
+
+            for cid in players {
+                self.send_to(cid, msg.clone()).await;
+            }
+            for &(board, white, black) in &boards {
+                // players that are away get their seat kept, like after a lost connection
+                let away: Vec<(ChessColor, Identity)> =
+                    [(ChessColor::White, white), (ChessColor::Black, black)]
+                        .into_iter()
+                        .filter(|(_, cid)| !self.clients.contains_key(cid))
+                        .filter_map(|(color, cid)| {
+                            let t = self.tournaments.get(&tid)?;
+                            Some((color, t.entrant(cid)?.identity.clone()))
+                        })
+                        .collect();
+                let gid = self
+                    .start_paired_game(white, black, params.clone(), variant)
+                    .await;
+                if let Some(game) = self.games.get_mut(&gid) {
+                    game.tournament = Some(tid);
+                    for (color, identity) in away {
+                        game.keep_seat(color, identity);
+                    }
+                }
+                self.persist(gid).await;
+                if let Some(t) = self.tournaments.get_mut(&tid) {
+                    t.set_game(board, gid);
+                }
+            }
+            if !boards.is_empty() {
+                return;
+            }
+        }
+    }
+
+    /// A tournament game ended; the last one of a round brings the next round.
+    pub(crate) async fn record_tournament_result(
+        &mut self,
+        tid: TournamentId,
+        game: &ChessGame,
+        reason: GameOverReason,
+    ) {
+        let white_points = match reason.get_winner() {
+            Some(ChessColor::White) => 1.0,
+            Some(ChessColor::Black) => 0.0,
+            None => 0.5,
+        };
+        let Some(t) = self.tournaments.get_mut(&tid) else {
+            return;
+        };
+        t.record_result(game.id, white_points, game.move_history.clone());
+        if t.is_round_over() {
+            self.next_round(tid).await;
+        }
+    }
+
+    /// A player is back: their place in the tournaments goes to the new connection.
+    pub(crate) async fn rejoin_tournaments(&mut self, cid: ClientId, identity: &Identity) {
+        let tids: Vec<TournamentId> = self
+            .tournaments
+            .iter_mut()
+            .filter(|(_, t)| !t.is_finished())
+            .filter_map(|(&tid, t)| t.reconnect(identity, cid).then_some(tid))
+            .collect();
+        for tid in tids {
+            log::info!("client {} is back in tournament {}", cid, tid);
+            if self.tournaments.get(&tid).is_some_and(|t| t.is_started()) {
+                if let Some(msg) = self.standings(tid) {
+                    self.send_to(cid, msg).await;
+                }
+            } else {
+                self.announce_players(tid).await;
+            }
+        }
+    }
+
+    /// Tell everyone who takes part in a tournament that hasn't started yet.
+    async fn announce_players(&self, tid: TournamentId) {
+        let Some(t) = self.tournaments.get(&tid) else {
+            return;
+        };
+        let players = t.players.iter().map(|p| p.cid).collect();
+        let msg = ServerMessage::TournamentPlayers(tid, players);
+        for c in self.clients.values() {
+            let _ = c.tx.send(msg.clone()).await;
+        }
+    }
+
+    /// The standings of a tournament after the last complete round.
+    pub(crate) fn standings(&self, tid: TournamentId) -> Option<ServerMessage> {
+        let t = self.tournaments.get(&tid)?;
+        let played = t.rounds.len() - !t.is_round_over() as usize;
+        Some(ServerMessage::Standings(
+            tid,
+            played as u8,
+            t.is_finished(),
+            t.standings(),
+        ))
+    }
+
+    /// Send the standings to the players and the organizer.
+    async fn send_standings(&self, tid: TournamentId) {
+        let (Some(t), Some(msg)) = (self.tournaments.get(&tid), self.standings(tid)) else {
+            return;
+        };
+        let mut recipients: Vec<ClientId> = t.players.iter().map(|p| p.cid).collect();
+        if !recipients.contains(&t.organizer) {
+            recipients.push(t.organizer);
+        }
+        for cid in recipients {
+            self.send_to(cid, msg.clone()).await;
+        }
+    }
+
+    pub(crate) async fn handle_export_tournament(
+        &self,
+        cid: ClientId,
+        tid: TournamentId,
+        format: ExportFormat,
+    ) {
+        let Some(t) = self.tournaments.get(&tid) else {
+            return;
+        };
+        let text = match format {
+            ExportFormat::Text => t.crosstable(),
+            ExportFormat::Pgn => t.pgn(),
+        };
+        self.send_to(cid, ServerMessage::TournamentExport(tid, text))
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_core::Variant;
+
+    fn tournament(system: TournamentSystem, players: usize) -> Tournament {
+        let params = TournamentParams {
+            name: "Club Championship".to_string(),
+            system,
+            rounds: 3,
+            time: 300,
+            time_inc: 3,
+            variant: Variant::Standard,
+            rated: false,
+        };
+        let mut t = Tournament::new(1, 1, params);
+        for i in 0..players {
+            t.join(Entrant {
+                cid: i + 1,
+                identity: Identity::Guest(format!("token{}", i + 1)),
+                name: format!("player{}", i + 1),
+                rating: Some(2000 - i as u16 * 100),
+                withdrawn: false,
+            })
+            .unwrap();
+        }
+        t.start("2026.10.19".to_string()).unwrap();
+        t
+    }
+
+    /// Play the current round: the higher seed wins, except for draws on the given boards.
+    fn play_round(t: &mut Tournament, gid: &mut GameId, draws: &[usize]) {
+        for (board, white, black) in t.boards_to_play() {
+            *gid += 1;
+            t.set_game(board, *gid);
+            let points = if draws.contains(&board) {
+                0.5
+            } else if white < black {
+                1.0
+            } else {
+                0.0
+            };
+            let e4 = MoveRecord {
+                san: "e4".to_string(),
+                ..MoveRecord::default()
+            };
+            assert!(t.record_result(*gid, points, vec![e4]));
+        }
+    }
+
+    #[test]
+    fn test_round_robin() {
+        for n in 2..=7 {
+            let rounds = round_robin(n);
+            assert_eq!(rounds.len(), if n % 2 == 0 { n - 1 } else { n });
+            let mut met = vec![vec![0; n]; n];
+            let mut whites = vec![0i32; n];
+            for round in &rounds {
+                for &(white, black) in round {
+                    if let Some(black) = black {
+                        met[white][black] += 1;
+                        met[black][white] += 1;
+                        whites[white] += 1;
+                    }
+                }
+            }
+            for (a, row) in met.iter().enumerate() {
+                for (b, &count) in row.iter().enumerate() {
+                    assert_eq!(count, (a != b) as i32, "{} players", n);
+                }
+            }
+            let games = n as i32 - 1;
+            assert!(whites.iter().all(|&w| (w * 2 - games).abs() <= 2));
+        }
+    }
+
+    #[test]
+    fn test_swiss() {
+        let mut t = tournament(TournamentSystem::Swiss, 5);
+        let mut gid = 0;
+        let mut byes = vec![];
+        while !t.is_finished() {
+            t.pair_next_round().unwrap();
+            byes.push(t.bye().unwrap());
+            play_round(&mut t, &mut gid, &[]);
+        }
+        assert_eq!(t.rounds.len(), 3);
+        // no rematches, and nobody gets two byes
+        let mut boards: Vec<(usize, usize)> = t
+            .rounds
+            .iter()
+            .flatten()
+            .filter_map(|p| p.black.map(|b| (p.white.min(b), p.white.max(b))))
+            .collect();
+        let count = boards.len();
+        boards.sort();
+        boards.dedup();
+        assert_eq!(boards.len(), count);
+        byes.sort();
+        byes.dedup();
+        assert_eq!(byes.len(), 3);
+        // round 1: the upper half against the lower half, the lowest seed sits out
+        let first: Vec<(usize, Option<usize>)> =
+            t.rounds[0].iter().map(|p| (p.white, p.black)).collect();
+        assert_eq!(first.len(), 3);
+        assert!(first.contains(&(4, None)));
+        assert!(first
+            .iter()
+            .any(|&(w, b)| (w, b) == (0, Some(2)) || (w, b) == (2, Some(0))));
+        // the top seed won everything
+        assert_eq!(t.standings()[0].player, 1);
+        assert_eq!(t.standings()[0].score, 3.0);
+    }
+
+    #[test]
+    fn test_tiebreaks_and_export() {
+        let mut t = tournament(TournamentSystem::RoundRobin, 3);
+        let mut gid = 0;
+        t.pair_next_round().unwrap();
+        play_round(&mut t, &mut gid, &[0]);
+        t.pair_next_round().unwrap();
+        play_round(&mut t, &mut gid, &[]);
+        // player 3 leaves before their bye, which is then worth nothing
+        assert!(t.withdraw(3));
+        t.pair_next_round().unwrap();
+        assert_eq!(t.bye(), Some(3));
+        play_round(&mut t, &mut gid, &[]);
+        assert!(t.is_finished());
+
+        let standings = t.standings();
+        let total: f32 = standings.iter().map(|s| s.score).sum();
+        // three games and two byes
+        assert_eq!(total, 5.0);
+        for s in &standings {
+            assert!(s.sonneborn_berger <= s.buchholz);
+        }
+        let text = t.crosstable();
+        assert!(text.starts_with("Club Championship\nRound robin, 3 rounds, 5+3, Standard"));
+        assert!(text.contains("bye"));
+        let pgn = t.pgn();
+        assert!(pgn.contains("[Event \"Club Championship\"]"));
+        assert!(pgn.contains("[Date \"2026.10.19\"]"));
+        assert!(pgn.contains("1. e4 1/2-1/2"));
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let mut t = tournament(TournamentSystem::Swiss, 2);
+        let identity = Identity::Guest("token2".to_string());
+        // the same player from another connection is still the same player
+        let again = Entrant {
+            cid: 9,
+            identity: identity.clone(),
+            name: "player2".to_string(),
+            rating: None,
+            withdrawn: false,
+        };
+        t.started = false;
+        assert_eq!(t.join(again), Err(TournamentError::AlreadyJoined));
+        t.started = true;
+
+        assert!(t.reconnect(&identity, 9));
+        assert!(!t.reconnect(&Identity::Guest("token3".to_string()), 10));
+        assert_eq!(t.active_players(), vec![1, 9]);
+        t.pair_next_round().unwrap();
+        assert_eq!(t.boards_to_play()[0].2, 9);
+    }
+}
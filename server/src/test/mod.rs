@@ -5,22 +5,39 @@ const TEST_GAME_FILE: &str = "testgame2";
 
 #[cfg(test)]
 pub mod testgames {
+    use crate::chess::chess::Chess;
+    use crate::server::accounts::FREE_FAILED_LOGINS;
+    use crate::server::chessgame::{Identity, EMPTY_GAME_GRACE};
+    use crate::server::config::Config;
     use crate::server::server::Server;
+    use crate::server::store::{GameStore, SavedGame, Seat};
     use std::fs::File;
     use std::io::{self, BufRead};
     use std::time::Duration;
 
     use super::testclient::TestClient;
     use crate::test;
+    use chess_core::protocol::messages::ClientMessage;
     use chess_core::protocol::messages::ServerMessage;
-    use chess_core::protocol::UserRoleSelection;
+    use chess_core::protocol::parser::NetMessage;
+    use chess_core::protocol::{
+        ArenaParams, BughouseParams, ChallengeOutcome, ChallengeParams, ChatChannel,
+        ConsultationParams, Credentials, DeclineReason, PuzzleParams, RoomSource, SeekParams,
+        SimulParams, TournamentParams, TournamentSystem, UserRoleSelection, VotingMode,
+    };
+    use chess_core::states::GameOverReason;
+    use chess_core::{ChessColor, ChessPiece, Variant, Wdl, WoodPiece};
     use smol::Timer;
     use smol_macros::test;
 
     async fn start_server(port: u16) {
+        start_server_with_config(port, Config::read("server.cfg")).await;
+    }
+
+    async fn start_server_with_config(port: u16, config: Config) {
         smol::spawn(async move {
             let mut server = Server::new();
-            let _ = server.run(port).await;
+            let _ = server.run_with_config(port, &config).await;
         })
         .detach();
 
@@ -210,7 +227,7 @@ pub mod testgames {
             let game_id = client.create_game(1, 120, 5).await;
 
             let details = client.get_game_details(game_id).await;
-            if let ServerMessage::GameDetails(id, white, black, time, inc) = details {
+            if let ServerMessage::GameDetails(id, white, black, time, inc, ..) = details {
                 assert_eq!(id, game_id);
                 assert_eq!(time, 120);
                 assert_eq!(inc, 5);
@@ -251,4 +268,1119 @@ pub mod testgames {
             }
         }
     }
+
+    test! {
+        async fn test_seek_pairing() {
+            env_logger::try_init().ok();
+
+            let port = 7885;
+            start_server(port).await;
+
+            let mut white = TestClient::new(port).await;
+            let mut black = TestClient::new(port).await;
+            let params = SeekParams {
+                time: 300,
+                time_inc: 3,
+                variant: Variant::Standard,
+                rated: false,
+                rating_min: 0,
+                rating_max: u16::MAX,
+                color: UserRoleSelection::White,
+            };
+
+            let response = white.seek(params.clone()).await;
+            assert_eq!(response.opcode(), ServerMessage::SEEK_PENDING);
+
+            // another seek for white doesn't fit, one with the colour left open does
+            let mut other = TestClient::new(port).await;
+            let response = other.seek(params.clone()).await;
+            assert_eq!(response.opcode(), ServerMessage::SEEK_PENDING);
+            let random = SeekParams { color: UserRoleSelection::Random, ..params };
+            let gid = match black.seek(random).await {
+                ServerMessage::GameJoined(gid, _, UserRoleSelection::Black) => gid,
+                e => panic!("Expected to join as black, got {:?}", e),
+            };
+            match white.wait_for_seek().await {
+                ServerMessage::GameJoined(id, _, UserRoleSelection::White) => assert_eq!(id, gid),
+                e => panic!("Expected to join as white, got {:?}", e),
+            }
+            // the opponent joining
+            let response = white.wait_for_seek().await;
+            assert_eq!(response.opcode(), ServerMessage::GAME_JOINED);
+
+            let response = white.make_move(gid, "e2e4").await;
+            assert_eq!(response.opcode(), ServerMessage::MOVE_ACCEPTED);
+        }
+    }
+
+    test! {
+        async fn test_challenge() {
+            env_logger::try_init().ok();
+
+            let port = 7886;
+            start_server(port).await;
+
+            let mut alice = TestClient::new(port).await;
+            let mut bob = TestClient::new(port).await;
+            let params = ChallengeParams {
+                time: 600,
+                time_inc: 5,
+                variant: Variant::Standard,
+                rated: false,
+                color: UserRoleSelection::Black,
+            };
+            let challenge = ClientMessage::Challenge(bob.id, params);
+
+            // the challenge reaches bob without him asking for anything
+            alice.conn.write_out(&challenge.to_bytes()).await.unwrap();
+            let id = match bob.read_until(ServerMessage::CHALLENGE_ISSUED).await {
+                ServerMessage::ChallengeIssued(id, from, to, _) => {
+                    assert_eq!((from, to), (alice.id, bob.id));
+                    id
+                }
+                e => panic!("Expected a challenge, got {:?}", e),
+            };
+            let decline = ClientMessage::DeclineChallenge(id, DeclineReason::Later);
+            bob.conn.write_out(&decline.to_bytes()).await.unwrap();
+            match alice.read_until(ServerMessage::CHALLENGE_CLOSED).await {
+                ServerMessage::ChallengeClosed(closed, outcome) => {
+                    assert_eq!(closed, id);
+                    assert_eq!(outcome, ChallengeOutcome::Declined(DeclineReason::Later));
+                }
+                e => panic!("Expected the challenge to be declined, got {:?}", e),
+            }
+
+            // once more, and this time bob takes it; alice asked for black
+            alice.conn.write_out(&challenge.to_bytes()).await.unwrap();
+            let id = match bob.read_until(ServerMessage::CHALLENGE_ISSUED).await {
+                ServerMessage::ChallengeIssued(id, ..) => id,
+                e => panic!("Expected a challenge, got {:?}", e),
+            };
+            let accept = ClientMessage::AcceptChallenge(id);
+            bob.conn.write_out(&accept.to_bytes()).await.unwrap();
+            let gid = match bob.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(gid, cid, side) => {
+                    assert_eq!((cid, side), (bob.id, UserRoleSelection::White));
+                    gid
+                }
+                e => panic!("Expected to join the game, got {:?}", e),
+            };
+            // then alice joins as black
+            match alice.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(id, cid, side) => {
+                    assert_eq!((id, cid, side), (gid, alice.id, UserRoleSelection::Black));
+                }
+                e => panic!("Expected to join the game, got {:?}", e),
+            }
+            bob.read_until(ServerMessage::GAME_JOINED).await;
+
+            let response = bob.make_move(gid, "e2e4").await;
+            assert_eq!(response.opcode(), ServerMessage::MOVE_ACCEPTED);
+        }
+    }
+
+    test! {
+        async fn test_chat() {
+            env_logger::try_init().ok();
+
+            let port = 7887;
+            start_server(port).await;
+
+            let mut white = TestClient::new(port).await;
+            let mut black = TestClient::new(port).await;
+            let mut spectator = TestClient::new(port).await;
+            let gid = white.create_game(1, 300, 0).await;
+            white.join_game(gid, UserRoleSelection::White).await;
+            black.join_game(gid, UserRoleSelection::Black).await;
+            spectator.join_game(gid, UserRoleSelection::Spectator).await;
+
+            let kibitz = ClientMessage::Chat(ChatChannel::Kibitz(gid), "nice opening".to_string());
+            spectator.conn.write_out(&kibitz.to_bytes()).await.unwrap();
+            let hello = ClientMessage::Chat(ChatChannel::Game(gid), " good luck ".to_string());
+            white.conn.write_out(&hello.to_bytes()).await.unwrap();
+
+            // black hears white, but not the spectator
+            match black.read_until(ServerMessage::CHAT).await {
+                ServerMessage::Chat(channel, cid, _, text) => {
+                    assert_eq!((channel, cid, text.as_str()), (ChatChannel::Game(gid), white.id, "good luck"));
+                }
+                e => panic!("Expected a chat line, got {:?}", e),
+            }
+
+            // spectators can't talk to the players
+            let rude = ClientMessage::Chat(ChatChannel::Game(gid), "blunder!".to_string());
+            spectator.conn.write_out(&rude.to_bytes()).await.unwrap();
+            let response = spectator.read_until(ServerMessage::CHAT_REJECTED).await;
+            assert_eq!(response.opcode(), ServerMessage::CHAT_REJECTED);
+
+            // a late spectator gets to read the kibitz
+            let mut late = TestClient::new(port).await;
+            late.join_game(gid, UserRoleSelection::Spectator).await;
+            match late.read_until(ServerMessage::CHAT).await {
+                ServerMessage::Chat(channel, _, _, text) => {
+                    assert_eq!((channel, text.as_str()), (ChatChannel::Kibitz(gid), "nice opening"));
+                }
+                e => panic!("Expected the kibitz, got {:?}", e),
+            }
+        }
+    }
+
+    test! {
+        async fn test_takeback() {
+            env_logger::try_init().ok();
+
+            let port = 7888;
+            start_server(port).await;
+
+            let mut white = TestClient::new(port).await;
+            let mut black = TestClient::new(port).await;
+            let gid = white.create_game(1, 300, 0).await;
+            white.join_game(gid, UserRoleSelection::White).await;
+            black.join_game(gid, UserRoleSelection::Black).await;
+
+            white.make_move(gid, "e2e4").await;
+            black.read_until(ServerMessage::MOVE_ACCEPTED).await;
+            black.make_move(gid, "e7e5").await;
+            white.read_until(ServerMessage::MOVE_ACCEPTED).await;
+
+            // white wants the last move back, black agrees
+            let request = ClientMessage::RequestTakeback(gid, 1);
+            white.conn.write_out(&request.to_bytes()).await.unwrap();
+            match black.read_until(ServerMessage::TAKEBACK_OFFERED).await {
+                ServerMessage::TakebackOffered(id, plies) => assert_eq!((id, plies), (gid, 1)),
+                e => panic!("Expected a takeback offer, got {:?}", e),
+            }
+            let answer = ClientMessage::AnswerTakeback(gid, true);
+            black.conn.write_out(&answer.to_bytes()).await.unwrap();
+            for client in [&mut white, &mut black] {
+                match client.read_until(ServerMessage::MOVE_HISTORY).await {
+                    ServerMessage::MoveHistory(id, moves) => {
+                        assert_eq!(id, gid);
+                        let sans: Vec<&str> = moves.iter().map(|r| r.san.as_str()).collect();
+                        assert_eq!(sans, vec!["e4"]);
+                        assert_eq!(moves[0].mov, Some("e2e4".parse().unwrap()));
+                        assert_eq!(moves[0].color(), ChessColor::White);
+                        assert!(moves[0].fen.starts_with("rnbqkbnr/pppppppp/8/8/4P3/8/"));
+                        assert!(moves[0].timestamp > 0);
+                        assert!(moves[0].clock.is_some_and(|c| c <= 300_000));
+                    }
+                    e => panic!("Expected the move history, got {:?}", e),
+                }
+            }
+
+            // it's black's move again
+            let response = black.make_move(gid, "c7c5").await;
+            assert_eq!(response.opcode(), ServerMessage::MOVE_ACCEPTED);
+        }
+    }
+
+    test! {
+        async fn test_abort() {
+            env_logger::try_init().ok();
+
+            let port = 7889;
+            start_server(port).await;
+
+            let mut white = TestClient::new(port).await;
+            let mut black = TestClient::new(port).await;
+            let gid = white.create_game(1, 300, 0).await;
+            white.join_game(gid, UserRoleSelection::White).await;
+            black.join_game(gid, UserRoleSelection::Black).await;
+            white.make_move(gid, "e2e4").await;
+            black.read_until(ServerMessage::MOVE_ACCEPTED).await;
+
+            // black hasn't moved yet, so the game can still go without a result
+            let abort = ClientMessage::Abort(gid);
+            black.conn.write_out(&abort.to_bytes()).await.unwrap();
+            match white.read_until(ServerMessage::GAME_ABORTED).await {
+                ServerMessage::GameAborted(id) => assert_eq!(id, gid),
+                e => panic!("Expected the game to be aborted, got {:?}", e),
+            }
+            assert!(!white.list_games().await.contains(&gid));
+        }
+    }
+
+    test! {
+        async fn test_rematch() {
+            env_logger::try_init().ok();
+
+            let port = 7890;
+            start_server(port).await;
+
+            let mut alice = TestClient::new(port).await;
+            let mut bob = TestClient::new(port).await;
+            let gid = alice.create_game(1, 300, 0).await;
+            alice.join_game(gid, UserRoleSelection::White).await;
+            bob.join_game(gid, UserRoleSelection::Black).await;
+            alice.make_move(gid, "e2e4").await;
+
+            let resign = ClientMessage::Resign(gid);
+            bob.conn.write_out(&resign.to_bytes()).await.unwrap();
+            alice.read_until(ServerMessage::GAME_OVER).await;
+
+            let rematch = ClientMessage::Rematch(gid);
+            alice.conn.write_out(&rematch.to_bytes()).await.unwrap();
+            match bob.read_until(ServerMessage::REMATCH_OFFERED).await {
+                ServerMessage::RematchOffered(id, cid) => assert_eq!((id, cid), (gid, alice.id)),
+                e => panic!("Expected a rematch offer, got {:?}", e),
+            }
+
+            // bob agrees: same game, colours swapped
+            bob.conn.write_out(&rematch.to_bytes()).await.unwrap();
+            let new_gid = match bob.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(id, cid, side) => {
+                    assert_eq!((cid, side), (bob.id, UserRoleSelection::White));
+                    id
+                }
+                e => panic!("Expected to join the rematch, got {:?}", e),
+            };
+            assert_ne!(new_gid, gid);
+            match alice.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(id, cid, side) => {
+                    assert_eq!((id, cid, side), (new_gid, alice.id, UserRoleSelection::Black));
+                }
+                e => panic!("Expected to join the rematch, got {:?}", e),
+            }
+            bob.read_until(ServerMessage::GAME_JOINED).await;
+
+            let response = bob.make_move(new_gid, "d2d4").await;
+            assert_eq!(response.opcode(), ServerMessage::MOVE_ACCEPTED);
+        }
+    }
+
+    test! {
+        async fn test_tournament() {
+            env_logger::try_init().ok();
+
+            let port = 7891;
+            start_server(port).await;
+
+            let mut alice = TestClient::new(port).await;
+            let mut bob = TestClient::new(port).await;
+            let params = TournamentParams {
+                name: "Club championship".to_string(),
+                system: TournamentSystem::RoundRobin,
+                rounds: 0,
+                time: 300,
+                time_inc: 0,
+                variant: Variant::Standard,
+                rated: false,
+            };
+            let create = ClientMessage::CreateTournament(params);
+            alice.conn.write_out(&create.to_bytes()).await.unwrap();
+            let tid = match bob.read_until(ServerMessage::TOURNAMENT_CREATED).await {
+                ServerMessage::TournamentCreated(tid, organizer, _) => {
+                    assert_eq!(organizer, alice.id);
+                    tid
+                }
+                e => panic!("Expected a new tournament, got {:?}", e),
+            };
+
+            let join = ClientMessage::JoinTournament(tid);
+            alice.conn.write_out(&join.to_bytes()).await.unwrap();
+            bob.conn.write_out(&join.to_bytes()).await.unwrap();
+            loop {
+                match alice.read_until(ServerMessage::TOURNAMENT_PLAYERS).await {
+                    ServerMessage::TournamentPlayers(_, players) if players.len() == 2 => break,
+                    ServerMessage::TournamentPlayers(..) => continue,
+                    e => panic!("Expected the players, got {:?}", e),
+                }
+            }
+
+            // two players: a single round, with one game
+            let start = ClientMessage::StartTournament(tid);
+            alice.conn.write_out(&start.to_bytes()).await.unwrap();
+            match bob.read_until(ServerMessage::TOURNAMENT_ROUND).await {
+                ServerMessage::TournamentRound(id, round, bye) => {
+                    assert_eq!((id, round, bye), (tid, 1, None));
+                }
+                e => panic!("Expected the first round, got {:?}", e),
+            }
+            let gid = match bob.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(gid, ..) => gid,
+                e => panic!("Expected to join the game, got {:?}", e),
+            };
+
+            let resign = ClientMessage::Resign(gid);
+            bob.conn.write_out(&resign.to_bytes()).await.unwrap();
+            let winner = alice.id;
+            for client in [&mut alice, &mut bob] {
+                match client.read_until(ServerMessage::STANDINGS).await {
+                    ServerMessage::Standings(id, rounds, finished, standings) => {
+                        assert_eq!((id, rounds, finished), (tid, 1, true));
+                        assert_eq!(standings[0].player, winner);
+                        assert_eq!((standings[0].score, standings[1].score), (1.0, 0.0));
+                    }
+                    e => panic!("Expected the final standings, got {:?}", e),
+                }
+            }
+        }
+    }
+
+    test! {
+        async fn test_arena() {
+            env_logger::try_init().ok();
+
+            let port = 7892;
+            start_server(port).await;
+
+            let mut alice = TestClient::new(port).await;
+            let mut bob = TestClient::new(port).await;
+            let params = ArenaParams {
+                name: "Hourly blitz".to_string(),
+                minutes: 60,
+                time: 180,
+                time_inc: 0,
+                variant: Variant::Standard,
+                rated: false,
+            };
+            let create = ClientMessage::CreateArena(params);
+            alice.conn.write_out(&create.to_bytes()).await.unwrap();
+            let tid = match bob.read_until(ServerMessage::ARENA_CREATED).await {
+                ServerMessage::ArenaCreated(tid, ..) => tid,
+                e => panic!("Expected a new arena, got {:?}", e),
+            };
+
+            let join = ClientMessage::JoinArena(tid);
+            alice.conn.write_out(&join.to_bytes()).await.unwrap();
+            alice.read_until(ServerMessage::LEADERBOARD).await;
+            bob.conn.write_out(&join.to_bytes()).await.unwrap();
+            let gid = match bob.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(gid, ..) => gid,
+                e => panic!("Expected to join the game, got {:?}", e),
+            };
+
+            // as soon as the game is over, the next one starts
+            let resign = ClientMessage::Resign(gid);
+            bob.conn.write_out(&resign.to_bytes()).await.unwrap();
+            loop {
+                match alice.read_until(ServerMessage::LEADERBOARD).await {
+                    ServerMessage::Leaderboard(_, _, standings) if standings[0].games == 0 => {
+                        continue
+                    }
+                    ServerMessage::Leaderboard(id, seconds_left, standings) => {
+                        assert_eq!(id, tid);
+                        assert!(seconds_left > 0);
+                        assert_eq!((standings[0].player, standings[0].score), (alice.id, 2));
+                        assert_eq!((standings[1].score, standings[1].games), (0, 1));
+                        break;
+                    }
+                    e => panic!("Expected the leaderboard, got {:?}", e),
+                }
+            }
+            match alice.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(id, ..) => assert_ne!(id, gid),
+                e => panic!("Expected the next game, got {:?}", e),
+            }
+        }
+    }
+
+    test! {
+        async fn test_simul() {
+            env_logger::try_init().ok();
+
+            let port = 7893;
+            start_server(port).await;
+
+            let mut host = TestClient::new(port).await;
+            let mut bob = TestClient::new(port).await;
+            let mut carol = TestClient::new(port).await;
+            let params = SimulParams {
+                name: "Exhibition".to_string(),
+                host_white: true,
+                boards: 0,
+                time: 0,
+                time_inc: 0,
+                variant: Variant::Standard,
+            };
+            let create = ClientMessage::CreateSimul(params);
+            host.conn.write_out(&create.to_bytes()).await.unwrap();
+            let sid = match bob.read_until(ServerMessage::SIMUL_CREATED).await {
+                ServerMessage::SimulCreated(sid, cid, _) => {
+                    assert_eq!(cid, host.id);
+                    sid
+                }
+                e => panic!("Expected a new simul, got {:?}", e),
+            };
+            let join = ClientMessage::JoinSimul(sid);
+            bob.conn.write_out(&join.to_bytes()).await.unwrap();
+            carol.conn.write_out(&join.to_bytes()).await.unwrap();
+            loop {
+                match host.read_until(ServerMessage::SIMUL_PLAYERS).await {
+                    ServerMessage::SimulPlayers(_, players) if players.len() == 2 => break,
+                    ServerMessage::SimulPlayers(..) => continue,
+                    e => panic!("Expected the opponents, got {:?}", e),
+                }
+            }
+
+            // a board per opponent, the host plays white everywhere
+            let start = ClientMessage::StartSimul(sid);
+            host.conn.write_out(&start.to_bytes()).await.unwrap();
+            let boards = match host.read_until(ServerMessage::SIMUL_BOARDS).await {
+                ServerMessage::SimulBoards(_, boards) => boards,
+                e => panic!("Expected the boards, got {:?}", e),
+            };
+            assert_eq!(boards.len(), 2);
+            assert!(boards.iter().all(|b| b.host_to_move && b.result.is_none()));
+            let gid = boards.iter().find(|b| b.opponent == bob.id).unwrap().game;
+
+            let response = host.make_move(gid, "e2e4").await;
+            assert_eq!(response.opcode(), ServerMessage::MOVE_ACCEPTED);
+            match host.read_until(ServerMessage::SIMUL_BOARDS).await {
+                ServerMessage::SimulBoards(_, boards) => {
+                    for board in boards {
+                        assert_eq!(board.host_to_move, board.game != gid);
+                    }
+                }
+                e => panic!("Expected the boards, got {:?}", e),
+            }
+
+            let resign = ClientMessage::Resign(gid);
+            bob.conn.write_out(&resign.to_bytes()).await.unwrap();
+            match host.read_until(ServerMessage::SIMUL_BOARDS).await {
+                ServerMessage::SimulBoards(_, boards) => {
+                    let board = boards.iter().find(|b| b.game == gid).unwrap();
+                    assert_eq!(board.result, Some(1.0));
+                }
+                e => panic!("Expected the boards, got {:?}", e),
+            }
+        }
+    }
+
+    test! {
+        async fn test_bughouse() {
+            env_logger::try_init().ok();
+
+            let port = 7894;
+            start_server(port).await;
+
+            // seats 0 and 3 are a team: white on the first board, black on the second
+            let mut clients = vec![];
+            for _ in 0..4 {
+                clients.push(TestClient::new(port).await);
+            }
+            let ids: Vec<_> = clients.iter().map(|c| c.id).collect();
+            let params = BughouseParams {
+                time: 300,
+                time_inc: 0,
+            };
+            let create = ClientMessage::CreateBughouse(params);
+            clients[0].conn.write_out(&create.to_bytes()).await.unwrap();
+            let bid = match clients[3].read_until(ServerMessage::BUGHOUSE_CREATED).await {
+                ServerMessage::BughouseCreated(bid, cid, _) => {
+                    assert_eq!(cid, ids[0]);
+                    bid
+                }
+                e => panic!("Expected a new match, got {:?}", e),
+            };
+            for (seat, client) in clients.iter_mut().enumerate() {
+                let join = ClientMessage::JoinBughouse(bid, seat as u8);
+                client.conn.write_out(&join.to_bytes()).await.unwrap();
+                client.read_until(ServerMessage::BUGHOUSE_SEATS).await;
+            }
+            let mut boards = vec![];
+            for client in clients.iter_mut() {
+                match client.read_until(ServerMessage::BUGHOUSE_STATE).await {
+                    ServerMessage::BughouseState(id, None, state) => {
+                        assert_eq!(id, bid);
+                        assert_eq!((state[0].white, state[1].black), (ids[0], ids[3]));
+                        assert!(state.iter().all(|b| b.pocket.is_empty()));
+                        boards = state.iter().map(|b| b.game).collect();
+                    }
+                    e => panic!("Expected the boards, got {:?}", e),
+                }
+            }
+
+            // a pawn taken on the first board goes to the pocket of the second
+            for (seat, mov) in [(0, "e2e4"), (1, "d7d5"), (0, "e4d5"), (2, "e2e4")] {
+                let board = seat / 2;
+                let cmd = ClientMessage::Move(boards[board], mov.parse().unwrap());
+                clients[seat].conn.write_out(&cmd.to_bytes()).await.unwrap();
+                for client in &mut clients[2 * board..2 * board + 2] {
+                    client.read_until(ServerMessage::MOVE_ACCEPTED).await;
+                }
+            }
+            let pawn = WoodPiece::new(ChessPiece::Pawn, ChessColor::Black);
+            loop {
+                match clients[3].read_until(ServerMessage::BUGHOUSE_STATE).await {
+                    ServerMessage::BughouseState(_, _, state) if state[1].fen.contains(" b ") => {
+                        assert!(state[0].pocket.is_empty());
+                        assert_eq!(state[1].pocket, vec![pawn]);
+                        break;
+                    }
+                    ServerMessage::BughouseState(..) => continue,
+                    e => panic!("Expected the boards, got {:?}", e),
+                }
+            }
+            let drop = ClientMessage::Drop(boards[1], "P@d6".parse().unwrap());
+            clients[3].conn.write_out(&drop.to_bytes()).await.unwrap();
+            match clients[3].read_until(ServerMessage::MOVE_ACCEPTED).await {
+                ServerMessage::MoveAccepted(_, _, san, changes) => {
+                    assert_eq!(san, "P@d6");
+                    assert_eq!(changes.len(), 1);
+                }
+                e => panic!("Expected the drop, got {:?}", e),
+            }
+
+            // black resigns on the first board, which ends the second too
+            let resign = ClientMessage::Resign(boards[0]);
+            clients[1].conn.write_out(&resign.to_bytes()).await.unwrap();
+            match clients[3].read_until(ServerMessage::GAME_OVER).await {
+                ServerMessage::GameOver(gid, reason) => {
+                    assert_eq!(gid, boards[1]);
+                    let winner = Some(ChessColor::Black);
+                    assert_eq!(reason, GameOverReason::Adjudication(winner));
+                }
+                e => panic!("Expected the end of the game, got {:?}", e),
+            }
+            match clients[3].read_until(ServerMessage::BUGHOUSE_STATE).await {
+                ServerMessage::BughouseState(_, result, _) => assert_eq!(result, Some(1.0)),
+                e => panic!("Expected the result, got {:?}", e),
+            }
+        }
+    }
+
+    test! {
+        async fn test_consultation() {
+            env_logger::try_init().ok();
+
+            let port = 7895;
+            start_server(port).await;
+
+            let mut alice = TestClient::new(port).await;
+            let mut bob = TestClient::new(port).await;
+            let mut carol = TestClient::new(port).await;
+            let params = ConsultationParams {
+                time: 0,
+                time_inc: 0,
+                mode: VotingMode::Majority,
+                vote_time: 0,
+            };
+            let create = ClientMessage::CreateConsultation(params);
+            alice.conn.write_out(&create.to_bytes()).await.unwrap();
+            let gid = match carol.read_until(ServerMessage::GAME_CREATED).await {
+                ServerMessage::GameCreated(gid, _) => gid,
+                e => panic!("Expected a new game, got {:?}", e),
+            };
+
+            // alice and bob play white together, alice is the captain
+            let white = ClientMessage::JoinTeam(gid, ChessColor::White);
+            let black = ClientMessage::JoinTeam(gid, ChessColor::Black);
+            alice.conn.write_out(&white.to_bytes()).await.unwrap();
+            alice.read_until(ServerMessage::GAME_JOINED).await;
+            bob.conn.write_out(&white.to_bytes()).await.unwrap();
+            carol.conn.write_out(&black.to_bytes()).await.unwrap();
+            loop {
+                match carol.read_until(ServerMessage::TEAMS).await {
+                    ServerMessage::Teams(_, mode, white, black) if black.len() == 1 => {
+                        assert_eq!(mode, VotingMode::Majority);
+                        assert_eq!((white, black), (vec![alice.id, bob.id], vec![carol.id]));
+                        break;
+                    }
+                    ServerMessage::Teams(..) => continue,
+                    e => panic!("Expected the teams, got {:?}", e),
+                }
+            }
+
+            // one of two isn't a majority yet, but the team sees the proposal
+            let e4 = ClientMessage::Move(gid, "e2e4".parse().unwrap());
+            alice.conn.write_out(&e4.to_bytes()).await.unwrap();
+            loop {
+                match bob.read_until(ServerMessage::PROPOSALS).await {
+                    ServerMessage::Proposals(_, proposals) if proposals.is_empty() => continue,
+                    ServerMessage::Proposals(id, proposals) => {
+                        assert_eq!(id, gid);
+                        assert_eq!(proposals, vec![(alice.id, "e4".to_string())]);
+                        break;
+                    }
+                    e => panic!("Expected the proposals, got {:?}", e),
+                }
+            }
+            let early = ClientMessage::Move(gid, "e7e5".parse().unwrap());
+            carol.conn.write_out(&early.to_bytes()).await.unwrap();
+            let response = carol.read_until(ServerMessage::ILLEGAL_MOVE).await;
+            assert_eq!(response.opcode(), ServerMessage::ILLEGAL_MOVE);
+
+            bob.conn.write_out(&e4.to_bytes()).await.unwrap();
+            match carol.read_until(ServerMessage::MOVE_ACCEPTED).await {
+                ServerMessage::MoveAccepted(id, _, san, _) => {
+                    assert_eq!((id, san.as_str()), (gid, "e4"));
+                }
+                e => panic!("Expected the move, got {:?}", e),
+            }
+
+            // the team talks in private
+            let team = ChatChannel::Team(gid, ChessColor::White);
+            let plan = ClientMessage::Chat(team, "d4 next".to_string());
+            bob.conn.write_out(&plan.to_bytes()).await.unwrap();
+            match alice.read_until(ServerMessage::CHAT).await {
+                ServerMessage::Chat(channel, cid, _, text) => {
+                    assert_eq!((channel, cid, text.as_str()), (team, bob.id, "d4 next"));
+                }
+                e => panic!("Expected a chat line, got {:?}", e),
+            }
+            carol.conn.write_out(&plan.to_bytes()).await.unwrap();
+            let response = carol.read_until(ServerMessage::CHAT_REJECTED).await;
+            assert_eq!(response.opcode(), ServerMessage::CHAT_REJECTED);
+        }
+    }
+
+    test! {
+        async fn test_analysis_room() {
+            env_logger::try_init().ok();
+
+            let port = 7896;
+            start_server(port).await;
+
+            let mut alice = TestClient::new(port).await;
+            let mut bob = TestClient::new(port).await;
+
+            // Fool's Mate, to analyse afterwards
+            let gid = alice.create_game(1, 120, 0).await;
+            alice.join_game(gid, UserRoleSelection::Both).await;
+            for mov in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+                alice.make_move(gid, mov).await;
+            }
+
+            let create = ClientMessage::CreateRoom(RoomSource::Game(gid));
+            alice.conn.write_out(&create.to_bytes()).await.unwrap();
+            let rid = match bob.read_until(ServerMessage::ROOM_CREATED).await {
+                ServerMessage::RoomCreated(rid, cid) => {
+                    assert_eq!(cid, alice.id);
+                    rid
+                }
+                e => panic!("Expected a new room, got {:?}", e),
+            };
+            match alice.read_until(ServerMessage::ROOM_STATE).await {
+                ServerMessage::RoomState(id, tree) => {
+                    assert_eq!(id, rid);
+                    assert_eq!(tree.line(), vec!["f3", "e5", "g4", "Qh4#"]);
+                }
+                e => panic!("Expected the room, got {:?}", e),
+            }
+            let join = ClientMessage::JoinRoom(rid);
+            bob.conn.write_out(&join.to_bytes()).await.unwrap();
+            bob.read_until(ServerMessage::ROOM_STATE).await;
+
+            // bob goes back and tries another move; alice sees the variation
+            let back = ClientMessage::RoomGoTo(rid, 2);
+            bob.conn.write_out(&back.to_bytes()).await.unwrap();
+            let nc3 = ClientMessage::RoomMove(rid, "b1c3".parse().unwrap());
+            bob.conn.write_out(&nc3.to_bytes()).await.unwrap();
+            loop {
+                match alice.read_until(ServerMessage::ROOM_STATE).await {
+                    ServerMessage::RoomState(_, tree) if tree.current == 2 => continue,
+                    ServerMessage::RoomState(_, tree) => {
+                        assert_eq!(tree.line(), vec!["f3", "e5", "Nc3"]);
+                        assert_eq!(tree.children(2), vec![3, 5]);
+                        break;
+                    }
+                    e => panic!("Expected the room, got {:?}", e),
+                }
+            }
+            let illegal = ClientMessage::RoomMove(rid, "c3c5".parse().unwrap());
+            bob.conn.write_out(&illegal.to_bytes()).await.unwrap();
+            let response = bob.read_until(ServerMessage::ILLEGAL_MOVE).await;
+            assert_eq!(response.opcode(), ServerMessage::ILLEGAL_MOVE);
+
+            // a position that can't come up in a game, with the engine on
+            let fen = "4k2P/8/8/8/8/8/8/QQQQKQQQ w - - 0 1";
+            let setup = ClientMessage::RoomSetup(rid, fen.to_string());
+            alice.conn.write_out(&setup.to_bytes()).await.unwrap();
+            let engine = ClientMessage::RoomEngine(rid, true);
+            alice.conn.write_out(&engine.to_bytes()).await.unwrap();
+            match bob.read_until(ServerMessage::ROOM_ANALYSIS).await {
+                ServerMessage::RoomAnalysis(id, node, _, _, pv) => {
+                    assert_eq!((id, node), (rid, 0));
+                    assert!(!pv.is_empty());
+                }
+                e => panic!("Expected the analysis, got {:?}", e),
+            }
+
+            let leave = ClientMessage::LeaveRoom(rid);
+            bob.conn.write_out(&leave.to_bytes()).await.unwrap();
+            loop {
+                match alice.read_until(ServerMessage::ROOM_MEMBERS).await {
+                    ServerMessage::RoomMembers(_, members) if members.len() == 2 => continue,
+                    ServerMessage::RoomMembers(_, members) => {
+                        assert_eq!(members, vec![alice.id]);
+                        break;
+                    }
+                    e => panic!("Expected the members, got {:?}", e),
+                }
+            }
+        }
+    }
+
+    test! {
+        async fn test_play_against_computer() {
+            env_logger::try_init().ok();
+
+            let port = 7884;
+            start_server(port).await;
+
+            let mut client = TestClient::new(port).await;
+
+            let game_id = client.create_game(1, 120, 0).await;
+            client.join_game(game_id, UserRoleSelection::White).await;
+            client.add_computer(game_id, UserRoleSelection::Black, 1).await;
+
+            let response = client.make_move(game_id, "e2e4").await;
+            assert_eq!(response.opcode(), ServerMessage::MOVE_ACCEPTED);
+
+            // the computer answers on its own
+            match client.conn.read_msg::<ServerMessage>().await {
+                Ok(ServerMessage::MoveAccepted(_, _, san, _)) => log::info!("computer played {}", san),
+                Ok(e) => panic!("Expected the computer's move, got {:?}", e),
+                Err(e) => panic!("Error reading computer move: {:?}", e),
+            }
+
+            // and it's our turn again
+            let response = client.make_move(game_id, "d2d4").await;
+            assert_ne!(response.opcode(), ServerMessage::ILLEGAL_MOVE);
+        }
+    }
+
+    test! {
+        async fn test_no_puzzles() {
+            env_logger::try_init().ok();
+
+            // the test server has no puzzle file
+            let port = 7897;
+            start_server(port).await;
+
+            let mut client = TestClient::new(port).await;
+            let request = ClientMessage::RequestPuzzle(PuzzleParams {
+                theme: "fork".to_string(),
+                ..Default::default()
+            });
+            client.conn.write_out(&request.to_bytes()).await.unwrap();
+            match client.read_until(ServerMessage::NO_PUZZLE).await {
+                ServerMessage::NoPuzzle(reason) => log::info!("no puzzle: {}", reason),
+                e => panic!("Expected no puzzle, got {:?}", e),
+            }
+        }
+    }
+
+    test! {
+        async fn test_guest_reconnect() {
+            env_logger::try_init().ok();
+
+            let port = 7898;
+            start_server(port).await;
+
+            let mut white = TestClient::new(port).await;
+            let mut black = TestClient::new(port).await;
+            let nick = ClientMessage::SetNickname("alice".to_string());
+            white.conn.write_out(&nick.to_bytes()).await.unwrap();
+            let gid = white.create_game(1, 300, 0).await;
+            white.join_game(gid, UserRoleSelection::White).await;
+            black.join_game(gid, UserRoleSelection::Black).await;
+            let token = white.token.clone();
+            drop(white);
+            black.read_until(ServerMessage::GAME_LEFT).await;
+
+            // the same nickname, or a made up token, doesn't get the seat
+            let mut impostor = TestClient::new(port).await;
+            impostor.conn.write_out(&nick.to_bytes()).await.unwrap();
+            let guess = ClientMessage::Reconnect("0123456789abcdef".to_string());
+            impostor.conn.write_out(&guess.to_bytes()).await.unwrap();
+            impostor.conn.write_out(&ClientMessage::QueryGames.to_bytes()).await.unwrap();
+            loop {
+                match impostor.conn.read_msg::<ServerMessage>().await {
+                    Ok(ServerMessage::GamesList(_)) => break,
+                    Ok(ServerMessage::GameJoined(id, cid, side)) => {
+                        panic!("{} got seat {} in game {}", cid, side, id)
+                    }
+                    Ok(_) => {}
+                    Err(e) => panic!("Error reading games list: {:?}", e),
+                }
+            }
+
+            // the token of the lost connection does
+            let mut returning = TestClient::new(port).await;
+            let reconnect = ClientMessage::Reconnect(token.clone());
+            returning.conn.write_out(&reconnect.to_bytes()).await.unwrap();
+            match returning.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(id, cid, side) => {
+                    assert_eq!((id, cid, side), (gid, returning.id, UserRoleSelection::White))
+                }
+                e => panic!("Expected the seat back, got {:?}", e),
+            }
+            match returning.read_until(ServerMessage::RECONNECT_TOKEN).await {
+                ServerMessage::ReconnectToken(kept) => assert_eq!(kept, token),
+                e => panic!("Expected the token, got {:?}", e),
+            }
+            let response = returning.make_move(gid, "e2e4").await;
+            assert_eq!(response.opcode(), ServerMessage::MOVE_ACCEPTED);
+        }
+    }
+
+    test! {
+        async fn test_restored_game() {
+            env_logger::try_init().ok();
+
+            // a game that was running when the server stopped, the seats kept for both guests
+            let dir = std::env::temp_dir().join("chess-test-store-7899");
+            let _ = std::fs::remove_dir_all(&dir);
+            let dir = dir.to_str().unwrap().to_string();
+            let saved = SavedGame {
+                id: 1,
+                start_fen: Chess::new().get_fen(),
+                white: Some(Seat::Player(Identity::guest("white-token"))),
+                black: Some(Seat::Player(Identity::guest("black-token"))),
+                time: 600,
+                ..Default::default()
+            };
+            GameStore::open(&dir).unwrap().save(&saved).await.unwrap();
+
+            let port = 7899;
+            let mut config = Config::read("server.cfg");
+            config.game_store = Some(dir.clone());
+            start_server_with_config(port, config).await;
+
+            let mut white = TestClient::new(port).await;
+            let mut black = TestClient::new(port).await;
+            for (client, token) in [(&mut white, "white-token"), (&mut black, "black-token")] {
+                let reconnect = ClientMessage::Reconnect(token.to_string());
+                client.conn.write_out(&reconnect.to_bytes()).await.unwrap();
+                client.read_until(ServerMessage::GAME_JOINED).await;
+            }
+
+            // nobody was in the game at the start, but it's not an empty game
+            Timer::after(EMPTY_GAME_GRACE + Duration::from_secs(2)).await;
+            assert!(white.list_games().await.contains(&1));
+            let mov = ClientMessage::Move(1, "e2e4".parse().unwrap());
+            white.conn.write_out(&mov.to_bytes()).await.unwrap();
+            white.read_until(ServerMessage::MOVE_ACCEPTED).await;
+            assert!(std::path::Path::new(&dir).join("1.game").exists());
+        }
+    }
+
+    test! {
+        async fn test_tournament_reconnect() {
+            env_logger::try_init().ok();
+
+            let port = 7900;
+            start_server(port).await;
+
+            let mut alice = TestClient::new(port).await;
+            let mut bob = TestClient::new(port).await;
+            let params = TournamentParams {
+                name: "Club championship".to_string(),
+                system: TournamentSystem::Swiss,
+                rounds: 1,
+                time: 300,
+                time_inc: 0,
+                variant: Variant::Standard,
+                rated: false,
+            };
+            let create = ClientMessage::CreateTournament(params);
+            alice.conn.write_out(&create.to_bytes()).await.unwrap();
+            let tid = match alice.read_until(ServerMessage::TOURNAMENT_CREATED).await {
+                ServerMessage::TournamentCreated(tid, ..) => tid,
+                e => panic!("Expected a new tournament, got {:?}", e),
+            };
+            let join = ClientMessage::JoinTournament(tid);
+            bob.conn.write_out(&join.to_bytes()).await.unwrap();
+            alice.conn.write_out(&join.to_bytes()).await.unwrap();
+            loop {
+                match alice.read_until(ServerMessage::TOURNAMENT_PLAYERS).await {
+                    ServerMessage::TournamentPlayers(_, players) if players.len() == 2 => break,
+                    ServerMessage::TournamentPlayers(..) => continue,
+                    e => panic!("Expected the players, got {:?}", e),
+                }
+            }
+
+            // bob's connection drops before the start; the place is kept for the next one
+            let token = bob.token.clone();
+            drop(bob);
+            let mut bob = TestClient::new(port).await;
+            let reconnect = ClientMessage::Reconnect(token.clone());
+            bob.conn.write_out(&reconnect.to_bytes()).await.unwrap();
+            match alice.read_until(ServerMessage::TOURNAMENT_PLAYERS).await {
+                ServerMessage::TournamentPlayers(_, players) => {
+                    assert_eq!(players, vec![bob.id, alice.id])
+                }
+                e => panic!("Expected the players, got {:?}", e),
+            }
+
+            let start = ClientMessage::StartTournament(tid);
+            alice.conn.write_out(&start.to_bytes()).await.unwrap();
+            let gid = match bob.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(gid, ..) => gid,
+                e => panic!("Expected to join the game, got {:?}", e),
+            };
+
+            // and once more during the game, where the seat is kept
+            drop(bob);
+            alice.read_until(ServerMessage::GAME_LEFT).await;
+            let mut bob = TestClient::new(port).await;
+            bob.conn.write_out(&reconnect.to_bytes()).await.unwrap();
+            match bob.read_until(ServerMessage::GAME_JOINED).await {
+                ServerMessage::GameJoined(id, cid, _) => assert_eq!((id, cid), (gid, bob.id)),
+                e => panic!("Expected the seat back, got {:?}", e),
+            }
+            let resign = ClientMessage::Resign(gid);
+            bob.conn.write_out(&resign.to_bytes()).await.unwrap();
+            loop {
+                match bob.read_until(ServerMessage::STANDINGS).await {
+                    ServerMessage::Standings(_, _, false, _) => continue,
+                    ServerMessage::Standings(_, _, true, standings) => {
+                        assert_eq!(standings[0].player, alice.id);
+                        assert_eq!(standings[1].player, bob.id);
+                        break;
+                    }
+                    e => panic!("Expected the final standings, got {:?}", e),
+                }
+            }
+        }
+    }
+
+    test! {
+        #[cfg(unix)]
+        async fn test_tablebase_probe_runs_aside() {
+            use std::os::unix::fs::PermissionsExt;
+            env_logger::try_init().ok();
+
+            // a table for KRvK and a slow prober that always finds the mate
+            let dir = std::env::temp_dir().join("chess-test-syzygy-7901");
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("KRvK.rtbw"), b"").unwrap();
+            let prober = dir.join("fathom.sh");
+            let answer = "[WDL \"Win\"]\n[DTZ \"1\"]\n[WinningMoves \"Rh8\"]\n";
+            let script = format!("#!/bin/sh\nsleep 2\ncat <<'EOF'\n{}EOF\n", answer);
+            std::fs::write(&prober, script).unwrap();
+            std::fs::set_permissions(&prober, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+            // the position comes from a stored game
+            let store = dir.join("games");
+            let store = store.to_str().unwrap();
+            let saved = SavedGame {
+                id: 1,
+                start_fen: "1k6/8/1K6/8/8/8/8/7R w - - 0 1".to_string(),
+                white: Some(Seat::Player(Identity::guest("white-token"))),
+                time: 600,
+                ..Default::default()
+            };
+            GameStore::open(store).unwrap().save(&saved).await.unwrap();
+
+            let port = 7901;
+            let mut config = Config::read("server.cfg");
+            config.game_store = Some(store.to_string());
+            config.syzygy_path = Some(dir.to_str().unwrap().to_string());
+            config.syzygy_prober = prober.to_str().unwrap().to_string();
+            start_server_with_config(port, config).await;
+
+            let mut client = TestClient::new(port).await;
+            let reconnect = ClientMessage::Reconnect("white-token".to_string());
+            client.conn.write_out(&reconnect.to_bytes()).await.unwrap();
+            client.read_until(ServerMessage::GAME_JOINED).await;
+
+            // the server answers other requests while the prober runs
+            let query = ClientMessage::QueryTablebase(1);
+            client.conn.write_out(&query.to_bytes()).await.unwrap();
+            client.conn.write_out(&ClientMessage::QueryGames.to_bytes()).await.unwrap();
+            loop {
+                match client.conn.read_msg::<ServerMessage>().await {
+                    Ok(ServerMessage::GamesList(_)) => break,
+                    Ok(ServerMessage::TablebaseResult(..)) => panic!("The probe held up the server"),
+                    Ok(_) => {}
+                    Err(e) => panic!("Error reading games list: {:?}", e),
+                }
+            }
+            match client.read_until(ServerMessage::TABLEBASE_RESULT).await {
+                ServerMessage::TablebaseResult(gid, Some((wdl, dtz, best))) => {
+                    assert_eq!((gid, wdl, dtz, best.as_str()), (1, Wdl::Win, 1, "Rh8#"));
+                }
+                e => panic!("Expected the tablebase result, got {:?}", e),
+            }
+        }
+    }
+
+    test! {
+        async fn test_login_throttled() {
+            env_logger::try_init().ok();
+            let path = std::env::temp_dir().join("chess-test-accounts-7902.db");
+            let _ = std::fs::remove_file(&path);
+
+            let port = 7902;
+            let mut config = Config::read("server.cfg");
+            config.accounts = Some(path.to_str().unwrap().to_string());
+            start_server_with_config(port, config).await;
+
+            let credentials = |password: &str| Credentials {
+                name: "alice".to_string(),
+                password: password.to_string(),
+            };
+            async fn login_failed(client: &mut TestClient) -> String {
+                match client.read_until(ServerMessage::LOGIN_FAILED).await {
+                    ServerMessage::LoginFailed(reason) => reason,
+                    e => panic!("Expected a failed login, got {:?}", e),
+                }
+            }
+
+            let mut owner = TestClient::new(port).await;
+            let create = ClientMessage::CreateAccount(credentials("secret1"));
+            owner.conn.write_out(&create.to_bytes()).await.unwrap();
+            owner.read_until(ServerMessage::LOGGED_IN).await;
+
+            // one password at a time: the second login comes while the first is checked
+            let mut client = TestClient::new(port).await;
+            let login = ClientMessage::Login(credentials("wrong1"));
+            client.conn.write_out(&login.to_bytes()).await.unwrap();
+            client.conn.write_out(&login.to_bytes()).await.unwrap();
+            assert_eq!(login_failed(&mut client).await, "Still checking the last login");
+            assert_eq!(login_failed(&mut client).await, "Wrong name or password");
+
+            // unknown names look the same as wrong passwords
+            let unknown = ClientMessage::Login(Credentials {
+                name: "bob".to_string(),
+                password: "secret1".to_string(),
+            });
+            client.conn.write_out(&unknown.to_bytes()).await.unwrap();
+            assert_eq!(login_failed(&mut client).await, "Wrong name or password");
+
+            // the wrong passwords count for the name, not the connection
+            for _ in 1..FREE_FAILED_LOGINS {
+                let mut client = TestClient::new(port).await;
+                client.conn.write_out(&login.to_bytes()).await.unwrap();
+                assert_eq!(login_failed(&mut client).await, "Wrong name or password");
+            }
+            // so even the right password from a new connection has to wait now
+            let mut client = TestClient::new(port).await;
+            let login = ClientMessage::Login(credentials("secret1"));
+            client.conn.write_out(&login.to_bytes()).await.unwrap();
+            assert_eq!(
+                login_failed(&mut client).await,
+                "Too many failed logins, try again later"
+            );
+
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    test! {
+        async fn test_computer_game_ends() {
+            env_logger::try_init().ok();
+
+            let port = 7903;
+            start_server(port).await;
+
+            let mut client = TestClient::new(port).await;
+            let gid = client.create_game(1, 300, 0).await;
+            client.join_game(gid, UserRoleSelection::White).await;
+            let computer = ClientMessage::AddComputer(gid, UserRoleSelection::Black, 1);
+            client.conn.write_out(&computer.to_bytes()).await.unwrap();
+            client.read_until(ServerMessage::GAME_JOINED).await;
+
+            let resign = ClientMessage::Resign(gid);
+            client.conn.write_out(&resign.to_bytes()).await.unwrap();
+            client.read_until(ServerMessage::GAME_OVER).await;
+
+            // the game is saved and gone, and the manager still answers
+            assert!(!client.list_games().await.contains(&gid));
+        }
+    }
 }
@@ -5,13 +5,15 @@ use chess_core::protocol::messages::{ClientMessage, ServerMessage};
 #[cfg(test)]
 use chess_core::protocol::parser::NetMessage;
 #[cfg(test)]
-use chess_core::protocol::{JoinGameParams, NewGameParams, UserRoleSelection};
+use chess_core::protocol::{JoinGameParams, NewGameParams, SeekParams, UserRoleSelection};
 #[cfg(test)]
 use smol::net::TcpStream;
 
 #[cfg(test)]
 pub struct TestClient {
     pub conn: Connection,
+    pub id: usize,     // our client ID on the server
+    pub token: String, // to get our seats back from another connection
 }
 
 #[cfg(test)]
@@ -23,18 +25,24 @@ impl TestClient {
         let mut conn = Connection::new(stream);
 
         // Consume login message
-        match conn.read_msg::<ServerMessage>().await {
-            Ok(ServerMessage::LoginAccepted(_)) => {}
+        let id = match conn.read_msg::<ServerMessage>().await {
+            Ok(ServerMessage::LoginAccepted(id)) => id,
             Ok(e) => panic!("Expected Login event, got {:?}", e),
             Err(e) => panic!("Error reading login message: {:?}", e),
-        }
+        };
+        let token = match conn.read_msg::<ServerMessage>().await {
+            Ok(ServerMessage::ReconnectToken(token)) => token,
+            Ok(e) => panic!("Expected a reconnect token, got {:?}", e),
+            Err(e) => panic!("Error reading reconnect token: {:?}", e),
+        };
 
-        TestClient { conn }
+        TestClient { conn, id, token }
     }
 
     pub async fn create_game(&mut self, mode: u8, time: u32, time_inc: u32) -> u32 {
         let cmd = ClientMessage::NewGame(NewGameParams {
             mode,
+            rated: false,
             time,
             time_inc,
         });
@@ -65,7 +73,7 @@ impl TestClient {
         self.conn.write_out(&cmd.to_bytes()).await.unwrap();
 
         match self.conn.read_msg::<ServerMessage>().await {
-            Ok(event @ ServerMessage::GameDetails(_, _, _, _, _)) => event,
+            Ok(event @ ServerMessage::GameDetails(..)) => event,
             Ok(e) => panic!("Expected GameDetails, got {:?}", e),
             Err(e) => panic!("Error reading game details: {:?}", e),
         }
@@ -87,6 +95,50 @@ impl TestClient {
         }
     }
 
+    /// Post a seek; returns `SeekPending`, or `GameJoined` for our seat if a seek matched.
+    pub async fn seek(&mut self, params: SeekParams) -> ServerMessage {
+        let cmd = ClientMessage::Seek(params);
+        self.conn.write_out(&cmd.to_bytes()).await.unwrap();
+        self.wait_for_seek().await
+    }
+
+    /// Wait for our waiting seek to be paired (or cancelled).
+    pub async fn wait_for_seek(&mut self) -> ServerMessage {
+        loop {
+            match self.conn.read_msg::<ServerMessage>().await {
+                Ok(event @ ServerMessage::SeekPending)
+                | Ok(event @ ServerMessage::SeekCancelled(_))
+                | Ok(event @ ServerMessage::GameJoined(..)) => return event,
+                Ok(_) => {}
+                Err(e) => panic!("Error reading seek response: {:?}", e),
+            }
+        }
+    }
+
+    /// Skip messages until one with the given opcode arrives.
+    pub async fn read_until(&mut self, opcode: u8) -> ServerMessage {
+        loop {
+            match self.conn.read_msg::<ServerMessage>().await {
+                Ok(event) if event.opcode() == opcode => return event,
+                Ok(_) => {}
+                Err(e) => panic!("Error waiting for 0x{:02X}: {:?}", opcode, e),
+            }
+        }
+    }
+
+    pub async fn add_computer(&mut self, game_id: u32, role: UserRoleSelection, level: u8) {
+        let cmd = ClientMessage::AddComputer(game_id, role, level);
+        self.conn.write_out(&cmd.to_bytes()).await.unwrap();
+
+        loop {
+            match self.conn.read_msg::<ServerMessage>().await {
+                Ok(ServerMessage::GameJoined(_, _, _)) => return,
+                Ok(_) => {}
+                Err(e) => panic!("Error adding computer: {:?}", e),
+            }
+        }
+    }
+
     pub async fn make_move(&mut self, game_id: u32, mov_str: &str) -> ServerMessage {
         let mov = mov_str.parse().unwrap();
         let cmd = ClientMessage::Move(game_id, mov);
@@ -95,7 +147,7 @@ impl TestClient {
         loop {
             match self.conn.read_msg::<ServerMessage>().await {
                 Ok(event) => match event {
-                    ServerMessage::MoveAccepted(_, _, _) => {
+                    ServerMessage::MoveAccepted(..) => {
                         // For the purpose of the test_checkmate, we KNOW a CHECKMATE follows d8h4.
                         if mov_str == "d8h4" {
                             if let Ok(next_event) = self.conn.read_msg::<ServerMessage>().await {